@@ -0,0 +1,162 @@
+use alloc::Vec;
+use ball::BALL_RADIUS;
+use lcd::HEIGHT;
+use network::sim::XorShift;
+use network::{BallPacket, GamestatePacket, InputPacket};
+use racket::RACKET_WIDTH;
+
+const MAX_PREDICTION_STEPS: usize = 1000;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    pub fn next(&self) -> Difficulty {
+        match *self {
+            Difficulty::Easy => Difficulty::Normal,
+            Difficulty::Normal => Difficulty::Hard,
+            Difficulty::Hard => Difficulty::Easy,
+        }
+    }
+
+    // frames the cpu lags behind the ball
+    fn reaction_frames(&self) -> usize {
+        match *self {
+            Difficulty::Easy => 20,
+            Difficulty::Normal => 10,
+            Difficulty::Hard => 3,
+        }
+    }
+
+    // the prediction is off by up to this many pixels
+    fn prediction_error(&self) -> i16 {
+        match *self {
+            Difficulty::Easy => 35,
+            Difficulty::Normal => 20,
+            Difficulty::Hard => 8,
+        }
+    }
+
+    // pixels per frame, the physics allow five
+    fn racket_speed(&self) -> i16 {
+        match *self {
+            Difficulty::Easy => 3,
+            Difficulty::Normal => 4,
+            Difficulty::Hard => 5,
+        }
+    }
+}
+
+/// Plays one racket by predicting where the ball will cross its goal line.
+pub struct CpuPlayer {
+    player: usize,
+    difficulty: Difficulty,
+    seen: Vec<BallPacket>, // ring buffer, the cpu acts on the oldest ball
+    next: usize,
+    approaching: bool,
+    error: i16,
+    random: XorShift,
+}
+
+impl CpuPlayer {
+    pub fn new(player: usize, difficulty: Difficulty, seed: u32) -> CpuPlayer {
+        CpuPlayer {
+            player: player,
+            difficulty: difficulty,
+            seen: Vec::with_capacity(difficulty.reaction_frames()),
+            next: 0,
+            approaching: false,
+            error: 0,
+            random: XorShift::new(seed),
+        }
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    /// Forgets the last rally, for a new match.
+    pub fn reset(&mut self) {
+        self.seen.clear();
+        self.next = 0;
+        self.approaching = false;
+    }
+
+    /// Call once per frame, moves `input.goal_y` towards the predicted
+    /// position.
+    pub fn play(&mut self, gamestate: &GamestatePacket, input: &mut InputPacket) {
+        let reaction_frames = self.difficulty.reaction_frames();
+        let ball = if self.seen.len() < reaction_frames {
+            self.seen.push(gamestate.ball);
+            self.seen[0]
+        } else {
+            let oldest = self.seen[self.next];
+            self.seen[self.next] = gamestate.ball;
+            self.next = (self.next + 1) % reaction_frames;
+            oldest
+        };
+
+        let racket = gamestate.rackets[self.player];
+        let approaching = if self.player == 0 {
+            ball.x_vel < 0
+        } else {
+            ball.x_vel > 0
+        };
+        if approaching && !self.approaching {
+            // a new guess for every shot
+            let error = self.difficulty.prediction_error();
+            self.error = self.random.below(2 * error as usize + 1) as i16 - error;
+        }
+        self.approaching = approaching;
+
+        let target = if approaching {
+            let face = if self.player == 0 {
+                racket.x + RACKET_WIDTH as i16 + BALL_RADIUS as i16
+            } else {
+                racket.x - RACKET_WIDTH as i16 - BALL_RADIUS as i16
+            };
+            predict_y(&ball, face) + self.error
+        } else {
+            (HEIGHT / 2) as i16
+        };
+
+        let speed = self.difficulty.racket_speed();
+        let step = (target - racket.y).max(-speed).min(speed);
+        input.goal_y = racket.y + step;
+    }
+}
+
+/// Where the ball will be when it reaches `x`, bouncing off the walls the
+/// same way `physics` does.
+pub fn predict_y(ball: &BallPacket, x: i16) -> i16 {
+    let radius = BALL_RADIUS as i16;
+    let height = HEIGHT as i16;
+    let mut ball = *ball;
+    if ball.x_vel == 0 {
+        return ball.y;
+    }
+    for _ in 0..MAX_PREDICTION_STEPS {
+        if (ball.x_vel < 0 && ball.x <= x) || (ball.x_vel > 0 && ball.x >= x) {
+            break;
+        }
+        let y_new = ball.y + ball.y_vel;
+        if y_new <= radius || y_new >= height - 1 - radius {
+            ball.y_vel *= -1;
+        }
+        ball.y += ball.y_vel;
+        ball.x += ball.x_vel;
+    }
+    ball.y
+}
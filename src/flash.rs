@@ -0,0 +1,101 @@
+//! The last sector of the internal flash, `stm32f7.ld` keeps the program out
+//! of it.
+
+use board::flash::{Flash as FlashRegisters, Keyr};
+use core::ptr;
+use storage::{Flash, FlashError};
+
+const SECTOR: u8 = 7;
+const SECTOR_START: usize = 0x080C_0000;
+const SECTOR_LENGTH: usize = 256 * 1024;
+const KEY_1: u32 = 0x4567_0123;
+const KEY_2: u32 = 0xCDEF_89AB;
+const PSIZE_X8: u8 = 0b00; // byte writes, work at any supply voltage
+
+pub struct InternalFlash {
+    registers: &'static mut FlashRegisters,
+}
+
+impl InternalFlash {
+    pub fn new(registers: &'static mut FlashRegisters) -> InternalFlash {
+        InternalFlash {
+            registers: registers,
+        }
+    }
+
+    fn unlock(&mut self) {
+        if self.registers.cr.read().lock() {
+            for &key in &[KEY_1, KEY_2] {
+                let mut keyr = Keyr::default();
+                keyr.set_key(key);
+                self.registers.keyr.write(keyr);
+            }
+        }
+    }
+
+    fn lock(&mut self) {
+        self.registers.cr.update(|r| r.set_lock(true));
+    }
+
+    // waits for the running operation, true if it succeeded
+    fn finish(&mut self) -> bool {
+        while self.registers.sr.read().bsy() {}
+        let sr = self.registers.sr.read();
+        let failed = sr.operr() || sr.wrperr() || sr.pgaerr() || sr.pgperr() || sr.erserr();
+        // the flags are cleared by writing them back
+        self.registers.sr.update(|_| {});
+        !failed
+    }
+}
+
+impl Flash for InternalFlash {
+    fn len(&self) -> usize {
+        SECTOR_LENGTH
+    }
+
+    fn read(&self, offset: usize, buffer: &mut [u8]) {
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = unsafe { ptr::read_volatile((SECTOR_START + offset + i) as *const u8) };
+        }
+    }
+
+    fn erase(&mut self) -> Result<(), FlashError> {
+        self.unlock();
+        self.registers.cr.update(|r| {
+            r.set_ser(true);
+            r.set_snb(SECTOR);
+        });
+        self.registers.cr.update(|r| r.set_strt(true));
+        let erased = self.finish();
+        self.registers.cr.update(|r| r.set_ser(false));
+        self.lock();
+        if erased {
+            Ok(())
+        } else {
+            Err(FlashError::Erase)
+        }
+    }
+
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), FlashError> {
+        self.unlock();
+        self.registers.cr.update(|r| {
+            r.set_psize(PSIZE_X8);
+            r.set_pg(true);
+        });
+        let mut programmed = true;
+        for (i, &byte) in data.iter().enumerate() {
+            unsafe { ptr::write_volatile((SECTOR_START + offset + i) as *mut u8, byte) };
+            if !self.finish() {
+                programmed = false;
+                break;
+            }
+        }
+        self.registers.cr.update(|r| r.set_pg(false));
+        self.lock();
+        if programmed {
+            Ok(())
+        } else {
+            Err(FlashError::Program)
+        }
+    }
+}
@@ -1,16 +1,35 @@
+use alloc::String;
 use ball;
+use cpu::CpuPlayer;
 use fps;
 use graphics;
 use graphics::GraphicsCache;
 use input::Input;
 use lcd::Framebuffer;
 use lcd::FramebufferL8;
+use lcd::Overlay;
 use lcd::TextWriter;
+use matchfile::{self, MatchRecorder};
 use network::{Client, EthClient, EthServer, GamestatePacket, InputPacket, Network, Server};
+use network::packets::Serializable;
+use network::{handle_network_client, handle_network_server};
 use physics;
 use racket;
-use network::packets::STATE_WON_PLAYER_1;
+use network::packets::{STATE_RUNNING, STATE_WON_PLAYER_1, VOTE_NONE};
+use pause::Pause;
 use physics::PhysicsCache;
+use rematch::{self, Partner};
+use replay::Replay;
+use rules::MatchSettings;
+use scene::Font;
+use {ENABLE_MATCH_RECORDING, MATCH_RECORDING_LENGTH, PADDING};
+
+const SECOND_PLAYER_NAME: &str = "Player 2"; // the first one is set in the settings
+const CPU_NAME: &str = "CPU";
+const ATTRACT_RESTART_DELAY: usize = 3000; // ms the result of a demo match stays up
+const ATTRACT_LABEL: &str = "Demo - touch to play";
+const ATTRACT_LABEL_X: usize = 140;
+const LEAVE_MESSAGES: usize = 5; // some may get lost
 
 pub enum GameState {
     Splash,
@@ -18,14 +37,40 @@ pub enum GameState {
     ChooseClientOrServer,
     ChooseOnlyLocal,
     ConnectToNetwork,
+    NetworkError(String), // why init failed
+    ChooseMatchSettings(GameMode),
     GameRunningLocal,
+    GameRunningVsCpu,
     WaitForPartner(Network),
     GameRunningNetwork(Network),
+    ChangeNetworkSettings(Network), // the server, between two matches
+    Attract,
+    Settings,
+    SaveSettings,
+}
+
+/// What a menu leads to once the match settings are chosen.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GameMode {
+    Local,
+    VsCpu,
+    Network,
+}
+
+/// The buttons on the screen after a match.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PostMatchChoice {
+    Rematch,
+    ChangeSettings,
+    MainMenu,
 }
 
+/// Two players on one board, or one against the cpu when `cpu` is given.
+/// Returns the choice that leaves the match, rematches start right here.
 pub fn game_loop_local(
     just_entered_state: bool,
     framebuffer: &mut FramebufferL8,
+    overlay: &mut Overlay,
     input: &mut Input,
     fps: &fps::FpsCounter,
     rackets: &mut [racket::Racket; 2],
@@ -34,49 +79,120 @@ pub fn game_loop_local(
     local_input_2: &mut InputPacket,
     local_gamestate: &mut GamestatePacket,
     menu_font: &mut TextWriter,
+    button_font: &mut TextWriter,
     cache: &mut GraphicsCache,
     total_time: usize,
     delta_time: usize,
     physics_cache: &mut PhysicsCache,
-) {
+    replay: &mut Replay,
+    match_recorder: &mut Option<MatchRecorder>,
+    match_settings: &MatchSettings,
+    player_name: &str,
+    pause: &mut Pause,
+    mut cpu: Option<&mut CpuPlayer>,
+) -> Option<PostMatchChoice> {
     if just_entered_state {
         framebuffer.clear();
-        graphics::draw_initial(framebuffer, rackets, ball);
+        *cache = GraphicsCache::new();
+        replay.reset();
+        pause.reset();
+        *local_gamestate = GamestatePacket::new(total_time);
+        *physics_cache = PhysicsCache::new();
+        start_recording(match_recorder, total_time, match_settings, player_names(player_name, &cpu));
+        if let Some(ref mut cpu) = cpu {
+            cpu.reset();
+        }
     }
 
-    handle_local_calculations(local_gamestate, local_input_1, local_input_2, total_time, physics_cache);
+    // the match clock stands still during a replay, timed sets lose nothing
+    if replay.is_playing() {
+        pause.hold(total_time);
+        play_replay(framebuffer, overlay, input, rackets, ball, menu_font, button_font, cache, replay,
+                    match_settings, total_time, delta_time);
+        graphics::draw_fps(overlay, fps);
+        return None;
+    }
+    pause.release(total_time);
+
+    let game_time = pause.game_time(total_time);
+    let was_running = local_gamestate.state == STATE_RUNNING;
+    if was_running {
+        if let Some(ref mut cpu) = cpu {
+            cpu.play(local_gamestate, local_input_2);
+        }
+    }
+    handle_local_calculations(local_gamestate, local_input_1, local_input_2, game_time, match_settings,
+                              physics_cache);
+    replay.record(local_gamestate);
+    if was_running {
+        let inputs = [*local_input_1, *local_input_2];
+        record_tick(match_recorder, inputs, game_time, local_gamestate);
+    }
 
     // handle input
+    let mut choice = None;
     if local_gamestate.state >= STATE_WON_PLAYER_1 {
-        let touch = input.handle_menu();
-        if touch.is_down && !touch.any_touch_last_frame {
+        choice = post_match_touch(input, cache, replay, true);
+        if choice == Some(PostMatchChoice::Rematch) {
+            choice = None;
+            pause.reset();
             *local_gamestate = GamestatePacket::new(total_time);
             *physics_cache = PhysicsCache::new();
             *local_input_1 = InputPacket::new();
             *local_input_2 = InputPacket::new();
+            start_recording(match_recorder, total_time, match_settings, player_names(player_name, &cpu));
+            if let Some(ref mut cpu) = cpu {
+                cpu.reset();
+            }
         }
     } else {
-        input.evaluate_touch_two_players(local_input_1, local_input_2);
+        // touches on the cpu's side are ignored
+        let mut touch_input_2 = *local_input_2;
+        let touch = input.evaluate_touch_two_players(
+            local_input_1,
+            if cpu.is_some() { &mut touch_input_2 } else { &mut *local_input_2 },
+        );
+        // the score area starts a replay, the rest of the centre pauses
+        if touch.is_down && !touch.any_touch_last_frame {
+            if graphics::is_in_score_area(touch.x, touch.y) {
+                if !pause.is_paused() {
+                    replay.start();
+                    pause.hold(total_time);
+                }
+            } else {
+                pause.toggle(local_gamestate, total_time);
+            }
+        }
     }
 
+    if local_gamestate.state >= STATE_WON_PLAYER_1 {
+        graphics::update_post_match(cache, true, "");
+    }
     // move rackets and ball
     graphics::update_graphics(
         framebuffer,
+        overlay,
         local_gamestate,
+        match_settings,
         rackets,
         ball,
         menu_font,
+        button_font,
         cache,
         total_time,
         delta_time,
     );
 
-    graphics::draw_fps(framebuffer, fps);
+    graphics::draw_fps(overlay, fps);
+    choice
 }
 
+/// Both boards vote for a rematch, the server starts it once both agree.
+/// Returns the choice that leaves the match.
 pub fn game_loop_network(
     just_entered_state: bool,
     framebuffer: &mut FramebufferL8,
+    overlay: &mut Overlay,
     input: &mut Input,
     fps: &fps::FpsCounter,
     rackets: &mut [racket::Racket; 2],
@@ -88,47 +204,210 @@ pub fn game_loop_network(
     is_server: bool,
     network: &mut Network,
     menu_font: &mut TextWriter,
+    button_font: &mut TextWriter,
     cache: &mut GraphicsCache,
     total_time: usize,
     delta_time: usize,
     physics_cache: &mut PhysicsCache,
-) {
+    replay: &mut Replay,
+    match_recorder: &mut Option<MatchRecorder>,
+    match_settings: &mut MatchSettings,
+    player_name: &str,
+    pause: &mut Pause,
+) -> Option<PostMatchChoice> {
     if just_entered_state {
         framebuffer.clear();
-        graphics::draw_initial(framebuffer, rackets, ball);
+        *cache = GraphicsCache::new();
+        replay.reset();
+        pause.reset();
+        if is_server {
+            *local_gamestate = GamestatePacket::new(total_time);
+            *physics_cache = PhysicsCache::new();
+            let names = [player_name, SECOND_PLAYER_NAME];
+            start_recording(match_recorder, total_time, match_settings, names);
+        }
     }
 
+    let was_running = local_gamestate.state < STATE_WON_PLAYER_1;
+    let client_input;
     if is_server {
-        handle_network_server(server, network, local_gamestate, local_input_1, total_time, physics_cache);
+        let game_time = pause.game_time(total_time);
+        let ticking = local_gamestate.state == STATE_RUNNING;
+        let inputs = handle_network_server(server, network, local_gamestate, local_input_1, game_time,
+                                           match_settings, physics_cache);
+        if ticking {
+            record_tick(match_recorder, inputs, game_time, local_gamestate);
+        }
+        // the client asks, the server decides
+        pause.handle_requests(inputs[1].pause_requests, local_gamestate, total_time);
+        client_input = inputs[1];
+        if local_gamestate.state >= STATE_WON_PLAYER_1 && rematch::count_votes(local_gamestate, &client_input) {
+            pause.reset();
+            *local_gamestate = GamestatePacket::new(total_time);
+            *physics_cache = PhysicsCache::new();
+            *local_input_1 = InputPacket::new();
+            let names = [player_name, SECOND_PLAYER_NAME];
+            start_recording(match_recorder, total_time, match_settings, names);
+        }
     } else {
         handle_network_client(client, network, local_gamestate, local_input_1);
+        if let Some(settings) = client.match_settings() {
+            *match_settings = settings;
+        }
+        // the vote is used up once the next match runs
+        if local_gamestate.state < STATE_WON_PLAYER_1 {
+            local_input_1.vote = VOTE_NONE;
+        }
+        client_input = *local_input_1;
+    }
+    if was_running && local_gamestate.state >= STATE_WON_PLAYER_1 {
+        network.dump_capture();
     }
 
-    if is_server && local_gamestate.state >= STATE_WON_PLAYER_1
-     {
-        let touch = input.handle_menu();
-        if touch.is_down && !touch.any_touch_last_frame {
-            *local_gamestate = GamestatePacket::new(total_time);
-            *physics_cache = PhysicsCache::new();
-            *local_input_1 = InputPacket::new();
+    // the partner keeps playing, so replays are only possible after the match
+    if replay.is_playing() {
+        if local_gamestate.state == STATE_RUNNING {
+            replay.stop();
+            leave_replay(framebuffer, cache);
+        } else {
+            play_replay(framebuffer, overlay, input, rackets, ball, menu_font, button_font, cache, replay,
+                        match_settings, total_time, delta_time);
+            graphics::draw_fps(overlay, fps);
+            return None;
+        }
+    }
+    replay.record(local_gamestate);
+
+    let mut choice = None;
+    let mut status = "";
+    let mut can_change_settings = false;
+    if local_gamestate.state >= STATE_WON_PLAYER_1 {
+        let partner = rematch::partner(local_gamestate, &client_input, is_server);
+        // only the server picks the rules, and only for a partner who is still there
+        can_change_settings = is_server && partner != Partner::Left;
+        choice = post_match_touch(input, cache, replay, can_change_settings);
+        match choice {
+            Some(PostMatchChoice::Rematch) => {
+                choice = None;
+                if partner != Partner::Left {
+                    rematch::vote(local_gamestate, local_input_1, is_server);
+                }
+            }
+            Some(PostMatchChoice::MainMenu) => {
+                rematch::leave(local_gamestate, local_input_1, is_server);
+                send_leave(client, server, network, local_gamestate, local_input_1, is_server);
+            }
+            _ => {}
         }
+        status = if partner == Partner::Left {
+            "Partner left"
+        } else if rematch::has_voted(local_gamestate, local_input_1, is_server) {
+            "Waiting for partner"
+        } else if partner == Partner::WantsRematch {
+            "Partner wants a rematch"
+        } else {
+            ""
+        };
     } else {
-        // handle input
-        input.evaluate_touch_one_player(local_input_1);
+        // handle input, a two finger tap pauses or resumes
+        if input.evaluate_touch_one_player(local_input_1) {
+            if is_server {
+                pause.toggle(local_gamestate, total_time);
+            } else {
+                local_input_1.pause_requests = local_input_1.pause_requests.wrapping_add(1);
+            }
+        }
+    }
+    if local_gamestate.state >= STATE_WON_PLAYER_1 {
+        graphics::update_post_match(cache, can_change_settings, status);
     }
     // move rackets and ball
     graphics::update_graphics(
         framebuffer,
+        overlay,
         local_gamestate,
+        match_settings,
         rackets,
         ball,
         menu_font,
+        button_font,
         cache,
         total_time,
         delta_time,
     );
 
-    graphics::draw_fps(framebuffer, fps);
+    graphics::draw_fps(overlay, fps);
+    choice
+}
+
+/// A demo match between two cpu players for idle menus. Returns true once
+/// the screen is touched.
+pub fn game_loop_attract(
+    just_entered_state: bool,
+    framebuffer: &mut FramebufferL8,
+    overlay: &mut Overlay,
+    input: &mut Input,
+    fps: &fps::FpsCounter,
+    rackets: &mut [racket::Racket; 2],
+    ball: &mut ball::Ball,
+    cpus: &mut [CpuPlayer; 2],
+    local_gamestate: &mut GamestatePacket,
+    menu_font: &mut TextWriter,
+    label_font: &mut TextWriter,
+    cache: &mut GraphicsCache,
+    total_time: usize,
+    delta_time: usize,
+    physics_cache: &mut PhysicsCache,
+    won_at: &mut Option<usize>,
+) -> bool {
+    let settings = MatchSettings::new();
+    let restart = match *won_at {
+        Some(time) => total_time.wrapping_sub(time) > ATTRACT_RESTART_DELAY,
+        None => false,
+    };
+    if just_entered_state || restart {
+        framebuffer.clear();
+        *cache = GraphicsCache::new();
+        graphics::set_label(cache, ATTRACT_LABEL, ATTRACT_LABEL_X, Font::Small);
+        *local_gamestate = GamestatePacket::new(total_time);
+        *physics_cache = PhysicsCache::new();
+        *won_at = None;
+        for cpu in cpus.iter_mut() {
+            cpu.reset();
+        }
+    }
+
+    let touch = input.handle_menu();
+    if touch.is_down && !touch.any_touch_last_frame {
+        framebuffer.clear();
+        *cache = GraphicsCache::new();
+        return true;
+    }
+
+    let mut inputs = [InputPacket::new(), InputPacket::new()];
+    for (cpu, input) in cpus.iter_mut().zip(inputs.iter_mut()) {
+        cpu.play(local_gamestate, input);
+    }
+    physics::calculate_physics(local_gamestate, inputs, total_time, &settings, physics_cache);
+    if local_gamestate.state >= STATE_WON_PLAYER_1 && won_at.is_none() {
+        *won_at = Some(total_time);
+    }
+
+    graphics::update_graphics(
+        framebuffer,
+        overlay,
+        local_gamestate,
+        &settings,
+        rackets,
+        ball,
+        menu_font,
+        label_font,
+        cache,
+        total_time,
+        delta_time,
+    );
+    graphics::draw_fps(overlay, fps);
+    false
 }
 
 fn handle_local_calculations(
@@ -136,31 +415,134 @@ fn handle_local_calculations(
     local_input_1: &InputPacket,
     local_input_2: &InputPacket,
     total_time: usize,
+    match_settings: &MatchSettings,
     physics_cache: &mut PhysicsCache,
 ) {
     let inputs = [*local_input_1, *local_input_2];
-    physics::calculate_physics(local_gamestate, inputs, total_time, physics_cache);
+    physics::calculate_physics(local_gamestate, inputs, total_time, match_settings, physics_cache);
+}
+
+fn player_names<'a>(player_name: &'a str, cpu: &Option<&mut CpuPlayer>) -> [&'a str; 2] {
+    if cpu.is_some() {
+        [player_name, CPU_NAME]
+    } else {
+        [player_name, SECOND_PLAYER_NAME]
+    }
+}
+
+// a touch on the post-match screen, touches on the score start a replay
+fn post_match_touch(
+    input: &mut Input,
+    cache: &GraphicsCache,
+    replay: &mut Replay,
+    can_change_settings: bool,
+) -> Option<PostMatchChoice> {
+    let touch = input.handle_menu();
+    if !touch.is_down || touch.any_touch_last_frame || !graphics::is_post_match_shown(cache) {
+        return None;
+    }
+    if graphics::is_in_score_area(touch.x, touch.y) && replay.has_last_point() {
+        replay.start();
+        return None;
+    }
+    graphics::post_match_choice(touch.x, touch.y, can_change_settings)
 }
 
-fn handle_network_server(
+// packets only go out when the network is polled, so every copy gets a poll
+// before the network is dropped
+fn send_leave(
+    client: &mut EthClient,
     server: &mut EthServer,
     network: &mut Network,
-    local_gamestate: &mut GamestatePacket,
-    local_input_1: &InputPacket,
+    gamestate: &GamestatePacket,
+    input: &InputPacket,
+    is_server: bool,
+) {
+    for _ in 0..LEAVE_MESSAGES {
+        if is_server {
+            server.send_gamestate(network, gamestate);
+        } else {
+            client.send_input(network, input);
+        }
+        let _ = network.get_udp_packet();
+    }
+}
+
+fn start_recording(
+    match_recorder: &mut Option<MatchRecorder>,
     total_time: usize,
-    physics_cache: &mut PhysicsCache,
+    match_settings: &MatchSettings,
+    names: [&str; 2],
 ) {
-    let inputs = [*local_input_1, server.receive_input(network)];
-    physics::calculate_physics(local_gamestate, inputs, total_time, physics_cache);
-    server.send_gamestate(network, local_gamestate);
+    if ENABLE_MATCH_RECORDING {
+        let settings = match_settings.serialize();
+        *match_recorder = Some(MatchRecorder::new(total_time, &settings, names, MATCH_RECORDING_LENGTH));
+    }
 }
 
-fn handle_network_client(
-    client: &mut EthClient,
-    network: &mut Network,
-    local_gamestate: &mut GamestatePacket,
-    local_input_1: &InputPacket,
+// dumps the match once it is decided
+fn record_tick(
+    match_recorder: &mut Option<MatchRecorder>,
+    inputs: [InputPacket; 2],
+    total_time: usize,
+    gamestate: &GamestatePacket,
 ) {
-    *local_gamestate = client.receive_gamestate(network);
-    client.send_input(network, local_input_1);
+    let finished = match *match_recorder {
+        Some(ref mut recorder) => {
+            recorder.record(inputs, total_time, gamestate);
+            gamestate.state >= STATE_WON_PLAYER_1
+        }
+        None => false,
+    };
+    if finished {
+        if let Some(recorder) = match_recorder.take() {
+            matchfile::dump(&recorder.to_bytes(), |line| hprintln!("{}", line));
+        }
+    }
+}
+
+fn play_replay(
+    framebuffer: &mut FramebufferL8,
+    overlay: &mut Overlay,
+    input: &mut Input,
+    rackets: &mut [racket::Racket; 2],
+    ball: &mut ball::Ball,
+    menu_font: &mut TextWriter,
+    label_font: &mut TextWriter,
+    cache: &mut GraphicsCache,
+    replay: &mut Replay,
+    match_settings: &MatchSettings,
+    total_time: usize,
+    delta_time: usize,
+) {
+    let touch = input.handle_menu();
+    if touch.is_down && !touch.any_touch_last_frame {
+        replay.handle_touch(touch.x);
+    }
+
+    match replay.next_frame() {
+        Some(snapshot) => {
+            graphics::set_label(cache, replay.label(), PADDING, Font::Large);
+            graphics::update_graphics(
+                framebuffer,
+                overlay,
+                &snapshot,
+                match_settings,
+                rackets,
+                ball,
+                menu_font,
+                label_font,
+                cache,
+                total_time,
+                delta_time,
+            );
+        }
+        None => leave_replay(framebuffer, cache),
+    }
+}
+
+// the live game continues from a clean screen
+fn leave_replay(framebuffer: &mut FramebufferL8, cache: &mut GraphicsCache) {
+    framebuffer.clear();
+    *cache = GraphicsCache::new();
 }
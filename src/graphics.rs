@@ -1,23 +1,55 @@
 use ball;
 use fps;
 use lcd;
+use lcd::draw;
 use lcd::Framebuffer;
 use lcd::TextWriter;
 use network;
+use palette;
 use racket;
 use PADDING;
-use network::packets::STATE_RUNNING;
+use network::packets::STATE_PAUSED;
 use network::packets::STATE_WON_PLAYER_1;
 use network::packets::STATE_WON_PLAYER_2;
 use lcd::WIDTH;
-use ball::BALL_RADIUS;
-use racket::RACKET_WIDTH;
-
+use game::PostMatchChoice;
+use rect::Rect;
+use rules::MatchSettings;
+use scene;
+use scene::{Font, Scene, Sprite};
 
 const SCORE_1_X: usize = 480 / 2 - 20 - 15;
 const SCORE_1_Y: usize = 272 - 50;
 const SCORE_2_X: usize = 480 / 2 + 20;
 const SCORE_2_Y: usize = 272 - 50;
+const SCORE_DIGIT_WIDTH: usize = 24;
+
+const SET_MARK_RADIUS: u16 = 4;
+const SET_MARK_SPACING: usize = 14;
+const SET_MARKS_1_X: usize = SCORE_1_X - SCORE_DIGIT_WIDTH - 12; // leftwards
+const SET_MARKS_2_X: usize = SCORE_2_X + 2 * SCORE_DIGIT_WIDTH + 12; // rightwards
+const SET_MARKS_Y: usize = SCORE_1_Y + 20;
+
+const PAUSE_X: usize = 480 / 2 - 80;
+const PAUSE_Y: usize = 272 / 2 - 60;
+const PAUSE_WIDTH: usize = 160;
+const PAUSE_HEIGHT: usize = 60;
+
+const POST_MATCH_LEFT: usize = 90;
+const POST_MATCH_RIGHT: usize = 390;
+const POST_MATCH_TOP: usize = 70;
+const POST_MATCH_BOTTOM: usize = 210;
+const POST_MATCH_TEXT_X: usize = 150;
+const POST_MATCH_ROW_Y: usize = 80;
+const POST_MATCH_ROW_HEIGHT: usize = 35;
+const POST_MATCH_BUTTONS: [(&str, PostMatchChoice); 3] = [
+    ("Rematch", PostMatchChoice::Rematch),
+    ("Change settings", PostMatchChoice::ChangeSettings),
+    ("Main menu", PostMatchChoice::MainMenu),
+];
+
+const CLOCK_X: usize = 16; // keeps clear of the fps counter
+const CLOCK_HEIGHT: usize = 3;
 
 pub fn draw_rectangle(
     buffer: &mut lcd::Framebuffer,
@@ -27,71 +59,15 @@ pub fn draw_rectangle(
     y_bottom: u16,
     color: u8,
 ) {
-    for y in y_top..=y_bottom {
-        for x in x_left..=x_right {
-            buffer.set_pixel(x as usize, y as usize, color);
-        }
-    }
-}
-
-pub fn draw_circle(
-    buffer: &mut lcd::Framebuffer,
-    x_pos_centre: u32,
-    y_pos_centre: u32,
-    radius: u32,
-    color: u8,
-) {
-    for y in y_pos_centre - radius..=y_pos_centre + radius {
-        for x in x_pos_centre - radius..=x_pos_centre + radius {
-            if x * x + x_pos_centre * x_pos_centre - 2 * x * x_pos_centre + y * y
-                + y_pos_centre * y_pos_centre - 2 * y * y_pos_centre
-                <= radius * radius
-            {
-                buffer.set_pixel(x as usize, y as usize, color);
-            }
-        }
-    }
-}
-pub fn draw_partial_circle(
-    buffer: &mut lcd::FramebufferL8,
-    x_pos_centre: u32,
-    y_pos_centre: u32,
-    x_pos_centre_part: u32,
-    y_pos_centre_part: u32,
-    radius: u32,
-    radius_part: u32,
-    color: u8,
-) {
-    // all coordinates of square around circle
-    for y in y_pos_centre - radius..=y_pos_centre + radius {
-        for x in x_pos_centre - radius..=x_pos_centre + radius {
-            // if coordinates not inside square around overlapping circle
-            if (x < x_pos_centre_part - radius_part || x > x_pos_centre + radius_part)
-                && (y < y_pos_centre_part - radius_part || y > y_pos_centre_part + radius_part)
-            {
-                // if coordinates fulfil circle equation
-                if x * x + x_pos_centre * x_pos_centre - 2 * x * x_pos_centre + y * y
-                    + y_pos_centre * y_pos_centre - 2 * y * y_pos_centre
-                    <= radius * radius
-                {
-                    buffer.set_pixel(x as usize, y as usize, color);
-                }
-            }
-            // if coordinates inside square around overlapping circle, check for each pixel individually
-            else if (x * x + x_pos_centre_part * x_pos_centre_part - 2 * x * x_pos_centre_part
-                + y * y + y_pos_centre_part * y_pos_centre_part
-                - 2 * y * y_pos_centre_part > radius_part * radius_part)
-                && (x * x + x_pos_centre * x_pos_centre - 2 * x * x_pos_centre + y * y
-                    + y_pos_centre * y_pos_centre - 2 * y * y_pos_centre
-                    <= radius * radius)
-            {
-                buffer.set_pixel(x as usize, y as usize, color);
-            }
-        }
+    if x_right < x_left || y_bottom < y_top {
+        return;
     }
+    let width = (x_right - x_left) as usize + 1;
+    let height = (y_bottom - y_top) as usize + 1;
+    buffer.fill_rect(Rect::new(x_left as usize, y_top as usize, width, height), color);
 }
 
-pub fn draw_fps(framebuffer: &mut lcd::FramebufferL8, fps: &fps::FpsCounter) {
+pub fn draw_fps(framebuffer: &mut Framebuffer, fps: &fps::FpsCounter) {
     let mut number = fps.last_fps;
     if number > 99 {
         number = 99;
@@ -99,7 +75,7 @@ pub fn draw_fps(framebuffer: &mut lcd::FramebufferL8, fps: &fps::FpsCounter) {
     draw_number(framebuffer, 0, 0, number / 10);
     draw_number(framebuffer, 5, 0, number % 10);
 }
-fn draw_number(framebuffer: &mut lcd::FramebufferL8, x: usize, y: usize, number: usize) {
+fn draw_number(framebuffer: &mut Framebuffer, x: usize, y: usize, number: usize) {
     if number == 0 {
         draw_seven_segment(framebuffer, x, y, true, true, true, false, true, true, true);
     } else if number == 1 {
@@ -189,7 +165,7 @@ fn draw_number(framebuffer: &mut lcd::FramebufferL8, x: usize, y: usize, number:
     }
 }
 fn draw_seven_segment(
-    framebuffer: &mut lcd::FramebufferL8,
+    framebuffer: &mut Framebuffer,
     x: usize,
     y: usize,
     top: bool,
@@ -200,8 +176,8 @@ fn draw_seven_segment(
     bottom_right: bool,
     bottom: bool,
 ) {
-    let black = 0;
-    let white = 255;
+    let black = palette::BACKGROUND;
+    let white = palette::TEXT;
     framebuffer.set_pixel(x + 0, y + 0, if top { white } else { black });
     framebuffer.set_pixel(x + 1, y + 0, if top { white } else { black });
     framebuffer.set_pixel(x + 2, y + 0, if top { white } else { black });
@@ -221,145 +197,229 @@ fn draw_seven_segment(
     framebuffer.set_pixel(x + 2, y + 6, if bottom { white } else { black });
 }
 
-pub fn quad(x: usize, y: usize, size: usize, color: &u8, framebuffer: &mut lcd::FramebufferL8) {
-    for y in y..y + size {
-        for x in x..x + size {
-            framebuffer.set_pixel(x, y, *color);
-        }
-    }
-}
-
-pub fn draw_initial(
-    framebuffer: &mut lcd::FramebufferL8,
-    rackets: &[racket::Racket; 2],
-    ball: &ball::Ball,
-) {
-    // Draw Racket Start Position
-    for racket in rackets.iter() {
-        racket.draw_racket(framebuffer);
-    }
-    ball.draw_ball(framebuffer);
-}
+/// Sets the scene from a gamestate and repaints what changed. The ball and
+/// the rackets are drawn to the framebuffer, everything else to the overlay
+/// above it.
 pub fn update_graphics(
     framebuffer: &mut lcd::FramebufferL8,
+    overlay: &mut lcd::Overlay,
     gamestate: &network::GamestatePacket,
+    settings: &MatchSettings,
     rackets: &mut [racket::Racket; 2],
     ball: &mut ball::Ball,
     menu_font: &mut TextWriter,
+    label_font: &mut TextWriter,
     cache: &mut GraphicsCache,
     _total_time: usize,
     _delta_time: usize,
 ) {
-    if gamestate.state != STATE_RUNNING {
-        if cache.last_state != gamestate.state {
-            if gamestate.state == STATE_WON_PLAYER_1 {
-                menu_font.write_at(framebuffer, "Player 1 wins!", 115, PADDING);
-            } else if gamestate.state == STATE_WON_PLAYER_2 {
-                menu_font.write_at(framebuffer, "Player 2 wins!", 115, PADDING);
-            } 
-            cache.last_state = gamestate.state;
-        }
-    } else if cache.last_state != 0 {
-        cache.last_state = 0;
-        framebuffer.clear();
-        *cache = GraphicsCache::new();
-        draw_initial(framebuffer, rackets, ball);
-        // TODO: redraw
+    // send gamestate to ball and rackets
+    let field = &mut cache.field;
+    ball.update_ball_pos(gamestate.ball);
+    field.set(scene::BALL, Some(ball.sprite()));
+    for id in 0..2 {
+        rackets[id].set_height(settings.racket_size as u16);
+        rackets[id].update_racket_pos(gamestate.rackets[id].y as u16);
+        field.set(scene::RACKETS + id, Some(rackets[id].sprite()));
     }
 
-    // send gamestate to ball
-    ball.update_ball_pos(framebuffer, gamestate.ball);
-    // send gamestate to racket to let racket move
+    let hud = &mut cache.hud;
+
+    // two digit scores grow away from the centre
+    let x = if gamestate.score[0] >= 10 {
+        SCORE_1_X - SCORE_DIGIT_WIDTH
+    } else {
+        SCORE_1_X
+    };
+    hud.set(scene::SCORES, Some(score(x, SCORE_1_Y, gamestate.score[0])));
+    hud.set(scene::SCORES + 1, Some(score(SCORE_2_X, SCORE_2_Y, gamestate.score[1])));
+
     for id in 0..2 {
-        rackets[id].update_racket_pos(framebuffer, gamestate.rackets[id].y as u16);
+        let marks = if settings.best_of > 1 {
+            Some(set_marks(id, gamestate.sets[id], settings.sets_to_win()))
+        } else {
+            None
+        };
+        hud.set(scene::SET_MARKS + id, marks);
     }
 
-    // FIXME super dirty hack that just draws over bad situations 🙈
-    if ball.get_xpos_centre_old() <= BALL_RADIUS + 2* RACKET_WIDTH {
-        rackets[0].draw_racket(framebuffer);
-    } else if ball.get_xpos_centre_old() >= (WIDTH as u16) - BALL_RADIUS - 2*RACKET_WIDTH {
-        rackets[1].draw_racket(framebuffer);
-    }
+    let clock = if settings.time_limit > 0 {
+        Some(clock(gamestate.time_left, settings.time_limit))
+    } else {
+        None
+    };
+    hud.set(scene::CLOCK, clock);
 
-    let redraw_score_1 = gamestate.score[0] != cache.score[0]
-        || is_ball_in_score_area(ball);
-    let redraw_score_2 = gamestate.score[1] != cache.score[1]
-        || is_ball_in_score_area(ball);
+    let banner = match gamestate.state {
+        STATE_WON_PLAYER_1 => Some("Player 1 wins!"),
+        STATE_WON_PLAYER_2 => Some("Player 2 wins!"),
+        _ => None,
+    };
+    hud.set(scene::BANNER, banner.map(|text| Sprite::Text {
+        x: 115,
+        y: PADDING,
+        text: text,
+        font: Font::Large,
+    }));
 
+    // after a match the panel belongs to `update_post_match`
+    if gamestate.state == STATE_PAUSED {
+        let rect = Rect::new(PAUSE_X, PAUSE_Y, PAUSE_WIDTH + 1, PAUSE_HEIGHT + 1);
+        let text = Sprite::Text {
+            x: PAUSE_X + 25,
+            y: PAUSE_Y + 10,
+            text: "Paused",
+            font: Font::Large,
+        };
+        set_panel(hud, Some(rect), &[Some(text)]);
+    } else if gamestate.state < STATE_WON_PLAYER_1 {
+        set_panel(hud, None, &[]);
+        cache.post_match_shown = false;
+    }
 
-    if redraw_score_1 {
-        if gamestate.score[0] == 0 && cache.score[0] != 0 {
-            draw_fix_for_score_0(framebuffer, SCORE_1_X, SCORE_1_Y);
-        }
-        if gamestate.score[0] == 1 && cache.score[0] != 1 {
-            draw_fix_for_score_1(framebuffer, SCORE_1_X, SCORE_1_Y);
+    field.render(framebuffer, menu_font, label_font);
+    hud.render(overlay, menu_font, label_font);
+}
+
+/// Puts the buttons after a match into the scene, `update_graphics` draws
+/// them. `status` is a line about the partner's vote.
+pub fn update_post_match(cache: &mut GraphicsCache, can_change_settings: bool, status: &'static str) {
+    let mut lines = [None; scene::PANEL_LINE_COUNT];
+    for (row, &(text, choice)) in POST_MATCH_BUTTONS.iter().enumerate() {
+        if choice != PostMatchChoice::ChangeSettings || can_change_settings {
+            lines[row] = Some(post_match_line(row, text));
         }
+    }
+    if !status.is_empty() {
+        lines[POST_MATCH_BUTTONS.len()] = Some(post_match_line(POST_MATCH_BUTTONS.len(), status));
+    }
+    let rect = Rect::new(
+        POST_MATCH_LEFT,
+        POST_MATCH_TOP,
+        POST_MATCH_RIGHT - POST_MATCH_LEFT + 1,
+        POST_MATCH_BOTTOM - POST_MATCH_TOP + 1,
+    );
+    set_panel(&mut cache.hud, Some(rect), &lines);
+    cache.post_match_shown = true;
+}
 
-        cache.score[0] = gamestate.score[0];
-        menu_font.write_at(
-            framebuffer,
-            &format!("{}", gamestate.score[0]),
-            SCORE_1_X,
-            SCORE_1_Y,
-        );
+pub fn is_post_match_shown(cache: &GraphicsCache) -> bool {
+    cache.post_match_shown
+}
+
+/// The post-match button at a touch, if any.
+pub fn post_match_choice(x: u16, y: u16, can_change_settings: bool) -> Option<PostMatchChoice> {
+    if (x as usize) < POST_MATCH_LEFT || x as usize > POST_MATCH_RIGHT {
+        return None;
     }
-    if redraw_score_2 {
-        if gamestate.score[1] == 0 && cache.score[1] != 0 {
-            draw_fix_for_score_0(framebuffer, SCORE_2_X, SCORE_2_Y);
-        }
-        if gamestate.score[1] == 1 && cache.score[1] != 1 {
-            draw_fix_for_score_1(framebuffer, SCORE_2_X, SCORE_2_Y);
+    for (row, &(_, choice)) in POST_MATCH_BUTTONS.iter().enumerate() {
+        let top = post_match_row_y(row) - POST_MATCH_ROW_HEIGHT / 4;
+        if y as usize >= top && (y as usize) < top + POST_MATCH_ROW_HEIGHT {
+            if choice == PostMatchChoice::ChangeSettings && !can_change_settings {
+                return None;
+            }
+            return Some(choice);
         }
-
-        cache.score[1] = gamestate.score[1];
-        menu_font.write_at(
-            framebuffer,
-            &format!("{}", gamestate.score[1]),
-            SCORE_2_X,
-            SCORE_2_Y,
-        );
     }
+    None
 }
 
-fn is_ball_in_score_area(ball: &mut ball::Ball) -> bool {
-    ball.get_ypos_centre() > SCORE_1_Y as u16 - BALL_RADIUS && 
-    ball.get_xpos_centre() > SCORE_1_X as u16 - BALL_RADIUS &&
-    ball.get_xpos_centre() < SCORE_2_X as u16 + BALL_RADIUS + 30
+/// A line of text over the field, e.g. what a replay shows. It stays until
+/// the cache is reset.
+pub fn set_label(cache: &mut GraphicsCache, text: &'static str, x: usize, font: Font) {
+    cache.hud.set(scene::LABEL, Some(Sprite::Text {
+        x: x,
+        y: PADDING,
+        text: text,
+        font: font,
+    }));
 }
 
-fn draw_fix_for_score_0(framebuffer: &mut Framebuffer, x: usize, y: usize) {
-    let x = x as u16;
-    let y = y as u16;
-    draw_rectangle(framebuffer, x, x + 1, y + 10, y + 30, 0);
+fn post_match_row_y(row: usize) -> usize {
+    POST_MATCH_ROW_Y + row * POST_MATCH_ROW_HEIGHT
 }
-fn draw_fix_for_score_1(framebuffer: &mut Framebuffer, x: usize, y: usize) {
-    let x = x as u16;
-    let y = y as u16;
-    draw_rectangle(framebuffer, x + 10, x + 16, y + 10, y + 30, 0);
+
+fn post_match_line(row: usize, text: &'static str) -> Sprite {
+    Sprite::Text {
+        x: POST_MATCH_TEXT_X,
+        y: post_match_row_y(row),
+        text: text,
+        font: Font::Small,
+    }
 }
 
-pub fn draw_guidelines(framebuffer: &mut Framebuffer) {
-    // center guidelines
-    for y in 0..272 {
-        framebuffer.set_pixel(480 / 4, y, 64);
-        framebuffer.set_pixel(480 / 2, y, 128);
-        framebuffer.set_pixel(480 / 4 * 3, y, 64);
+// the box over the field and the lines in it, None takes them away
+fn set_panel(scene: &mut Scene, rect: Option<Rect>, lines: &[Option<Sprite>]) {
+    scene.set(scene::PANEL, rect.map(|rect| Sprite::Panel { rect: rect }));
+    for i in 0..scene::PANEL_LINE_COUNT {
+        scene.set(scene::PANEL_LINES + i, lines.get(i).and_then(|line| *line));
+    }
+}
+
+fn score(x: usize, y: usize, value: u8) -> Sprite {
+    Sprite::Number {
+        x: x,
+        y: y,
+        value: value,
+        font: Font::Large,
+    }
+}
+
+// filled circles for won sets, dim ones for the sets still needed, growing
+// outwards from the scores
+fn set_marks(id: usize, sets: u8, sets_to_win: u8) -> Sprite {
+    let (x, spacing) = if id == 0 {
+        (SET_MARKS_1_X, -(SET_MARK_SPACING as isize))
+    } else {
+        (SET_MARKS_2_X, SET_MARK_SPACING as isize)
+    };
+    Sprite::Marks {
+        x: x,
+        y: SET_MARKS_Y,
+        radius: SET_MARK_RADIUS,
+        spacing: spacing,
+        count: sets_to_win,
+        filled: sets,
     }
-    for x in 0..480 {
-        framebuffer.set_pixel(x, 272 / 2, 128);
+}
+
+// a bar along the top that shrinks as the set runs out of time
+fn clock(time_left: u16, time_limit: u16) -> Sprite {
+    let width = WIDTH - 2 * CLOCK_X;
+    let filled = width * time_left as usize / time_limit.max(1) as usize;
+    Sprite::Block {
+        rect: Rect::new(CLOCK_X, 0, filled, CLOCK_HEIGHT),
+        color: palette::CLOCK,
     }
 }
 
+/// Whether a touch hit the scores, which is where an instant replay starts.
+pub fn is_in_score_area(x: u16, y: u16) -> bool {
+    y >= SCORE_1_Y as u16 && x >= SCORE_1_X as u16 && x < SCORE_2_X as u16 + 30
+}
+
+pub fn draw_guidelines(framebuffer: &mut Framebuffer) {
+    // center guidelines, the vertical one dashed like a net
+    let (width, height) = (lcd::WIDTH as i32, lcd::HEIGHT as i32);
+    draw::line(framebuffer, width / 4, 0, width / 4, height - 1, palette::MARK_OPEN);
+    draw::dashed_line(framebuffer, width / 2, 0, width / 2, height - 1, 8, 6, palette::CLOCK);
+    draw::line(framebuffer, width / 4 * 3, 0, width / 4 * 3, height - 1, palette::MARK_OPEN);
+    draw::line(framebuffer, 0, height / 2, width - 1, height / 2, palette::CLOCK);
+}
+
+/// What is on the game screen, reset whenever something else drew over it.
 pub struct GraphicsCache {
-    score: [u8; 2],
-    last_state: u8,
+    field: Scene, // the ball and the rackets
+    hud: Scene,   // on the overlay
+    post_match_shown: bool,
 }
 
 impl GraphicsCache {
     pub fn new() -> GraphicsCache {
         GraphicsCache {
-            score: [99, 99],
-            last_state: 0,
+            field: Scene::new(),
+            hud: Scene::new(),
+            post_match_shown: false,
         }
     }
 }
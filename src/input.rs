@@ -5,6 +5,8 @@ use touch;
 pub struct Input {
     i2c_3: i2c::I2C,
     touch_last_frame: bool,
+    centre_touch_last_frame: bool,
+    two_fingers_last_frame: bool,
 }
 
 pub struct Touch {
@@ -19,14 +21,23 @@ impl Input {
         Input {
             i2c_3: i2c_3,
             touch_last_frame: false,
+            centre_touch_last_frame: false,
+            two_fingers_last_frame: false,
         }
     }
 
+    // returns the touch between the two player areas, if there is one
     pub fn evaluate_touch_two_players(
         &mut self,
         local_input_1: &mut InputPacket,
         local_input_2: &mut InputPacket,
-    ) {
+    ) -> Touch {
+        let mut centre = Touch {
+            is_down: false,
+            x: 0,
+            y: 0,
+            any_touch_last_frame: self.centre_touch_last_frame,
+        };
         // poll for new touch data
         for touch in &touch::touches(&mut self.i2c_3).unwrap() {
             // Player_1
@@ -34,17 +45,36 @@ impl Input {
                 local_input_1.goal_y = touch.y as i16;
             }
             // Player_2
-            if touch.x >= 280 {
+            else if touch.x >= 280 {
                 local_input_2.goal_y = touch.y as i16;
+            } else {
+                centre.is_down = true;
+                centre.x = touch.x;
+                centre.y = touch.y;
             }
         }
+        self.centre_touch_last_frame = centre.is_down;
+        centre
     }
 
-    pub fn evaluate_touch_one_player(&mut self, local_input_1: &mut InputPacket) {
+    // returns whether a second finger just touched down
+    pub fn evaluate_touch_one_player(&mut self, local_input_1: &mut InputPacket) -> bool {
         // poll for new touch data
-        for touch in &touch::touches(&mut self.i2c_3).unwrap() {
-            local_input_1.goal_y = touch.y as i16;
+        let touches = touch::touches(&mut self.i2c_3).unwrap();
+        let two_fingers = touches.len() >= 2;
+        if !two_fingers {
+            for touch in &touches {
+                local_input_1.goal_y = touch.y as i16;
+            }
         }
+        let two_finger_tap = two_fingers && !self.two_fingers_last_frame;
+        self.two_fingers_last_frame = two_fingers;
+        two_finger_tap
+    }
+
+    /// Whether the last `handle_menu` saw a touch.
+    pub fn is_touched(&self) -> bool {
+        self.touch_last_frame
     }
 
     pub fn handle_menu(&mut self) -> Touch {
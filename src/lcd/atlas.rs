@@ -0,0 +1,102 @@
+//! Glyphs ready to draw, looked up by character and size. The first time a
+//! glyph is written it is taken apart into the spans it covers fully, which
+//! are filled, and the pixels along its edges, which are set in shades.
+//! Text redrawn every frame, like the scores, only walks them again.
+
+use alloc::Vec;
+use lcd::draw;
+use lcd::font::{BitmapFont, LEVELS};
+use lcd::Framebuffer;
+use palette;
+
+pub struct GlyphAtlas<'a> {
+    font: BitmapFont<'a>,
+    glyphs: Vec<((u8, char), AtlasGlyph)>, // sorted by size and character
+    spans: Vec<(u8, u8, u8)>,              // x, y, length
+    edges: Vec<(u8, u8, u8)>,              // x, y, level
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AtlasGlyph {
+    pub advance: u8,
+    pub left: i8,
+    pub top: i8,
+    pub width: u8,
+    pub height: u8,
+    spans: (usize, usize),
+    edges: (usize, usize),
+}
+
+impl<'a> GlyphAtlas<'a> {
+    pub fn new(font: BitmapFont<'a>) -> GlyphAtlas<'a> {
+        GlyphAtlas {
+            font: font,
+            glyphs: Vec::new(),
+            spans: Vec::new(),
+            edges: Vec::new(),
+        }
+    }
+
+    pub fn font(&self) -> &BitmapFont<'a> {
+        &self.font
+    }
+
+    /// How many glyphs were taken apart so far.
+    pub fn len(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    /// `c` at `size`, taken from the font on first use. `None` if the font
+    /// does not have it.
+    pub fn glyph(&mut self, c: char, size: u8) -> Option<AtlasGlyph> {
+        let position = match self.glyphs.binary_search_by_key(&(size, c), |&(key, _)| key) {
+            Ok(position) => return Some(self.glyphs[position].1),
+            Err(position) => position,
+        };
+        let glyph = self.font.glyph(c, size)?;
+        let (spans, edges) = (self.spans.len(), self.edges.len());
+        for y in 0..usize::from(glyph.height) {
+            let mut x = 0;
+            while x < usize::from(glyph.width) {
+                let level = glyph.level(x, y);
+                if level == LEVELS - 1 {
+                    let start = x;
+                    while x < usize::from(glyph.width) && glyph.level(x, y) == LEVELS - 1 {
+                        x += 1;
+                    }
+                    self.spans.push((start as u8, y as u8, (x - start) as u8));
+                } else {
+                    if level > 0 {
+                        self.edges.push((x as u8, y as u8, level));
+                    }
+                    x += 1;
+                }
+            }
+        }
+        let cached = AtlasGlyph {
+            advance: glyph.advance,
+            left: glyph.left,
+            top: glyph.top,
+            width: glyph.width,
+            height: glyph.height,
+            spans: (spans, self.spans.len()),
+            edges: (edges, self.edges.len()),
+        };
+        self.glyphs.insert(position, ((size, c), cached));
+        Some(cached)
+    }
+
+    /// Draws a glyph written at `x`, `y`, the top left of where it is on
+    /// its line, in the shades of `color`. What it does not cover is left.
+    pub fn draw(&self, framebuffer: &mut Framebuffer, glyph: &AtlasGlyph, x: i32, y: i32, color: u8) {
+        let (x, y) = (x + i32::from(glyph.left), y + i32::from(glyph.top));
+        let full = palette::shade(color, 255);
+        for &(left, top, length) in &self.spans[glyph.spans.0..glyph.spans.1] {
+            draw::fill_rect(framebuffer, x + i32::from(left), y + i32::from(top), length.into(), 1, full);
+        }
+        for &(left, top, level) in &self.edges[glyph.edges.0..glyph.edges.1] {
+            let shade = palette::shade(color, level * (255 / (LEVELS - 1)));
+            draw::pixel(framebuffer, x + i32::from(left), y + i32::from(top), shade);
+        }
+    }
+}
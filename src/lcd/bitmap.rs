@@ -0,0 +1,221 @@
+//! Bitmaps of palette indices, for sprites, logos and skins. `pong-host
+//! asset convert` makes them from PNG and PGM files, the firmware takes them
+//! in with `include_bytes!`.
+//!
+//! ```text
+//! "PBMP" version:u8 flags:u8 key:u8 width:u16 height:u16 pixels
+//! ```
+//!
+//! Numbers are big endian like in the match files. The pixels are palette
+//! indices line by line. With `FLAG_RLE` they come in packets that run on
+//! across line ends: a byte `n` below 0x80 and `n + 1` pixels as they are,
+//! or `0x80 + n` and one pixel repeated `n + 1` times. With `FLAG_KEYED`
+//! pixels of index `key` are transparent.
+
+use lcd::draw;
+use lcd::Framebuffer;
+use rect::Rect;
+
+pub const VERSION: u8 = 1;
+pub const FLAG_RLE: u8 = 1;
+pub const FLAG_KEYED: u8 = 2;
+pub const MAGIC: &[u8; 4] = b"PBMP";
+pub const HEADER_LEN: usize = 11;
+/// A packet byte with this bit repeats one pixel.
+pub const REPEAT: u8 = 0x80;
+pub const MAX_PACKET: usize = 0x80;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BitmapError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    Invalid(&'static str),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Bitmap<'a> {
+    width: usize,
+    height: usize,
+    rle: bool,
+    key: Option<u8>,
+    pixels: &'a [u8],
+}
+
+/// Pixels of a bitmap in the order they are stored.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Run<'a> {
+    Literal(&'a [u8]),
+    Repeat(u8, usize),
+}
+
+impl<'a> Bitmap<'a> {
+    /// Checks the header and that the pixels fill the bitmap exactly, so
+    /// drawing never reads past them.
+    pub fn parse(bytes: &'a [u8]) -> Result<Bitmap<'a>, BitmapError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(BitmapError::Truncated);
+        }
+        if &bytes[..4] != MAGIC {
+            return Err(BitmapError::BadMagic);
+        }
+        if bytes[4] != VERSION {
+            return Err(BitmapError::UnsupportedVersion(bytes[4]));
+        }
+        let flags = bytes[5];
+        if flags & !(FLAG_RLE | FLAG_KEYED) != 0 {
+            return Err(BitmapError::Invalid("unknown flags"));
+        }
+        let bitmap = Bitmap {
+            width: usize::from(bytes[7]) << 8 | usize::from(bytes[8]),
+            height: usize::from(bytes[9]) << 8 | usize::from(bytes[10]),
+            rle: flags & FLAG_RLE != 0,
+            key: if flags & FLAG_KEYED != 0 { Some(bytes[6]) } else { None },
+            pixels: &bytes[HEADER_LEN..],
+        };
+
+        let mut count = 0;
+        let mut runs = bitmap.runs();
+        while let Some(run) = runs.next() {
+            count += match run {
+                Run::Literal(pixels) => pixels.len(),
+                Run::Repeat(_, length) => length,
+            };
+        }
+        if runs.truncated || count < bitmap.width * bitmap.height {
+            return Err(BitmapError::Truncated);
+        }
+        if count > bitmap.width * bitmap.height {
+            return Err(BitmapError::Invalid("more pixels than the bitmap has"));
+        }
+        Ok(bitmap)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The index that is not drawn, if any.
+    pub fn key(&self) -> Option<u8> {
+        self.key
+    }
+
+    pub fn runs(&self) -> Runs<'a> {
+        Runs {
+            pixels: self.pixels,
+            rle: self.rle,
+            truncated: false,
+        }
+    }
+
+    /// The area `draw` covers with its top left corner at `x`, `y`.
+    pub fn bounds(&self, x: i32, y: i32, within: &Rect) -> Rect {
+        let (x, y) = (i64::from(x), i64::from(y));
+        draw::clip(within, x, y, x + self.width as i64, y + self.height as i64)
+    }
+
+    /// Draws the bitmap with its top left corner at `x`, `y`, leaving out
+    /// the transparent pixels and what is off the framebuffer. Repeats are
+    /// filled, the rest copied in spans between transparent pixels.
+    pub fn draw(&self, framebuffer: &mut Framebuffer, x: i32, y: i32) {
+        let bounds = framebuffer.bounds();
+        let visible = self.bounds(x, y, &bounds);
+        if visible.is_empty() {
+            return;
+        }
+        if !self.rle && self.key.is_none() {
+            let column = (visible.x as i64 - i64::from(x)) as usize;
+            let row = (visible.y as i64 - i64::from(y)) as usize;
+            let start = row * self.width + column;
+            framebuffer.copy_rect(&self.pixels[start..], self.width, visible);
+            return;
+        }
+
+        let (x, y) = (i64::from(x), i64::from(y));
+        let mut position = 0;
+        for run in self.runs() {
+            let length = match run {
+                Run::Literal(pixels) => pixels.len(),
+                Run::Repeat(_, length) => length,
+            };
+            // one piece of the run for each line it is on
+            let mut done = 0;
+            while done < length {
+                let (row, column) = ((position + done) / self.width, (position + done) % self.width);
+                let piece = (length - done).min(self.width - column);
+                let left = x + column as i64;
+                let area = draw::clip(&visible, left, y + row as i64, left + piece as i64, y + row as i64 + 1);
+                if !area.is_empty() {
+                    let skip = (area.x as i64 - left) as usize + done;
+                    match run {
+                        Run::Repeat(color, _) => {
+                            if Some(color) != self.key {
+                                framebuffer.fill_rect(area, color);
+                            }
+                        }
+                        Run::Literal(pixels) => self.copy_opaque(framebuffer, &pixels[skip..skip + area.width], area),
+                    }
+                }
+                done += piece;
+            }
+            position += length;
+            if position >= (visible.bottom() as i64 - y) as usize * self.width {
+                break; // the rest is below the framebuffer
+            }
+        }
+    }
+
+    // copies `pixels` to a one line `area` in spans without the key
+    fn copy_opaque(&self, framebuffer: &mut Framebuffer, pixels: &[u8], area: Rect) {
+        let mut start = 0;
+        while start < pixels.len() {
+            let opaque = pixels[start..].iter().take_while(|&&pixel| Some(pixel) != self.key).count();
+            if opaque > 0 {
+                let span = Rect::new(area.x + start, area.y, opaque, 1);
+                framebuffer.copy_rect(&pixels[start..start + opaque], opaque, span);
+            }
+            start += opaque;
+            start += pixels[start..].iter().take_while(|&&pixel| Some(pixel) == self.key).count();
+        }
+    }
+}
+
+pub struct Runs<'a> {
+    pixels: &'a [u8],
+    rle: bool,
+    truncated: bool, // a packet ended early
+}
+
+impl<'a> Iterator for Runs<'a> {
+    type Item = Run<'a>;
+
+    fn next(&mut self) -> Option<Run<'a>> {
+        if self.pixels.is_empty() {
+            return None;
+        }
+        if !self.rle {
+            let pixels = self.pixels;
+            self.pixels = &[];
+            return Some(Run::Literal(pixels));
+        }
+        let packet = self.pixels[0];
+        let length = usize::from(packet & !REPEAT) + 1;
+        let needed = if packet & REPEAT != 0 { 2 } else { 1 + length };
+        if self.pixels.len() < needed {
+            self.truncated = true;
+            self.pixels = &[];
+            return None;
+        }
+        let run = if packet & REPEAT != 0 {
+            Run::Repeat(self.pixels[1], length)
+        } else {
+            Run::Literal(&self.pixels[1..needed])
+        };
+        self.pixels = &self.pixels[needed..];
+        Some(run)
+    }
+}
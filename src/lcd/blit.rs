@@ -0,0 +1,302 @@
+//! Rectangle fills, copies, pixel format conversion and alpha blending in
+//! software, with the arithmetic of the DMA2D (RM0385, chapter 9). `Dma2d`
+//! hands everything it cannot do to `Software`, the host checks `Software`
+//! bit for bit.
+//!
+//! Pixels are little endian, `rect` is in target pixels and must lie inside
+//! the target, sources are read from their `x`, `y` on.
+
+use rect::Rect;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PixelFormat {
+    Argb8888,
+    Rgb888,
+    Rgb565,
+    Argb1555,
+    Argb4444,
+    L8, // index into a clut
+    Al88, // alpha in the high byte, index into a clut in the low one
+    A8, // alpha only, the color is fixed
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match *self {
+            PixelFormat::Argb8888 => 4,
+            PixelFormat::Rgb888 => 3,
+            PixelFormat::Rgb565 | PixelFormat::Argb1555 | PixelFormat::Argb4444 | PixelFormat::Al88 => 2,
+            PixelFormat::L8 | PixelFormat::A8 => 1,
+        }
+    }
+
+    /// Whether the DMA2D can write pixels of this format.
+    pub fn is_output(&self) -> bool {
+        match *self {
+            PixelFormat::L8 | PixelFormat::Al88 | PixelFormat::A8 => false,
+            _ => true,
+        }
+    }
+
+    /// Whether pixels of this format are indices into a clut.
+    pub fn has_clut(&self) -> bool {
+        match *self {
+            PixelFormat::L8 | PixelFormat::Al88 => true,
+            _ => false,
+        }
+    }
+
+    /// Expands a raw pixel to ARGB8888. Narrow channels repeat their high
+    /// bits in the low ones, so full and empty channels stay full and empty.
+    pub fn to_argb8888(&self, raw: u32, clut: &[u32], color: u32) -> u32 {
+        match *self {
+            PixelFormat::Argb8888 => raw,
+            PixelFormat::Rgb888 => 0xFF00_0000 | raw & 0x00FF_FFFF,
+            PixelFormat::Rgb565 => argb(0xFF, expand(raw >> 11, 5), expand(raw >> 5, 6), expand(raw, 5)),
+            PixelFormat::Argb1555 => {
+                let alpha = if raw & 0x8000 != 0 { 0xFF } else { 0 };
+                argb(alpha, expand(raw >> 10, 5), expand(raw >> 5, 5), expand(raw, 5))
+            }
+            PixelFormat::Argb4444 => {
+                argb(expand(raw >> 12, 4), expand(raw >> 8, 4), expand(raw >> 4, 4), expand(raw, 4))
+            }
+            PixelFormat::L8 => clut.get(raw as usize).cloned().unwrap_or(0),
+            PixelFormat::Al88 => {
+                let index = (raw & 0xFF) as usize;
+                (raw >> 8 & 0xFF) << 24 | clut.get(index).cloned().unwrap_or(0) & 0x00FF_FFFF
+            }
+            PixelFormat::A8 => (raw & 0xFF) << 24 | color & 0x00FF_FFFF,
+        }
+    }
+
+    /// Narrows ARGB8888 to a raw pixel by dropping low bits.
+    pub fn from_argb8888(&self, pixel: u32) -> u32 {
+        let (a, r, g, b) = (pixel >> 24, pixel >> 16 & 0xFF, pixel >> 8 & 0xFF, pixel & 0xFF);
+        match *self {
+            PixelFormat::Argb8888 => pixel,
+            PixelFormat::Rgb888 => pixel & 0x00FF_FFFF,
+            PixelFormat::Rgb565 => (r >> 3) << 11 | (g >> 2) << 5 | b >> 3,
+            PixelFormat::Argb1555 => (a >> 7) << 15 | (r >> 3) << 10 | (g >> 3) << 5 | b >> 3,
+            PixelFormat::Argb4444 => (a >> 4) << 12 | (r >> 4) << 8 | (g >> 4) << 4 | b >> 4,
+            // the green channel as a grey level, like the game's indices
+            PixelFormat::L8 => g,
+            PixelFormat::Al88 => a << 8 | g,
+            PixelFormat::A8 => a,
+        }
+    }
+}
+
+/// How the alpha of a source is changed before it is used.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Alpha {
+    Keep,
+    Replace(u8),
+    Multiply(u8),
+}
+
+impl Alpha {
+    pub fn apply(&self, alpha: u32) -> u32 {
+        match *self {
+            Alpha::Keep => alpha,
+            Alpha::Replace(value) => u32::from(value),
+            Alpha::Multiply(value) => alpha * u32::from(value) / 255,
+        }
+    }
+}
+
+pub struct Source<'a> {
+    pub data: &'a [u8],
+    pub format: PixelFormat,
+    pub pitch: usize, // pixels from one line to the next
+    pub x: usize,
+    pub y: usize,
+    pub alpha: Alpha,
+    pub color: u32,       // ARGB8888, the color of A8 pixels
+    pub clut: &'a [u32],  // ARGB8888, the colors of L8 pixels
+}
+
+impl<'a> Source<'a> {
+    /// Reads from the top left corner with the alpha of the pixels.
+    pub fn new(data: &'a [u8], format: PixelFormat, pitch: usize) -> Source<'a> {
+        Source {
+            data: data,
+            format: format,
+            pitch: pitch,
+            x: 0,
+            y: 0,
+            alpha: Alpha::Keep,
+            color: 0,
+            clut: &[],
+        }
+    }
+
+    pub fn at(self, x: usize, y: usize) -> Source<'a> {
+        Source { x: x, y: y, ..self }
+    }
+
+    pub fn with_alpha(self, alpha: Alpha) -> Source<'a> {
+        Source { alpha: alpha, ..self }
+    }
+
+    pub fn with_color(self, color: u32) -> Source<'a> {
+        Source { color: color, ..self }
+    }
+
+    pub fn with_clut(self, clut: &'a [u32]) -> Source<'a> {
+        Source { clut: clut, ..self }
+    }
+
+    /// Byte offset of the pixel `column`, `row` from the start of the source.
+    pub fn offset(&self, column: usize, row: usize) -> usize {
+        ((self.y + row) * self.pitch + self.x + column) * self.format.bytes_per_pixel()
+    }
+
+    fn raw(&self, column: usize, row: usize) -> u32 {
+        read(self.data, self.offset(column, row), self.format.bytes_per_pixel())
+    }
+
+    // the pixel in ARGB8888 after the alpha mode
+    fn argb8888(&self, column: usize, row: usize) -> u32 {
+        let pixel = self.format.to_argb8888(self.raw(column, row), self.clut, self.color);
+        self.alpha.apply(pixel >> 24) << 24 | pixel & 0x00FF_FFFF
+    }
+}
+
+pub struct Target<'a> {
+    pub data: &'a mut [u8],
+    pub format: PixelFormat,
+    pub pitch: usize, // pixels from one line to the next
+}
+
+impl<'a> Target<'a> {
+    pub fn new(data: &'a mut [u8], format: PixelFormat, pitch: usize) -> Target<'a> {
+        Target {
+            data: data,
+            format: format,
+            pitch: pitch,
+        }
+    }
+
+    /// Byte offset of the pixel `x`, `y`.
+    pub fn offset(&self, x: usize, y: usize) -> usize {
+        (y * self.pitch + x) * self.format.bytes_per_pixel()
+    }
+}
+
+pub trait Blitter {
+    /// Sets every pixel to `color`, a raw pixel of the target format.
+    fn fill(&mut self, target: &mut Target, rect: Rect, color: u32);
+    /// Copies the raw pixels of a source in the target format.
+    fn copy(&mut self, source: &Source, target: &mut Target, rect: Rect);
+    /// Copies a source of any format, converting each pixel.
+    fn convert(&mut self, source: &Source, target: &mut Target, rect: Rect);
+    /// Draws `foreground` over `background` into the target.
+    fn blend(&mut self, foreground: &Source, background: &Source, target: &mut Target, rect: Rect);
+}
+
+pub struct Software;
+
+impl Blitter for Software {
+    fn fill(&mut self, target: &mut Target, rect: Rect, color: u32) {
+        let bytes = target.format.bytes_per_pixel();
+        for y in rect.y..rect.bottom() {
+            let start = target.offset(rect.x, y);
+            for pixel in target.data[start..start + rect.width * bytes].chunks_mut(bytes) {
+                write(pixel, color);
+            }
+        }
+    }
+
+    fn copy(&mut self, source: &Source, target: &mut Target, rect: Rect) {
+        assert_eq!(source.format, target.format);
+        let length = rect.width * target.format.bytes_per_pixel();
+        for row in 0..rect.height {
+            let from = source.offset(0, row);
+            let to = target.offset(rect.x, rect.y + row);
+            target.data[to..to + length].copy_from_slice(&source.data[from..from + length]);
+        }
+    }
+
+    fn convert(&mut self, source: &Source, target: &mut Target, rect: Rect) {
+        let bytes = target.format.bytes_per_pixel();
+        for row in 0..rect.height {
+            for column in 0..rect.width {
+                let pixel = target.format.from_argb8888(source.argb8888(column, row));
+                let offset = target.offset(rect.x + column, rect.y + row);
+                write(&mut target.data[offset..offset + bytes], pixel);
+            }
+        }
+    }
+
+    fn blend(&mut self, foreground: &Source, background: &Source, target: &mut Target, rect: Rect) {
+        let bytes = target.format.bytes_per_pixel();
+        for row in 0..rect.height {
+            for column in 0..rect.width {
+                let pixel = blend_pixel(foreground.argb8888(column, row), background.argb8888(column, row));
+                let offset = target.offset(rect.x + column, rect.y + row);
+                write(&mut target.data[offset..offset + bytes], target.format.from_argb8888(pixel));
+            }
+        }
+    }
+}
+
+/// The DMA2D blender:
+///
+/// ```text
+/// a_mult = a_fg * a_bg / 255
+/// a_out = a_fg + a_bg - a_mult
+/// c_out = (c_fg * a_fg + c_bg * a_bg - c_bg * a_mult) / a_out
+/// ```
+pub fn blend_pixel(foreground: u32, background: u32) -> u32 {
+    let (alpha_fg, alpha_bg) = (foreground >> 24, background >> 24);
+    let alpha_mult = alpha_fg * alpha_bg / 255;
+    let alpha_out = alpha_fg + alpha_bg - alpha_mult;
+    if alpha_out == 0 {
+        return 0;
+    }
+    let channel = |shift: u32| {
+        let (fg, bg) = (foreground >> shift & 0xFF, background >> shift & 0xFF);
+        (fg * alpha_fg + bg * alpha_bg - bg * alpha_mult) / alpha_out
+    };
+    argb(alpha_out, channel(16), channel(8), channel(0))
+}
+
+/// Splits an L8 rect into the columns left of and right of a middle that
+/// starts on a word address and is a whole number of words wide. Blitters
+/// that cannot write L8 fill the middle as ARGB8888. `alignment` is the
+/// address of the first pixel modulo 4, the pitch has to be a multiple of 4.
+pub fn split_l8_words(rect: Rect, alignment: usize) -> [Rect; 3] {
+    let start = (rect.x + (4 - (rect.x + alignment) % 4) % 4).min(rect.right());
+    let end = start + (rect.right() - start) / 4 * 4;
+    [
+        Rect::new(rect.x, rect.y, start - rect.x, rect.height),
+        Rect::new(start, rect.y, end - start, rect.height),
+        Rect::new(end, rect.y, rect.right() - end, rect.height),
+    ]
+}
+
+/// An L8 value repeated in all bytes of a word.
+pub fn l8_word(color: u32) -> u32 {
+    (color & 0xFF) * 0x0101_0101
+}
+
+fn argb(a: u32, r: u32, g: u32, b: u32) -> u32 {
+    a << 24 | r << 16 | g << 8 | b
+}
+
+// the low `bits` of a channel widened to 8 bits
+fn expand(channel: u32, bits: u32) -> u32 {
+    let channel = channel & ((1 << bits) - 1);
+    let wide = channel << (8 - bits);
+    wide | wide >> bits
+}
+
+fn read(data: &[u8], offset: usize, bytes: usize) -> u32 {
+    data[offset..offset + bytes].iter().rev().fold(0, |pixel, &byte| pixel << 8 | u32::from(byte))
+}
+
+fn write(pixel: &mut [u8], value: u32) {
+    for (i, byte) in pixel.iter_mut().enumerate() {
+        *byte = (value >> (8 * i)) as u8;
+    }
+}
@@ -0,0 +1,119 @@
+//! A framebuffer in memory, in any of the pixel formats of `format`. It
+//! stores the colors of one theme, for images drawn ahead of time and for the
+//! host checks.
+
+use alloc::Vec;
+use core::marker::PhantomData;
+use lcd::blit::{Blitter, PixelFormat, Software, Source, Target};
+use lcd::format::Format;
+use lcd::Framebuffer;
+use palette;
+use rect::Rect;
+
+pub struct Canvas<F> {
+    data: Vec<u8>,
+    width: usize,
+    height: usize,
+    clut: [u32; 256], // ARGB8888 of every palette index
+    format: PhantomData<F>,
+}
+
+impl<F: Format> Canvas<F> {
+    /// A cleared canvas in the colors of `palette::THEMES[theme]`.
+    pub fn new(width: usize, height: usize, theme: u8) -> Canvas<F> {
+        let mut canvas = Canvas {
+            data: vec![0; width * height * F::FORMAT.bytes_per_pixel()],
+            width: width,
+            height: height,
+            clut: [0; 256],
+            format: PhantomData,
+        };
+        canvas.set_theme(theme);
+        canvas.clear();
+        canvas
+    }
+
+    /// What is drawn from now on takes the colors of another theme.
+    pub fn set_theme(&mut self, theme: u8) {
+        for (i, entry) in self.clut.iter_mut().enumerate() {
+            let (red, green, blue) = palette::clut_entry(theme, i as u8);
+            *entry = 0xFF00_0000 | u32::from(red) << 16 | u32::from(green) << 8 | u32::from(blue);
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The pixels line by line, little endian.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The raw pixel at `x`, `y`.
+    pub fn pixel(&self, x: usize, y: usize) -> u32 {
+        let bytes = F::FORMAT.bytes_per_pixel();
+        let offset = (y * self.width + x) * bytes;
+        self.data[offset..offset + bytes].iter().rev().fold(0, |pixel, &byte| pixel << 8 | u32::from(byte))
+    }
+}
+
+impl<F: Format> Framebuffer for Canvas<F> {
+    fn set_pixel(&mut self, x: usize, y: usize, color: u8) {
+        if x < self.width && y < self.height {
+            let raw = F::raw(color, &self.clut);
+            let bytes = F::FORMAT.bytes_per_pixel();
+            let offset = (y * self.width + x) * bytes;
+            for (i, byte) in self.data[offset..offset + bytes].iter_mut().enumerate() {
+                *byte = (raw >> (8 * i)) as u8;
+            }
+        }
+    }
+
+    fn swap_buffers(&mut self) {}
+
+    fn clear(&mut self) {
+        let bounds = self.bounds();
+        self.fill_rect(bounds, palette::BACKGROUND);
+    }
+
+    fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    fn fill_rect(&mut self, rect: Rect, color: u8) {
+        let rect = rect.intersection(&self.bounds());
+        if rect.is_empty() {
+            return;
+        }
+        let raw = F::raw(color, &self.clut);
+        Software.fill(&mut Target::new(&mut self.data, F::FORMAT, self.width), rect, raw);
+    }
+
+    // the image holds palette indices, the clut turns them into colors
+    fn copy_rect(&mut self, image: &[u8], pitch: usize, rect: Rect) {
+        let visible = rect.intersection(&self.bounds());
+        if visible.is_empty() {
+            return;
+        }
+        let Canvas {
+            ref mut data,
+            ref clut,
+            width,
+            ..
+        } = *self;
+        let source = Source::new(image, PixelFormat::L8, pitch)
+            .at(visible.x - rect.x, visible.y - rect.y)
+            .with_clut(clut);
+        let mut target = Target::new(data, F::FORMAT, width);
+        if F::FORMAT == PixelFormat::L8 {
+            Software.copy(&source, &mut target, visible);
+        } else {
+            Software.convert(&source, &mut target, visible);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! The parts of a buffer drawn since it was last swapped.
+//!
+//! Rects closer than `SLACK` are merged, so the pixels of a glyph end up in
+//! one rect around it instead of many small ones. Once `CAPACITY` rects are
+//! not enough, everything becomes one rect around all of them.
+
+use rect::Rect;
+
+const CAPACITY: usize = 16;
+const SLACK: usize = 8;
+
+pub struct Damage {
+    rects: [Rect; CAPACITY],
+    len: usize,
+}
+
+impl Damage {
+    pub fn new() -> Damage {
+        Damage {
+            rects: [Rect::new(0, 0, 0, 0); CAPACITY],
+            len: 0,
+        }
+    }
+
+    pub fn add(&mut self, rect: Rect) {
+        if rect.is_empty() || self.rects().iter().any(|damaged| damaged.union(&rect) == *damaged) {
+            return;
+        }
+        let mut area = rect;
+        let mut i = 0;
+        while i < self.len {
+            if near(&self.rects[i], &area) {
+                area = area.union(&self.rects[i]);
+                self.len -= 1;
+                self.rects[i] = self.rects[self.len];
+                i = 0;
+            } else {
+                i += 1;
+            }
+        }
+        if self.len == CAPACITY {
+            for damaged in &self.rects {
+                area = area.union(damaged);
+            }
+            self.len = 0;
+        }
+        self.rects[self.len] = area;
+        self.len += 1;
+    }
+
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects[..self.len]
+    }
+
+    /// Pixels in all rects, more than damaged if the rects overlap.
+    pub fn area(&self) -> usize {
+        self.rects().iter().map(|rect| rect.width * rect.height).sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+fn near(a: &Rect, b: &Rect) -> bool {
+    a.x <= b.right() + SLACK && b.x <= a.right() + SLACK && a.y <= b.bottom() + SLACK && b.y <= a.bottom() + SLACK
+}
@@ -0,0 +1,258 @@
+//! The Chrom-ART accelerator. Each call starts a transfer and waits for it,
+//! so the CPU never draws into memory the DMA2D is still writing. Whatever
+//! the DMA2D cannot do, like writing L8, or a transfer it rejects, is done
+//! by `blit::Software` instead.
+
+use super::blit::{self, Alpha, Blitter, PixelFormat, Software, Source, Target};
+use board::dma2d::{Dma2d as Dma2dRegisters, Ifcr, Ocolr};
+use rect::Rect;
+
+const MODE_MEMORY_TO_MEMORY: u8 = 0b00;
+const MODE_CONVERT: u8 = 0b01;
+const MODE_BLEND: u8 = 0b10;
+const MODE_FILL: u8 = 0b11;
+
+const MAX_PIXELS_PER_LINE: usize = (1 << 14) - 1;
+const MAX_LINE_OFFSET: usize = (1 << 14) - 1;
+
+pub struct Dma2d {
+    registers: &'static mut Dma2dRegisters,
+}
+
+impl Dma2d {
+    /// `lcd::init` enables the clock.
+    pub fn new(registers: &'static mut Dma2dRegisters) -> Dma2d {
+        Dma2d {
+            registers: registers,
+        }
+    }
+
+    // output address, line offset, size and format, false if the registers
+    // cannot hold them
+    fn set_output(&mut self, target: &Target, rect: &Rect) -> bool {
+        if !fits(rect.width, target.pitch) {
+            return false;
+        }
+        let address = target.data.as_ptr() as u32 + target.offset(rect.x, rect.y) as u32;
+        let line_offset = target.pitch - rect.width;
+        let mode = color_mode(target.format);
+        self.registers.omar.update(|r| r.set_ma(address));
+        self.registers.oor.update(|r| r.set_lo(line_offset as u16));
+        self.registers.nlr.update(|r| {
+            r.set_pl(rect.width as u16);
+            r.set_nl(rect.height as u16);
+        });
+        if target.format.is_output() {
+            // copies keep the foreground format
+            self.registers.opfccr.update(|r| r.set_cm(mode));
+        }
+        true
+    }
+
+    fn set_foreground(&mut self, source: &Source, width: usize) -> bool {
+        if !fits(width, source.pitch) {
+            return false;
+        }
+        let address = source.data.as_ptr() as u32 + source.offset(0, 0) as u32;
+        let line_offset = source.pitch - width;
+        self.registers.fgmar.update(|r| r.set_ma(address));
+        self.registers.fgor.update(|r| r.set_lo(line_offset as u16));
+        self.registers.fgcolr.update(|r| {
+            r.set_red((source.color >> 16) as u8);
+            r.set_green((source.color >> 8) as u8);
+            r.set_blue(source.color as u8);
+        });
+        let (alpha_mode, alpha) = alpha_mode(source.alpha);
+        self.registers.fgpfccr.update(|r| {
+            r.set_cm(color_mode(source.format));
+            r.set_am(alpha_mode);
+            r.set_alpha(alpha);
+        });
+        true
+    }
+
+    fn set_background(&mut self, source: &Source, width: usize) -> bool {
+        if !fits(width, source.pitch) {
+            return false;
+        }
+        let address = source.data.as_ptr() as u32 + source.offset(0, 0) as u32;
+        let line_offset = source.pitch - width;
+        self.registers.bgmar.update(|r| r.set_ma(address));
+        self.registers.bgor.update(|r| r.set_lo(line_offset as u16));
+        self.registers.bgcolr.update(|r| {
+            r.set_red((source.color >> 16) as u8);
+            r.set_green((source.color >> 8) as u8);
+            r.set_blue(source.color as u8);
+        });
+        let (alpha_mode, alpha) = alpha_mode(source.alpha);
+        self.registers.bgpfccr.update(|r| {
+            r.set_cm(color_mode(source.format));
+            r.set_am(alpha_mode);
+            r.set_alpha(alpha);
+        });
+        true
+    }
+
+    // loads the colors of L8 and AL88 pixels for a conversion, false if the source
+    // has none; copies keep the indices and need no clut
+    fn load_foreground_clut(&mut self, source: &Source) -> bool {
+        if !source.format.has_clut() {
+            return true;
+        }
+        if source.clut.is_empty() || source.clut.len() > 256 {
+            return false;
+        }
+        self.registers.fgcmar.update(|r| r.set_ma(source.clut.as_ptr() as u32));
+        self.registers.fgpfccr.update(|r| {
+            r.set_ccm(false); // ARGB8888
+            r.set_cs((source.clut.len() - 1) as u8);
+            r.set_start(true);
+        });
+        while self.registers.fgpfccr.read().start() {}
+        true
+    }
+
+    fn load_background_clut(&mut self, source: &Source) -> bool {
+        if !source.format.has_clut() {
+            return true;
+        }
+        if source.clut.is_empty() || source.clut.len() > 256 {
+            return false;
+        }
+        self.registers.bgcmar.update(|r| r.set_ma(source.clut.as_ptr() as u32));
+        self.registers.bgpfccr.update(|r| {
+            r.set_ccm(false);
+            r.set_cs((source.clut.len() - 1) as u8);
+            r.set_start(true);
+        });
+        while self.registers.bgpfccr.read().start() {}
+        true
+    }
+
+    // runs a configured transfer to the end, true if it succeeded
+    fn run(&mut self, mode: u8) -> bool {
+        self.registers.cr.update(|r| {
+            r.set_mode(mode);
+            r.set_start(true);
+        });
+        while self.registers.cr.read().start() {}
+        let isr = self.registers.isr.read();
+        let failed = isr.teif() || isr.caeif() || isr.ceif();
+        let mut ifcr = Ifcr::default();
+        ifcr.set_ctcif(true);
+        ifcr.set_cteif(true);
+        ifcr.set_caecif(true);
+        ifcr.set_cceif(true);
+        self.registers.ifcr.write(ifcr);
+        !failed
+    }
+
+    fn fill_output(&mut self, target: &mut Target, rect: Rect, color: u32) -> bool {
+        if rect.width == 0 || rect.height == 0 {
+            return true;
+        }
+        if !self.set_output(target, &rect) {
+            return false;
+        }
+        // the fields are named for ARGB8888, the register holds a raw pixel
+        // of the output format
+        let mut ocolr = Ocolr::default();
+        ocolr.set_aplha((color >> 24) as u8); // sic
+        ocolr.set_red((color >> 16) as u8);
+        ocolr.set_green((color >> 8) as u8);
+        ocolr.set_blue(color as u8);
+        self.registers.ocolr.write(ocolr);
+        self.run(MODE_FILL)
+    }
+
+    // the word aligned middle as ARGB8888 words holding four pixels each
+    fn fill_l8_words(&mut self, target: &mut Target, rect: Rect, color: u32) -> bool {
+        if target.pitch % 4 != 0 {
+            return false;
+        }
+        let start = target.offset(0, rect.y) + rect.x;
+        let mut words = Target::new(&mut target.data[start..], PixelFormat::Argb8888, target.pitch / 4);
+        let rect = Rect::new(0, 0, rect.width / 4, rect.height);
+        self.fill_output(&mut words, rect, blit::l8_word(color))
+    }
+}
+
+impl Blitter for Dma2d {
+    fn fill(&mut self, target: &mut Target, rect: Rect, color: u32) {
+        if target.format.is_output() {
+            if !self.fill_output(target, rect, color) {
+                Software.fill(target, rect, color);
+            }
+        } else if target.format == PixelFormat::L8 {
+            let alignment = target.data.as_ptr() as usize % 4;
+            let parts = blit::split_l8_words(rect, alignment);
+            if !self.fill_l8_words(target, parts[1], color) {
+                Software.fill(target, parts[1], color);
+            }
+            Software.fill(target, parts[0], color);
+            Software.fill(target, parts[2], color);
+        } else {
+            Software.fill(target, rect, color);
+        }
+    }
+
+    fn copy(&mut self, source: &Source, target: &mut Target, rect: Rect) {
+        let done = source.format == target.format
+            && self.set_foreground(source, rect.width)
+            && self.set_output(target, &rect)
+            && self.run(MODE_MEMORY_TO_MEMORY);
+        if !done {
+            Software.copy(source, target, rect);
+        }
+    }
+
+    fn convert(&mut self, source: &Source, target: &mut Target, rect: Rect) {
+        let done = target.format.is_output()
+            && self.set_foreground(source, rect.width)
+            && self.load_foreground_clut(source)
+            && self.set_output(target, &rect)
+            && self.run(MODE_CONVERT);
+        if !done {
+            Software.convert(source, target, rect);
+        }
+    }
+
+    fn blend(&mut self, foreground: &Source, background: &Source, target: &mut Target, rect: Rect) {
+        let done = target.format.is_output()
+            && self.set_foreground(foreground, rect.width)
+            && self.load_foreground_clut(foreground)
+            && self.set_background(background, rect.width)
+            && self.load_background_clut(background)
+            && self.set_output(target, &rect)
+            && self.run(MODE_BLEND);
+        if !done {
+            Software.blend(foreground, background, target, rect);
+        }
+    }
+}
+
+// the line registers are 14 bits wide
+fn fits(width: usize, pitch: usize) -> bool {
+    width <= MAX_PIXELS_PER_LINE && pitch >= width && pitch - width <= MAX_LINE_OFFSET
+}
+
+fn color_mode(format: PixelFormat) -> u8 {
+    match format {
+        PixelFormat::Argb8888 => 0b0000,
+        PixelFormat::Rgb888 => 0b0001,
+        PixelFormat::Rgb565 => 0b0010,
+        PixelFormat::Argb1555 => 0b0011,
+        PixelFormat::Argb4444 => 0b0100,
+        PixelFormat::L8 => 0b0101,
+        PixelFormat::Al88 => 0b0111,
+        PixelFormat::A8 => 0b1001,
+    }
+}
+
+fn alpha_mode(alpha: Alpha) -> (u8, u8) {
+    match alpha {
+        Alpha::Keep => (0b00, 0xFF),
+        Alpha::Replace(value) => (0b01, value),
+        Alpha::Multiply(value) => (0b10, value),
+    }
+}
@@ -0,0 +1,356 @@
+//! Drawing primitives at signed coordinates. Shapes may reach past the edges
+//! or lie off the framebuffer entirely, only the part inside its `bounds` is
+//! drawn. Wrap it in a `Clipped` to draw to a part of it.
+//!
+//! Filled shapes are drawn row by row, one `fill_rect` per span, and only
+//! the rows and columns inside the bounds are worked out, so a shape far
+//! larger than the screen costs no more than one that fills it.
+
+use alloc::Vec;
+use lcd::Framebuffer;
+use rect::Rect;
+
+/// The part of the columns `left..right` and the rows `top..bottom` inside
+/// `bounds`, empty if they miss it.
+pub fn clip(bounds: &Rect, left: i64, top: i64, right: i64, bottom: i64) -> Rect {
+    let clamp = |value: i64, from: usize, to: usize| value.max(from as i64).min(to as i64) as usize;
+    let left = clamp(left, bounds.x, bounds.right());
+    let top = clamp(top, bounds.y, bounds.bottom());
+    let right = clamp(right, left, bounds.right());
+    let bottom = clamp(bottom, top, bounds.bottom());
+    Rect::new(left, top, right - left, bottom - top)
+}
+
+pub fn pixel(framebuffer: &mut Framebuffer, x: i32, y: i32, color: u8) {
+    let (x, y) = (i64::from(x), i64::from(y));
+    let area = clip(&framebuffer.bounds(), x, y, x + 1, y + 1);
+    if !area.is_empty() {
+        framebuffer.set_pixel(area.x, area.y, color);
+    }
+}
+
+/// Fills `width` by `height` pixels from `x`, `y` to the bottom right.
+pub fn fill_rect(framebuffer: &mut Framebuffer, x: i32, y: i32, width: u32, height: u32, color: u8) {
+    let (x, y) = (i64::from(x), i64::from(y));
+    let area = clip(&framebuffer.bounds(), x, y, x + i64::from(width), y + i64::from(height));
+    if !area.is_empty() {
+        framebuffer.fill_rect(area, color);
+    }
+}
+
+/// A Bresenham line from `x0`, `y0` to `x1`, `y1`, both ends included.
+pub fn line(framebuffer: &mut Framebuffer, x0: i32, y0: i32, x1: i32, y1: i32, color: u8) {
+    dashed_line(framebuffer, x0, y0, x1, y1, 1, 0, color);
+}
+
+/// A line of `dash` pixels on and `gap` off along its longer axis, from a
+/// dash at `x0`, `y0`. Like the net across the middle of the court.
+pub fn dashed_line(
+    framebuffer: &mut Framebuffer,
+    x0: i32,
+    y0: i32,
+    x1: i32,
+    y1: i32,
+    dash: u32,
+    gap: u32,
+    color: u8,
+) {
+    let bounds = framebuffer.bounds();
+    let (x0, y0, x1, y1) = (i64::from(x0), i64::from(y0), i64::from(x1), i64::from(y1));
+    // walked along the major axis a, which moves every step, the minor one
+    // b moves on some
+    let steep = (y1 - y0).abs() > (x1 - x0).abs();
+    let (a0, a1, b0, b1) = if steep { (y0, y1, x0, x1) } else { (x0, x1, y0, y1) };
+    let (a_bounds, b_bounds) = if steep {
+        ((bounds.y as i64, bounds.bottom() as i64), (bounds.x as i64, bounds.right() as i64))
+    } else {
+        ((bounds.x as i64, bounds.right() as i64), (bounds.y as i64, bounds.bottom() as i64))
+    };
+    let step_a = if a1 < a0 { -1 } else { 1 };
+    let step_b = if b1 < b0 { -1 } else { 1 };
+    let (da, db) = ((a1 - a0).abs() as u64, (b1 - b0).abs() as u64);
+
+    // only the steps inside the bounds along the major axis are walked
+    let (first, last) = if step_a > 0 {
+        (a_bounds.0 - a0, a_bounds.1 - 1 - a0)
+    } else {
+        (a0 - (a_bounds.1 - 1), a0 - a_bounds.0)
+    };
+    let (first, last) = (first.max(0), last.min(da as i64));
+    if first > last {
+        return;
+    }
+
+    // step k is (2 * k * db + da - 1) / (2 * da) along the minor axis, halves
+    // rounded down, the error is what the division leaves
+    let (mut minor, mut error) = if da == 0 {
+        (0, 0)
+    } else {
+        let product = first as u64 * db; // each factor below 2^32
+        let (quotient, remainder) = (product / da, product % da);
+        (quotient + (2 * remainder + da - 1) / (2 * da), (2 * remainder + da - 1) % (2 * da))
+    };
+    let period = u64::from(dash) + u64::from(gap);
+    for k in first as u64..=last as u64 {
+        let b = b0 + step_b * minor as i64;
+        let on = period == 0 || k % period < u64::from(dash);
+        if on && b >= b_bounds.0 && b < b_bounds.1 {
+            let a = (a0 + step_a * k as i64) as usize;
+            if steep {
+                framebuffer.set_pixel(b as usize, a, color);
+            } else {
+                framebuffer.set_pixel(a, b as usize, color);
+            }
+        }
+        error += 2 * db;
+        if error >= 2 * da {
+            error -= 2 * da;
+            minor += 1;
+        }
+    }
+}
+
+/// A filled circle around `x`, `y`: the pixels at most `radius` from it.
+pub fn disc(framebuffer: &mut Framebuffer, x: i32, y: i32, radius: u32, color: u8) {
+    round(framebuffer, x, y, radius, false, color);
+}
+
+/// The edge of `disc`.
+pub fn circle(framebuffer: &mut Framebuffer, x: i32, y: i32, radius: u32, color: u8) {
+    round(framebuffer, x, y, radius, true, color);
+}
+
+/// A filled ellipse around `x`, `y`, `radius_x` to the sides and `radius_y`
+/// up and down.
+pub fn fill_ellipse(framebuffer: &mut Framebuffer, x: i32, y: i32, radius_x: u16, radius_y: u16, color: u8) {
+    oval(framebuffer, x, y, radius_x, radius_y, false, color);
+}
+
+/// The edge of `fill_ellipse`.
+pub fn ellipse(framebuffer: &mut Framebuffer, x: i32, y: i32, radius_x: u16, radius_y: u16, color: u8) {
+    oval(framebuffer, x, y, radius_x, radius_y, true, color);
+}
+
+/// `fill_rect` with its corners rounded off by quarter discs of `radius`,
+/// which shrinks to fit.
+pub fn fill_rounded_rect(
+    framebuffer: &mut Framebuffer,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    radius: u32,
+    color: u8,
+) {
+    rounded(framebuffer, x, y, width, height, radius, false, color);
+}
+
+/// The edge of `fill_rounded_rect`.
+pub fn rounded_rect(
+    framebuffer: &mut Framebuffer,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    radius: u32,
+    color: u8,
+) {
+    rounded(framebuffer, x, y, width, height, radius, true, color);
+}
+
+/// Lines between the corners, back to the first one.
+pub fn polygon(framebuffer: &mut Framebuffer, points: &[(i16, i16)], color: u8) {
+    for (i, &(x0, y0)) in points.iter().enumerate() {
+        let (x1, y1) = points[(i + 1) % points.len()];
+        line(framebuffer, x0.into(), y0.into(), x1.into(), y1.into(), color);
+    }
+}
+
+/// The pixels whose centres are inside the polygon, by the even-odd rule:
+/// edges crossing each other leave holes where they overlap. Corners are
+/// 16 bit, which reaches far past the screen and keeps the arithmetic of the
+/// edges in 64 bits.
+pub fn fill_polygon(framebuffer: &mut Framebuffer, points: &[(i16, i16)], color: u8) {
+    let bounds = framebuffer.bounds();
+    let top = points.iter().map(|point| i64::from(point.1)).min().unwrap_or(0);
+    let bottom = points.iter().map(|point| i64::from(point.1)).max().unwrap_or(-1) + 1;
+    let rows = clip(&bounds, bounds.x as i64, top, bounds.right() as i64, bottom);
+    let mut crossings = Vec::with_capacity(points.len());
+    for y in rows.y..rows.bottom() {
+        // in half pixels, where the centre of the row is odd and no corner
+        // lies on it
+        let centre = 2 * y as i64 + 1;
+        crossings.clear();
+        for (i, &(xa, ya)) in points.iter().enumerate() {
+            let (xb, yb) = points[(i + 1) % points.len()];
+            let (xa, ya, xb, yb) = (i64::from(xa), i64::from(ya), i64::from(xb), i64::from(yb));
+            if (2 * ya < centre) != (2 * yb < centre) {
+                // twice the column of the crossing is `twice / rows`, the
+                // first pixel centre at or right of it starts a span
+                let rows = yb - ya;
+                let twice = 2 * xa * rows + (centre - 2 * ya) * (xb - xa);
+                let (twice, rows) = if rows < 0 { (-twice, -rows) } else { (twice, rows) };
+                crossings.push(div_ceil(twice - rows, 2 * rows));
+            }
+        }
+        crossings.sort_unstable();
+        for pair in crossings.chunks(2) {
+            if pair.len() == 2 {
+                span(framebuffer, &bounds, y, pair[0], pair[1], color);
+            }
+        }
+    }
+}
+
+pub fn triangle(framebuffer: &mut Framebuffer, a: (i16, i16), b: (i16, i16), c: (i16, i16), color: u8) {
+    polygon(framebuffer, &[a, b, c], color);
+}
+
+pub fn fill_triangle(framebuffer: &mut Framebuffer, a: (i16, i16), b: (i16, i16), c: (i16, i16), color: u8) {
+    fill_polygon(framebuffer, &[a, b, c], color);
+}
+
+fn round(framebuffer: &mut Framebuffer, x: i32, y: i32, radius: u32, outline: bool, color: u8) {
+    let (x, y, reach) = (i64::from(x), i64::from(y), i64::from(radius));
+    // squares of 32 bit distances only fit unsigned
+    let square = u64::from(radius) * u64::from(radius);
+    let span_of = |row: i64| {
+        let dy = (row - y).abs() as u64;
+        if dy > u64::from(radius) {
+            return None;
+        }
+        let half = isqrt(square - dy * dy) as i64;
+        Some((x - half, x + half))
+    };
+    rows(framebuffer, y - reach, y + reach + 1, span_of, outline, color);
+}
+
+fn oval(framebuffer: &mut Framebuffer, x: i32, y: i32, radius_x: u16, radius_y: u16, outline: bool, color: u8) {
+    let (x, y) = (i64::from(x), i64::from(y));
+    let (square_x, square_y) = (u64::from(radius_x).pow(2), u64::from(radius_y).pow(2));
+    // the widest column whose square times `square_y` stays within
+    // `square_x * (square_y - dy * dy)`, which fits for 16 bit radii
+    let span_of = |row: i64| {
+        let dy = (row - y).abs() as u64;
+        if dy > u64::from(radius_y) {
+            return None;
+        }
+        let half = if radius_y == 0 {
+            u64::from(radius_x)
+        } else {
+            isqrt(square_x * (square_y - dy * dy) / square_y)
+        };
+        Some((x - half as i64, x + half as i64))
+    };
+    let reach = i64::from(radius_y);
+    rows(framebuffer, y - reach, y + reach + 1, span_of, outline, color);
+}
+
+fn rounded(
+    framebuffer: &mut Framebuffer,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    radius: u32,
+    outline: bool,
+    color: u8,
+) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    let (x, y, width, height) = (i64::from(x), i64::from(y), i64::from(width), i64::from(height));
+    let radius = i64::from(radius).min((width - 1) / 2).min((height - 1) / 2);
+    let square = (radius * radius) as u64;
+    let span_of = |row: i64| {
+        if row < y || row >= y + height {
+            return None;
+        }
+        // how far the row is into a corner, from the row of its centre
+        let dy = (y + radius - row).max(row - (y + height - 1 - radius)).max(0) as u64;
+        let inset = radius - isqrt(square - dy * dy) as i64;
+        Some((x + inset, x + width - 1 - inset))
+    };
+    rows(framebuffer, y, y + height, span_of, outline, color);
+}
+
+// Draws a shape between the rows `top..bottom` by its spans, the first and
+// last column of each row, None for rows it does not reach. Its outline is
+// what of each span is not also in the spans above and below, which makes a
+// closed edge for shapes that are convex.
+fn rows<S>(framebuffer: &mut Framebuffer, top: i64, bottom: i64, span_of: S, outline: bool, color: u8)
+where
+    S: Fn(i64) -> Option<(i64, i64)>,
+{
+    let bounds = framebuffer.bounds();
+    let rows = clip(&bounds, bounds.x as i64, top, bounds.right() as i64, bottom);
+    for y in rows.y..rows.bottom() {
+        let row = y as i64;
+        let (left, right) = match span_of(row) {
+            Some(columns) => columns,
+            None => continue,
+        };
+        // the columns up to `left_end` and from `right_start` are the edge
+        let edges = if outline {
+            match (span_of(row - 1), span_of(row + 1)) {
+                (Some(above), Some(below)) => {
+                    let left_end = (above.0.max(below.0) - 1).max(left);
+                    let right_start = (above.1.min(below.1) + 1).min(right);
+                    if left_end + 1 < right_start {
+                        Some((left_end, right_start))
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            }
+        } else {
+            None
+        };
+        match edges {
+            Some((left_end, right_start)) => {
+                span(framebuffer, &bounds, y, left, left_end + 1, color);
+                span(framebuffer, &bounds, y, right_start, right + 1, color);
+            }
+            None => span(framebuffer, &bounds, y, left, right + 1, color),
+        }
+    }
+}
+
+// the columns `left..right` of row `y`, as far as they are inside `bounds`
+fn span(framebuffer: &mut Framebuffer, bounds: &Rect, y: usize, left: i64, right: i64, color: u8) {
+    let area = clip(bounds, left, y as i64, right, y as i64 + 1);
+    if !area.is_empty() {
+        framebuffer.fill_rect(area, color);
+    }
+}
+
+// the largest number whose square is at most `n`, a digit at a time
+fn isqrt(n: u64) -> u64 {
+    let mut rest = n;
+    let mut root = 0;
+    let mut bit = 1 << 62;
+    while bit > rest {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if rest >= root + bit {
+            rest -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root
+}
+
+// rounds towards positive infinity, for a positive `divisor`
+fn div_ceil(dividend: i64, divisor: i64) -> i64 {
+    let quotient = dividend / divisor;
+    if dividend % divisor > 0 {
+        quotient + 1
+    } else {
+        quotient
+    }
+}
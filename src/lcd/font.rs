@@ -0,0 +1,157 @@
+//! Fonts rasterised ahead of time, so no glyph is rasterised on the board.
+//! `pong-host font convert` makes them from TrueType files, the firmware
+//! takes them in with `include_bytes!`.
+//!
+//! ```text
+//! "PFNT" version:u8 sizes:u8 glyphs:u16
+//! sizes times: size:u8 line_height:u8
+//! glyphs times: size:u8 char:u32 advance:u8 left:i8 top:i8 width:u8 height:u8 offset:u32
+//! coverage
+//! ```
+//!
+//! Numbers are big endian like in the bitmaps. The glyphs are sorted by size
+//! and then character. `left` and `top` place a glyph from where it is
+//! written, the top of the line. Its coverage is a level from 0 to 15 per
+//! pixel, line by line, two to a byte with the first in the high nibble,
+//! starting at `offset` into the coverage. 16 levels are as many as a
+//! palette ramp has shades.
+
+use core::char;
+
+pub const VERSION: u8 = 1;
+pub const MAGIC: &[u8; 4] = b"PFNT";
+pub const HEADER_LEN: usize = 8;
+pub const SIZE_LEN: usize = 2;
+pub const GLYPH_LEN: usize = 14;
+pub const LEVELS: u8 = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FontError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    Invalid(&'static str),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BitmapFont<'a> {
+    sizes: &'a [u8],
+    glyphs: &'a [u8],
+    coverage: &'a [u8],
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Glyph<'a> {
+    /// How far the next glyph is written to the right.
+    pub advance: u8,
+    pub left: i8,
+    pub top: i8,
+    pub width: u8,
+    pub height: u8,
+    coverage: &'a [u8],
+}
+
+impl<'a> BitmapFont<'a> {
+    /// Checks the header, the order of the glyphs and that their coverage is
+    /// in the file, so looking them up never reads past it.
+    pub fn parse(bytes: &'a [u8]) -> Result<BitmapFont<'a>, FontError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(FontError::Truncated);
+        }
+        if &bytes[..4] != MAGIC {
+            return Err(FontError::BadMagic);
+        }
+        if bytes[4] != VERSION {
+            return Err(FontError::UnsupportedVersion(bytes[4]));
+        }
+        let sizes_end = HEADER_LEN + usize::from(bytes[5]) * SIZE_LEN;
+        let glyphs_end = sizes_end + (usize::from(bytes[6]) << 8 | usize::from(bytes[7])) * GLYPH_LEN;
+        if bytes.len() < glyphs_end {
+            return Err(FontError::Truncated);
+        }
+        let font = BitmapFont {
+            sizes: &bytes[HEADER_LEN..sizes_end],
+            glyphs: &bytes[sizes_end..glyphs_end],
+            coverage: &bytes[glyphs_end..],
+        };
+
+        if font.sizes.chunks(SIZE_LEN).zip(font.sizes.chunks(SIZE_LEN).skip(1)).any(|(a, b)| a[0] >= b[0]) {
+            return Err(FontError::Invalid("sizes out of order"));
+        }
+        let mut previous = None;
+        for record in font.glyphs.chunks(GLYPH_LEN) {
+            let key = (record[0], be32(&record[1..]));
+            if previous.map_or(false, |previous| previous >= key) {
+                return Err(FontError::Invalid("glyphs out of order"));
+            }
+            previous = Some(key);
+            if font.line_height(record[0]).is_none() {
+                return Err(FontError::Invalid("a glyph of a size the font does not have"));
+            }
+            if char::from_u32(key.1).is_none() {
+                return Err(FontError::Invalid("a glyph of no character"));
+            }
+            let offset = be32(&record[10..]) as usize;
+            if offset > font.coverage.len() || font.coverage.len() - offset < coverage_len(record) {
+                return Err(FontError::Truncated);
+            }
+        }
+        Ok(font)
+    }
+
+    /// The sizes the font has, smallest first.
+    pub fn sizes(&self) -> impl Iterator<Item = u8> + 'a {
+        self.sizes.chunks(SIZE_LEN).map(|size| size[0])
+    }
+
+    /// How far apart lines of `size` are, if the font has the size.
+    pub fn line_height(&self, size: u8) -> Option<u8> {
+        self.sizes.chunks(SIZE_LEN).find(|entry| entry[0] == size).map(|entry| entry[1])
+    }
+
+    pub fn glyph(&self, c: char, size: u8) -> Option<Glyph<'a>> {
+        let key = (size, c as u32);
+        let (mut low, mut high) = (0, self.glyphs.len() / GLYPH_LEN);
+        while low < high {
+            let middle = (low + high) / 2;
+            let record = &self.glyphs[middle * GLYPH_LEN..(middle + 1) * GLYPH_LEN];
+            let found = (record[0], be32(&record[1..]));
+            if found == key {
+                let offset = be32(&record[10..]) as usize;
+                return Some(Glyph {
+                    advance: record[5],
+                    left: record[6] as i8,
+                    top: record[7] as i8,
+                    width: record[8],
+                    height: record[9],
+                    coverage: &self.coverage[offset..offset + coverage_len(record)],
+                });
+            } else if found < key {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        None
+    }
+}
+
+impl<'a> Glyph<'a> {
+    /// The coverage of a pixel of the glyph, from 0 to `LEVELS - 1`.
+    pub fn level(&self, x: usize, y: usize) -> u8 {
+        let i = y * usize::from(self.width) + x;
+        if i % 2 == 0 {
+            self.coverage[i / 2] >> 4
+        } else {
+            self.coverage[i / 2] & 0x0F
+        }
+    }
+}
+
+fn coverage_len(record: &[u8]) -> usize {
+    (usize::from(record[8]) * usize::from(record[9]) + 1) / 2
+}
+
+fn be32(bytes: &[u8]) -> u32 {
+    u32::from(bytes[0]) << 24 | u32::from(bytes[1]) << 16 | u32::from(bytes[2]) << 8 | u32::from(bytes[3])
+}
@@ -0,0 +1,39 @@
+//! The pixel formats a framebuffer can store palette colors in.
+
+use lcd::blit::PixelFormat;
+
+pub trait Format {
+    const FORMAT: PixelFormat;
+
+    /// The raw pixel of palette index `color`, `clut` holds the ARGB8888
+    /// colors of the theme.
+    fn raw(color: u8, clut: &[u32]) -> u32 {
+        Self::FORMAT.from_argb8888(clut[color as usize])
+    }
+}
+
+/// The index itself, the LTDC looks it up in its CLUT.
+pub struct L8;
+pub struct Rgb565;
+pub struct Argb4444;
+pub struct Argb8888;
+
+impl Format for L8 {
+    const FORMAT: PixelFormat = PixelFormat::L8;
+
+    fn raw(color: u8, _clut: &[u32]) -> u32 {
+        u32::from(color)
+    }
+}
+
+impl Format for Rgb565 {
+    const FORMAT: PixelFormat = PixelFormat::Rgb565;
+}
+
+impl Format for Argb4444 {
+    const FORMAT: PixelFormat = PixelFormat::Argb4444;
+}
+
+impl Format for Argb8888 {
+    const FORMAT: PixelFormat = PixelFormat::Argb8888;
+}
@@ -0,0 +1,100 @@
+//! What everything is drawn through. Colors are palette indices (see
+//! `palette`), each framebuffer stores them in its own pixel format, so the
+//! primitives and the text writer draw to any of them.
+//!
+//! Nothing is drawn outside of `bounds`, `Clipped` narrows them to a clip
+//! rectangle.
+
+use lcd::{HEIGHT, WIDTH};
+use palette;
+use rect::Rect;
+
+pub trait Framebuffer {
+    fn set_pixel(&mut self, x: usize, y: usize, color: u8);
+    fn swap_buffers(&mut self);
+    fn clear(&mut self);
+
+    /// The pixels that can be drawn to, the screen unless the buffer is
+    /// smaller or clipped. `set_pixel` leaves out the others.
+    fn bounds(&self) -> Rect {
+        screen()
+    }
+
+    /// Sets a rectangle to `color`, leaving out what is off the buffer.
+    fn fill_rect(&mut self, rect: Rect, color: u8) {
+        let rect = rect.intersection(&self.bounds());
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                self.set_pixel(x, y, color);
+            }
+        }
+    }
+
+    /// Copies an image of `pitch` pixels per line into a rectangle, leaving
+    /// out what is off the buffer.
+    fn copy_rect(&mut self, image: &[u8], pitch: usize, rect: Rect) {
+        let visible = rect.intersection(&self.bounds());
+        for y in visible.y..visible.bottom() {
+            for x in visible.x..visible.right() {
+                self.set_pixel(x, y, image[(y - rect.y) * pitch + x - rect.x]);
+            }
+        }
+    }
+}
+
+pub fn screen() -> Rect {
+    Rect::new(0, 0, WIDTH, HEIGHT)
+}
+
+/// A framebuffer that drops what is drawn outside of a clip rectangle.
+/// Clipping a clipped one draws to where both clips overlap.
+pub struct Clipped<'a> {
+    framebuffer: &'a mut Framebuffer,
+    clip: Rect,
+}
+
+impl<'a> Clipped<'a> {
+    pub fn new(framebuffer: &'a mut Framebuffer, clip: &Rect) -> Clipped<'a> {
+        let clip = clip.intersection(&framebuffer.bounds());
+        Clipped {
+            framebuffer: framebuffer,
+            clip: clip,
+        }
+    }
+}
+
+impl<'a> Framebuffer for Clipped<'a> {
+    fn set_pixel(&mut self, x: usize, y: usize, color: u8) {
+        let clip = self.clip;
+        if x >= clip.x && x < clip.right() && y >= clip.y && y < clip.bottom() {
+            self.framebuffer.set_pixel(x, y, color);
+        }
+    }
+
+    fn swap_buffers(&mut self) {
+        self.framebuffer.swap_buffers();
+    }
+
+    fn clear(&mut self) {
+        let clip = self.clip;
+        self.framebuffer.fill_rect(clip, palette::BACKGROUND);
+    }
+
+    fn bounds(&self) -> Rect {
+        self.clip
+    }
+
+    fn fill_rect(&mut self, rect: Rect, color: u8) {
+        let rect = rect.intersection(&self.clip);
+        self.framebuffer.fill_rect(rect, color);
+    }
+
+    fn copy_rect(&mut self, image: &[u8], pitch: usize, rect: Rect) {
+        let visible = rect.intersection(&self.clip);
+        if visible.is_empty() {
+            return;
+        }
+        let start = (visible.y - rect.y) * pitch + visible.x - rect.x;
+        self.framebuffer.copy_rect(&image[start..], pitch, visible);
+    }
+}
@@ -1,13 +1,16 @@
-use super::{LAYER_1_START, LAYER_1_START_2, Lcd};
+use super::{Lcd, Overlay, LAYER_2_LENGTH, LAYER_2_START};
 use board::ltdc::Ltdc;
 use board::rcc::Rcc;
+use core::slice;
 use embedded::interfaces::gpio::{Gpio, OutputPin};
 
 const HEIGHT: u16 = super::HEIGHT as u16;
 const WIDTH: u16 = super::WIDTH as u16;
 const LAYER_1_OCTETS_PER_PIXEL: u16 = super::LAYER_1_OCTETS_PER_PIXEL as u16;
+const LAYER_2_OCTETS_PER_PIXEL: u16 = super::overlay::OCTETS_PER_PIXEL as u16;
 
-pub fn init(ltdc: &'static mut Ltdc, rcc: &mut Rcc, gpio: &mut Gpio) -> Lcd {
+/// The sdram has to be initialized, layer 2 lives in it.
+pub fn init(ltdc: &'static mut Ltdc, rcc: &mut Rcc, gpio: &mut Gpio) -> (Lcd, Overlay) {
     // init gpio pins
     let (mut display_enable, mut backlight_enable) = init_pins(gpio);
 
@@ -78,9 +81,10 @@ pub fn init(ltdc: &'static mut Ltdc, rcc: &mut Rcc, gpio: &mut Gpio) -> Lcd {
         r.set_lie(true); // LINE_INTERRUPT_ENABLE
     });
 
-    // set the line the interrupt should happen on
+    // set the line the interrupt should happen on, the first one after the
+    // active area, so a buffer swap never shows in the middle of a frame
     ltdc.lipcr.update(|r| {
-        r.set_lipos(HEIGHT);
+        r.set_lipos(HEIGHT + 10 + 2);
     });
 
     // configure layers
@@ -108,8 +112,7 @@ pub fn init(ltdc: &'static mut Ltdc, rcc: &mut Rcc, gpio: &mut Gpio) -> Lcd {
         r.set_dcblue(0);
     });
 
-    // configure color frame buffer start address
-    // ltdc.l1cfbar.update(|r| r.set_cfbadd(LAYER_1_START as u32)); // don't draw in waiting time
+    // the color frame buffer start address is set by Lcd::show
 
     // configure color frame buffer line length and pitch
     ltdc.l1cfblr.update(|r| {
@@ -124,6 +127,49 @@ pub fn init(ltdc: &'static mut Ltdc, rcc: &mut Rcc, gpio: &mut Gpio) -> Lcd {
         r.set_len(true); // enable layer 1
     });
 
+    // layer 2 covers layer 1 and starts out transparent
+    let overlay = unsafe { slice::from_raw_parts_mut(LAYER_2_START as *mut u8, LAYER_2_LENGTH) };
+    for byte in overlay.iter_mut() {
+        *byte = 0;
+    }
+
+    ltdc.l2whpcr.update(|r| {
+        r.set_whstpos(0 + 41 + 13); // window_horizontal_start_position
+        r.set_whsppos(WIDTH + 41 + 13 - 1); // window_horizontal_stop_position
+    });
+    ltdc.l2wvpcr.update(|r| {
+        r.set_wvstpos(0 + 10 + 2); // window_vertical_start_position
+        r.set_wvsppos(HEIGHT + 10 + 2 - 1); // window_vertical_stop_position
+    });
+
+    ltdc.l2pfcr.update(|r| r.set_pf(0b111)); // set_pixel_format to AL88
+
+    // transparent outside of the window
+    ltdc.l2dccr.update(|r| {
+        r.set_dcalpha(0);
+        r.set_dcred(0);
+        r.set_dcgreen(0);
+        r.set_dcblue(0);
+    });
+
+    // blend with the alpha of each pixel
+    ltdc.l2cacr.update(|r| r.set_consta(255)); // constant_alpha
+    ltdc.l2bfcr.update(|r| {
+        r.set_bf1(0b110); // pixel_alpha * constant_alpha
+        r.set_bf2(0b111); // 1 - pixel_alpha * constant_alpha
+    });
+
+    ltdc.l2cfbar.update(|r| r.set_cfbadd(LAYER_2_START as u32));
+    ltdc.l2cfblr.update(|r| {
+        r.set_cfbp(WIDTH * LAYER_2_OCTETS_PER_PIXEL); // pitch
+        r.set_cfbll(WIDTH * LAYER_2_OCTETS_PER_PIXEL + 3); // line_length
+    });
+    ltdc.l2cfblnr.update(|r| r.set_cfblnbr(HEIGHT)); // line_number
+
+    ltdc.l2cr.update(|r| {
+        r.set_len(true); // enable layer 2
+    });
+
     // reload shadow registers
     ltdc.srcr.update(|r| r.set_imr(true)); // IMMEDIATE_RELOAD
 
@@ -134,15 +180,13 @@ pub fn init(ltdc: &'static mut Ltdc, rcc: &mut Rcc, gpio: &mut Gpio) -> Lcd {
     display_enable.set(true);
     backlight_enable.set(true);
 
-    Lcd {
+    let lcd = Lcd {
         controller: ltdc,
         display_enable: display_enable,
         backlight_enable: backlight_enable,
-        write_to_buffer_2: false,
-        framebuffer_addr: LAYER_1_START as u32,
-        backbuffer_addr: LAYER_1_START_2 as u32,
         clut: [(0, 0, 0); 256],
-    }
+    };
+    (lcd, Overlay::new(overlay))
 }
 
 pub fn init_pins(gpio: &mut Gpio) -> (OutputPin, OutputPin) {
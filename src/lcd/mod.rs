@@ -1,18 +1,39 @@
 #![allow(dead_code)]
 
+pub use self::bitmap::Bitmap;
+pub use self::canvas::Canvas;
+pub use self::font::BitmapFont;
+pub use self::dma2d::Dma2d;
+pub use self::framebuffer::{screen, Clipped, Framebuffer};
 pub use self::init::init;
+pub use self::overlay::Overlay;
 pub use self::text::TextWriter;
 
+use self::blit::{Blitter, PixelFormat, Software, Source, Target};
+use self::damage::Damage;
 use alloc::Vec;
 use board::ltdc;
 use board::ltdc::L1clutwr;
+use board::ltdc::L2clutwr;
 use board::ltdc::Ltdc;
 use core::ptr;
 use embedded::interfaces::gpio::OutputPin;
+use rect::Rect;
 use stm32f7::lcd::Color;
 
+pub mod atlas;
+pub mod bitmap;
+pub mod blit;
+mod canvas;
+mod damage;
+mod dma2d;
+pub mod draw;
+pub mod font;
+pub mod format;
+mod framebuffer;
 #[macro_use]
 mod init;
+mod overlay;
 mod text;
 
 pub const HEIGHT: usize = 272;
@@ -22,18 +43,13 @@ const LAYER_1_OCTETS_PER_PIXEL: usize = 1;
 const LAYER_1_LENGTH: usize = HEIGHT * WIDTH * LAYER_1_OCTETS_PER_PIXEL;
 
 const SDRAM_START: usize = 0xC000_0000;
-const LAYER_1_START: usize = SDRAM_START;
-const LAYER_1_START_2: usize = SDRAM_START + 1024 * 1024 * 1; // move backbuffer to second SDRAM bank
-
-static EMPTY_IMG: &[u8] = include_bytes!("../../res/empty.img");
+const LAYER_2_START: usize = SDRAM_START; // the buffers of layer 1 are on the heap
+const LAYER_2_LENGTH: usize = HEIGHT * WIDTH * overlay::OCTETS_PER_PIXEL;
 
 pub struct Lcd {
     controller: &'static mut Ltdc,
     display_enable: OutputPin,
     backlight_enable: OutputPin,
-    write_to_buffer_2: bool,
-    pub framebuffer_addr: u32,
-    pub backbuffer_addr: u32,
     pub clut: [(u8, u8, u8); 256],
 }
 
@@ -42,23 +58,13 @@ impl Lcd {
         self.controller.bccr.update(|r| r.set_bc(color.to_rgb()));
     }
 
-    pub fn swap_buffers(&mut self) {
-        if self.write_to_buffer_2 {
-            let framebuffer_addr = self.framebuffer_addr;
-            self.controller
-                .l1cfbar
-                .update(|r| r.set_cfbadd(framebuffer_addr));
-        } else {
-            let backbuffer_addr = self.backbuffer_addr;
-            self.controller
-                .l1cfbar
-                .update(|r| r.set_cfbadd(backbuffer_addr));
-        }
+    /// Shows the buffer at `address`. The new address is used at once, so
+    /// this belongs in the line interrupt, which fires after the last line.
+    pub fn show(&mut self, address: u32) {
+        self.controller.l1cfbar.update(|r| r.set_cfbadd(address));
 
         // reload shadow registers
         self.controller.srcr.update(|r| r.set_imr(true)); // IMMEDIATE_RELOAD
-
-        self.write_to_buffer_2 = !self.write_to_buffer_2;
     }
 
     pub fn clr_line_interrupt(&mut self) {
@@ -67,7 +73,8 @@ impl Lcd {
         self.controller.icr.write(clr_flags);
     }
 
-    // reads the rgb colors defined in the lcd.clut and sets them
+    // reads the rgb colors defined in the lcd.clut and sets them, both layers
+    // share them
     pub fn update_clut(&mut self) {
         // define CLUT for layer 1
         for c in 0..=255 {
@@ -81,22 +88,32 @@ impl Lcd {
         self.controller.l1cr.update(|r| {
             r.set_cluten(true); // enable CLUT for layer 1
         });
-    }
-}
 
-pub trait Framebuffer {
-    fn set_pixel(&mut self, x: usize, y: usize, color: u8);
-    fn swap_buffers(&mut self);
-    fn clear(&mut self);
-    fn copy_full(&mut self, src_start_ptr: *const u8);
+        // define CLUT for layer 2
+        for c in 0..=255 {
+            let mut clut = L2clutwr::default();
+            clut.set_red(self.clut[c].0);
+            clut.set_green(self.clut[c].1);
+            clut.set_blue(self.clut[c].2);
+            clut.set_clutadd(c as u8);
+            self.controller.l2clutwr.write(clut);
+        }
+        self.controller.l2cr.update(|r| {
+            r.set_cluten(true); // enable CLUT for layer 2
+        });
+    }
 }
 
+/// Two buffers, one drawn to while the lcd shows the other. After a swap
+/// both hold the same picture, only what was drawn in between is copied.
 pub struct FramebufferL8 {
     pub write_to_buffer_2: bool,
     pub framebuffer: Vec<u8>,
     pub backbuffer: Vec<u8>,
     pub framebuffer_addr: *const u8,
     pub backbuffer_addr: *const u8,
+    dma2d: Option<Dma2d>, // drawing falls back to the cpu without it
+    damage: Damage,       // drawn to the current buffer, missing in the other
 }
 
 impl FramebufferL8 {
@@ -109,9 +126,16 @@ impl FramebufferL8 {
             backbuffer: vec![0; WIDTH * HEIGHT],
             framebuffer_addr: 0 as *const u8,
             backbuffer_addr: 0 as *const u8,
+            dma2d: None,
+            damage: Damage::new(),
         }
     }
 
+    /// Fills, copies and clears run on the DMA2D from now on.
+    pub fn use_dma2d(&mut self, dma2d: Dma2d) {
+        self.dma2d = Some(dma2d);
+    }
+
     pub fn init(&mut self) {
         // need to set these intern addresses correct
         self.framebuffer_addr = &(self.framebuffer[0]) as *const u8;
@@ -126,6 +150,15 @@ impl FramebufferL8 {
         }
     }
 
+    /// The buffer drawn to, for the lcd to show once the frame is done.
+    pub fn drawing_addr(&self) -> u32 {
+        if self.write_to_buffer_2 {
+            self.backbuffer_addr as u32
+        } else {
+            self.framebuffer_addr as u32
+        }
+    }
+
     pub fn get_framebuffer_addr(&self) -> *const u8 {
         self.framebuffer_addr
         // &(self.framebuffer[0]) as *const u8
@@ -139,60 +172,93 @@ impl FramebufferL8 {
 
 impl Framebuffer for FramebufferL8 {
     fn set_pixel(&mut self, x: usize, y: usize, color: u8) {
+        if x >= WIDTH || y >= HEIGHT {
+            return;
+        }
         let pixel = y * WIDTH + x;
 
         let pixel_ptr = (self.current_base_addr() + pixel * LAYER_1_OCTETS_PER_PIXEL) as *mut u8;
         unsafe {
             ptr::write_volatile(pixel_ptr, color);
         };
+        self.damage.add(Rect::new(x, y, 1, 1));
     }
 
+    // the lcd shows the buffer drawn to until now, the other one gets what
+    // was drawn to it and is drawn to next
     fn swap_buffers(&mut self) {
-        let src_start_ptr;
-        let dest_start_ptr;
-
-        if self.write_to_buffer_2 {
-            src_start_ptr = self.get_backbuffer_addr() as *mut u32;
-            dest_start_ptr = self.get_framebuffer_addr() as *mut u32;
+        let FramebufferL8 {
+            ref mut write_to_buffer_2,
+            ref mut framebuffer,
+            ref mut backbuffer,
+            ref mut dma2d,
+            ref mut damage,
+            ..
+        } = *self;
+        let (src, dest) = if *write_to_buffer_2 {
+            (backbuffer, framebuffer)
         } else {
-            src_start_ptr = self.get_framebuffer_addr() as *mut u32;
-            dest_start_ptr = self.get_backbuffer_addr() as *mut u32;
-        }
-
-        self.write_to_buffer_2 = !self.write_to_buffer_2;
+            (framebuffer, backbuffer)
+        };
+        *write_to_buffer_2 = !*write_to_buffer_2;
 
-        let mut count = WIDTH * HEIGHT;
-        if LAYER_1_OCTETS_PER_PIXEL == 1 {
-            count /= 4; // we only store u8 for every pixel
-        }
-        if LAYER_1_OCTETS_PER_PIXEL == 2 {
-            count /= 2;
-        }
-        unsafe {
-            ptr::copy_nonoverlapping(src_start_ptr, dest_start_ptr, count);
+        {
+            let rects = damage.rects();
+            with_blitter(dma2d, |blitter| {
+                let mut target = Target::new(dest, PixelFormat::L8, WIDTH);
+                for rect in rects {
+                    let source = Source::new(src, PixelFormat::L8, WIDTH).at(rect.x, rect.y);
+                    blitter.copy(&source, &mut target, *rect);
+                }
+            });
         }
+        damage.clear();
     }
 
     fn clear(&mut self) {
-        // slow
-        // for i in 0..HEIGHT {
-        // for j in 0..WIDTH {
-        // self.set_pixel(j, i, 0);
-        // }
-        // }
-        let src_start_ptr = &EMPTY_IMG[0] as *const u8;
-        self.copy_full(src_start_ptr);
+        self.fill_rect(screen(), 0);
     }
 
-    fn copy_full(&mut self, src_start_ptr: *const u8) {
-        let dest_start_ptr = self.current_base_addr() as *mut u32;
+    fn fill_rect(&mut self, rect: Rect, color: u8) {
+        let rect = rect.intersection(&screen());
+        if rect.is_empty() {
+            return; // past the edge it starts off the buffer
+        }
+        self.damage.add(rect);
+        let (dma2d, mut target) = self.target();
+        with_blitter(dma2d, |blitter| blitter.fill(&mut target, rect, color.into()));
+    }
 
-        unsafe {
-            ptr::copy_nonoverlapping(
-                src_start_ptr as *const u32,
-                dest_start_ptr,
-                WIDTH * HEIGHT / 4, // we only store u8 for every pixel
-            );
+    fn copy_rect(&mut self, image: &[u8], pitch: usize, rect: Rect) {
+        let visible = rect.intersection(&screen());
+        if visible.is_empty() {
+            return;
         }
+        let source = Source::new(image, PixelFormat::L8, pitch).at(visible.x - rect.x, visible.y - rect.y);
+        self.damage.add(visible);
+        let (dma2d, mut target) = self.target();
+        with_blitter(dma2d, |blitter| blitter.copy(&source, &mut target, visible));
+    }
+}
+
+impl FramebufferL8 {
+    // the buffer drawn to, with the DMA2D
+    fn target(&mut self) -> (&mut Option<Dma2d>, Target) {
+        let FramebufferL8 {
+            write_to_buffer_2,
+            ref mut framebuffer,
+            ref mut backbuffer,
+            ref mut dma2d,
+            ..
+        } = *self;
+        let buffer = if write_to_buffer_2 { backbuffer } else { framebuffer };
+        (dma2d, Target::new(buffer, PixelFormat::L8, WIDTH))
+    }
+}
+
+fn with_blitter<F: FnOnce(&mut Blitter)>(dma2d: &mut Option<Dma2d>, f: F) {
+    match *dma2d {
+        Some(ref mut dma2d) => f(dma2d),
+        None => f(&mut Software),
     }
 }
@@ -0,0 +1,68 @@
+//! Layer 2, which the LTDC blends over the game. Its AL88 pixels are an
+//! alpha and an index into the same CLUT as layer 1.
+//!
+//! Drawing takes the colors of the palette: a named color is opaque, its
+//! shades let the game show through instead of fading to the background,
+//! and the background is transparent. Pixels set on opaque ones stay the
+//! shade, like text on a panel, whose color is the background's. There is
+//! one buffer, the hud changes seldom enough that a half drawn frame does
+//! not show.
+
+use super::blit::{Blitter, PixelFormat, Software, Target};
+use super::{screen, Framebuffer, HEIGHT, WIDTH};
+use palette::{self, SHADES};
+use rect::Rect;
+
+pub const OCTETS_PER_PIXEL: usize = 2;
+
+pub struct Overlay {
+    buffer: &'static mut [u8],
+}
+
+impl Overlay {
+    /// `lcd::init` hands out the buffer layer 2 shows.
+    pub fn new(buffer: &'static mut [u8]) -> Overlay {
+        Overlay { buffer: buffer }
+    }
+
+    fn target(&mut self) -> Target {
+        Target::new(self.buffer, PixelFormat::Al88, WIDTH)
+    }
+}
+
+impl Framebuffer for Overlay {
+    fn set_pixel(&mut self, x: usize, y: usize, color: u8) {
+        if x < WIDTH && y < HEIGHT {
+            let offset = (y * WIDTH + x) * OCTETS_PER_PIXEL;
+            let pixel = if self.buffer[offset + 1] == 0xFF {
+                0xFF00 | u16::from(color)
+            } else {
+                al88(color)
+            };
+            self.buffer[offset] = pixel as u8;
+            self.buffer[offset + 1] = (pixel >> 8) as u8;
+        }
+    }
+
+    fn swap_buffers(&mut self) {}
+
+    fn clear(&mut self) {
+        self.fill_rect(screen(), palette::BACKGROUND);
+    }
+
+    fn fill_rect(&mut self, rect: Rect, color: u8) {
+        let rect = rect.intersection(&screen());
+        if rect.is_empty() {
+            return;
+        }
+        // by the cpu, the dma2d belongs to layer 1's framebuffer
+        Software.fill(&mut self.target(), rect, al88(color).into());
+    }
+}
+
+// the full color of a shade, as opaque as the shade is strong
+fn al88(color: u8) -> u16 {
+    let shade = u16::from(color % SHADES);
+    let alpha = shade * 255 / u16::from(SHADES - 1);
+    alpha << 8 | u16::from(color | (SHADES - 1))
+}
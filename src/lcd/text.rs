@@ -1,44 +1,61 @@
 use lcd;
+use lcd::atlas::GlyphAtlas;
+use lcd::font::BitmapFont;
 use lcd::Framebuffer;
-use stm32f7::lcd::FontRenderer;
+use palette;
+
+const REPLACEMENT: char = '?'; // for characters the font does not have
 
 pub struct TextWriter<'a> {
-    font_renderer: FontRenderer<'a>,
+    atlas: GlyphAtlas<'a>,
+    size: u8,
     x_pos: i32,
     y_pos: i32,
+    color: u8, // a named color of the palette
 }
 
 impl<'a> TextWriter<'a> {
-    pub fn new(font_data: &[u8], font_height: f32) -> TextWriter {
+    /// Writes in the glyphs `font` has at `size` pixels.
+    pub fn new(font: BitmapFont<'a>, size: u8) -> TextWriter<'a> {
         TextWriter {
-            font_renderer: FontRenderer::new(font_data, font_height),
+            atlas: GlyphAtlas::new(font),
+            size: size,
             x_pos: 0,
             y_pos: 0,
+            color: palette::TEXT,
         }
     }
-    pub fn write(&mut self, framebuffer: &mut Framebuffer, text: &str) {
-        let &mut TextWriter {
-            ref mut font_renderer,
-            ref mut x_pos,
-            ref mut y_pos,
-            ..
-        } = self;
 
-        let font_height = font_renderer.font_height() as i32;
-        font_renderer.render(text, |x, y, v| {
-            let x = x as i32;
-            let y = y as i32;
-            if *x_pos + x >= lcd::WIDTH as i32 {
-                *x_pos = -x;
-                *y_pos += font_height;
+    /// Glyph edges are drawn in the shades of `color` towards the background.
+    pub fn set_color(&mut self, color: u8) {
+        self.color = color;
+    }
+
+    pub fn atlas(&self) -> &GlyphAtlas<'a> {
+        &self.atlas
+    }
+
+    pub fn write(&mut self, framebuffer: &mut Framebuffer, text: &str) {
+        let size = self.size;
+        let line_height = i32::from(self.atlas.font().line_height(size).unwrap_or(size));
+        for c in text.chars() {
+            let glyph = match self.atlas.glyph(c, size).or_else(|| self.atlas.glyph(REPLACEMENT, size)) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+            // at the screen edge, not the bounds a clip narrows
+            let right = self.x_pos + i32::from(glyph.left) + i32::from(glyph.width);
+            if right > lcd::WIDTH as i32 && self.x_pos > 0 {
+                self.x_pos = 0;
+                self.y_pos += line_height;
             }
-            if *y_pos + font_height >= lcd::HEIGHT as i32 {
-                *y_pos = 0;
+            if self.y_pos + line_height >= lcd::HEIGHT as i32 {
+                self.y_pos = 0;
                 // TODO: no place for text D:
             }
-            let alpha = (v * 255.0 + 0.5) as u8;
-            framebuffer.set_pixel((*x_pos + x) as usize, (*y_pos + y) as usize, alpha);
-        });
+            self.atlas.draw(framebuffer, &glyph, self.x_pos, self.y_pos, self.color);
+            self.x_pos += i32::from(glyph.advance);
+        }
     }
     pub fn write_at(&mut self, framebuffer: &mut Framebuffer, text: &str, x: usize, y: usize) {
         self.x_pos = x as i32;
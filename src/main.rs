@@ -14,21 +14,36 @@ extern crate alloc;
 extern crate smoltcp;
 
 mod ball;
+mod cpu;
+mod crc;
+mod flash;
 mod fps;
 mod game;
 mod graphics;
 mod input;
 mod lcd; // use custom LCD implementation
+mod matchfile;
 mod menu;
 mod network;
+mod palette;
+mod pause;
 mod physics;
 mod racket;
+mod rematch;
+mod rect;
+mod replay;
+mod rules;
+mod scene;
+mod settings;
+mod storage;
+mod ui;
 
 use core::mem::discriminant;
 use core::ptr;
 use embedded::interfaces::gpio::Gpio;
-use game::GameState;
+use game::{GameMode, GameState, PostMatchChoice};
 use graphics::GraphicsCache;
+use lcd::BitmapFont;
 use lcd::Framebuffer;
 use lcd::FramebufferL8;
 use lcd::TextWriter;
@@ -41,15 +56,18 @@ use physics::PhysicsCache;
 const USE_DOUBLE_BUFFER: bool = true;
 const ENABLE_FPS_OUTPUT: bool = false;
 const PRINT_START_MESSAGE: bool = false;
-const BGCOLOR: u8 = 0;
+const ENABLE_PACKET_CAPTURE: bool = false; // dumped via semihosting when a network game ends
+const PACKET_CAPTURE_LENGTH: usize = 1024;
+const ENABLE_MATCH_RECORDING: bool = false; // dumped via semihosting when a match ends
+const MATCH_RECORDING_LENGTH: usize = 16 * 1024;
+const ATTRACT_MODE_DELAY: usize = 30_000; // ms the menus sit idle before a demo match starts
 const PADDING: usize = 20;
 
 const CLIENT_ETH_ADDR: EthernetAddress = EthernetAddress([0x00, 0x11, 0x22, 0x33, 0x44, 0x01]);
-const CLIENT_IP_ADDR: Ipv4Address = Ipv4Address([141, 52, 46, 2]);
 const SERVER_ETH_ADDR: EthernetAddress = EthernetAddress([0x00, 0x11, 0x22, 0x33, 0x44, 0x02]);
-const SERVER_IP_ADDR: Ipv4Address = Ipv4Address([141, 52, 46, 1]);
 
-static TTF: &[u8] = include_bytes!("../res/RobotoMono-Bold.ttf");
+// RobotoMono-Bold.ttf at 20 and 40 px, see `lcd::font`
+static FONT: &[u8] = include_bytes!("../res/RobotoMono-Bold.pfnt");
 
 #[no_mangle]
 pub unsafe extern "C" fn reset() -> ! {
@@ -97,6 +115,7 @@ fn main(hw: board::Hardware) -> ! {
         flash,
         fmc,
         ltdc,
+        dma2d,
         gpio_a,
         gpio_b,
         gpio_c,
@@ -141,7 +160,7 @@ fn main(hw: board::Hardware) -> ! {
     sdram::init(rcc, fmc, &mut gpio);
 
     // set up LCD
-    let mut lcd = lcd::init(ltdc, rcc, &mut gpio);
+    let (mut lcd, mut overlay) = lcd::init(ltdc, rcc, &mut gpio);
     lcd.set_background_color(Color {
         red: 0,
         green: 0,
@@ -150,27 +169,27 @@ fn main(hw: board::Hardware) -> ! {
     });
     let mut framebuffer = FramebufferL8::new();
     framebuffer.init();
-    lcd.framebuffer_addr = framebuffer.get_framebuffer_addr() as u32;
-    lcd.backbuffer_addr = framebuffer.get_backbuffer_addr() as u32;
-    if !USE_DOUBLE_BUFFER {
-        lcd.swap_buffers();
-    }
-    lcd.swap_buffers();
-
-    // for i in 0..255 {
-    // lcd.clut[i] = (0, i as u8, 0);
-    // }
-    // lcd.clut[255] = (255, 0, 0);
-    // lcd.update_clut();
-
-    // set up font renderer
-    let mut loading_font = TextWriter::new(TTF, 40.0);
+    framebuffer.use_dma2d(lcd::Dma2d::new(dma2d));
+    // the loading text is drawn to the other buffer
+    lcd.show(framebuffer.get_backbuffer_addr() as u32);
+
+    // the settings live in the last flash sector
+    let mut store = storage::Store::open(flash::InternalFlash::new(flash));
+    let mut settings = store
+        .load()
+        .and_then(|record| settings::Settings::deserialize(&record))
+        .unwrap_or_else(settings::Settings::new);
+    let mut edited_settings = settings.clone();
+    apply_theme(&mut lcd, settings.theme);
+
+    // set up the fonts, rasterised ahead of time
+    let font = BitmapFont::parse(FONT).unwrap();
+    let mut loading_font = TextWriter::new(font, 40);
     loading_font.write_at(&mut framebuffer, "loading...", PADDING, PADDING);
 
-    let mut menu_font = TextWriter::new(TTF, 20.0);
-    let mut debug_font = TextWriter::new(TTF, 20.0);
+    let mut menu_font = TextWriter::new(font, 20);
 
-    lcd.swap_buffers(); // show loading text
+    lcd.show(framebuffer.drawing_addr()); // show loading text
     framebuffer.swap_buffers();
 
     // init touch screen
@@ -178,7 +197,7 @@ fn main(hw: board::Hardware) -> ! {
     let mut i2c_3 = i2c::init(i2c_3);
     touch::check_family_id(&mut i2c_3).unwrap();
 
-    let mut network = Some((ethernet_dma, ethernet_mac));
+    let mut ethernet = Some((ethernet_dma, ethernet_mac)); // None while a network uses them
 
     let mut gamestate = GameState::Splash;
     let mut previous_gamestate = core::mem::discriminant(&gamestate); // Get the descriminant to be able to compare this
@@ -189,6 +208,11 @@ fn main(hw: board::Hardware) -> ! {
         move |interrupt_table| {
             let mut should_draw_now = false;
             let should_draw_now_ptr = &mut should_draw_now as *mut bool as usize;
+            let mut finished_buffer = framebuffer.drawing_addr(); // the frame to show next
+            let finished_buffer_ptr = &mut finished_buffer as *mut u32 as usize;
+            let mut theme = settings.theme; // picked up by the interrupt, it owns the lcd
+            let theme_ptr = &mut theme as *mut u8 as usize;
+            let mut applied_theme = settings.theme;
 
             let _interrupt_handler = interrupt_table
                 .register(
@@ -199,10 +223,15 @@ fn main(hw: board::Hardware) -> ! {
                             let need_draw = ptr::read_volatile(should_draw_now_ptr as *mut bool);
                             if !need_draw {
                                 if USE_DOUBLE_BUFFER {
-                                    lcd.swap_buffers();
+                                    lcd.show(ptr::read_volatile(finished_buffer_ptr as *mut u32));
                                 }
                                 ptr::write_volatile(should_draw_now_ptr as *mut bool, true);
                             }
+                            let theme = ptr::read_volatile(theme_ptr as *mut u8);
+                            if theme != applied_theme {
+                                apply_theme(&mut lcd, theme);
+                                applied_theme = theme;
+                            }
                         }
                         lcd.clr_line_interrupt();
                     },
@@ -228,8 +257,21 @@ fn main(hw: board::Hardware) -> ! {
             let mut local_input_2 = network::InputPacket::new();
 
             let mut input = input::Input::new(i2c_3);
+            let mut ui = ui::Ui::new();
             let mut cache = GraphicsCache::new();
             let mut physics_cache = PhysicsCache::new();
+            let mut replay = replay::Replay::new();
+            let mut pause = pause::Pause::new();
+            let mut match_recorder = None;
+            let mut match_settings = settings.match_settings;
+            let mut difficulty = cpu::Difficulty::Normal;
+            let mut cpu_player = cpu::CpuPlayer::new(1, difficulty, system_clock::ticks() as u32);
+            let mut attract_cpus = [
+                cpu::CpuPlayer::new(0, cpu::Difficulty::Normal, 1),
+                cpu::CpuPlayer::new(1, cpu::Difficulty::Normal, 2),
+            ];
+            let mut attract_won_at = None;
+            let mut idle_since = 0;
 
             let start_time = system_clock::ticks();
             let mut last_time = start_time;
@@ -252,6 +294,9 @@ fn main(hw: board::Hardware) -> ! {
                     last_time = now;
 
                     let just_entered_state = !(previous_gamestate == discriminant(&gamestate));
+                    if just_entered_state {
+                        overlay.clear(); // the hud of the last state
+                    }
                     previous_gamestate = discriminant(&gamestate);
 
                     gamestate = match gamestate {
@@ -261,12 +306,14 @@ fn main(hw: board::Hardware) -> ! {
                             &mut framebuffer,
                             &mut menu_font,
                             &mut input,
+                            &mut ui,
                         ),
                         GameState::ChooseClientOrServer => menu::choose_client_server(
                             just_entered_state,
                             &mut framebuffer,
                             &mut menu_font,
                             &mut input,
+                            &mut ui,
                             &mut is_server,
                         ),
                         GameState::ChooseOnlyLocal => menu::choose_only_local(
@@ -274,8 +321,27 @@ fn main(hw: board::Hardware) -> ! {
                             &mut framebuffer,
                             &mut menu_font,
                             &mut input,
+                            &mut ui,
                         ),
+                        GameState::ChooseMatchSettings(mode) => match menu::choose_match_settings(
+                            just_entered_state,
+                            &mut framebuffer,
+                            &mut menu_font,
+                            &mut input,
+                            &mut ui,
+                            &mut match_settings,
+                            &mut difficulty,
+                            mode,
+                        ) {
+                            GameState::ChooseLocalOrNetwork => main_menu(ethernet.is_some()),
+                            next => next,
+                        },
                         GameState::ConnectToNetwork => {
+                            // nothing from an earlier connection may leak into this one
+                            client = network::EthClient::new();
+                            server = network::EthServer::new();
+                            // the new client counts its pause requests from 0
+                            pause = pause::Pause::new();
                             framebuffer.clear();
                             loading_font.write_at(
                                 &mut framebuffer,
@@ -284,62 +350,76 @@ fn main(hw: board::Hardware) -> ! {
                                 PADDING,
                             );
                             framebuffer.swap_buffers();
-                            match network.take() {
-                                Some((ethernet_dma, ethernet_mac)) => {
+                            match ethernet.take() {
+                                Some(peripherals) => {
                                     let network_option = if is_server {
                                         network::init(
                                             rcc,
                                             syscfg,
-                                            ethernet_mac,
-                                            ethernet_dma,
+                                            peripherals,
                                             &mut gpio,
                                             SERVER_ETH_ADDR,
-                                            SERVER_IP_ADDR,
-                                            CLIENT_IP_ADDR,
+                                            Ipv4Address(settings.server_ip),
+                                            Ipv4Address(settings.client_ip),
                                         )
                                     } else {
                                         network::init(
                                             rcc,
                                             syscfg,
-                                            ethernet_mac,
-                                            ethernet_dma,
+                                            peripherals,
                                             &mut gpio,
                                             CLIENT_ETH_ADDR,
-                                            CLIENT_IP_ADDR,
-                                            SERVER_IP_ADDR,
+                                            Ipv4Address(settings.client_ip),
+                                            Ipv4Address(settings.server_ip),
                                         )
                                     };
 
                                     match network_option {
-                                        Ok(network) => GameState::WaitForPartner(network),
+                                        Ok(mut network) => {
+                                            if ENABLE_PACKET_CAPTURE {
+                                                network.enable_capture(PACKET_CAPTURE_LENGTH);
+                                            }
+                                            GameState::WaitForPartner(network)
+                                        }
                                         Err(e) => {
-                                            framebuffer.clear();
-                                            debug_font.write(
-                                                &mut framebuffer,
-                                                &format!("Network error: {:?}", e),
-                                            );
-                                            GameState::ChooseOnlyLocal
+                                            hprintln!("Network init failed: {:?}", e.error);
+                                            let reason = e.reason();
+                                            ethernet = Some(e.peripherals);
+                                            GameState::NetworkError(reason)
                                         }
                                     }
                                 }
                                 None => panic!(),
                             }
                         }
+                        GameState::NetworkError(reason) => menu::network_error(
+                            just_entered_state,
+                            &mut framebuffer,
+                            &mut menu_font,
+                            &mut input,
+                            &mut ui,
+                            reason,
+                        ),
                         GameState::WaitForPartner(mut network) => {
-                            if just_entered_state {
-                                menu_font.write_at(
-                                    &mut framebuffer,
-                                    if is_server {
-                                        "Waiting for client..."
-                                    } else {
-                                        "Server needs to be started first..."
-                                    },
-                                    PADDING,
-                                    60,
-                                );
-                            }
-
-                            if is_server {
+                            ui.begin(just_entered_state, input.handle_menu());
+                            let status = ui::Rect::new(PADDING, 60, 440, 30);
+                            ui.label(
+                                &mut framebuffer,
+                                &mut menu_font,
+                                status,
+                                if is_server {
+                                    "Waiting for client..."
+                                } else {
+                                    "Server needs to be started first..."
+                                },
+                            );
+                            let back = menu::back_button(&mut ui, &mut framebuffer, &mut menu_font);
+                            ui.end();
+                            if back {
+                                // cancel, the peripherals can be initialised again
+                                ethernet = Some(network.close(rcc));
+                                GameState::ChooseClientOrServer
+                            } else if is_server {
                                 if server.is_client_connected(&mut network) {
                                     // server.send_whoami(&mut network);
                                     GameState::GameRunningNetwork(network)
@@ -361,9 +441,10 @@ fn main(hw: board::Hardware) -> ! {
                             }
                         }
                         GameState::GameRunningLocal => {
-                            game::game_loop_local(
+                            let choice = game::game_loop_local(
                                 just_entered_state,
                                 &mut framebuffer,
+                                &mut overlay,
                                 &mut input,
                                 &fps,
                                 &mut rackets,
@@ -372,17 +453,67 @@ fn main(hw: board::Hardware) -> ! {
                                 &mut local_input_2,
                                 &mut server_gamestate,
                                 &mut loading_font,
+                                &mut menu_font,
                                 &mut cache,
                                 total_time,
                                 delta_time,
                                 &mut physics_cache,
+                                &mut replay,
+                                &mut match_recorder,
+                                &mut match_settings,
+                                &settings.player_name,
+                                &mut pause,
+                                None,
                             );
-                            GameState::GameRunningLocal
+                            match choice {
+                                Some(PostMatchChoice::ChangeSettings) => {
+                                    GameState::ChooseMatchSettings(GameMode::Local)
+                                }
+                                Some(_) => main_menu(ethernet.is_some()),
+                                None => GameState::GameRunningLocal,
+                            }
+                        }
+                        GameState::GameRunningVsCpu => {
+                            if just_entered_state && cpu_player.difficulty() != difficulty {
+                                cpu_player = cpu::CpuPlayer::new(1, difficulty, system_clock::ticks() as u32);
+                            }
+                            let choice = game::game_loop_local(
+                                just_entered_state,
+                                &mut framebuffer,
+                                &mut overlay,
+                                &mut input,
+                                &fps,
+                                &mut rackets,
+                                &mut ball,
+                                &mut local_input_1,
+                                &mut local_input_2,
+                                &mut server_gamestate,
+                                &mut loading_font,
+                                &mut menu_font,
+                                &mut cache,
+                                total_time,
+                                delta_time,
+                                &mut physics_cache,
+                                &mut replay,
+                                &mut match_recorder,
+                                &mut match_settings,
+                                &settings.player_name,
+                                &mut pause,
+                                Some(&mut cpu_player),
+                            );
+                            match choice {
+                                Some(PostMatchChoice::ChangeSettings) => {
+                                    GameState::ChooseMatchSettings(GameMode::VsCpu)
+                                }
+                                Some(_) => main_menu(ethernet.is_some()),
+                                None => GameState::GameRunningVsCpu,
+                            }
                         }
                         GameState::GameRunningNetwork(mut network) => {
-                            game::game_loop_network(
+                            let choice = game::game_loop_network(
                                 just_entered_state,
                                 &mut framebuffer,
+                                &mut overlay,
                                 &mut input,
                                 &fps,
                                 &mut rackets,
@@ -394,20 +525,124 @@ fn main(hw: board::Hardware) -> ! {
                                 is_server,
                                 &mut network,
                                 &mut loading_font,
+                                &mut menu_font,
+                                &mut cache,
+                                total_time,
+                                delta_time,
+                                &mut physics_cache,
+                                &mut replay,
+                                &mut match_recorder,
+                                &mut match_settings,
+                                &settings.player_name,
+                                &mut pause,
+                            );
+                            match choice {
+                                Some(PostMatchChoice::ChangeSettings) => {
+                                    GameState::ChangeNetworkSettings(network)
+                                }
+                                Some(_) => {
+                                    ethernet = Some(network.close(rcc));
+                                    main_menu(true)
+                                }
+                                None => GameState::GameRunningNetwork(network),
+                            }
+                        }
+                        GameState::ChangeNetworkSettings(network) => {
+                            match menu::choose_match_settings(
+                                just_entered_state,
+                                &mut framebuffer,
+                                &mut menu_font,
+                                &mut input,
+                                &mut ui,
+                                &mut match_settings,
+                                &mut difficulty,
+                                GameMode::Network,
+                            ) {
+                                // the next match starts right away, back keeps the rules
+                                GameState::ConnectToNetwork | GameState::ChooseClientOrServer => {
+                                    GameState::GameRunningNetwork(network)
+                                }
+                                _ => GameState::ChangeNetworkSettings(network),
+                            }
+                        }
+                        GameState::Attract => {
+                            let touched = game::game_loop_attract(
+                                just_entered_state,
+                                &mut framebuffer,
+                                &mut overlay,
+                                &mut input,
+                                &fps,
+                                &mut rackets,
+                                &mut ball,
+                                &mut attract_cpus,
+                                &mut server_gamestate,
+                                &mut loading_font,
+                                &mut menu_font,
                                 &mut cache,
                                 total_time,
                                 delta_time,
                                 &mut physics_cache,
+                                &mut attract_won_at,
                             );
-                            GameState::GameRunningNetwork(network)
+                            if !touched {
+                                GameState::Attract
+                            } else if ethernet.is_some() {
+                                GameState::ChooseLocalOrNetwork
+                            } else {
+                                GameState::ChooseOnlyLocal
+                            }
+                        }
+                        GameState::Settings => {
+                            if just_entered_state {
+                                edited_settings = settings.clone();
+                            }
+                            match menu::edit_settings(
+                                just_entered_state,
+                                &mut framebuffer,
+                                &mut menu_font,
+                                &mut input,
+                                &mut ui,
+                                &mut edited_settings,
+                            ) {
+                                GameState::ChooseLocalOrNetwork => main_menu(ethernet.is_some()),
+                                next => next,
+                            }
+                        }
+                        GameState::SaveSettings => {
+                            settings = edited_settings.clone();
+                            match_settings = settings.match_settings;
+                            if let Err(e) = store.save(&settings.serialize()) {
+                                hprintln!("Saving the settings failed: {:?}", e);
+                            }
+                            unsafe {
+                                ptr::write_volatile(theme_ptr as *mut u8, settings.theme);
+                            }
+                            main_menu(ethernet.is_some())
                         }
                     };
 
+                    // show a demo match when nobody touched the menus for a while
+                    let in_menu = match gamestate {
+                        GameState::ChooseLocalOrNetwork
+                        | GameState::ChooseClientOrServer
+                        | GameState::ChooseOnlyLocal
+                        | GameState::ChooseMatchSettings(_)
+                        | GameState::NetworkError(_) => true,
+                        _ => false,
+                    };
+                    if !in_menu || input.is_touched() {
+                        idle_since = total_time;
+                    } else if total_time - idle_since > ATTRACT_MODE_DELAY {
+                        gamestate = GameState::Attract;
+                    }
+
                     // graphics::draw_guidelines(&mut framebuffer);
-                    graphics::draw_fps(&mut framebuffer, &fps);
+                    graphics::draw_fps(&mut overlay, &fps);
                     // end of frame
                     fps.count_frame();
                     unsafe {
+                        // framebuffer.swap_buffers() may have been called more than once
+                        ptr::write_volatile(finished_buffer_ptr as *mut u32, framebuffer.drawing_addr());
                         ptr::write_volatile(should_draw_now_ptr as *mut bool, false);
                     }
                 }
@@ -415,3 +650,20 @@ fn main(hw: board::Hardware) -> ! {
         },
     )
 }
+
+// shows the framebuffer in one of `palette::THEMES`
+fn apply_theme(lcd: &mut lcd::Lcd, theme: u8) {
+    for i in 0..256 {
+        lcd.clut[i] = palette::clut_entry(theme, i as u8);
+    }
+    lcd.update_clut();
+}
+
+// the menu to return to after a match, without network only local games are left
+fn main_menu(network_available: bool) -> GameState {
+    if network_available {
+        GameState::ChooseLocalOrNetwork
+    } else {
+        GameState::ChooseOnlyLocal
+    }
+}
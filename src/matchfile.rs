@@ -0,0 +1,460 @@
+//! Match files: everything needed to replay a whole match from its inputs.
+//!
+//! ```text
+//! "PONG" version:u8 flags:u8 seed:u32
+//! settings_len:u8 settings  name_len:u8 name  name_len:u8 name
+//! tick_count:u32 body_len:u32 body
+//! final gamestate (as sent over the network)  crc32:u32
+//! ```
+//!
+//! All numbers are big endian like the network packets. The settings are a
+//! serialized `MatchSettings`, empty for the classic rules. The body is a run
+//! length coded input stream: a flags byte says which of the two inputs and
+//! the frame time changed and how many ticks (1 to 32) the values last, the
+//! changes follow as zigzag varints relative to the previous tick.
+
+use alloc::String;
+use alloc::Vec;
+use core::fmt::Write;
+use crc;
+use lcd::HEIGHT;
+use network::packets::Serializable;
+use network::{GamestatePacket, InputPacket};
+use physics;
+use physics::PhysicsCache;
+use rules::MatchSettings;
+
+pub const VERSION: u8 = 4;
+pub const LINE_PREFIX: &str = "match:";
+pub const MAX_NAME_LEN: usize = 16;
+
+const MAGIC: &[u8; 4] = b"PONG";
+const FLAG_TRUNCATED: u8 = 1;
+
+const RUN_INPUT_1: u8 = 0x80;
+const RUN_INPUT_2: u8 = 0x40;
+const RUN_TIME: u8 = 0x20;
+const RUN_LENGTH_MASK: u8 = 0x1f;
+const MAX_RUN_LENGTH: usize = RUN_LENGTH_MASK as usize + 1;
+
+const DUMP_BYTES_PER_LINE: usize = 32;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MatchError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    Checksum { stored: u32, computed: u32 },
+    Invalid(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchHeader {
+    pub version: u8,
+    /// The recording hit its size limit, the ticks end before the match did.
+    pub truncated: bool,
+    /// `total_time` the first gamestate was created with.
+    pub seed: u32,
+    pub settings: Vec<u8>,
+    pub names: [String; 2],
+}
+
+/// Inputs the physics ran with in one frame.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tick {
+    pub inputs: [InputPacket; 2],
+    pub total_time: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchFile {
+    pub header: MatchHeader,
+    pub ticks: Vec<Tick>,
+    pub final_state: GamestatePacket,
+}
+
+impl MatchFile {
+    pub fn parse(data: &[u8]) -> Result<MatchFile, MatchError> {
+        if data.len() < MAGIC.len() || &data[..MAGIC.len()] != MAGIC {
+            return Err(MatchError::BadMagic);
+        }
+        let mut reader = Reader { data: data, position: MAGIC.len() };
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(MatchError::UnsupportedVersion(version));
+        }
+        let crc_at = data.len().checked_sub(4).ok_or(MatchError::Truncated)?;
+        let stored = Reader { data: data, position: crc_at }.u32()?;
+        let computed = crc::crc32(&data[..crc_at]);
+        if stored != computed {
+            return Err(MatchError::Checksum { stored: stored, computed: computed });
+        }
+
+        let flags = reader.u8()?;
+        let seed = reader.u32()?;
+        let settings = reader.block()?.to_vec();
+        let name_1 = reader.name()?;
+        let name_2 = reader.name()?;
+        let tick_count = reader.u32()? as usize;
+        let body_len = reader.u32()? as usize;
+        let body = reader.bytes(body_len)?;
+        let final_state = GamestatePacket::deserialize(reader.bytes(GamestatePacket::len())?);
+        if reader.position != crc_at {
+            return Err(MatchError::Invalid("trailing bytes before the checksum"));
+        }
+
+        let file = MatchFile {
+            header: MatchHeader {
+                version: version,
+                truncated: flags & FLAG_TRUNCATED != 0,
+                seed: seed,
+                settings: settings,
+                names: [name_1, name_2],
+            },
+            ticks: decode_ticks(body, tick_count, seed as usize)?,
+            final_state: final_state,
+        };
+        file.settings()?;
+        Ok(file)
+    }
+
+    /// The settings the match was played with, files without any were
+    /// played by the classic rules.
+    pub fn settings(&self) -> Result<MatchSettings, MatchError> {
+        let data = &self.header.settings;
+        if data.is_empty() {
+            return Ok(MatchSettings::new());
+        }
+        if data.len() != MatchSettings::len() {
+            return Err(MatchError::Invalid("settings have the wrong length"));
+        }
+        let settings = MatchSettings::deserialize(data);
+        if !settings.is_valid() {
+            return Err(MatchError::Invalid("settings are out of range"));
+        }
+        Ok(settings)
+    }
+
+    /// Runs the physics over all ticks, `on_tick` sees the gamestate after
+    /// each of them. Returns the state the match ended in.
+    pub fn simulate<F: FnMut(&Tick, &GamestatePacket)>(&self, mut on_tick: F) -> GamestatePacket {
+        let mut gamestate = GamestatePacket::new(self.header.seed as usize);
+        let settings = self.settings().unwrap_or_else(|_| MatchSettings::new());
+        let mut cache = PhysicsCache::new();
+        for tick in &self.ticks {
+            physics::calculate_physics(&mut gamestate, tick.inputs, tick.total_time, &settings, &mut cache);
+            on_tick(tick, &gamestate);
+        }
+        gamestate
+    }
+
+    /// Whether the inputs reproduce the stored final gamestate.
+    pub fn verify(&self) -> bool {
+        self.simulate(|_, _| {}) == self.final_state
+    }
+}
+
+fn decode_ticks(body: &[u8], tick_count: usize, seed: usize) -> Result<Vec<Tick>, MatchError> {
+    // every run takes a byte at least, so the count is checked before it
+    // decides how much to allocate
+    if tick_count as u64 > body.len() as u64 * MAX_RUN_LENGTH as u64 {
+        return Err(MatchError::Invalid("more ticks than the input stream can hold"));
+    }
+    let mut reader = Reader { data: body, position: 0 };
+    let mut ticks = Vec::with_capacity(tick_count);
+    let mut inputs = [InputPacket::new(), InputPacket::new()];
+    let mut total_time = seed;
+    let mut frame_time = 0;
+    while ticks.len() < tick_count {
+        let run = reader.u8()?;
+        if run & RUN_INPUT_1 != 0 {
+            inputs[0].goal_y = inputs[0].goal_y.wrapping_add(reader.signed()? as i16);
+        }
+        if run & RUN_INPUT_2 != 0 {
+            inputs[1].goal_y = inputs[1].goal_y.wrapping_add(reader.signed()? as i16);
+        }
+        if run & RUN_TIME != 0 {
+            frame_time = reader.varint()? as usize;
+        }
+        // the physics take the targets to be on screen
+        if inputs.iter().any(|input| input.goal_y < 0 || input.goal_y >= HEIGHT as i16) {
+            return Err(MatchError::Invalid("racket target off screen"));
+        }
+        let length = (run & RUN_LENGTH_MASK) as usize + 1;
+        if ticks.len() + length > tick_count {
+            return Err(MatchError::Invalid("input stream runs past the tick count"));
+        }
+        for _ in 0..length {
+            total_time = total_time.wrapping_add(frame_time);
+            ticks.push(Tick {
+                inputs: inputs,
+                total_time: total_time,
+            });
+        }
+    }
+    if reader.position != body.len() {
+        return Err(MatchError::Invalid("input stream is longer than the tick count"));
+    }
+    Ok(ticks)
+}
+
+/// Collects the inputs of a running match, small enough to keep on the
+/// board. Once `max_bytes` are reached further ticks are dropped and the
+/// file is marked as truncated.
+pub struct MatchRecorder {
+    seed: u32,
+    settings: Vec<u8>,
+    names: [String; 2],
+    body: Vec<u8>,
+    max_bytes: usize,
+    tick_count: u32,
+    truncated: bool,
+    run_start: Option<usize>, // flags byte of the current run
+    run_length: usize,
+    inputs: [InputPacket; 2],
+    total_time: usize,
+    frame_time: usize,
+    final_state: GamestatePacket,
+}
+
+impl MatchRecorder {
+    pub fn new(seed: usize, settings: &[u8], names: [&str; 2], max_bytes: usize) -> MatchRecorder {
+        MatchRecorder {
+            seed: seed as u32,
+            settings: settings.to_vec(),
+            names: [truncate_name(names[0]), truncate_name(names[1])],
+            body: Vec::new(),
+            max_bytes: max_bytes,
+            tick_count: 0,
+            truncated: false,
+            run_start: None,
+            run_length: 0,
+            inputs: [InputPacket::new(), InputPacket::new()],
+            total_time: seed,
+            frame_time: 0,
+            final_state: GamestatePacket::new(seed),
+        }
+    }
+
+    /// Call after every physics step with the inputs and time it got and
+    /// the gamestate it produced.
+    pub fn record(&mut self, inputs: [InputPacket; 2], total_time: usize, gamestate: &GamestatePacket) {
+        if self.truncated {
+            return;
+        }
+        let frame_time = total_time.wrapping_sub(self.total_time);
+        let mut flags = 0;
+        // only the racket targets matter to the physics
+        if inputs[0].goal_y != self.inputs[0].goal_y {
+            flags |= RUN_INPUT_1;
+        }
+        if inputs[1].goal_y != self.inputs[1].goal_y {
+            flags |= RUN_INPUT_2;
+        }
+        if frame_time != self.frame_time {
+            flags |= RUN_TIME;
+        }
+
+        match self.run_start {
+            Some(_) if flags == 0 && self.run_length < MAX_RUN_LENGTH => {
+                self.run_length += 1;
+            }
+            _ => {
+                // a new run needs at most a flags byte and three varints
+                if self.body.len() + 1 + 3 * 5 > self.max_bytes {
+                    self.truncated = true;
+                    return;
+                }
+                self.run_start = Some(self.body.len());
+                self.run_length = 1;
+                self.body.push(flags);
+                if flags & RUN_INPUT_1 != 0 {
+                    push_signed(&mut self.body, inputs[0].goal_y.wrapping_sub(self.inputs[0].goal_y) as i32);
+                }
+                if flags & RUN_INPUT_2 != 0 {
+                    push_signed(&mut self.body, inputs[1].goal_y.wrapping_sub(self.inputs[1].goal_y) as i32);
+                }
+                if flags & RUN_TIME != 0 {
+                    push_varint(&mut self.body, frame_time as u32);
+                }
+            }
+        }
+        if let Some(start) = self.run_start {
+            self.body[start] = (self.body[start] & !RUN_LENGTH_MASK) | (self.run_length - 1) as u8;
+        }
+
+        self.inputs = inputs;
+        self.total_time = total_time;
+        self.frame_time = frame_time;
+        self.tick_count += 1;
+        self.final_state = *gamestate;
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn tick_count(&self) -> usize {
+        self.tick_count as usize
+    }
+
+    /// The complete match file up to the last recorded tick.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut file = Vec::with_capacity(self.body.len() + 64);
+        file.extend_from_slice(MAGIC);
+        file.push(VERSION);
+        file.push(if self.truncated { FLAG_TRUNCATED } else { 0 });
+        push_u32(&mut file, self.seed);
+        file.push(self.settings.len() as u8);
+        file.extend_from_slice(&self.settings);
+        for name in &self.names {
+            file.push(name.len() as u8);
+            file.extend_from_slice(name.as_bytes());
+        }
+        push_u32(&mut file, self.tick_count);
+        push_u32(&mut file, self.body.len() as u32);
+        file.extend_from_slice(&self.body);
+        file.extend(self.final_state.serialize());
+        let checksum = crc::crc32(&file);
+        push_u32(&mut file, checksum);
+        file
+    }
+}
+
+fn truncate_name(name: &str) -> String {
+    let mut end = name.len().min(MAX_NAME_LEN);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    String::from(&name[..end])
+}
+
+/// Writes a match file as hex lines for semihosting, the host tools read
+/// them back with `parse_dump`.
+pub fn dump<F: FnMut(&str)>(file: &[u8], mut output: F) {
+    output(&format!("{} begin {} bytes", LINE_PREFIX, file.len()));
+    for chunk in file.chunks(DUMP_BYTES_PER_LINE) {
+        let mut line = String::from(LINE_PREFIX);
+        line.push(' ');
+        for byte in chunk {
+            let _ = write!(line, "{:02x}", byte);
+        }
+        output(&line);
+    }
+    output(&format!("{} end", LINE_PREFIX));
+}
+
+/// The last complete dump in a semihosting log.
+pub fn parse_dump(log: &str) -> Option<Vec<u8>> {
+    let mut dump = None;
+    let mut current: Option<Vec<u8>> = None;
+    for line in log.lines() {
+        let line = line.trim();
+        if !line.starts_with(LINE_PREFIX) {
+            continue;
+        }
+        let content = line[LINE_PREFIX.len()..].trim();
+        if content.starts_with("begin") {
+            current = Some(Vec::new());
+        } else if content == "end" {
+            dump = current.take().or(dump);
+        } else if let Some(bytes) = current.as_mut() {
+            let digits = content.as_bytes();
+            if digits.len() % 2 != 0 {
+                current = None;
+                continue;
+            }
+            for pair in digits.chunks(2) {
+                match (hex_digit(pair[0]), hex_digit(pair[1])) {
+                    (Some(high), Some(low)) => bytes.push(high << 4 | low),
+                    _ => {}
+                }
+            }
+        }
+    }
+    dump
+}
+
+fn hex_digit(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
+
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+    out.push((value >> 24) as u8);
+    out.push((value >> 16) as u8);
+    out.push((value >> 8) as u8);
+    out.push(value as u8);
+}
+
+fn push_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn push_signed(out: &mut Vec<u8>, value: i32) {
+    push_varint(out, ((value << 1) ^ (value >> 31)) as u32);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], MatchError> {
+        if self.data.len() - self.position < len {
+            return Err(MatchError::Truncated);
+        }
+        let bytes = &self.data[self.position..self.position + len];
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, MatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, MatchError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from(bytes[0]) << 24 | u32::from(bytes[1]) << 16 | u32::from(bytes[2]) << 8
+            | u32::from(bytes[3]))
+    }
+
+    fn block(&mut self) -> Result<&'a [u8], MatchError> {
+        let len = self.u8()? as usize;
+        self.bytes(len)
+    }
+
+    fn name(&mut self) -> Result<String, MatchError> {
+        let bytes = self.block()?;
+        if bytes.len() > MAX_NAME_LEN {
+            return Err(MatchError::Invalid("player name too long"));
+        }
+        String::from_utf8(bytes.to_vec()).map_err(|_| MatchError::Invalid("player name is not utf-8"))
+    }
+
+    fn varint(&mut self) -> Result<u32, MatchError> {
+        let mut value = 0u32;
+        for shift in 0..5 {
+            let byte = self.u8()?;
+            value |= u32::from(byte & 0x7f) << (7 * shift);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(MatchError::Invalid("varint longer than 5 bytes"))
+    }
+
+    fn signed(&mut self) -> Result<i32, MatchError> {
+        let value = self.varint()?;
+        Ok((value >> 1) as i32 ^ -((value & 1) as i32))
+    }
+}
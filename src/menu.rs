@@ -1,32 +1,87 @@
-use game::GameState;
+use alloc::String;
+use cpu::Difficulty;
+use game::{GameMode, GameState};
 use input::Input;
 use lcd;
 use lcd::Framebuffer;
 use lcd::TextWriter;
+use palette::THEMES;
+use rules::{MatchSettings, MAX_BALL_SPEED, MAX_BEST_OF, MAX_RACKET_SIZE, MIN_RACKET_SIZE};
+use settings::{Settings, NAME_CHARACTERS, NAME_LENGTH};
+use ui::{Column, Rect, Ui};
+use PADDING;
+
+const BUTTON_HEIGHT: usize = 40;
+const BOTTOM_Y: usize = 224; // back and forward buttons
+const BOTTOM_WIDTH: usize = 120;
+const MAIN_MENU_Y: usize = 90;
+const MAIN_MENU_HEIGHT: usize = 60;
+const SETTINGS_WIDTH: usize = 300;
+const SETTINGS_ROW_HEIGHT: usize = 36;
+const SETTINGS_SPACING: usize = 6;
+const PREFERENCES_ROW_HEIGHT: usize = 32;
+const MAX_SCORE_LIMIT: u8 = 21;
+const NAME_X: usize = 130;
+const LETTER_WIDTH: usize = 36;
+const LETTER_SPACING: usize = 4;
 
 pub fn choose_local_network(
     just_entered: bool,
     framebuffer: &mut Framebuffer,
     text_writer: &mut TextWriter,
     input: &mut Input,
+    ui: &mut Ui,
+) -> GameState {
+    main_menu(just_entered, framebuffer, text_writer, input, ui, true)
+        .unwrap_or(GameState::ChooseLocalOrNetwork)
+}
+
+pub fn choose_only_local(
+    just_entered: bool,
+    framebuffer: &mut Framebuffer,
+    text_writer: &mut TextWriter,
+    input: &mut Input,
+    ui: &mut Ui,
 ) -> GameState {
+    main_menu(just_entered, framebuffer, text_writer, input, ui, false)
+        .unwrap_or(GameState::ChooseOnlyLocal)
+}
+
+// without a network its button is only a label
+fn main_menu(
+    just_entered: bool,
+    framebuffer: &mut Framebuffer,
+    text_writer: &mut TextWriter,
+    input: &mut Input,
+    ui: &mut Ui,
+    network_available: bool,
+) -> Option<GameState> {
     if just_entered {
         framebuffer.clear();
-        text_writer.write_at(framebuffer, "Local Multiplayer", 40, 125);
-        text_writer.write_at(framebuffer, "Network Multiplayer", 270, 125);
     }
-
-    let touch = input.handle_menu();
-
-    if touch.is_down && !touch.any_touch_last_frame {
-        if touch.x < lcd::WIDTH as u16 / 2 {
-            return GameState::GameRunningLocal;
-        } else {
-            return GameState::ChooseClientOrServer;
+    ui.begin(just_entered, input.handle_menu());
+    let modes = Rect::new(PADDING, MAIN_MENU_Y, 2 * 220, MAIN_MENU_HEIGHT);
+    let mut next = None;
+    if ui.button(framebuffer, text_writer, modes.column(0, 2).inset(10), "Local Multiplayer") {
+        next = Some(GameState::ChooseMatchSettings(GameMode::Local));
+    }
+    let network = modes.column(1, 2).inset(10);
+    if network_available {
+        if ui.button(framebuffer, text_writer, network, "Network Multiplayer") {
+            next = Some(GameState::ChooseClientOrServer);
         }
+    } else {
+        ui.label(framebuffer, text_writer, network.inset(10), "No network");
     }
-
-    GameState::ChooseLocalOrNetwork
+    let vs_cpu = Rect::new(140, MAIN_MENU_Y + MAIN_MENU_HEIGHT + 20, 200, MAIN_MENU_HEIGHT - 10);
+    if ui.button(framebuffer, text_writer, vs_cpu, "Play vs CPU") {
+        next = Some(GameState::ChooseMatchSettings(GameMode::VsCpu));
+    }
+    if forward_button(ui, framebuffer, text_writer, "Settings") {
+        next = Some(GameState::Settings);
+    }
+    ui.end();
+    next
 }
 
 pub fn choose_client_server(
@@ -34,46 +89,208 @@ pub fn choose_client_server(
     framebuffer: &mut Framebuffer,
     text_writer: &mut TextWriter,
     input: &mut Input,
+    ui: &mut Ui,
     is_server: &mut bool,
 ) -> GameState {
     if just_entered {
         framebuffer.clear();
-        text_writer.write_at(framebuffer, "Client", 95, 125);
-        text_writer.write_at(framebuffer, "Server", 335, 125);
     }
+    ui.begin(just_entered, input.handle_menu());
+    let roles = Rect::new(PADDING, MAIN_MENU_Y, 2 * 220, MAIN_MENU_HEIGHT);
+    let mut next = GameState::ChooseClientOrServer;
+    if ui.button(framebuffer, text_writer, roles.column(0, 2).inset(10), "Client") {
+        *is_server = false;
+        next = GameState::ConnectToNetwork;
+    }
+    if ui.button(framebuffer, text_writer, roles.column(1, 2).inset(10), "Server") {
+        *is_server = true;
+        next = GameState::ChooseMatchSettings(GameMode::Network);
+    }
+    if back_button(ui, framebuffer, text_writer) {
+        next = GameState::ChooseLocalOrNetwork;
+    }
+    ui.end();
+    next
+}
+
+/// Shows why the network did not come up. Retry starts over with the
+/// peripherals the failed attempt handed back.
+pub fn network_error(
+    just_entered: bool,
+    framebuffer: &mut Framebuffer,
+    text_writer: &mut TextWriter,
+    input: &mut Input,
+    ui: &mut Ui,
+    reason: String,
+) -> GameState {
+    if just_entered {
+        framebuffer.clear();
+    }
+    ui.begin(just_entered, input.handle_menu());
+    let mut lines = Column::new(Rect::new(PADDING, PADDING, 440, 30), 30, 30);
+    ui.label(framebuffer, text_writer, lines.row(), "Could not start the network");
+    ui.label(framebuffer, text_writer, lines.row(), &reason);
+    let mut next = None;
+    if forward_button(ui, framebuffer, text_writer, "Retry") {
+        next = Some(GameState::ConnectToNetwork);
+    }
+    if back_button(ui, framebuffer, text_writer) {
+        next = Some(GameState::ChooseClientOrServer);
+    }
+    ui.end();
+    next.unwrap_or(GameState::NetworkError(reason))
+}
 
-    let touch = input.handle_menu();
+/// One row per setting. Games against the cpu get a row for its difficulty.
+/// Back leads to `ChooseLocalOrNetwork` for local games and to
+/// `ChooseClientOrServer` for network games.
+pub fn choose_match_settings(
+    just_entered: bool,
+    framebuffer: &mut Framebuffer,
+    text_writer: &mut TextWriter,
+    input: &mut Input,
+    ui: &mut Ui,
+    settings: &mut MatchSettings,
+    difficulty: &mut Difficulty,
+    mode: GameMode,
+) -> GameState {
+    if just_entered {
+        framebuffer.clear();
+    }
+    ui.begin(just_entered, input.handle_menu());
+    let area = Rect::new(PADDING, 10, SETTINGS_WIDTH, SETTINGS_ROW_HEIGHT);
+    let mut rows = Column::new(area, SETTINGS_ROW_HEIGHT, SETTINGS_SPACING);
 
-    if touch.is_down && !touch.any_touch_last_frame {
-        if touch.x < lcd::WIDTH as u16 / 2 {
-            *is_server = false;
-            return GameState::ConnectToNetwork;
-        } else {
-            *is_server = true;
-            return GameState::ConnectToNetwork;
+    let text = format!("First to {}", settings.score_limit);
+    if ui.button(framebuffer, text_writer, rows.row(), &text) {
+        settings.cycle_score_limit();
+    }
+    ui.toggle(framebuffer, text_writer, rows.row(), "Win by two", &mut settings.win_by_two);
+    let text = if settings.time_limit == 0 {
+        String::from("Time limit: off")
+    } else {
+        format!("Time limit: {}:{:02}", settings.time_limit / 60, settings.time_limit % 60)
+    };
+    if ui.button(framebuffer, text_writer, rows.row(), &text) {
+        settings.cycle_time_limit();
+    }
+    let sets = rows.row();
+    ui.label(framebuffer, text_writer, sets.column(0, 2), "Sets, best of");
+    spin(ui, framebuffer, text_writer, sets.column(1, 2), 1, MAX_BEST_OF, 2, &mut settings.best_of);
+    if mode == GameMode::VsCpu {
+        let text = format!("CPU: {}", difficulty.name());
+        if ui.button(framebuffer, text_writer, rows.row(), &text) {
+            *difficulty = difficulty.next();
         }
     }
 
-    GameState::ChooseClientOrServer
+    let mut next = GameState::ChooseMatchSettings(mode);
+    if forward_button(ui, framebuffer, text_writer, "Start") {
+        next = match mode {
+            GameMode::Local => GameState::GameRunningLocal,
+            GameMode::VsCpu => GameState::GameRunningVsCpu,
+            GameMode::Network => GameState::ConnectToNetwork,
+        };
+    }
+    if back_button(ui, framebuffer, text_writer) {
+        next = match mode {
+            GameMode::Local | GameMode::VsCpu => GameState::ChooseLocalOrNetwork,
+            GameMode::Network => GameState::ChooseClientOrServer,
+        };
+    }
+    ui.end();
+    next
 }
 
-pub fn choose_only_local(
+/// Preferences that are kept in flash. Save leads to `SaveSettings`, back to
+/// `ChooseLocalOrNetwork` and drops the changes.
+pub fn edit_settings(
     just_entered: bool,
     framebuffer: &mut Framebuffer,
     text_writer: &mut TextWriter,
     input: &mut Input,
+    ui: &mut Ui,
+    settings: &mut Settings,
 ) -> GameState {
     if just_entered {
-        // framebuffer.clear(); // Don't clear as we cleared and wrote the debug message before
-        text_writer.write_at(framebuffer, "Local Multiplayer", 40, 125);
-        text_writer.write_at(framebuffer, "-------------------", 270, 125);
+        framebuffer.clear();
+    }
+    ui.begin(just_entered, input.handle_menu());
+    let area = Rect::new(PADDING, 10, 440, PREFERENCES_ROW_HEIGHT);
+    let mut rows = Column::new(area, PREFERENCES_ROW_HEIGHT, SETTINGS_SPACING);
+
+    let rules = &mut settings.match_settings;
+    let row = rows.row();
+    ui.label(framebuffer, text_writer, row.column(0, 4), "First to");
+    spin(ui, framebuffer, text_writer, row.column(1, 4), 1, MAX_SCORE_LIMIT, 1, &mut rules.score_limit);
+    ui.label(framebuffer, text_writer, row.column(2, 4), "Racket");
+    spin(ui, framebuffer, text_writer, row.column(3, 4), MIN_RACKET_SIZE, MAX_RACKET_SIZE, 5,
+         &mut rules.racket_size);
+
+    let row = rows.row();
+    ui.label(framebuffer, text_writer, row.column(0, 4), "Ball speed");
+    spin(ui, framebuffer, text_writer, row.column(1, 4), 1, MAX_BALL_SPEED, 1, &mut rules.ball_speed);
+    ui.label(framebuffer, text_writer, row.column(2, 4), "Colors");
+    if ui.button(framebuffer, text_writer, row.column(3, 4), THEMES[settings.theme as usize].name) {
+        settings.theme = (settings.theme + 1) % THEMES.len() as u8;
+    }
+
+    address_row(ui, framebuffer, text_writer, rows.row(), "Server", &mut settings.server_ip);
+    address_row(ui, framebuffer, text_writer, rows.row(), "Client", &mut settings.client_ip);
+
+    // a tap moves a letter on, trailing blanks are dropped
+    let row = rows.row();
+    ui.label(framebuffer, text_writer, Rect::new(row.x, row.y, NAME_X, row.height), "Name");
+    let mut name = [b' '; NAME_LENGTH];
+    name[..settings.player_name.len()].copy_from_slice(settings.player_name.as_bytes());
+    for (i, letter) in name.iter_mut().enumerate() {
+        let x = row.x + NAME_X + i * (LETTER_WIDTH + LETTER_SPACING);
+        let rect = Rect::new(x, row.y, LETTER_WIDTH, row.height);
+        let text = [*letter];
+        if ui.button(framebuffer, text_writer, rect, core::str::from_utf8(&text).unwrap_or("?")) {
+            let position = NAME_CHARACTERS.iter().position(|c| c == letter).unwrap_or(0);
+            *letter = NAME_CHARACTERS[(position + 1) % NAME_CHARACTERS.len()];
+        }
     }
+    let length = name.iter().rposition(|&c| c != b' ').map_or(0, |last| last + 1);
+    settings.player_name = name[..length].iter().map(|&c| c as char).collect();
 
-    let touch = input.handle_menu();
+    let mut next = GameState::Settings;
+    if forward_button(ui, framebuffer, text_writer, "Save") {
+        next = GameState::SaveSettings;
+    }
+    if back_button(ui, framebuffer, text_writer) {
+        next = GameState::ChooseLocalOrNetwork;
+    }
+    ui.end();
+    next
+}
+
+// the first three parts are fixed, the spinner picks the host
+fn address_row(ui: &mut Ui, framebuffer: &mut Framebuffer, text_writer: &mut TextWriter, row: Rect,
+               name: &str, address: &mut [u8; 4]) {
+    let text = format!("{} {}.{}.{}.", name, address[0], address[1], address[2]);
+    ui.label(framebuffer, text_writer, Rect::new(row.x, row.y, row.width / 2, row.height), &text);
+    spin(ui, framebuffer, text_writer, row.column(2, 4), 1, 254, 1, &mut address[3]);
+}
 
-    if touch.is_down && !touch.any_touch_last_frame && touch.x < lcd::WIDTH as u16 / 2 {
-        return GameState::GameRunningLocal;
+fn spin(ui: &mut Ui, framebuffer: &mut Framebuffer, text_writer: &mut TextWriter, rect: Rect, min: u8,
+        max: u8, step: u8, value: &mut u8) {
+    let mut wide = *value as i32;
+    if ui.spinner(framebuffer, text_writer, rect, min as i32, max as i32, step as i32, &mut wide) {
+        *value = wide as u8;
     }
+}
+
+/// The bottom left corner of every screen that is not the main menu.
+pub fn back_button(ui: &mut Ui, framebuffer: &mut Framebuffer, text_writer: &mut TextWriter) -> bool {
+    let rect = Rect::new(PADDING, BOTTOM_Y, BOTTOM_WIDTH, BUTTON_HEIGHT);
+    ui.button(framebuffer, text_writer, rect, "< Back")
+}
 
-    GameState::ChooseOnlyLocal
+// the bottom right corner, for the way forward
+fn forward_button(ui: &mut Ui, framebuffer: &mut Framebuffer, text_writer: &mut TextWriter,
+                  text: &str) -> bool {
+    let rect = Rect::new(lcd::WIDTH - PADDING - BOTTOM_WIDTH, BOTTOM_Y, BOTTOM_WIDTH, BUTTON_HEIGHT);
+    ui.button(framebuffer, text_writer, rect, text)
 }
@@ -0,0 +1,126 @@
+use alloc::String;
+use alloc::Vec;
+use core::fmt::Write;
+
+/// Prefix of every line a capture dump writes to semihosting.
+pub const LINE_PREFIX: &str = "capture:";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub time: usize, // system_clock ticks
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+/// Ring buffer that keeps the last `capacity` datagrams of a session.
+pub struct Recorder {
+    records: Vec<Record>,
+    next: usize,
+    capacity: usize,
+    pub overwritten: usize,
+}
+
+impl Recorder {
+    /// Keeps at least the last datagram.
+    pub fn new(capacity: usize) -> Recorder {
+        let capacity = capacity.max(1);
+        Recorder {
+            records: Vec::with_capacity(capacity),
+            next: 0,
+            capacity: capacity,
+            overwritten: 0,
+        }
+    }
+
+    pub fn record(&mut self, time: usize, direction: Direction, data: &[u8]) {
+        let record = Record {
+            time: time,
+            direction: direction,
+            data: Vec::from(data),
+        };
+        if self.records.len() < self.capacity {
+            self.records.push(record);
+        } else {
+            self.records[self.next] = record;
+            self.overwritten += 1;
+        }
+        self.next = (self.next + 1) % self.capacity;
+    }
+
+    /// All records, oldest first.
+    pub fn records<'a>(&'a self) -> impl Iterator<Item = &'a Record> + 'a {
+        let (newer, older) = if self.records.len() < self.capacity {
+            self.records.split_at(self.records.len())
+        } else {
+            self.records.split_at(self.next)
+        };
+        older.iter().chain(newer.iter())
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+        self.next = 0;
+        self.overwritten = 0;
+    }
+
+    /// Formats the whole buffer as text lines, see `parse_line`.
+    pub fn dump<F: FnMut(&str)>(&self, mut output: F) {
+        output(&format!(
+            "{} begin {} {}",
+            LINE_PREFIX,
+            self.records.len(),
+            self.overwritten
+        ));
+        for record in self.records() {
+            output(&format_record(record));
+        }
+        output(&format!("{} end", LINE_PREFIX));
+    }
+}
+
+/// `capture: <time> <tx|rx> <payload as hex>`
+pub fn format_record(record: &Record) -> String {
+    let mut line = String::new();
+    let direction = match record.direction {
+        Direction::Sent => "tx",
+        Direction::Received => "rx",
+    };
+    let _ = write!(line, "{} {} {} ", LINE_PREFIX, record.time, direction);
+    for byte in &record.data {
+        let _ = write!(line, "{:02x}", byte);
+    }
+    line
+}
+
+/// Parses a line written by `format_record`, `None` for anything else.
+pub fn parse_line(line: &str) -> Option<Record> {
+    let mut parts = line.trim().split_whitespace();
+    if parts.next() != Some(LINE_PREFIX) {
+        return None;
+    }
+    let time = parts.next()?.parse().ok()?;
+    let direction = match parts.next()? {
+        "tx" => Direction::Sent,
+        "rx" => Direction::Received,
+        _ => return None,
+    };
+    let hex = parts.next().unwrap_or("");
+    if hex.len() % 2 != 0 || parts.next().is_some() {
+        return None;
+    }
+    let mut data = Vec::with_capacity(hex.len() / 2);
+    for i in 0..hex.len() / 2 {
+        data.push(u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok()?);
+    }
+    Some(Record {
+        time: time,
+        direction: direction,
+        data: data,
+    })
+}
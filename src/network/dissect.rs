@@ -0,0 +1,206 @@
+use alloc::String;
+use alloc::Vec;
+use core::fmt::Write;
+use lcd::{HEIGHT, WIDTH};
+use racket::RACKET_WIDTH;
+use super::packets::{BallPacket, GamestatePacket, InputPacket, RacketPacket, Serializable,
+                     WhoamiPacket};
+use super::packets::{BALL_MAX_SPEED, STATE_PAUSED, STATE_RUNNING, STATE_WON_PLAYER_1,
+                     STATE_WON_PLAYER_2};
+use super::packets::{SERVER_LEFT, VOTED_PLAYER_1, VOTED_PLAYER_2, VOTE_LEAVE};
+use rules::{MatchSettings, MAX_BALL_SPEED, MAX_RACKET_SIZE, MIN_RACKET_SIZE};
+
+const MAX_SCORE: u8 = 99; // two digits on screen
+const MAX_SETS: u8 = 3; // best of five
+
+/// The protocol has no type byte, packets are told apart by their length.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PacketKind {
+    Whoami,
+    Input,
+    Settings,
+    Gamestate,
+    Unknown,
+}
+
+impl PacketKind {
+    pub fn of(data: &[u8]) -> PacketKind {
+        let len = data.len();
+        if len == GamestatePacket::len() {
+            PacketKind::Gamestate
+        } else if len == InputPacket::len() {
+            PacketKind::Input
+        } else if len == WhoamiPacket::len() {
+            PacketKind::Whoami
+        } else if len == MatchSettings::len() {
+            PacketKind::Settings
+        } else {
+            PacketKind::Unknown
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            PacketKind::Whoami => "whoami",
+            PacketKind::Input => "input",
+            PacketKind::Settings => "settings",
+            PacketKind::Gamestate => "gamestate",
+            PacketKind::Unknown => "unknown",
+        }
+    }
+}
+
+/// One decoded field and where it sits in the payload.
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub offset: usize,
+    pub len: usize,
+    pub name: &'static str,
+    pub value: i32,
+    pub problem: Option<&'static str>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Dissection {
+    pub kind: PacketKind,
+    pub fields: Vec<Field>,
+}
+
+impl Dissection {
+    /// Unknown length or any field with an impossible value.
+    pub fn is_malformed(&self) -> bool {
+        self.kind == PacketKind::Unknown || self.fields.iter().any(|f| f.problem.is_some())
+    }
+}
+
+pub fn dissect(data: &[u8]) -> Dissection {
+    let kind = PacketKind::of(data);
+    let mut fields = Vec::new();
+    match kind {
+        PacketKind::Gamestate => dissect_gamestate(&GamestatePacket::deserialize(data), &mut fields),
+        PacketKind::Input => {
+            let input = InputPacket::deserialize(data);
+            let problem = check(input.goal_y >= 0 && input.goal_y < HEIGHT as i16, "off screen");
+            push(&mut fields, 0, 2, "goal_y", input.goal_y as i32, problem);
+            push(&mut fields, 2, 1, "pause_requests", input.pause_requests as i32, None);
+            push(&mut fields, 3, 1, "vote", input.vote as i32,
+                 check(input.vote <= VOTE_LEAVE, "unknown vote"));
+        }
+        PacketKind::Settings => {
+            let settings = MatchSettings::deserialize(data);
+            push(&mut fields, 0, 1, "score_limit", settings.score_limit as i32,
+                 check(settings.score_limit > 0, "nobody could win"));
+            push(&mut fields, 1, 1, "win_by_two", data[1] as i32, check(data[1] <= 1, "not a bool"));
+            push(&mut fields, 2, 2, "time_limit", settings.time_limit as i32, None);
+            push(&mut fields, 4, 1, "best_of", settings.best_of as i32,
+                 check(settings.best_of % 2 == 1, "even number of sets"));
+            push(&mut fields, 5, 1, "racket_size", settings.racket_size as i32,
+                 check(settings.racket_size >= MIN_RACKET_SIZE && settings.racket_size <= MAX_RACKET_SIZE,
+                       "racket size out of range"));
+            push(&mut fields, 6, 1, "ball_speed", settings.ball_speed as i32,
+                 check(settings.ball_speed > 0 && settings.ball_speed <= MAX_BALL_SPEED,
+                       "ball speed out of range"));
+        }
+        PacketKind::Whoami => {
+            let problem = check(data[0] == 0 || data[0] == 255, "neither client (0) nor server (255)");
+            push(&mut fields, 0, 1, "is_server", data[0] as i32, problem);
+        }
+        PacketKind::Unknown => {}
+    }
+    Dissection {
+        kind: kind,
+        fields: fields,
+    }
+}
+
+fn dissect_gamestate(gamestate: &GamestatePacket, fields: &mut Vec<Field>) {
+    let racket_x = [RACKET_WIDTH as i16, WIDTH as i16 - RACKET_WIDTH as i16];
+    let names = [["rackets[0].x", "rackets[0].y"], ["rackets[1].x", "rackets[1].y"]];
+    for i in 0..2 {
+        let racket = gamestate.rackets[i];
+        let offset = i * RacketPacket::len();
+        push(fields, offset, 2, names[i][0], racket.x as i32,
+             check(racket.x == racket_x[i], "not at the goal line"));
+        push(fields, offset + 2, 2, names[i][1], racket.y as i32,
+             check(racket.y >= 0 && racket.y < HEIGHT as i16, "off screen"));
+    }
+
+    let ball = gamestate.ball;
+    let offset = 2 * RacketPacket::len();
+    push(fields, offset, 2, "ball.x", ball.x as i32,
+         check(ball.x >= 0 && ball.x < WIDTH as i16, "off screen"));
+    push(fields, offset + 2, 2, "ball.y", ball.y as i32,
+         check(ball.y >= 0 && ball.y < HEIGHT as i16, "off screen"));
+    push(fields, offset + 4, 2, "ball.x_vel", ball.x_vel as i32,
+         check(ball.x_vel >= -BALL_MAX_SPEED && ball.x_vel <= BALL_MAX_SPEED, "faster than BALL_MAX_SPEED"));
+    push(fields, offset + 6, 2, "ball.y_vel", ball.y_vel as i32,
+         check(ball.y_vel >= -BALL_MAX_SPEED && ball.y_vel <= BALL_MAX_SPEED, "faster than BALL_MAX_SPEED"));
+
+    let offset = offset + BallPacket::len();
+    push(fields, offset, 1, "score[0]", gamestate.score[0] as i32,
+         check(gamestate.score[0] <= MAX_SCORE, "above the score limit"));
+    push(fields, offset + 1, 1, "score[1]", gamestate.score[1] as i32,
+         check(gamestate.score[1] <= MAX_SCORE, "above the score limit"));
+    let state = gamestate.state;
+    let known = state == STATE_RUNNING || state == STATE_PAUSED || state == STATE_WON_PLAYER_1
+        || state == STATE_WON_PLAYER_2;
+    push(fields, offset + 2, 1, "state", state as i32, check(known, "unknown state"));
+    push(fields, offset + 3, 1, "sets[0]", gamestate.sets[0] as i32,
+         check(gamestate.sets[0] <= MAX_SETS, "more sets than best of five"));
+    push(fields, offset + 4, 1, "sets[1]", gamestate.sets[1] as i32,
+         check(gamestate.sets[1] <= MAX_SETS, "more sets than best of five"));
+    push(fields, offset + 5, 2, "time_left", gamestate.time_left as i32, None);
+    let known_votes = VOTED_PLAYER_1 | VOTED_PLAYER_2 | SERVER_LEFT;
+    push(fields, offset + 7, 1, "votes", gamestate.votes as i32,
+         check(gamestate.votes & !known_votes == 0, "unknown vote bits"));
+}
+
+fn push(fields: &mut Vec<Field>, offset: usize, len: usize, name: &'static str, value: i32,
+        problem: Option<&'static str>) {
+    fields.push(Field {
+        offset: offset,
+        len: len,
+        name: name,
+        value: value,
+        problem: problem,
+    });
+}
+
+fn check(ok: bool, problem: &'static str) -> Option<&'static str> {
+    if ok {
+        None
+    } else {
+        Some(problem)
+    }
+}
+
+/// Annotated dump, one line per field with its raw bytes:
+///
+/// ```text
+/// gamestate (24 bytes)
+///   0000  00 0a   rackets[0].x = 10
+/// ```
+pub fn format(data: &[u8], dissection: &Dissection) -> String {
+    let mut out = String::new();
+    let _ = write!(out, "{} ({} bytes)", dissection.kind.name(), data.len());
+    if dissection.kind == PacketKind::Unknown {
+        let _ = write!(out, "  !! malformed: no packet has this length\n  ");
+        for byte in data {
+            let _ = write!(out, " {:02x}", byte);
+        }
+    }
+    for field in &dissection.fields {
+        let _ = write!(out, "\n  {:04x} ", field.offset);
+        for byte in &data[field.offset..field.offset + field.len] {
+            let _ = write!(out, " {:02x}", byte);
+        }
+        for _ in field.len..2 {
+            out.push_str("   ");
+        }
+        let _ = write!(out, "   {} = {}", field.name, field.value);
+        if let Some(problem) = field.problem {
+            let _ = write!(out, "  !! {}", problem);
+        }
+    }
+    out
+}
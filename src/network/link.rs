@@ -0,0 +1,22 @@
+use alloc::Vec;
+
+/// Errors a link can report when polling for a datagram.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LinkError {
+    /// No buffer space left, try again next frame.
+    Exhausted,
+    /// Received something that is not meant for our socket.
+    Unrecognized,
+    /// Any other error of the underlying transport.
+    Transport(&'static str),
+}
+
+/// The send/receive surface the pong protocol needs from a transport.
+///
+/// `Network` implements this on top of smoltcp, `sim::SimEndpoint` in memory.
+pub trait Link {
+    /// Returns the next received datagram, or `None` if nothing arrived.
+    fn get_udp_packet(&mut self) -> Result<Option<Vec<u8>>, LinkError>;
+    /// Sends a datagram to the partner.
+    fn send_udp_packet(&mut self, data: &[u8]);
+}
@@ -5,14 +5,23 @@ use smoltcp::socket::{UdpPacketMetadata, UdpSocket, UdpSocketBuffer};
 use smoltcp::time::Instant;
 use smoltcp::wire::{EthernetAddress, IpAddress, IpEndpoint, Ipv4Address};
 
+pub mod capture;
+pub mod dissect;
+pub mod link;
 pub mod packets;
+pub mod peer;
+pub mod sim;
+pub use self::link::{Link, LinkError};
 pub use self::packets::BallPacket;
 pub use self::packets::GamestatePacket;
 pub use self::packets::InputPacket;
 pub use self::packets::RacketPacket;
-use self::packets::Serializable;
 pub use self::packets::WhoamiPacket;
+pub use self::peer::{Client, EthClient, EthServer, Server};
+pub use self::peer::{handle_network_client, handle_network_server};
 
+use self::capture::{Direction, Recorder};
+use alloc::String;
 use alloc::Vec;
 use board;
 use embedded;
@@ -21,14 +30,83 @@ use system_clock;
 
 const PORT: u16 = 2018;
 
+/// What `init` consumes and `Network::close` hands back.
+pub type EthernetPeripherals = (
+    &'static mut board::ethernet_dma::EthernetDma,
+    &'static mut board::ethernet_mac::EthernetMac,
+);
+
+/// A failed `init`, with the peripherals back for another attempt.
+pub struct InitError {
+    pub error: ethernet::Error,
+    pub peripherals: EthernetPeripherals,
+}
+
+impl InitError {
+    /// A line for the screen.
+    pub fn reason(&self) -> String {
+        match self.error {
+            // the PHY times out waiting for a link or its negotiation
+            ethernet::Error::PhyError(_) => String::from("No link, is the cable plugged in?"),
+            ethernet::Error::Exhausted => String::from("Out of network buffers"),
+            _ => format!("Network error: {:?}", self.error),
+        }
+    }
+}
+
 pub struct Network {
     ethernet_interface: EthernetInterface<'static, 'static, ethernet::EthernetDevice>,
     sockets: SocketSet<'static, 'static, 'static>,
     partner_ip_addr: Ipv4Address,
+    recorder: Option<Recorder>,
+    // the interface holds the references until `close`, these are only
+    // turned back into references once it is gone
+    ethernet_dma: *mut board::ethernet_dma::EthernetDma,
+    ethernet_mac: *mut board::ethernet_mac::EthernetMac,
 }
 
 impl Network {
+    /// Stops the interface and hands the peripherals back, so the network
+    /// can be initialised again.
+    pub fn close(self, rcc: &mut board::rcc::Rcc) -> EthernetPeripherals {
+        let Network {
+            ethernet_interface,
+            sockets,
+            ethernet_dma,
+            ethernet_mac,
+            ..
+        } = self;
+        // stop the dma before its descriptors and buffers are freed
+        reset_peripherals(rcc);
+        drop(sockets);
+        drop(ethernet_interface);
+        // SAFETY: the pointers come from the `&'static mut` references
+        // `init` moved into the device, which the interface owned. It was
+        // dropped above, so no other reference to the peripherals is left.
+        unsafe { (&mut *ethernet_dma, &mut *ethernet_mac) }
+    }
+
+    /// Starts logging the last `capacity` datagrams, see `dump_capture`.
+    pub fn enable_capture(&mut self, capacity: usize) {
+        self.recorder = Some(Recorder::new(capacity));
+    }
+
+    /// Writes the captured datagrams to semihosting, `pong-host capture` reads them.
+    pub fn dump_capture(&self) {
+        if let Some(ref recorder) = self.recorder {
+            recorder.dump(|line| hprintln!("{}", line));
+        }
+    }
+
     pub fn get_udp_packet(&mut self) -> Result<Option<Vec<u8>>, smoltcp::Error> {
+        let result = self.receive_udp_packet();
+        if let (&Ok(Some(ref data)), Some(ref mut recorder)) = (&result, self.recorder.as_mut()) {
+            recorder.record(system_clock::ticks(), Direction::Received, data);
+        }
+        result
+    }
+
+    fn receive_udp_packet(&mut self) -> Result<Option<Vec<u8>>, smoltcp::Error> {
         match self.ethernet_interface.poll(
             &mut self.sockets,
             Instant::from_millis(system_clock::ticks() as i64),
@@ -74,19 +152,49 @@ impl Network {
 
     pub fn send_udp_packet(&mut self, data: &[u8]) {
         let endpoint = IpEndpoint::new(IpAddress::Ipv4(self.partner_ip_addr), PORT);
+        let mut queued = false;
         for mut socket in self.sockets.iter_mut() {
-            Network::push_udp_packet(&mut socket, endpoint, data);
+            queued |= Network::push_udp_packet(&mut socket, endpoint, data);
+        }
+        if let (true, Some(ref mut recorder)) = (queued, self.recorder.as_mut()) {
+            recorder.record(system_clock::ticks(), Direction::Sent, data);
         }
     }
 
-    fn push_udp_packet(socket: &mut Socket, endpoint: IpEndpoint, data: &[u8]) {
+    fn push_udp_packet(socket: &mut Socket, endpoint: IpEndpoint, data: &[u8]) -> bool {
         match socket {
             &mut Socket::Udp(ref mut socket) => {
                 if socket.can_send() {
-                    let _result = socket.send_slice(data, endpoint); // TODO: Error handling
+                    // TODO: Error handling
+                    return socket.send_slice(data, endpoint).is_ok();
                 }
+                false
             }
-            _ => {}
+            _ => false,
+        }
+    }
+}
+
+impl Link for Network {
+    fn get_udp_packet(&mut self) -> Result<Option<Vec<u8>>, LinkError> {
+        Network::get_udp_packet(self).map_err(LinkError::from)
+    }
+
+    fn send_udp_packet(&mut self, data: &[u8]) {
+        Network::send_udp_packet(self, data)
+    }
+}
+
+impl From<smoltcp::Error> for LinkError {
+    fn from(error: smoltcp::Error) -> LinkError {
+        match error {
+            smoltcp::Error::Exhausted => LinkError::Exhausted,
+            smoltcp::Error::Unrecognized => LinkError::Unrecognized,
+            smoltcp::Error::Truncated => LinkError::Transport("truncated"),
+            smoltcp::Error::Checksum => LinkError::Transport("checksum"),
+            smoltcp::Error::Malformed => LinkError::Transport("malformed"),
+            smoltcp::Error::Dropped => LinkError::Transport("dropped"),
+            _ => LinkError::Transport("smoltcp"),
         }
     }
 }
@@ -94,13 +202,20 @@ impl Network {
 pub fn init(
     rcc: &mut board::rcc::Rcc,
     syscfg: &mut board::syscfg::Syscfg,
-    ethernet_mac: &'static mut board::ethernet_mac::EthernetMac,
-    ethernet_dma: &'static mut board::ethernet_dma::EthernetDma,
+    peripherals: EthernetPeripherals,
     gpio: &mut embedded::interfaces::gpio::Gpio,
     ethernet_addr: EthernetAddress,
     ip_addr: Ipv4Address,
     partner_ip_addr: Ipv4Address,
-) -> Result<Network, ethernet::Error> {
+) -> Result<Network, InitError> {
+    let (ethernet_dma, ethernet_mac) = peripherals;
+    let dma_ptr = ethernet_dma as *mut board::ethernet_dma::EthernetDma;
+    let mac_ptr = ethernet_mac as *mut board::ethernet_mac::EthernetMac;
+    // SAFETY: the references were turned into the pointers and are not used
+    // again. The device gets references made from the pointers, which are
+    // only turned into references again once the device is gone.
+    let (ethernet_dma, ethernet_mac) = unsafe { (&mut *dma_ptr, &mut *mac_ptr) };
+
     // Ethernet init
     let ethernet_interface = ethernet::EthernetDevice::new(
         Default::default(),
@@ -113,9 +228,15 @@ pub fn init(
         ethernet_addr,
     ).map(|device| device.into_interface(ip_addr));
     if let Err(e) = ethernet_interface {
-        // hprintln!("ethernet init failed: {:?}", e);
-        // return None;
-        return Err(e);
+        // the failed device dropped its borrows, start over from reset
+        reset_peripherals(rcc);
+        // SAFETY: `EthernetDevice::new` returned an error, so it dropped the
+        // references it was given and these pointers are the only way left
+        // to the peripherals.
+        return Err(InitError {
+            error: e,
+            peripherals: unsafe { (&mut *dma_ptr, &mut *mac_ptr) },
+        });
     }
 
     let mut sockets = SocketSet::new(Vec::new());
@@ -131,147 +252,13 @@ pub fn init(
         ethernet_interface: ethernet_interface.unwrap(),
         sockets: sockets,
         partner_ip_addr: partner_ip_addr,
+        recorder: None,
+        ethernet_dma: dma_ptr,
+        ethernet_mac: mac_ptr,
     })
 }
 
-pub trait Client {
-    fn send_input(&mut self, network: &mut Network, input: &InputPacket);
-    fn receive_gamestate(&mut self, network: &mut Network) -> GamestatePacket;
-    fn is_server_connected(&mut self, network: &mut Network) -> bool;
-    fn send_whoami(&mut self, network: &mut Network);
-}
-
-pub trait Server {
-    fn receive_input(&mut self, network: &mut Network) -> InputPacket;
-    fn send_gamestate(&mut self, network: &mut Network, gamestate: &GamestatePacket);
-    fn is_client_connected(&mut self, network: &mut Network) -> bool;
-    fn send_whoami(&mut self, network: &mut Network);
-}
-
-pub struct EthServer {
-    player_input: InputPacket,
-}
-
-impl Server for EthServer {
-    fn receive_input(&mut self, network: &mut Network) -> InputPacket {
-        let result = network.get_udp_packet();
-        match result {
-            Ok(value) => match value {
-                Some(data) => {
-                    if data.len() == InputPacket::len() {
-                        self.player_input = InputPacket::deserialize(&data);
-                    }
-                    // hprintln!("input: {:?} {:?}", data, self.player_input);
-                }
-                None => {}
-            },
-            Err(smoltcp::Error::Exhausted) => {}
-            Err(smoltcp::Error::Unrecognized) => {}
-            Err(e) => {
-                hprintln!("Server error: {:?}", e);
-            }
-        }
-        self.player_input
-    }
-    fn send_gamestate(&mut self, network: &mut Network, gamestate: &GamestatePacket) {
-        network.send_udp_packet(&gamestate.serialize());
-    }
-
-    fn is_client_connected(&mut self, network: &mut Network) -> bool {
-        let result = network.get_udp_packet();
-        match result {
-            Ok(value) => match value {
-                Some(data) => {
-                    if data.len() == WhoamiPacket::len() {
-                        let whoami = WhoamiPacket::deserialize(&data);
-                        if whoami.is_server == false {
-                            return true;
-                        }
-                    }
-                }
-                None => {}
-            },
-            // Err(smoltcp::Error::Exhausted) => {hprint!("e")},
-            // Err(smoltcp::Error::Unrecognized) => {},
-            Err(e) => {
-                hprintln!("Server init error: {:?}", e);
-            }
-        }
-        false
-    }
-
-    fn send_whoami(&mut self, network: &mut Network) {
-        network.send_udp_packet(&WhoamiPacket { is_server: true }.serialize())
-    }
-}
-
-impl EthServer {
-    pub fn new() -> EthServer {
-        EthServer {
-            player_input: InputPacket::new(),
-        }
-    }
-}
-
-pub struct EthClient {
-    gamestate: GamestatePacket,
-}
-
-impl Client for EthClient {
-    fn send_input(&mut self, network: &mut Network, input: &InputPacket) {
-        network.send_udp_packet(&input.serialize());
-    }
-    fn receive_gamestate(&mut self, network: &mut Network) -> GamestatePacket {
-        let result = network.get_udp_packet();
-        match result {
-            Ok(value) => match value {
-                Some(data) => {
-                    if data.len() == GamestatePacket::len() {
-                        self.gamestate = GamestatePacket::deserialize(&data);
-                    }
-                    // hprintln!("state: {:?} {:?}", data, self.gamestate);
-                }
-                None => {}
-            },
-            Err(smoltcp::Error::Exhausted) => {}
-            Err(smoltcp::Error::Unrecognized) => {}
-            Err(e) => {
-                hprintln!("Client error: {:?}", e);
-            }
-        }
-        self.gamestate
-    }
-
-    fn is_server_connected(&mut self, network: &mut Network) -> bool {
-        let result = network.get_udp_packet();
-        match result {
-            Ok(value) => match value {
-                Some(data) => {
-                    if data.len() == WhoamiPacket::len() {}
-                    if data.len() == GamestatePacket::len() {
-                        return true;
-                    }
-                }
-                None => {}
-            },
-            // Err(smoltcp::Error::Exhausted) => {},
-            // Err(smoltcp::Error::Unrecognized) => {},
-            Err(e) => {
-                hprintln!("Client init error: {:?}", e);
-            }
-        }
-        false
-    }
-
-    fn send_whoami(&mut self, network: &mut Network) {
-        network.send_udp_packet(&WhoamiPacket { is_server: false }.serialize())
-    }
-}
-
-impl EthClient {
-    pub fn new() -> EthClient {
-        EthClient {
-            gamestate: GamestatePacket::new(0),
-        }
-    }
+fn reset_peripherals(rcc: &mut board::rcc::Rcc) {
+    rcc.ahb1rstr.update(|r| r.set_ethmacrst(true));
+    rcc.ahb1rstr.update(|r| r.set_ethmacrst(false));
 }
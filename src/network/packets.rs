@@ -2,37 +2,53 @@ use alloc::Vec;
 use lcd::{HEIGHT, WIDTH};
 use racket::RACKET_WIDTH;
 
-const BALL_MAX_SPEED: i16 = 20;
+pub const BALL_MAX_SPEED: i16 = 20;
 const BALL_MIN_SPEED: i16 = 10;
 
 pub const STATE_RUNNING: u8 = 0;
+pub const STATE_PAUSED: u8 = 1;
 pub const STATE_WON_PLAYER_1: u8 = 100;
 pub const STATE_WON_PLAYER_2: u8 = 101;
 
-#[derive(Debug, Copy, Clone)]
+// what a player wants once the match is over, sent with every input
+pub const VOTE_NONE: u8 = 0;
+pub const VOTE_REMATCH: u8 = 1;
+pub const VOTE_LEAVE: u8 = 2;
+
+// bits of `GamestatePacket::votes`, kept by the server
+pub const VOTED_PLAYER_1: u8 = 1 << 0;
+pub const VOTED_PLAYER_2: u8 = 1 << 1;
+pub const SERVER_LEFT: u8 = 1 << 7;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct GamestatePacket {
     pub rackets: [RacketPacket; 2],
     pub ball: BallPacket,
     pub score: [u8; 2],
     pub state: u8,
+    pub sets: [u8; 2],
+    pub time_left: u16, // seconds left in a timed set
+    pub votes: u8,      // rematch votes after the match
 }
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct RacketPacket {
     pub x: i16, // center_x
     pub y: i16, // center_y
 }
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct BallPacket {
     pub x: i16, // center_x
     pub y: i16, // center_y
     pub x_vel: i16,
     pub y_vel: i16,
 }
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct InputPacket {
     pub goal_y: i16,
+    pub pause_requests: u8, // counts pause taps, the server toggles when it changes
+    pub vote: u8,
 }
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct WhoamiPacket {
     pub is_server: bool,
 }
@@ -59,6 +75,9 @@ impl GamestatePacket {
             },
             score: [0, 0],
             state: 0,
+            sets: [0, 0],
+            time_left: 0,
+            votes: 0,
         }
     }
 }
@@ -75,7 +94,11 @@ impl BallPacket {
 
 impl InputPacket {
     pub fn new() -> InputPacket {
-        InputPacket { goal_y: 272 / 2 }
+        InputPacket {
+            goal_y: 272 / 2,
+            pause_requests: 0,
+            vote: VOTE_NONE,
+        }
     }
 }
 
@@ -102,6 +125,11 @@ impl Serializable for GamestatePacket {
         result.push(self.score[0]);
         result.push(self.score[1]);
         result.push(self.state);
+        result.push(self.sets[0]);
+        result.push(self.sets[1]);
+        result.push((self.time_left >> 8) as u8);
+        result.push(self.time_left as u8);
+        result.push(self.votes);
         result
     }
 
@@ -116,17 +144,23 @@ impl Serializable for GamestatePacket {
         let score_player1 = input[index];
         let score_player2 = input[index + 1];
         let state = input[index + 2];
+        let sets = [input[index + 3], input[index + 4]];
+        let time_left = u16::from(input[index + 5]) << 8 | u16::from(input[index + 6]);
+        let votes = input[index + 7];
 
         GamestatePacket {
             rackets: [racket1, racket2],
             ball,
             score: [score_player1, score_player2],
             state: state,
+            sets: sets,
+            time_left: time_left,
+            votes: votes,
         }
     }
 
     fn len() -> usize {
-        2 * RacketPacket::len() + BallPacket::len() + 2 + 1
+        2 * RacketPacket::len() + BallPacket::len() + 2 + 1 + 2 + 2 + 1
     }
 }
 
@@ -183,17 +217,21 @@ impl Serializable for InputPacket {
         let mut result = Vec::new();
         result.push(upper_byte(self.goal_y));
         result.push(lower_byte(self.goal_y));
+        result.push(self.pause_requests);
+        result.push(self.vote);
         result
     }
 
     fn deserialize(input: &[u8]) -> InputPacket {
         InputPacket {
             goal_y: merge(input[0], input[1]),
+            pause_requests: input[2],
+            vote: input[3],
         }
     }
 
     fn len() -> usize {
-        2
+        4
     }
 }
 
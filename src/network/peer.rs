@@ -0,0 +1,209 @@
+use super::link::{Link, LinkError};
+use super::packets::Serializable;
+use super::{GamestatePacket, InputPacket, WhoamiPacket};
+use physics;
+use physics::PhysicsCache;
+use rules::MatchSettings;
+
+const SETTINGS_INTERVAL: usize = 30; // frames between settings announcements
+
+pub trait Client {
+    fn send_input(&mut self, network: &mut Link, input: &InputPacket);
+    fn receive_gamestate(&mut self, network: &mut Link) -> GamestatePacket;
+    fn is_server_connected(&mut self, network: &mut Link) -> bool;
+    fn send_whoami(&mut self, network: &mut Link);
+    /// The settings the server announced, once one arrived.
+    fn match_settings(&self) -> Option<MatchSettings>;
+}
+
+pub trait Server {
+    fn receive_input(&mut self, network: &mut Link) -> InputPacket;
+    fn send_gamestate(&mut self, network: &mut Link, gamestate: &GamestatePacket);
+    fn is_client_connected(&mut self, network: &mut Link) -> bool;
+    fn send_whoami(&mut self, network: &mut Link);
+    /// Sends the match settings every few calls, so the client gets them
+    /// even if some are lost.
+    fn announce_settings(&mut self, network: &mut Link, settings: &MatchSettings);
+}
+
+pub struct EthServer {
+    player_input: InputPacket,
+    frames_since_settings: usize,
+}
+
+impl Server for EthServer {
+    fn receive_input(&mut self, network: &mut Link) -> InputPacket {
+        let result = network.get_udp_packet();
+        match result {
+            Ok(value) => match value {
+                Some(data) => {
+                    if data.len() == InputPacket::len() {
+                        self.player_input = InputPacket::deserialize(&data);
+                    }
+                    // hprintln!("{}", dissect::format(&data, &dissect::dissect(&data)));
+                }
+                None => {}
+            },
+            Err(LinkError::Exhausted) => {}
+            Err(LinkError::Unrecognized) => {}
+            Err(e) => {
+                hprintln!("Server error: {:?}", e);
+            }
+        }
+        self.player_input
+    }
+    fn send_gamestate(&mut self, network: &mut Link, gamestate: &GamestatePacket) {
+        network.send_udp_packet(&gamestate.serialize());
+    }
+
+    fn is_client_connected(&mut self, network: &mut Link) -> bool {
+        let result = network.get_udp_packet();
+        match result {
+            Ok(value) => match value {
+                Some(data) => {
+                    if data.len() == WhoamiPacket::len() {
+                        let whoami = WhoamiPacket::deserialize(&data);
+                        if whoami.is_server == false {
+                            return true;
+                        }
+                    }
+                }
+                None => {}
+            },
+            // Err(LinkError::Exhausted) => {hprint!("e")},
+            // Err(LinkError::Unrecognized) => {},
+            Err(e) => {
+                hprintln!("Server init error: {:?}", e);
+            }
+        }
+        false
+    }
+
+    fn send_whoami(&mut self, network: &mut Link) {
+        network.send_udp_packet(&WhoamiPacket { is_server: true }.serialize())
+    }
+
+    fn announce_settings(&mut self, network: &mut Link, settings: &MatchSettings) {
+        if self.frames_since_settings == 0 {
+            network.send_udp_packet(&settings.serialize());
+        }
+        self.frames_since_settings = (self.frames_since_settings + 1) % SETTINGS_INTERVAL;
+    }
+}
+
+impl EthServer {
+    pub fn new() -> EthServer {
+        EthServer {
+            player_input: InputPacket::new(),
+            frames_since_settings: 0,
+        }
+    }
+}
+
+pub struct EthClient {
+    gamestate: GamestatePacket,
+    settings: Option<MatchSettings>,
+}
+
+impl Client for EthClient {
+    fn send_input(&mut self, network: &mut Link, input: &InputPacket) {
+        network.send_udp_packet(&input.serialize());
+    }
+    fn receive_gamestate(&mut self, network: &mut Link) -> GamestatePacket {
+        let result = network.get_udp_packet();
+        match result {
+            Ok(value) => match value {
+                Some(data) => {
+                    if data.len() == GamestatePacket::len() {
+                        self.gamestate = GamestatePacket::deserialize(&data);
+                    } else if data.len() == MatchSettings::len() {
+                        self.receive_settings(&data);
+                    }
+                    // hprintln!("{}", dissect::format(&data, &dissect::dissect(&data)));
+                }
+                None => {}
+            },
+            Err(LinkError::Exhausted) => {}
+            Err(LinkError::Unrecognized) => {}
+            Err(e) => {
+                hprintln!("Client error: {:?}", e);
+            }
+        }
+        self.gamestate
+    }
+
+    fn is_server_connected(&mut self, network: &mut Link) -> bool {
+        let result = network.get_udp_packet();
+        match result {
+            Ok(value) => match value {
+                Some(data) => {
+                    if data.len() == WhoamiPacket::len() {}
+                    if data.len() == MatchSettings::len() {
+                        self.receive_settings(&data);
+                    }
+                    // the game starts once the rules are known
+                    if data.len() == GamestatePacket::len() && self.settings.is_some() {
+                        return true;
+                    }
+                }
+                None => {}
+            },
+            // Err(LinkError::Exhausted) => {},
+            // Err(LinkError::Unrecognized) => {},
+            Err(e) => {
+                hprintln!("Client init error: {:?}", e);
+            }
+        }
+        false
+    }
+
+    fn send_whoami(&mut self, network: &mut Link) {
+        network.send_udp_packet(&WhoamiPacket { is_server: false }.serialize())
+    }
+
+    fn match_settings(&self) -> Option<MatchSettings> {
+        self.settings
+    }
+}
+
+impl EthClient {
+    pub fn new() -> EthClient {
+        EthClient {
+            gamestate: GamestatePacket::new(0),
+            settings: None,
+        }
+    }
+
+    fn receive_settings(&mut self, data: &[u8]) {
+        let settings = MatchSettings::deserialize(data);
+        if settings.is_valid() {
+            self.settings = Some(settings);
+        }
+    }
+}
+
+pub fn handle_network_server(
+    server: &mut Server,
+    network: &mut Link,
+    local_gamestate: &mut GamestatePacket,
+    local_input_1: &InputPacket,
+    total_time: usize,
+    settings: &MatchSettings,
+    physics_cache: &mut PhysicsCache,
+) -> [InputPacket; 2] {
+    let inputs = [*local_input_1, server.receive_input(network)];
+    physics::calculate_physics(local_gamestate, inputs, total_time, settings, physics_cache);
+    server.send_gamestate(network, local_gamestate);
+    server.announce_settings(network, settings);
+    inputs
+}
+
+pub fn handle_network_client(
+    client: &mut Client,
+    network: &mut Link,
+    local_gamestate: &mut GamestatePacket,
+    local_input_1: &InputPacket,
+) {
+    *local_gamestate = client.receive_gamestate(network);
+    client.send_input(network, local_input_1);
+}
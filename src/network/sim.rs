@@ -0,0 +1,259 @@
+use alloc::Vec;
+use super::link::{Link, LinkError};
+
+/// Impairments applied to every datagram that crosses a `SimWire`.
+#[derive(Debug, Copy, Clone)]
+pub struct SimConfig {
+    pub latency_ms: usize,
+    pub jitter_ms: usize,
+    pub loss_percent: u8,
+    pub duplicate_percent: u8,
+    pub reorder_percent: u8,
+    pub capacity: usize, // datagrams each receive buffer can hold, like the UDP socket
+}
+
+impl SimConfig {
+    pub fn perfect() -> SimConfig {
+        SimConfig {
+            latency_ms: 0,
+            jitter_ms: 0,
+            loss_percent: 0,
+            duplicate_percent: 0,
+            reorder_percent: 0,
+            capacity: 3,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Side {
+    Server,
+    Client,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SimStats {
+    pub sent: usize,
+    pub delivered: usize,
+    pub lost: usize,
+    pub duplicated: usize,
+    pub reordered: usize,
+    pub overflowed: usize, // dropped because the receive buffer was full
+}
+
+struct InFlight {
+    deliver_at: usize,
+    data: Vec<u8>,
+}
+
+struct Direction {
+    in_flight: Vec<InFlight>,
+    held_back: Option<Vec<u8>>, // released behind the next datagram to reorder them
+    received: Vec<Vec<u8>>,
+    last_deliver_at: usize,
+}
+
+impl Direction {
+    fn new() -> Direction {
+        Direction {
+            in_flight: Vec::new(),
+            held_back: None,
+            received: Vec::new(),
+            last_deliver_at: 0,
+        }
+    }
+}
+
+/// In-memory stand-in for the cable between two boards.
+///
+/// Time only advances through `set_time`, so a whole session is reproducible
+/// from the config and the seed.
+pub struct SimWire {
+    config: SimConfig,
+    now: usize,
+    rng: XorShift,
+    to_server: Direction,
+    to_client: Direction,
+    pub stats: SimStats,
+}
+
+impl SimWire {
+    pub fn new(config: SimConfig, seed: u32) -> SimWire {
+        SimWire {
+            config: config,
+            now: 0,
+            rng: XorShift::new(seed),
+            to_server: Direction::new(),
+            to_client: Direction::new(),
+            stats: SimStats {
+                sent: 0,
+                delivered: 0,
+                lost: 0,
+                duplicated: 0,
+                reordered: 0,
+                overflowed: 0,
+            },
+        }
+    }
+
+    pub fn set_config(&mut self, config: SimConfig) {
+        self.config = config;
+    }
+
+    /// Advances the clock and moves every datagram that arrived by now into
+    /// the receive buffer of its endpoint.
+    pub fn set_time(&mut self, now: usize) {
+        self.now = now;
+        let capacity = self.config.capacity;
+        let stats = &mut self.stats;
+        for direction in [&mut self.to_server, &mut self.to_client].iter_mut() {
+            while let Some(index) = next_arrival(&direction.in_flight, now) {
+                let datagram = direction.in_flight.remove(index);
+                if direction.received.len() < capacity {
+                    direction.received.push(datagram.data);
+                    stats.delivered += 1;
+                } else {
+                    stats.overflowed += 1;
+                }
+            }
+        }
+    }
+
+    /// Number of datagrams that were sent but not yet received.
+    pub fn in_flight(&self) -> usize {
+        let held_back = |direction: &Direction| direction.held_back.is_some() as usize;
+        self.to_server.in_flight.len() + self.to_client.in_flight.len()
+            + self.to_server.received.len() + self.to_client.received.len()
+            + held_back(&self.to_server) + held_back(&self.to_client)
+    }
+
+    pub fn endpoint<'a>(&'a mut self, side: Side) -> SimEndpoint<'a> {
+        SimEndpoint {
+            wire: self,
+            side: side,
+        }
+    }
+
+    fn send(&mut self, to: Side, data: &[u8]) {
+        self.stats.sent += 1;
+        let config = self.config;
+        if self.rng.chance(config.loss_percent) {
+            self.stats.lost += 1;
+            return;
+        }
+        let deliver_at = self.now + config.latency_ms + self.rng.below(config.jitter_ms + 1);
+        let reorder = self.rng.chance(config.reorder_percent);
+        let duplicate = self.rng.chance(config.duplicate_percent);
+        let duplicate_at = deliver_at + self.rng.below(config.jitter_ms + 1);
+
+        let direction = match to {
+            Side::Server => &mut self.to_server,
+            Side::Client => &mut self.to_client,
+        };
+        if reorder && direction.held_back.is_none() {
+            self.stats.reordered += 1;
+            direction.held_back = Some(Vec::from(data));
+            return;
+        }
+        // without explicit reordering the wire keeps the send order
+        let deliver_at = if deliver_at < direction.last_deliver_at {
+            direction.last_deliver_at
+        } else {
+            deliver_at
+        };
+        direction.last_deliver_at = deliver_at;
+        direction.in_flight.push(InFlight {
+            deliver_at: deliver_at,
+            data: Vec::from(data),
+        });
+        if let Some(held_back) = direction.held_back.take() {
+            direction.in_flight.push(InFlight {
+                deliver_at: deliver_at,
+                data: held_back,
+            });
+        }
+        if duplicate {
+            self.stats.duplicated += 1;
+            direction.in_flight.push(InFlight {
+                deliver_at: duplicate_at,
+                data: Vec::from(data),
+            });
+        }
+    }
+
+    fn receive(&mut self, at: Side) -> Option<Vec<u8>> {
+        let direction = match at {
+            Side::Server => &mut self.to_server,
+            Side::Client => &mut self.to_client,
+        };
+        if direction.received.is_empty() {
+            None
+        } else {
+            Some(direction.received.remove(0))
+        }
+    }
+}
+
+// index of the earliest datagram that is due at `now`, in send order on ties
+fn next_arrival(in_flight: &[InFlight], now: usize) -> Option<usize> {
+    let mut next: Option<usize> = None;
+    for (index, datagram) in in_flight.iter().enumerate() {
+        if datagram.deliver_at > now {
+            continue;
+        }
+        next = match next {
+            Some(best) if in_flight[best].deliver_at <= datagram.deliver_at => Some(best),
+            _ => Some(index),
+        };
+    }
+    next
+}
+
+/// One side of a `SimWire`, usable wherever a `Network` is.
+pub struct SimEndpoint<'a> {
+    wire: &'a mut SimWire,
+    side: Side,
+}
+
+impl<'a> Link for SimEndpoint<'a> {
+    fn get_udp_packet(&mut self) -> Result<Option<Vec<u8>>, LinkError> {
+        Ok(self.wire.receive(self.side))
+    }
+
+    fn send_udp_packet(&mut self, data: &[u8]) {
+        let to = match self.side {
+            Side::Server => Side::Client,
+            Side::Client => Side::Server,
+        };
+        self.wire.send(to, data);
+    }
+}
+
+/// xorshift32, good enough to roll dice for packet loss and the aim of the
+/// cpu player. The host tools draw their random checks from it too.
+pub struct XorShift {
+    state: u32,
+}
+
+impl XorShift {
+    pub fn new(seed: u32) -> XorShift {
+        XorShift { state: seed | 1 }
+    }
+
+    pub fn next(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    pub fn below(&mut self, limit: usize) -> usize {
+        self.next() as usize % limit
+    }
+
+    pub fn chance(&mut self, percent: u8) -> bool {
+        self.below(100) < percent as usize
+    }
+}
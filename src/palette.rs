@@ -0,0 +1,133 @@
+//! Named colors in the L8 framebuffer and the themes that give them RGB
+//! values through the LTDC CLUT.
+//!
+//! The high nibble of a pixel picks a color, the low one how far it is faded
+//! in from the background, so anti-aliased text keeps its color at the edges.
+//! The named colors are the last, full shade of their ramp.
+
+/// Shades in a ramp, the first one is the background.
+pub const SHADES: u8 = 16;
+
+pub const BACKGROUND: u8 = 0x00;
+pub const TEXT: u8 = 0x1F;
+pub const BALL: u8 = 0x2F;
+pub const RACKET: u8 = 0x3F;
+pub const CLOCK: u8 = 0x4F;
+pub const MARK_WON: u8 = 0x5F;
+pub const MARK_OPEN: u8 = 0x6F;
+pub const PANEL_BORDER: u8 = 0x7F;
+pub const BORDER: u8 = 0x8F;
+pub const FOCUS: u8 = 0x9F;
+pub const PRESSED: u8 = 0xAF;
+pub const PANEL: u8 = 0xBF; // the background, but opaque on the overlay
+const COLORS: usize = 12;
+
+pub struct Theme {
+    pub name: &'static str,
+    colors: [(u8, u8, u8); COLORS], // by ramp, the background first
+    tint: (u8, u8, u8),             // scales every color, white leaves them
+}
+
+const WHITE: (u8, u8, u8) = (255, 255, 255);
+
+// the grey levels the game was drawn in before it had colors
+const GREYS: [(u8, u8, u8); COLORS] = [
+    (0, 0, 0),
+    (255, 255, 255),
+    (255, 255, 255),
+    (150, 150, 150),
+    (128, 128, 128),
+    (255, 255, 255),
+    (64, 64, 64),
+    (255, 255, 255),
+    (96, 96, 96),
+    (192, 192, 192),
+    (255, 255, 255),
+    (0, 0, 0),
+];
+
+/// Selected in the settings. The second to fourth are the tints the settings
+/// had before the themes, so stored settings keep their colors.
+pub const THEMES: [Theme; 6] = [
+    Theme {
+        name: "Classic",
+        colors: GREYS,
+        tint: WHITE,
+    },
+    Theme {
+        name: "Green",
+        colors: GREYS,
+        tint: (64, 255, 96),
+    },
+    Theme {
+        name: "Amber",
+        colors: GREYS,
+        tint: (255, 176, 0),
+    },
+    Theme {
+        name: "Blue",
+        colors: GREYS,
+        tint: (96, 160, 255),
+    },
+    Theme {
+        name: "Neon",
+        colors: [
+            (8, 0, 24),
+            (0, 255, 255),
+            (255, 64, 200),
+            (0, 230, 255),
+            (160, 64, 255),
+            (255, 240, 0),
+            (80, 32, 120),
+            (255, 0, 200),
+            (64, 64, 160),
+            (0, 255, 160),
+            (255, 64, 200),
+            (8, 0, 24),
+        ],
+        tint: WHITE,
+    },
+    Theme {
+        name: "Contrast",
+        colors: [
+            (0, 0, 0),
+            (255, 255, 255),
+            (255, 255, 0),
+            (255, 255, 255),
+            (255, 255, 255),
+            (255, 255, 0),
+            (128, 128, 128),
+            (255, 255, 0),
+            (255, 255, 255),
+            (255, 255, 0),
+            (255, 255, 0),
+            (0, 0, 0),
+        ],
+        tint: WHITE,
+    },
+];
+
+/// The shade of `color` for a glyph coverage from 0 to 255.
+pub fn shade(color: u8, coverage: u8) -> u8 {
+    let full = color & (SHADES - 1);
+    color & !(SHADES - 1) | (u16::from(coverage) * u16::from(full) / 255) as u8
+}
+
+/// Pixel value `index` in one of `THEMES`, for `Lcd::clut`. Ramps without
+/// a named color show the background.
+pub fn clut_entry(theme: u8, index: u8) -> (u8, u8, u8) {
+    let theme = &THEMES[theme as usize];
+    let background = theme.colors[0];
+    let color = theme.colors.get((index / SHADES) as usize).cloned().unwrap_or(background);
+    let shade = u16::from(index % SHADES);
+    let last = u16::from(SHADES - 1);
+    let mix = |from: u8, to: u8, tint: u8| {
+        let channel = (u16::from(from) * (last - shade) + u16::from(to) * shade) / last;
+        (channel * u16::from(tint) / 255) as u8
+    };
+    (
+        mix(background.0, color.0, theme.tint.0),
+        mix(background.1, color.1, theme.tint.1),
+        mix(background.2, color.2, theme.tint.2),
+    )
+}
@@ -0,0 +1,90 @@
+use network::GamestatePacket;
+use network::packets::{STATE_PAUSED, STATE_RUNNING};
+
+/// Freezes a running match. The physics only ever see the game time, which
+/// stands still while paused or held for a replay, so timed sets and recorded
+/// matches are not affected by either.
+pub struct Pause {
+    paused_at: Option<usize>,
+    held_at: Option<usize>, // the clock alone, the state stays as it is
+    paused_total: usize,
+    requests_seen: u8, // the client's pause counter, on the server
+}
+
+impl Pause {
+    pub fn new() -> Pause {
+        Pause {
+            paused_at: None,
+            held_at: None,
+            paused_total: 0,
+            requests_seen: 0,
+        }
+    }
+
+    /// For a new match on the same connection, the client keeps counting its
+    /// requests. A new connection needs a new `Pause`.
+    pub fn reset(&mut self) {
+        self.paused_at = None;
+        self.held_at = None;
+        self.paused_total = 0;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    /// `total_time` without the time spent paused or held.
+    pub fn game_time(&self, total_time: usize) -> usize {
+        let since = |at: Option<usize>| match at {
+            Some(at) => total_time.wrapping_sub(at),
+            None => 0,
+        };
+        let stopped_now = since(self.paused_at).wrapping_add(since(self.held_at));
+        total_time.wrapping_sub(self.paused_total).wrapping_sub(stopped_now)
+    }
+
+    /// Stops the clock without pausing the match, for a replay of the last
+    /// point in the middle of it. A paused clock already stands still.
+    pub fn hold(&mut self, total_time: usize) {
+        if self.paused_at.is_none() && self.held_at.is_none() {
+            self.held_at = Some(total_time);
+        }
+    }
+
+    /// Lets the clock run again after `hold`.
+    pub fn release(&mut self, total_time: usize) {
+        if let Some(held_at) = self.held_at.take() {
+            self.paused_total = self.paused_total.wrapping_add(total_time.wrapping_sub(held_at));
+        }
+    }
+
+    /// Pauses or resumes, finished matches stay as they are.
+    pub fn toggle(&mut self, gamestate: &mut GamestatePacket, total_time: usize) {
+        match self.paused_at {
+            Some(paused_at) => {
+                self.paused_total = self.paused_total.wrapping_add(total_time.wrapping_sub(paused_at));
+                self.paused_at = None;
+                if gamestate.state == STATE_PAUSED {
+                    gamestate.state = STATE_RUNNING;
+                }
+            }
+            None => {
+                if gamestate.state == STATE_RUNNING {
+                    self.paused_at = Some(total_time);
+                    gamestate.state = STATE_PAUSED;
+                }
+            }
+        }
+    }
+
+    /// On the server: toggles for every pause tap the client counted since
+    /// the last input.
+    pub fn handle_requests(&mut self, requests: u8, gamestate: &mut GamestatePacket, total_time: usize) {
+        if requests != self.requests_seen {
+            if requests.wrapping_sub(self.requests_seen) % 2 == 1 {
+                self.toggle(gamestate, total_time);
+            }
+            self.requests_seen = requests;
+        }
+    }
+}
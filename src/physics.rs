@@ -5,8 +5,8 @@ use network;
 use network::packets::STATE_WON_PLAYER_1;
 use network::packets::STATE_WON_PLAYER_2;
 use network::packets::STATE_RUNNING;
-use racket::RACKET_HEIGHT;
 use racket::RACKET_WIDTH;
+use rules::MatchSettings;
 
 const RACKET_SPEED: i16 = 5;
 const INCREASE_VELOCITY_AFTER_RACKET_HITS: usize = 5;
@@ -15,10 +15,29 @@ pub fn calculate_physics(
     server_gamestate: &mut network::GamestatePacket,
     inputs: [network::InputPacket; 2],
     total_time: usize,
+    settings: &MatchSettings,
+    cache: &mut PhysicsCache,
+) {
+    if server_gamestate.state != STATE_RUNNING {
+        return;
+    }
+    if cache.serve_pending {
+        serve(&mut server_gamestate.ball, settings);
+        cache.serve_pending = false;
+    }
+    move_rackets_and_ball(server_gamestate, inputs, total_time, settings, cache);
+    update_sets(server_gamestate, total_time, settings, cache);
+}
+
+fn move_rackets_and_ball(
+    server_gamestate: &mut network::GamestatePacket,
+    inputs: [network::InputPacket; 2],
+    total_time: usize,
+    settings: &MatchSettings,
     cache: &mut PhysicsCache,
 ) {
     let racket_width = RACKET_WIDTH as i16;
-    let racket_height = RACKET_HEIGHT as i16;
+    let racket_height = settings.racket_size as i16;
     let ball_radius = BALL_RADIUS as i16;
     let height = HEIGHT as i16;
     let width = WIDTH as i16;
@@ -128,20 +147,66 @@ pub fn calculate_physics(
     // if ball touches goal increase score and reset ball position
     if in_goal {
         if x_pos_new <= ball_radius {
-            server_gamestate.score[1] += 1;
-            if server_gamestate.score[1] >= 9 {
-                server_gamestate.state = STATE_WON_PLAYER_2;
-            }
+            server_gamestate.score[1] = server_gamestate.score[1].saturating_add(1);
         }
         if x_pos_new >= width - 1 - ball_radius {
-            server_gamestate.score[0] += 1;
-            if server_gamestate.score[0] >= 9 {
-                server_gamestate.state = STATE_WON_PLAYER_1;
-            }
+            server_gamestate.score[0] = server_gamestate.score[0].saturating_add(1);
         }
         ball.reset(total_time);
         cache.racket_hits = 0;
+        cache.serve_pending = true;
+    }
+}
+
+// speeds a freshly reset ball up or down, it keeps its direction
+fn serve(ball: &mut network::BallPacket, settings: &MatchSettings) {
+    let speed = (abs(ball.x_vel) + settings.serve_boost()).max(1);
+    ball.x_vel = if ball.x_vel < 0 { -speed } else { speed };
+}
+
+// ends the set when the score or the clock decides it
+fn update_sets(
+    server_gamestate: &mut network::GamestatePacket,
+    total_time: usize,
+    settings: &MatchSettings,
+    cache: &mut PhysicsCache,
+) {
+    let time_up = update_clock(server_gamestate, total_time, settings, cache);
+    if let Some(winner) = settings.set_winner(server_gamestate.score, time_up) {
+        server_gamestate.sets[winner] += 1;
+        if server_gamestate.sets[winner] >= settings.sets_to_win() {
+            server_gamestate.state = if winner == 0 {
+                STATE_WON_PLAYER_1
+            } else {
+                STATE_WON_PLAYER_2
+            };
+        } else {
+            // next set
+            server_gamestate.score = [0, 0];
+            server_gamestate.ball.reset(total_time);
+            cache.serve_pending = true;
+            cache.racket_hits = 0;
+            cache.set_start = Some(total_time);
+            update_clock(server_gamestate, total_time, settings, cache);
+        }
+    }
+}
+
+// updates the time left in a timed set and tells whether it ran out
+fn update_clock(
+    server_gamestate: &mut network::GamestatePacket,
+    total_time: usize,
+    settings: &MatchSettings,
+    cache: &mut PhysicsCache,
+) -> bool {
+    if settings.time_limit == 0 {
+        return false;
     }
+    let set_start = *cache.set_start.get_or_insert(total_time);
+    let elapsed = total_time.wrapping_sub(set_start) / 1000;
+    let limit = settings.time_limit as usize;
+    server_gamestate.time_left = if elapsed < limit { (limit - elapsed) as u16 } else { 0 };
+    elapsed >= limit
 }
 fn overlap_test(rectangle1: Rectangle, rectangle2: Rectangle) -> bool {
     !(rectangle2.right < rectangle1.left || rectangle2.left > rectangle1.right
@@ -174,10 +239,16 @@ impl Rectangle {
 
 pub struct PhysicsCache {
     pub racket_hits: usize,
+    pub set_start: Option<usize>, // total_time the current set started at
+    pub serve_pending: bool,      // the ball was reset and has not been served yet
 }
 
 impl PhysicsCache {
     pub fn new() -> PhysicsCache {
-        PhysicsCache { racket_hits: 0 }
+        PhysicsCache {
+            racket_hits: 0,
+            set_start: None,
+            serve_pending: true,
+        }
     }
 }
@@ -0,0 +1,67 @@
+//! Axis aligned rectangles in screen pixels.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Rect {
+        Rect {
+            x: x,
+            y: y,
+            width: width,
+            height: height,
+        }
+    }
+
+    pub fn contains(&self, x: u16, y: u16) -> bool {
+        let (x, y) = (x as usize, y as usize);
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    /// Column `i` of `count` equally wide ones.
+    pub fn column(&self, i: usize, count: usize) -> Rect {
+        let width = self.width / count;
+        Rect::new(self.x + i * width, self.y, width, self.height)
+    }
+
+    pub fn inset(&self, by: usize) -> Rect {
+        Rect::new(self.x + by, self.y + by, self.width - 2 * by, self.height - 2 * by)
+    }
+
+    pub fn right(&self) -> usize {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> usize {
+        self.y + self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        !self.intersection(other).is_empty()
+    }
+
+    /// The area in both, empty if they do not overlap.
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right()).max(x);
+        let bottom = self.bottom().min(other.bottom()).max(y);
+        Rect::new(x, y, right - x, bottom - y)
+    }
+
+    /// The smallest rect around both.
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect::new(x, y, self.right().max(other.right()) - x, self.bottom().max(other.bottom()) - y)
+    }
+}
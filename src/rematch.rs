@@ -0,0 +1,65 @@
+use network::{GamestatePacket, InputPacket};
+use network::packets::{SERVER_LEFT, VOTED_PLAYER_1, VOTED_PLAYER_2, VOTE_LEAVE, VOTE_REMATCH};
+
+/// What the other board chose on the post-match screen.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Partner {
+    Undecided,
+    WantsRematch,
+    Left,
+}
+
+/// On the server, once per frame after the match: takes over the client's
+/// vote. Returns true once both players want a rematch.
+pub fn count_votes(gamestate: &mut GamestatePacket, client_input: &InputPacket) -> bool {
+    if client_input.vote == VOTE_REMATCH {
+        gamestate.votes |= VOTED_PLAYER_2;
+    }
+    let both = VOTED_PLAYER_1 | VOTED_PLAYER_2;
+    gamestate.votes & both == both && client_input.vote != VOTE_LEAVE
+}
+
+/// This board's rematch vote, the server keeps its own in the gamestate.
+pub fn vote(gamestate: &mut GamestatePacket, input: &mut InputPacket, is_server: bool) {
+    if is_server {
+        gamestate.votes |= VOTED_PLAYER_1;
+    } else {
+        input.vote = VOTE_REMATCH;
+    }
+}
+
+/// Marks this board as gone, for the packets sent before it drops the
+/// network.
+pub fn leave(gamestate: &mut GamestatePacket, input: &mut InputPacket, is_server: bool) {
+    if is_server {
+        gamestate.votes |= SERVER_LEFT;
+    } else {
+        input.vote = VOTE_LEAVE;
+    }
+}
+
+/// Whether this board already voted for a rematch.
+pub fn has_voted(gamestate: &GamestatePacket, input: &InputPacket, is_server: bool) -> bool {
+    if is_server {
+        gamestate.votes & VOTED_PLAYER_1 != 0
+    } else {
+        input.vote == VOTE_REMATCH
+    }
+}
+
+/// The other board's vote, as far as this board knows it.
+pub fn partner(gamestate: &GamestatePacket, client_input: &InputPacket, is_server: bool) -> Partner {
+    if is_server {
+        match client_input.vote {
+            VOTE_LEAVE => Partner::Left,
+            VOTE_REMATCH => Partner::WantsRematch,
+            _ => Partner::Undecided,
+        }
+    } else if gamestate.votes & SERVER_LEFT != 0 {
+        Partner::Left
+    } else if gamestate.votes & VOTED_PLAYER_1 != 0 {
+        Partner::WantsRematch
+    } else {
+        Partner::Undecided
+    }
+}
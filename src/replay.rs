@@ -0,0 +1,160 @@
+use alloc::Vec;
+use lcd;
+use network::GamestatePacket;
+use network::packets::STATE_RUNNING;
+
+const REPLAY_SECONDS: usize = 5;
+const FRAMES_PER_SECOND: usize = 60;
+const REPLAY_LENGTH: usize = REPLAY_SECONDS * FRAMES_PER_SECOND;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Speed {
+    Normal,
+    Half,
+    Quarter,
+}
+
+impl Speed {
+    fn frames_per_snapshot(&self) -> usize {
+        match *self {
+            Speed::Normal => 1,
+            Speed::Half => 2,
+            Speed::Quarter => 4,
+        }
+    }
+}
+
+/// Keeps the last seconds of the current rally and plays back the last
+/// finished point.
+pub struct Replay {
+    snapshots: Vec<GamestatePacket>, // ring buffer of the running rally
+    next: usize,
+    last_score: [u8; 2],
+    last_point: Vec<GamestatePacket>,
+    playing: bool,
+    position: usize,
+    frame: usize,
+    speed: Speed,
+    paused: bool,
+}
+
+impl Replay {
+    pub fn new() -> Replay {
+        Replay {
+            snapshots: Vec::with_capacity(REPLAY_LENGTH),
+            next: 0,
+            last_score: [0, 0],
+            last_point: Vec::with_capacity(REPLAY_LENGTH),
+            playing: false,
+            position: 0,
+            frame: 0,
+            speed: Speed::Normal,
+            paused: false,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Replay::new();
+    }
+
+    /// Call once per frame with the live gamestate. A score change ends the
+    /// rally and makes it the new last point.
+    pub fn record(&mut self, gamestate: &GamestatePacket) {
+        if gamestate.score != self.last_score {
+            self.last_score = gamestate.score;
+            self.finish_point();
+        }
+        if gamestate.state != STATE_RUNNING {
+            return;
+        }
+        if self.snapshots.len() < REPLAY_LENGTH {
+            self.snapshots.push(*gamestate);
+        } else {
+            self.snapshots[self.next] = *gamestate;
+        }
+        self.next = (self.next + 1) % REPLAY_LENGTH;
+    }
+
+    fn finish_point(&mut self) {
+        if self.snapshots.is_empty() {
+            return;
+        }
+        self.last_point.clear();
+        if self.snapshots.len() == REPLAY_LENGTH {
+            self.last_point.extend_from_slice(&self.snapshots[self.next..]);
+        }
+        self.last_point.extend_from_slice(&self.snapshots[..self.next]);
+        self.snapshots.clear();
+        self.next = 0;
+    }
+
+    pub fn has_last_point(&self) -> bool {
+        !self.last_point.is_empty()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn start(&mut self) {
+        self.playing = self.has_last_point();
+        self.position = 0;
+        self.frame = 0;
+        self.speed = Speed::Normal;
+        self.paused = false;
+    }
+
+    pub fn stop(&mut self) {
+        self.playing = false;
+    }
+
+    /// Touch controls: left third cycles slow motion, the centre pauses and
+    /// the right third ends the replay.
+    pub fn handle_touch(&mut self, x: u16) {
+        let third = lcd::WIDTH as u16 / 3;
+        if x < third {
+            self.speed = match self.speed {
+                Speed::Normal => Speed::Half,
+                Speed::Half => Speed::Quarter,
+                Speed::Quarter => Speed::Normal,
+            };
+        } else if x < 2 * third {
+            self.paused = !self.paused;
+        } else {
+            self.stop();
+        }
+    }
+
+    /// The snapshot to show this frame, `None` once the replay is over.
+    pub fn next_frame(&mut self) -> Option<GamestatePacket> {
+        if !self.playing {
+            return None;
+        }
+        if self.position >= self.last_point.len() {
+            self.stop();
+            return None;
+        }
+        let snapshot = self.last_point[self.position];
+        if !self.paused {
+            self.frame += 1;
+            if self.frame >= self.speed.frames_per_snapshot() {
+                self.frame = 0;
+                self.position += 1;
+            }
+        }
+        Some(snapshot)
+    }
+
+    /// What the replay label shows.
+    pub fn label(&self) -> &'static str {
+        if self.paused {
+            "Replay paused"
+        } else {
+            match self.speed {
+                Speed::Normal => "Replay",
+                Speed::Half => "Replay 1/2x",
+                Speed::Quarter => "Replay 1/4x",
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use alloc::Vec;
+use network::packets::Serializable;
+
+const SCORE_LIMITS: [u8; 7] = [3, 5, 7, 9, 11, 15, 21];
+const TIME_LIMITS: [u16; 5] = [0, 60, 120, 180, 300];
+pub const MAX_BEST_OF: u8 = 5;
+pub const MIN_RACKET_SIZE: u8 = 10;
+pub const MAX_RACKET_SIZE: u8 = 60;
+pub const MAX_BALL_SPEED: u8 = 5;
+const NORMAL_BALL_SPEED: u8 = 3;
+
+/// How a match is won, chosen before the game and sent to the client.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MatchSettings {
+    pub score_limit: u8, // first to this many points wins the set
+    pub win_by_two: bool,
+    pub time_limit: u16, // seconds per set, 0 for no limit
+    pub best_of: u8,     // sets
+    pub racket_size: u8, // half the racket height in pixels
+    pub ball_speed: u8,  // 1 to MAX_BALL_SPEED, how fast the ball is served
+}
+
+impl MatchSettings {
+    /// The classic single set to nine points.
+    pub fn new() -> MatchSettings {
+        MatchSettings {
+            score_limit: 9,
+            win_by_two: false,
+            time_limit: 0,
+            best_of: 1,
+            racket_size: 30,
+            ball_speed: NORMAL_BALL_SPEED,
+        }
+    }
+
+    /// How much faster than normal the ball leaves the centre, in pixels
+    /// per frame.
+    pub fn serve_boost(&self) -> i16 {
+        self.ball_speed as i16 - NORMAL_BALL_SPEED as i16
+    }
+
+    pub fn sets_to_win(&self) -> u8 {
+        self.best_of / 2 + 1
+    }
+
+    /// The player who took the set with this score. When the time is up the
+    /// leader wins, on a tie the next point decides.
+    pub fn set_winner(&self, score: [u8; 2], time_up: bool) -> Option<usize> {
+        let leader = if score[0] > score[1] {
+            0
+        } else if score[1] > score[0] {
+            1
+        } else {
+            return None;
+        };
+        let lead = score[leader] - score[1 - leader];
+        if time_up || (score[leader] >= self.score_limit && (!self.win_by_two || lead >= 2)) {
+            Some(leader)
+        } else {
+            None
+        }
+    }
+
+    pub fn cycle_score_limit(&mut self) {
+        self.score_limit = next(&SCORE_LIMITS, self.score_limit);
+    }
+
+    pub fn cycle_time_limit(&mut self) {
+        self.time_limit = next(&TIME_LIMITS, self.time_limit);
+    }
+
+    /// Whether a received packet holds settings this firmware can play.
+    pub fn is_valid(&self) -> bool {
+        self.score_limit > 0 && self.best_of % 2 == 1 && self.best_of <= MAX_BEST_OF
+            && self.racket_size >= MIN_RACKET_SIZE && self.racket_size <= MAX_RACKET_SIZE
+            && self.ball_speed > 0 && self.ball_speed <= MAX_BALL_SPEED
+    }
+}
+
+// the value after `current`, unknown values start over
+fn next<T: Copy + PartialEq>(values: &[T], current: T) -> T {
+    match values.iter().position(|&value| value == current) {
+        Some(i) => values[(i + 1) % values.len()],
+        None => values[0],
+    }
+}
+
+impl Serializable for MatchSettings {
+    fn serialize(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.push(self.score_limit);
+        result.push(self.win_by_two as u8);
+        result.push((self.time_limit >> 8) as u8);
+        result.push(self.time_limit as u8);
+        result.push(self.best_of);
+        result.push(self.racket_size);
+        result.push(self.ball_speed);
+        result
+    }
+
+    fn deserialize(input: &[u8]) -> MatchSettings {
+        MatchSettings {
+            score_limit: input[0],
+            win_by_two: input[1] != 0,
+            time_limit: u16::from(input[2]) << 8 | u16::from(input[3]),
+            best_of: input[4],
+            racket_size: input[5],
+            ball_speed: input[6],
+        }
+    }
+
+    fn len() -> usize {
+        7
+    }
+}
@@ -0,0 +1,218 @@
+//! The game screen as sprites in fixed layers.
+//!
+//! Each frame the callers set what should be on screen. `render` compares it
+//! with what was drawn the frame before and repaints only the rectangles that
+//! changed: each one is cleared and every sprite touching it is drawn again,
+//! back to front, clipped to the rectangle.
+
+use alloc::Vec;
+use lcd;
+use lcd::draw;
+use lcd::{Clipped, Framebuffer};
+use lcd::TextWriter;
+use palette;
+use rect::Rect;
+
+// layers from back to front
+pub const CLOCK: usize = 0;
+pub const SET_MARKS: usize = 1; // one per player
+pub const SCORES: usize = 3; // one per player
+pub const LABEL: usize = 5;
+pub const RACKETS: usize = 6; // one per player
+pub const BALL: usize = 8;
+pub const BANNER: usize = 9;
+pub const PANEL: usize = 10;
+pub const PANEL_LINES: usize = 11;
+pub const PANEL_LINE_COUNT: usize = 4;
+const LAYERS: usize = PANEL_LINES + PANEL_LINE_COUNT;
+
+const PANEL_BORDER: usize = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Font {
+    Large,
+    Small,
+}
+
+impl Font {
+    // generous, a glyph drawn outside its box would not be cleared again
+    fn char_width(&self) -> usize {
+        match *self {
+            Font::Large => 24,
+            Font::Small => 12,
+        }
+    }
+
+    fn line_height(&self) -> usize {
+        match *self {
+            Font::Large => 48,
+            Font::Small => 26,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Sprite {
+    /// A filled circle around its centre.
+    Disc { x: u16, y: u16, radius: u16, color: u8 },
+    Block { rect: Rect, color: u8 },
+    /// `count` small discs `spacing` apart starting at the centre `x`, `y`,
+    /// the first `filled` ones bright.
+    Marks { x: usize, y: usize, radius: u16, spacing: isize, count: u8, filled: u8 },
+    Text { x: usize, y: usize, text: &'static str, font: Font },
+    Number { x: usize, y: usize, value: u8, font: Font },
+    /// An empty box with a border.
+    Panel { rect: Rect },
+}
+
+impl Sprite {
+    /// Every pixel the sprite may draw to.
+    pub fn bounds(&self) -> Rect {
+        match *self {
+            Sprite::Disc { x, y, radius, .. } => disc_bounds(x as isize, y as isize, radius),
+            Sprite::Block { rect, .. } | Sprite::Panel { rect } => rect,
+            Sprite::Marks { x, y, radius, spacing, count, .. } => {
+                if count == 0 {
+                    return Rect::new(x, y, 0, 0);
+                }
+                let first = disc_bounds(x as isize, y as isize, radius);
+                let last_x = x as isize + (count as isize - 1) * spacing;
+                first.union(&disc_bounds(last_x, y as isize, radius))
+            }
+            Sprite::Text { x, y, text, font } => {
+                Rect::new(x, y, text.len() * font.char_width(), font.line_height())
+            }
+            Sprite::Number { x, y, value, font } => {
+                let digits = if value >= 100 { 3 } else if value >= 10 { 2 } else { 1 };
+                Rect::new(x, y, digits * font.char_width(), font.line_height())
+            }
+        }
+    }
+
+    fn draw(
+        &self,
+        framebuffer: &mut Framebuffer,
+        clip: &Rect,
+        large_font: &mut TextWriter,
+        small_font: &mut TextWriter,
+    ) {
+        match *self {
+            Sprite::Disc { x, y, radius, color } => {
+                draw::disc(&mut Clipped::new(framebuffer, clip), x.into(), y.into(), radius.into(), color);
+            }
+            Sprite::Block { rect, color } => fill(framebuffer, &rect.intersection(clip), color),
+            Sprite::Marks { x, y, radius, spacing, count, filled } => {
+                let mut clipped = Clipped::new(framebuffer, clip);
+                for i in 0..count {
+                    let color = if i < filled { palette::MARK_WON } else { palette::MARK_OPEN };
+                    let x = x as isize + i as isize * spacing;
+                    draw::disc(&mut clipped, x as i32, y as i32, radius.into(), color);
+                }
+            }
+            Sprite::Text { x, y, text, font } => {
+                let mut clipped = Clipped::new(framebuffer, clip);
+                match font {
+                    Font::Large => large_font.write_at(&mut clipped, text, x, y),
+                    Font::Small => small_font.write_at(&mut clipped, text, x, y),
+                }
+            }
+            Sprite::Number { x, y, value, font } => {
+                let mut clipped = Clipped::new(framebuffer, clip);
+                let text = format!("{}", value);
+                match font {
+                    Font::Large => large_font.write_at(&mut clipped, &text, x, y),
+                    Font::Small => small_font.write_at(&mut clipped, &text, x, y),
+                }
+            }
+            Sprite::Panel { rect } => {
+                fill(framebuffer, &rect.intersection(clip), palette::PANEL_BORDER);
+                fill(framebuffer, &rect.inset(PANEL_BORDER).intersection(clip), palette::PANEL);
+            }
+        }
+    }
+}
+
+pub struct Scene {
+    sprites: [Option<Sprite>; LAYERS],
+    drawn: [Option<Sprite>; LAYERS], // what is on screen
+    redraw_all: bool,
+}
+
+impl Scene {
+    /// An empty scene, its first render repaints the whole screen.
+    pub fn new() -> Scene {
+        Scene {
+            sprites: [None; LAYERS],
+            drawn: [None; LAYERS],
+            redraw_all: true,
+        }
+    }
+
+    pub fn set(&mut self, layer: usize, sprite: Option<Sprite>) {
+        self.sprites[layer] = sprite;
+    }
+
+    /// Repaints everything that changed since the last render.
+    pub fn render(
+        &mut self,
+        framebuffer: &mut Framebuffer,
+        large_font: &mut TextWriter,
+        small_font: &mut TextWriter,
+    ) {
+        let mut dirty = Vec::new();
+        if self.redraw_all {
+            add_dirty(&mut dirty, lcd::screen());
+        } else {
+            for (sprite, drawn) in self.sprites.iter().zip(self.drawn.iter()) {
+                if sprite != drawn {
+                    for changed in drawn.iter().chain(sprite.iter()) {
+                        add_dirty(&mut dirty, changed.bounds());
+                    }
+                }
+            }
+        }
+
+        for area in &dirty {
+            fill(framebuffer, area, palette::BACKGROUND);
+            for sprite in self.sprites.iter().filter_map(|sprite| *sprite) {
+                if sprite.bounds().intersects(area) {
+                    sprite.draw(framebuffer, area, large_font, small_font);
+                }
+            }
+        }
+        self.drawn = self.sprites;
+        self.redraw_all = false;
+    }
+}
+
+// overlapping areas are merged, so no pixel is cleared after a sprite on it
+// was drawn
+fn add_dirty(dirty: &mut Vec<Rect>, area: Rect) {
+    let mut area = area.intersection(&lcd::screen());
+    if area.is_empty() {
+        return;
+    }
+    let mut i = 0;
+    while i < dirty.len() {
+        if dirty[i].intersects(&area) {
+            area = area.union(&dirty.swap_remove(i));
+            i = 0;
+        } else {
+            i += 1;
+        }
+    }
+    dirty.push(area);
+}
+
+fn disc_bounds(x: isize, y: isize, radius: u16) -> Rect {
+    let radius = radius as isize;
+    let left = (x - radius).max(0);
+    let top = (y - radius).max(0);
+    let right = (x + radius + 1).max(left);
+    let bottom = (y + radius + 1).max(top);
+    Rect::new(left as usize, top as usize, (right - left) as usize, (bottom - top) as usize)
+}
+
+fn fill(framebuffer: &mut Framebuffer, area: &Rect, color: u8) {
+    framebuffer.fill_rect(*area, color);
+}
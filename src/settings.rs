@@ -0,0 +1,78 @@
+//! Preferences kept in flash across resets.
+//!
+//! ```text
+//! version:u8 match_settings theme:u8 server_ip:[u8; 4] client_ip:[u8; 4]
+//! name_len:u8 name
+//! ```
+
+use alloc::String;
+use alloc::Vec;
+use network::packets::Serializable;
+use palette::THEMES;
+use rules::MatchSettings;
+
+pub const VERSION: u8 = 1;
+pub const NAME_LENGTH: usize = 8;
+pub const NAME_CHARACTERS: &[u8] = b" ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub match_settings: MatchSettings,
+    pub theme: u8, // index into palette::THEMES
+    pub server_ip: [u8; 4],
+    pub client_ip: [u8; 4],
+    pub player_name: String,
+}
+
+impl Settings {
+    pub fn new() -> Settings {
+        Settings {
+            match_settings: MatchSettings::new(),
+            theme: 0,
+            server_ip: [141, 52, 46, 1],
+            client_ip: [141, 52, 46, 2],
+            player_name: String::from("PLAYER 1"),
+        }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.push(VERSION);
+        result.extend_from_slice(&self.match_settings.serialize());
+        result.push(self.theme);
+        result.extend_from_slice(&self.server_ip);
+        result.extend_from_slice(&self.client_ip);
+        result.push(self.player_name.len() as u8);
+        result.extend_from_slice(self.player_name.as_bytes());
+        result
+    }
+
+    /// None for records of another version or with values out of range.
+    pub fn deserialize(data: &[u8]) -> Option<Settings> {
+        let rules_end = 1 + MatchSettings::len();
+        let name_start = rules_end + 10;
+        if data.len() < name_start || data[0] != VERSION {
+            return None;
+        }
+        let match_settings = MatchSettings::deserialize(&data[1..rules_end]);
+        let theme = data[rules_end];
+        let name_length = data[name_start - 1] as usize;
+        let name = &data[name_start..];
+        if !match_settings.is_valid() || theme as usize >= THEMES.len() || name_length > NAME_LENGTH
+            || name.len() != name_length || !name.iter().all(|byte| NAME_CHARACTERS.contains(byte))
+        {
+            return None;
+        }
+        let mut server_ip = [0; 4];
+        server_ip.copy_from_slice(&data[rules_end + 1..rules_end + 5]);
+        let mut client_ip = [0; 4];
+        client_ip.copy_from_slice(&data[rules_end + 5..rules_end + 9]);
+        Some(Settings {
+            match_settings: match_settings,
+            theme: theme,
+            server_ip: server_ip,
+            client_ip: client_ip,
+            player_name: name.iter().map(|&byte| byte as char).collect(),
+        })
+    }
+}
@@ -0,0 +1,165 @@
+//! A log of records in one flash sector.
+//!
+//! ```text
+//! magic:u8 length:u16 payload crc32:u32
+//! ```
+//!
+//! Saving appends a record behind the last one, the sector is only erased
+//! once it is full, so a 256K sector takes thousands of saves per erase.
+//! Loading returns the newest record with a valid crc, a save torn by a reset
+//! leaves the one before it in place. Numbers are big endian.
+
+use alloc::Vec;
+use crc::crc32;
+
+const MAGIC: u8 = 0xA5;
+const ERASED: u8 = 0xFF;
+const HEADER_LENGTH: usize = 3;
+const CRC_LENGTH: usize = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FlashError {
+    Erase,
+    Program,
+    Verify,   // the bytes read back differ from the ones written
+    TooLarge, // the record does not fit into the sector
+}
+
+/// One flash sector. Erasing sets every byte to 0xFF, only erased bytes can
+/// be programmed.
+pub trait Flash {
+    fn len(&self) -> usize;
+    fn read(&self, offset: usize, buffer: &mut [u8]);
+    fn erase(&mut self) -> Result<(), FlashError>;
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), FlashError>;
+}
+
+pub struct Store<F> {
+    flash: F,
+    latest: Option<(usize, usize)>, // offset and payload length of the newest record
+    free: usize,                    // where the next record goes
+}
+
+impl<F: Flash> Store<F> {
+    pub fn open(flash: F) -> Store<F> {
+        let mut store = Store {
+            flash: flash,
+            latest: None,
+            free: 0,
+        };
+        store.scan();
+        store
+    }
+
+    /// The payload of the newest intact record.
+    pub fn load(&self) -> Option<Vec<u8>> {
+        self.latest.map(|(offset, length)| {
+            let mut payload = vec![0; length];
+            self.flash.read(offset + HEADER_LENGTH, &mut payload);
+            payload
+        })
+    }
+
+    /// Appends a record, payloads equal to the stored one are not written
+    /// again.
+    pub fn save(&mut self, payload: &[u8]) -> Result<(), FlashError> {
+        if self.load().map_or(false, |stored| stored.as_slice() == payload) {
+            return Ok(());
+        }
+        let record = record(payload);
+        if record.len() > self.flash.len() || payload.len() > 0xFFFF {
+            return Err(FlashError::TooLarge);
+        }
+        if self.free + record.len() > self.flash.len() {
+            self.latest = None;
+            self.free = self.flash.len(); // until the erase succeeded
+            self.flash.erase()?;
+            self.free = 0;
+        }
+
+        let offset = self.free;
+        // whatever happens next, these bytes are no longer erased
+        self.free += record.len();
+        self.flash.program(offset, &record)?;
+        let mut written = vec![0; record.len()];
+        self.flash.read(offset, &mut written);
+        if written != record {
+            return Err(FlashError::Verify);
+        }
+        self.latest = Some((offset, payload.len()));
+        Ok(())
+    }
+
+    /// Bytes left before the next erase.
+    pub fn free_bytes(&self) -> usize {
+        self.flash.len() - self.free
+    }
+
+    pub fn flash(&self) -> &F {
+        &self.flash
+    }
+
+    // finds the newest intact record and the start of the erased space
+    fn scan(&mut self) {
+        let len = self.flash.len();
+        let mut offset = 0;
+        while offset + HEADER_LENGTH <= len {
+            let mut header = [0; HEADER_LENGTH];
+            self.flash.read(offset, &mut header);
+            if header[0] == ERASED {
+                break;
+            }
+            let length = (header[1] as usize) << 8 | header[2] as usize;
+            let total = HEADER_LENGTH + length + CRC_LENGTH;
+            if header[0] != MAGIC || offset + total > len {
+                // nothing after this can be trusted, the next save erases
+                offset = len;
+                break;
+            }
+            let mut record = vec![0; total];
+            self.flash.read(offset, &mut record);
+            if is_intact(&record) {
+                self.latest = Some((offset, length));
+            }
+            offset += total;
+        }
+        self.free = offset.min(len);
+        if !self.is_erased_from(self.free) {
+            // an erase was cut short
+            self.free = len;
+        }
+    }
+
+    fn is_erased_from(&self, offset: usize) -> bool {
+        let mut chunk = [0; 64];
+        let mut offset = offset;
+        while offset < self.flash.len() {
+            let length = chunk.len().min(self.flash.len() - offset);
+            self.flash.read(offset, &mut chunk[..length]);
+            if chunk[..length].iter().any(|&byte| byte != ERASED) {
+                return false;
+            }
+            offset += length;
+        }
+        true
+    }
+}
+
+fn record(payload: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(HEADER_LENGTH + payload.len() + CRC_LENGTH);
+    record.push(MAGIC);
+    record.push((payload.len() >> 8) as u8);
+    record.push(payload.len() as u8);
+    record.extend_from_slice(payload);
+    let crc = crc32(&record);
+    for shift in &[24, 16, 8, 0] {
+        record.push((crc >> shift) as u8);
+    }
+    record
+}
+
+fn is_intact(record: &[u8]) -> bool {
+    let (data, crc) = record.split_at(record.len() - CRC_LENGTH);
+    let stored = crc.iter().fold(0, |crc, &byte| crc << 8 | u32::from(byte));
+    crc32(data) == stored
+}
@@ -0,0 +1,328 @@
+//! Touch widgets for the menus.
+//!
+//! Screens declare their widgets every frame, always in the same order, and
+//! act on the return values:
+//!
+//! ```ignore
+//! ui.begin(just_entered, input.handle_menu());
+//! let mut rows = Column::new(Rect::new(20, 20, 300, 200), 40, 8);
+//! ui.label(framebuffer, text_writer, rows.row(), "Player name");
+//! if ui.button(framebuffer, text_writer, rows.row(), "Start") {
+//!     return GameState::GameRunningLocal;
+//! }
+//! ui.end();
+//! ```
+//!
+//! `Ui` remembers which widget the current touch started on and only redraws
+//! widgets whose look changed. A widget reacts when the touch is released on
+//! it, the highlight follows the finger, so sliding off cancels.
+
+use graphics::draw_rectangle;
+use input::Touch;
+use lcd::Framebuffer;
+use lcd::TextWriter;
+use palette;
+
+pub use rect::Rect;
+
+const TEXT_HEIGHT: usize = 20; // the menu font
+const CHAR_WIDTH: usize = 9; // RobotoMono at 20 px
+const TEXT_PADDING: usize = 10;
+const INDICATOR_SIZE: usize = 16;
+const KNOB_WIDTH: usize = 12;
+const LIST_ROW_HEIGHT: usize = 30;
+
+/// Hands out rows top to bottom.
+pub struct Column {
+    x: usize,
+    y: usize,
+    width: usize,
+    row_height: usize,
+    spacing: usize,
+}
+
+impl Column {
+    pub fn new(area: Rect, row_height: usize, spacing: usize) -> Column {
+        Column {
+            x: area.x,
+            y: area.y,
+            width: area.width,
+            row_height: row_height,
+            spacing: spacing,
+        }
+    }
+
+    pub fn row(&mut self) -> Rect {
+        let row = Rect::new(self.x, self.y, self.width, self.row_height);
+        self.y += self.row_height + self.spacing;
+        row
+    }
+}
+
+pub struct Ui {
+    is_down: bool,
+    started: bool,     // the touch began this frame
+    x: u16,            // where the touch was last seen, releases carry no position
+    y: u16,
+    pressed: Option<usize>, // the widget the touch started on
+    inside: bool,           // whether the touch is still on it
+    focus: Option<usize>,   // the widget used last
+    highlight: Option<usize>,
+    drawn_highlight: Option<usize>,
+    drawn_focus: Option<usize>,
+    redraw: Option<usize>, // used last frame, its label may have changed
+    redraw_next: Option<usize>,
+    redraw_all: bool,
+    next_id: usize,
+}
+
+impl Ui {
+    pub fn new() -> Ui {
+        Ui {
+            is_down: false,
+            started: false,
+            x: 0,
+            y: 0,
+            pressed: None,
+            inside: false,
+            focus: None,
+            highlight: None,
+            drawn_highlight: None,
+            drawn_focus: None,
+            redraw: None,
+            redraw_next: None,
+            redraw_all: true,
+            next_id: 0,
+        }
+    }
+
+    /// Starts a frame. A new screen forgets the old one and draws everything.
+    pub fn begin(&mut self, just_entered: bool, touch: Touch) {
+        if just_entered {
+            *self = Ui::new();
+        } else {
+            self.redraw_all = false;
+            self.redraw = self.redraw_next.take();
+        }
+        self.started = touch.is_down && !touch.any_touch_last_frame;
+        if self.started {
+            self.pressed = None;
+        }
+        self.is_down = touch.is_down;
+        if touch.is_down {
+            self.x = touch.x;
+            self.y = touch.y;
+        }
+        self.highlight = None;
+        self.next_id = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.drawn_highlight = self.highlight;
+        self.drawn_focus = self.focus;
+        if !self.is_down {
+            self.pressed = None;
+        }
+    }
+
+    /// Drawn once per screen.
+    pub fn label(&mut self, framebuffer: &mut Framebuffer, text_writer: &mut TextWriter, rect: Rect,
+                 text: &str) {
+        let id = self.next_id();
+        if self.redraw_all || self.redraw == Some(id) {
+            fill(framebuffer, &rect, palette::BACKGROUND);
+            text_writer.write_at(framebuffer, text, rect.x, text_y(&rect));
+        }
+    }
+
+    /// True when tapped. The label may change after a tap, it is redrawn
+    /// the frame after.
+    pub fn button(&mut self, framebuffer: &mut Framebuffer, text_writer: &mut TextWriter, rect: Rect,
+                  text: &str) -> bool {
+        let (id, tapped) = self.interact(&rect);
+        if self.needs_redraw(id) {
+            self.draw_frame(framebuffer, &rect, id);
+            text_writer.write_at(framebuffer, text, centred_x(&rect, text), text_y(&rect));
+        }
+        tapped
+    }
+
+    /// A switch with a box that is filled while on. True when it flipped.
+    pub fn toggle(&mut self, framebuffer: &mut Framebuffer, text_writer: &mut TextWriter, rect: Rect,
+                  text: &str, value: &mut bool) -> bool {
+        let (id, tapped) = self.interact(&rect);
+        if tapped {
+            *value = !*value;
+        }
+        if tapped || self.needs_redraw(id) {
+            self.draw_frame(framebuffer, &rect, id);
+            text_writer.write_at(framebuffer, text, rect.x + TEXT_PADDING, text_y(&rect));
+            let indicator = Rect::new(
+                rect.x + rect.width - TEXT_PADDING - INDICATOR_SIZE,
+                rect.y + (rect.height - INDICATOR_SIZE) / 2,
+                INDICATOR_SIZE,
+                INDICATOR_SIZE,
+            );
+            draw_border(framebuffer, &indicator, palette::FOCUS, 1);
+            if *value {
+                fill(framebuffer, &indicator.inset(3), palette::PRESSED);
+            }
+        }
+        tapped
+    }
+
+    /// Dragging anywhere along the track sets `value`. True when it changed.
+    pub fn slider(&mut self, framebuffer: &mut Framebuffer, rect: Rect, min: i32, max: i32,
+                  value: &mut i32) -> bool {
+        let (id, _) = self.interact(&rect);
+        // a slider no wider than its knob has no track to drag along
+        let track = rect.width.saturating_sub(KNOB_WIDTH);
+        let mut changed = false;
+        if self.pressed == Some(id) && self.is_down && track > 0 {
+            // the finger may leave the track while dragging
+            self.highlight = Some(id);
+            let track = track as i32;
+            let offset = (self.x as i32 - (rect.x + KNOB_WIDTH / 2) as i32).max(0).min(track);
+            let new_value = min + (offset * (max - min) + track / 2) / track;
+            changed = new_value != *value;
+            *value = new_value;
+        }
+        if changed || self.needs_redraw(id) {
+            self.draw_frame(framebuffer, &rect, id);
+            let middle = rect.y + rect.height / 2;
+            let line = Rect::new(rect.x + KNOB_WIDTH / 2, middle.saturating_sub(1), track, 2);
+            fill(framebuffer, &line, palette::BORDER);
+            let range = (max - min).max(1);
+            let knob_x = rect.x + ((*value - min) * track as i32 / range) as usize;
+            let knob = Rect::new(knob_x, rect.y + 4, KNOB_WIDTH, rect.height.saturating_sub(8));
+            fill(framebuffer, &knob, palette::PRESSED);
+        }
+        changed
+    }
+
+    /// A number with minus and plus at its ends. True when it changed.
+    pub fn spinner(&mut self, framebuffer: &mut Framebuffer, text_writer: &mut TextWriter, rect: Rect,
+                   min: i32, max: i32, step: i32, value: &mut i32) -> bool {
+        let (id, tapped) = self.interact(&rect);
+        let minus = Rect::new(rect.x, rect.y, rect.height, rect.height);
+        let plus = Rect::new(rect.x + rect.width - rect.height, rect.y, rect.height, rect.height);
+        let mut changed = false;
+        if tapped {
+            let old = *value;
+            if minus.contains(self.x, self.y) {
+                *value = (*value - step).max(min);
+            } else if plus.contains(self.x, self.y) {
+                *value = (*value + step).min(max);
+            }
+            changed = *value != old;
+        }
+        if changed || self.needs_redraw(id) {
+            self.draw_frame(framebuffer, &rect, id);
+            text_writer.write_at(framebuffer, "-", centred_x(&minus, "-"), text_y(&rect));
+            text_writer.write_at(framebuffer, "+", centred_x(&plus, "+"), text_y(&rect));
+            let text = format!("{}", value);
+            text_writer.write_at(framebuffer, &text, centred_x(&rect, &text), text_y(&rect));
+        }
+        changed
+    }
+
+    /// One row per item, a tap selects. True when the selection changed.
+    pub fn list(&mut self, framebuffer: &mut Framebuffer, text_writer: &mut TextWriter, rect: Rect,
+                items: &[&str], selected: &mut usize) -> bool {
+        let (id, tapped) = self.interact(&rect);
+        let mut changed = false;
+        if tapped {
+            let row = (self.y as usize - rect.y) / LIST_ROW_HEIGHT;
+            if row < items.len() && row != *selected {
+                *selected = row;
+                changed = true;
+            }
+        }
+        if changed || self.needs_redraw(id) {
+            self.draw_frame(framebuffer, &rect, id);
+            let mut rows = Column::new(rect.inset(2), LIST_ROW_HEIGHT, 0);
+            for (i, item) in items.iter().enumerate() {
+                let row = rows.row();
+                if row.y + row.height > rect.y + rect.height {
+                    break;
+                }
+                if i == *selected {
+                    draw_border(framebuffer, &row, palette::FOCUS, 1);
+                }
+                text_writer.write_at(framebuffer, item, row.x + TEXT_PADDING, text_y(&row));
+            }
+        }
+        changed
+    }
+
+    fn next_id(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    // registers a widget, returns its id and whether the touch was released on it
+    fn interact(&mut self, rect: &Rect) -> (usize, bool) {
+        let id = self.next_id();
+        if self.started && self.pressed.is_none() && rect.contains(self.x, self.y) {
+            self.pressed = Some(id);
+            self.focus = Some(id);
+        }
+        let mut tapped = false;
+        if self.pressed == Some(id) {
+            if self.is_down {
+                self.inside = rect.contains(self.x, self.y);
+                if self.inside {
+                    self.highlight = Some(id);
+                }
+            } else if self.inside {
+                tapped = true;
+                self.redraw_next = Some(id);
+            }
+        }
+        (id, tapped)
+    }
+
+    fn needs_redraw(&self, id: usize) -> bool {
+        self.redraw_all
+            || self.redraw == Some(id)
+            || (self.highlight == Some(id)) != (self.drawn_highlight == Some(id))
+            || (self.focus == Some(id)) != (self.drawn_focus == Some(id))
+    }
+
+    fn draw_frame(&self, framebuffer: &mut Framebuffer, rect: &Rect, id: usize) {
+        fill(framebuffer, rect, palette::BACKGROUND);
+        if self.highlight == Some(id) {
+            draw_border(framebuffer, rect, palette::PRESSED, 2);
+        } else if self.focus == Some(id) {
+            draw_border(framebuffer, rect, palette::FOCUS, 1);
+        } else {
+            draw_border(framebuffer, rect, palette::BORDER, 1);
+        }
+    }
+}
+
+fn fill(framebuffer: &mut Framebuffer, rect: &Rect, color: u8) {
+    if rect.is_empty() {
+        return;
+    }
+    draw_rectangle(framebuffer, rect.x as u16, (rect.x + rect.width - 1) as u16, rect.y as u16,
+                   (rect.y + rect.height - 1) as u16, color);
+}
+
+fn draw_border(framebuffer: &mut Framebuffer, rect: &Rect, color: u8, width: usize) {
+    let right = rect.x + rect.width;
+    let bottom = rect.y + rect.height;
+    fill(framebuffer, &Rect::new(rect.x, rect.y, rect.width, width), color);
+    fill(framebuffer, &Rect::new(rect.x, bottom - width, rect.width, width), color);
+    fill(framebuffer, &Rect::new(rect.x, rect.y, width, rect.height), color);
+    fill(framebuffer, &Rect::new(right - width, rect.y, width, rect.height), color);
+}
+
+fn centred_x(rect: &Rect, text: &str) -> usize {
+    rect.x + rect.width.saturating_sub(text.len() * CHAR_WIDTH) / 2
+}
+
+fn text_y(rect: &Rect) -> usize {
+    rect.y + rect.height.saturating_sub(TEXT_HEIGHT) / 2
+}
@@ -0,0 +1,69 @@
+use std::str::FromStr;
+
+/// Minimal `command --key value` parser, enough for the subcommands here.
+pub struct Args {
+    args: Vec<String>,
+}
+
+impl Args {
+    pub fn new(args: Vec<String>) -> Args {
+        Args { args }
+    }
+
+    pub fn command(&mut self) -> Option<String> {
+        if self.args.is_empty() || self.args[0].starts_with("--") {
+            None
+        } else {
+            Some(self.args.remove(0))
+        }
+    }
+
+    /// Removes the next argument that is not an option.
+    pub fn positional(&mut self, name: &str) -> Result<String, String> {
+        let mut index = 0;
+        while index < self.args.len() {
+            if self.args[index].starts_with("--") {
+                index += 2; // skip the value of the option
+            } else {
+                return Ok(self.args.remove(index));
+            }
+        }
+        Err(format!("missing {}", name))
+    }
+
+    /// Removes `--name value` and parses the value, `default` if missing.
+    pub fn value<T: FromStr>(&mut self, name: &str, default: T) -> Result<T, String> {
+        match self.args.iter().position(|arg| arg == name) {
+            None => Ok(default),
+            Some(index) => {
+                if index + 1 >= self.args.len() {
+                    return Err(format!("missing value for {}", name));
+                }
+                let value = self.args.remove(index + 1);
+                self.args.remove(index);
+                value
+                    .parse()
+                    .map_err(|_| format!("invalid value for {}: {}", name, value))
+            }
+        }
+    }
+
+    /// Removes `--name` and returns whether it was given.
+    pub fn flag(&mut self, name: &str) -> bool {
+        match self.args.iter().position(|arg| arg == name) {
+            None => false,
+            Some(index) => {
+                self.args.remove(index);
+                true
+            }
+        }
+    }
+
+    /// Fails on everything that was not consumed by `value` or `flag`.
+    pub fn finish(&self) -> Result<(), String> {
+        match self.args.first() {
+            None => Ok(()),
+            Some(arg) => Err(format!("unexpected argument: {}", arg)),
+        }
+    }
+}
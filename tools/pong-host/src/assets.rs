@@ -0,0 +1,519 @@
+// Turns PNG and PGM files into bitmaps for `include_bytes!` (see
+// `src/lcd/bitmap.rs`), and checks the bitmaps: whatever is encoded draws
+// back the same at any signed position through clip rectangles, with the
+// transparent pixels left out and nothing outside of the buffer, and broken
+// files are refused instead of drawn.
+
+use args::Args;
+use fuzz::{self, Buffer, Fuzz, XorShift, HEIGHT, WIDTH};
+use images;
+use lcd::bitmap::{Bitmap, BitmapError, Run, FLAG_KEYED, FLAG_RLE, HEADER_LEN, MAGIC, MAX_PACKET, REPEAT, VERSION};
+use palette;
+use rect::Rect;
+use std::fs;
+
+const USAGE: &str = "usage: pong-host asset convert IN OUT [--color NAME] [--indices] [--clear-black] [--rle]
+       pong-host asset check [--rounds N] [--seed N]";
+
+// the colors a grey image can take, the grey level picks the shade
+const COLORS: [(&str, u8); 12] = [
+    ("background", palette::BACKGROUND),
+    ("text", palette::TEXT),
+    ("ball", palette::BALL),
+    ("racket", palette::RACKET),
+    ("clock", palette::CLOCK),
+    ("won", palette::MARK_WON),
+    ("open", palette::MARK_OPEN),
+    ("panel-border", palette::PANEL_BORDER),
+    ("border", palette::BORDER),
+    ("focus", palette::FOCUS),
+    ("pressed", palette::PRESSED),
+    ("panel", palette::PANEL),
+];
+
+pub fn run(args: &mut Args) -> Result<(), String> {
+    match args.command().as_deref() {
+        Some("convert") => convert(args),
+        Some("check") => check(args),
+        _ => Err(String::from(USAGE)),
+    }
+}
+
+fn convert(args: &mut Args) -> Result<(), String> {
+    let name = args.value("--color", String::from("text"))?;
+    let indices = args.flag("--indices");
+    let clear_black = args.flag("--clear-black");
+    let rle = args.flag("--rle");
+    let input = args.positional("IN")?;
+    let output = args.positional("OUT")?;
+    args.finish()?;
+
+    let color = COLORS.iter().find(|&&(n, _)| n == name).map(|&(_, color)| color).ok_or_else(|| {
+        let names: Vec<&str> = COLORS.iter().map(|&(n, _)| n).collect();
+        format!("unknown color {}, one of {}", name, names.join(", "))
+    })?;
+    let bytes = fs::read(&input).map_err(|e| format!("{}: {}", input, e))?;
+    let image = images::decode(&bytes).map_err(|e| format!("{}: {}", input, e))?;
+
+    let pixels: Vec<Option<u8>> = image
+        .pixels
+        .iter()
+        .map(|&(grey, alpha)| {
+            if alpha < 128 || clear_black && grey == 0 {
+                None
+            } else if indices {
+                Some(grey)
+            } else {
+                Some(palette::shade(color, grey))
+            }
+        })
+        .collect();
+    let (pixels, key) = with_key(&pixels).ok_or("every palette index is opaque somewhere, none is left for the key")?;
+    let encoded = encode(image.width, image.height, &pixels, key, rle);
+    fs::write(&output, &encoded).map_err(|e| format!("{}: {}", output, e))?;
+    println!("{}: {}x{}, {} transparent pixels, {} bytes, {} without RLE", output, image.width, image.height,
+             pixels.iter().filter(|&&pixel| Some(pixel) == key).count(), encoded.len(),
+             image.width * image.height + HEADER_LEN);
+    Ok(())
+}
+
+// stands in the lowest index no opaque pixel has for the transparent ones
+fn with_key(pixels: &[Option<u8>]) -> Option<(Vec<u8>, Option<u8>)> {
+    if pixels.iter().all(Option::is_some) {
+        return Some((pixels.iter().map(|pixel| pixel.unwrap()).collect(), None));
+    }
+    let mut used = [false; 256];
+    for pixel in pixels.iter().filter_map(|&pixel| pixel) {
+        used[usize::from(pixel)] = true;
+    }
+    let key = used.iter().position(|&used| !used)? as u8;
+    Some((pixels.iter().map(|pixel| pixel.unwrap_or(key)).collect(), Some(key)))
+}
+
+/// A bitmap file of `pixels`, line by line.
+pub fn encode(width: usize, height: usize, pixels: &[u8], key: Option<u8>, rle: bool) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    out.push(if rle { FLAG_RLE } else { 0 } | if key.is_some() { FLAG_KEYED } else { 0 });
+    out.push(key.unwrap_or(0));
+    out.extend_from_slice(&[(width >> 8) as u8, width as u8, (height >> 8) as u8, height as u8]);
+    if !rle {
+        out.extend_from_slice(pixels);
+        return out;
+    }
+
+    // repeats of two only pay off between repeats, in a literal they cost
+    // a packet byte more
+    let mut literal = 0;
+    let mut i = 0;
+    while i < pixels.len() {
+        let repeat = pixels[i..].iter().take(MAX_PACKET).take_while(|&&pixel| pixel == pixels[i]).count();
+        if repeat >= 3 || repeat == 2 && literal == 0 {
+            out.push(REPEAT | (repeat - 1) as u8);
+            out.push(pixels[i]);
+            i += repeat;
+        } else {
+            let start = i;
+            i += 1;
+            while i < pixels.len() && i - start < MAX_PACKET {
+                if pixels[i..].iter().take(3).filter(|&&pixel| pixel == pixels[i]).count() == 3 {
+                    break;
+                }
+                i += 1;
+            }
+            literal = i - start;
+            out.push((literal - 1) as u8);
+            out.extend_from_slice(&pixels[start..i]);
+            continue;
+        }
+        literal = 0;
+    }
+    out
+}
+
+fn check(args: &mut Args) -> Result<(), String> {
+    let fuzz = Fuzz::from_args(args, 1000)?;
+    args.finish()?;
+
+    let (raw, compressed) = check_drawing(&fuzz)?;
+    println!("{} bitmaps, raw and RLE, keyed or not, draw back the same through clip rectangles, \
+              RLE at {}% of the size", fuzz.rounds(), compressed * 100 / raw.max(1));
+    check_broken(&fuzz)?;
+    println!("{} truncated and damaged files are refused or drawn inside the buffer", 2 * fuzz.rounds());
+    Ok(())
+}
+
+fn random_bitmap(random: &mut XorShift) -> (usize, usize, Vec<u8>, Option<u8>) {
+    let (width, height) = (1 + random.below(40), 1 + random.below(30));
+    // a few colors in runs, some of them long
+    let colors: Vec<u8> = (0..1 + random.below(4)).map(|_| 1 + random.below(255) as u8).collect();
+    let mut pixels = Vec::with_capacity(width * height);
+    while pixels.len() < width * height {
+        let color = colors[random.below(colors.len())];
+        let length = if random.below(4) == 0 { 1 + random.below(300) } else { 1 + random.below(4) };
+        for _ in 0..length {
+            pixels.push(color);
+        }
+    }
+    pixels.truncate(width * height);
+    let key = if random.below(2) == 0 { Some(colors[0]) } else { None };
+    (width, height, pixels, key)
+}
+
+fn check_drawing(fuzz: &Fuzz) -> Result<(usize, usize), String> {
+    let (mut raw, mut compressed) = (0, 0);
+    fuzz.run(|_, random| {
+        let (width, height, pixels, key) = random_bitmap(random);
+        let rle = random.below(2) == 0;
+        let encoded = encode(width, height, &pixels, key, rle);
+        let describe = || format!("{}x{} {} bitmap keyed {:?}", width, height, if rle { "RLE" } else { "raw" }, key);
+        let bitmap = Bitmap::parse(&encoded).map_err(|e| format!("{:?}, {}", e, describe()))?;
+        if (bitmap.width(), bitmap.height(), bitmap.key()) != (width, height, key) {
+            return Err(format!("header read back wrong, {}", describe()));
+        }
+        let mut decoded = Vec::new();
+        for run in bitmap.runs() {
+            match run {
+                Run::Literal(literal) => decoded.extend_from_slice(literal),
+                Run::Repeat(color, length) => decoded.extend((0..length).map(|_| color)),
+            }
+        }
+        if decoded != pixels {
+            return Err(format!("runs do not give back the pixels, {}", describe()));
+        }
+        if rle {
+            raw += encode(width, height, &pixels, key, false).len();
+            compressed += encoded.len();
+        }
+
+        for _ in 0..4 {
+            let x = random.below(WIDTH + 2 * width) as i32 - width as i32;
+            let y = random.below(HEIGHT + 2 * height) as i32 - height as i32;
+            let clips: Vec<Rect> = (0..random.below(3)).map(|_| fuzz::random_clip(random)).collect();
+            let mut buffer = Buffer::new();
+            fuzz::clipped(&mut buffer, &clips, &mut |framebuffer| bitmap.draw(framebuffer, x, y));
+            if buffer.strays > 0 {
+                return Err(format!("{} writes outside of the buffer at {}, {} clipped to {:?}, {}", buffer.strays,
+                                   x, y, clips, describe()));
+            }
+            for by in 0..HEIGHT {
+                for bx in 0..WIDTH {
+                    let (column, row) = (bx as i32 - x, by as i32 - y);
+                    let inside = clips.iter().all(|clip| clip.contains(bx as u16, by as u16))
+                        && column >= 0 && row >= 0 && (column as usize) < width && (row as usize) < height;
+                    let pixel = if inside { Some(pixels[row as usize * width + column as usize]) } else { None };
+                    let expected = match pixel {
+                        Some(pixel) if Some(pixel) != key => pixel,
+                        _ => 0,
+                    };
+                    if buffer.pixels[by * WIDTH + bx] != expected {
+                        return Err(format!("{}, {} is {}, not {}, drawn at {}, {} clipped to {:?}, {}", bx, by,
+                                           buffer.pixels[by * WIDTH + bx], expected, x, y, clips, describe()));
+                    }
+                }
+            }
+        }
+        Ok(())
+    })?;
+    Ok((raw, compressed))
+}
+
+// drawing a damaged file must not read past it or draw outside the buffer,
+// and a shortened one is always refused
+fn check_broken(fuzz: &Fuzz) -> Result<(), String> {
+    fuzz.run(|_, random| {
+        let (width, height, pixels, key) = random_bitmap(random);
+        let encoded = encode(width, height, &pixels, key, random.below(2) == 0);
+        fuzz::broken(&encoded, &BitmapError::Truncated, random, |bytes, buffer, random| {
+            let bitmap = Bitmap::parse(bytes)?;
+            bitmap.draw(buffer, random.below(40) as i32 - 20, random.below(40) as i32 - 20);
+            Ok(())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crc;
+    use images::Image;
+
+    // 16x12 grey and alpha, compressed with dynamic Huffman codes
+    const DYNAMIC_PNG: [u8; 200] = [
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52, 0x00, 0x00,
+        0x00, 0x10, 0x00, 0x00, 0x00, 0x0c, 0x08, 0x04, 0x00, 0x00, 0x00, 0xc1, 0xee, 0xf5, 0x0a, 0x00, 0x00, 0x00,
+        0x8f, 0x49, 0x44, 0x41, 0x54, 0x78, 0xda, 0x7d, 0x8f, 0xc1, 0x0d, 0xc3, 0x30, 0x0c, 0x03, 0x39, 0x8e, 0xc7,
+        0xf1, 0x38, 0x1e, 0xc7, 0xe3, 0x64, 0x1c, 0x6d, 0xc0, 0x9e, 0x58, 0xbf, 0xea, 0xa0, 0x08, 0x12, 0xf8, 0x44,
+        0x49, 0xf1, 0x49, 0x92, 0xcf, 0xf3, 0x7e, 0x92, 0x87, 0xa7, 0x97, 0xb7, 0x1e, 0x97, 0x43, 0x82, 0x1c, 0xd2,
+        0xe0, 0x25, 0xde, 0x2e, 0x8e, 0x8b, 0x22, 0x24, 0xc8, 0xa1, 0x64, 0x7c, 0x16, 0xf1, 0x24, 0x1c, 0x34, 0x8a,
+        0xe9, 0x72, 0x88, 0xc6, 0xce, 0x68, 0xd8, 0x4c, 0x3f, 0x14, 0xcb, 0xab, 0xc9, 0x21, 0x55, 0x87, 0x19, 0xe1,
+        0xdf, 0x8b, 0x62, 0xd1, 0xc8, 0x42, 0x87, 0x88, 0xb7, 0x43, 0x7d, 0xc9, 0xa2, 0xb8, 0x99, 0x9e, 0xb4, 0x85,
+        0x58, 0xbe, 0x1c, 0x52, 0x5f, 0xf4, 0x9f, 0x62, 0xbe, 0x97, 0x58, 0xa4, 0xd9, 0xf3, 0x95, 0xd6, 0x2d, 0x16,
+        0xd2, 0x91, 0xee, 0x3d, 0xbf, 0x62, 0x90, 0x8e, 0x74, 0x7b, 0xdd, 0x62, 0x43, 0x47, 0x9a, 0xe9, 0xed, 0x0f,
+        0x3a, 0x73, 0xde, 0xe0, 0x99, 0x8c, 0x0f, 0x15, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42,
+        0x60, 0x82,
+    ];
+
+    // 8x4 grey, compressed with the fixed Huffman codes
+    const FIXED_PNG: [u8; 102] = [
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52, 0x00, 0x00,
+        0x00, 0x08, 0x00, 0x00, 0x00, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00, 0x96, 0xa6, 0x21, 0x2c, 0x00, 0x00, 0x00,
+        0x2d, 0x49, 0x44, 0x41, 0x54, 0x78, 0x01, 0x63, 0x60, 0x50, 0x70, 0x48, 0x68, 0x58, 0x70, 0xe0, 0x01, 0x03,
+        0x87, 0x86, 0x47, 0x46, 0xc7, 0x8a, 0x13, 0x2f, 0x18, 0x04, 0x0c, 0x02, 0x0a, 0x26, 0x6c, 0xb8, 0xf0, 0x81,
+        0x41, 0xc2, 0x22, 0xa2, 0x62, 0xc6, 0x8e, 0x1b, 0x3f, 0x00, 0xf6, 0xe4, 0x0f, 0x81, 0x6f, 0xc8, 0x26, 0xef,
+        0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    #[test]
+    fn pngs_decode() -> Result<(), String> {
+        // every color type at 8 and 16 bits, with each filter
+        let (width, height) = (7, 5);
+        let expected: Vec<(u8, u8)> = (0..width * height)
+            .map(|i| ((i * 37 % 256) as u8, if i % 3 == 0 { 0 } else { (i * 11 % 256) as u8 }))
+            .collect();
+        for &(color_type, depth) in &[(0, 8), (0, 16), (2, 8), (2, 16), (3, 8), (4, 8), (4, 16), (6, 8), (6, 16)] {
+            for filter in 0..5 {
+                let png = make_png(width, height, color_type, depth, filter, &expected);
+                let image = images::decode(&png).map_err(|e| format!("color type {} at {} bits, filter {}: {}",
+                                                                     color_type, depth, filter, e))?;
+                let wanted: Vec<(u8, u8)> = expected
+                    .iter()
+                    .map(|&(grey, alpha)| (grey, if color_type == 0 || color_type == 2 { 255 } else { alpha }))
+                    .collect();
+                compare(&format!("color type {} at {} bits, filter {}", color_type, depth, filter), &image, width,
+                        height, &wanted)?;
+            }
+        }
+
+        let image = images::decode(&DYNAMIC_PNG)?;
+        let wanted: Vec<(u8, u8)> = (0..12 * 16)
+            .map(|i| ((i % 16 * (i / 16) % 7 * 40) as u8, if (i % 16 + i / 16) % 5 == 0 { 0 } else { 255 }))
+            .collect();
+        compare("dynamic Huffman", &image, 16, 12, &wanted)?;
+        let image = images::decode(&FIXED_PNG)?;
+        let wanted: Vec<(u8, u8)> = (0..4 * 8).map(|i| (((i % 8 * 32 + i / 8 * 8) & 255) as u8, 255)).collect();
+        compare("fixed Huffman", &image, 8, 4, &wanted)?;
+
+        let mut broken = DYNAMIC_PNG;
+        broken[60] ^= 0x10;
+        if images::decode(&broken).is_ok() {
+            return Err(String::from("a PNG with a damaged chunk decodes"));
+        }
+        for cut in 0..DYNAMIC_PNG.len() {
+            if images::decode(&DYNAMIC_PNG[..cut]).is_ok() {
+                return Err(format!("a PNG cut to {} bytes decodes", cut));
+            }
+        }
+        // sizes a bitmap cannot hold, or whose lines overflow
+        for &(width, height) in &[(0x10000u32, 1u32), (1, 0x10000), (0xFFFF_FFFF, 0xFFFF_FFFF)] {
+            let mut large = FIXED_PNG;
+            large[16..20].copy_from_slice(&width.to_be_bytes());
+            large[20..24].copy_from_slice(&height.to_be_bytes());
+            let checksum = crc::crc32(&large[12..29]);
+            large[29..33].copy_from_slice(&checksum.to_be_bytes());
+            if images::decode(&large).is_ok() {
+                return Err(format!("a PNG of {}x{} pixels decodes", width, height));
+            }
+        }
+        Ok(())
+    }
+
+    fn compare(name: &str, image: &Image, width: usize, height: usize, expected: &[(u8, u8)]) -> Result<(), String> {
+        if (image.width, image.height) != (width, height) {
+            return Err(format!("{}: {}x{}, not {}x{}", name, image.width, image.height, width, height));
+        }
+        match image.pixels.iter().zip(expected).position(|(pixel, expected)| pixel != expected) {
+            Some(i) => Err(format!("{}: pixel {} is {:?}, not {:?}", name, i, image.pixels[i], expected[i])),
+            None => Ok(()),
+        }
+    }
+
+    // a PNG of `pixels` in one stored deflate block; colors are greys, the
+    // palette holds every grey once
+    fn make_png(width: usize, height: usize, color_type: u8, depth: u8, filter: u8, pixels: &[(u8, u8)]) -> Vec<u8> {
+        let mut lines = Vec::new();
+        for line in pixels.chunks(width) {
+            let mut samples = Vec::new();
+            for &(grey, alpha) in line {
+                let channels: Vec<u8> = match color_type {
+                    0 => vec![grey],
+                    2 => vec![grey, grey, grey],
+                    3 => vec![grey],
+                    4 => vec![grey, alpha],
+                    _ => vec![grey, grey, grey, alpha],
+                };
+                for channel in channels {
+                    samples.push(channel);
+                    if depth == 16 {
+                        samples.push(channel ^ 0x5A); // the low byte is dropped
+                    }
+                }
+            }
+            lines.push(samples);
+        }
+        let pixel_bytes = lines[0].len() / width;
+        let mut data = Vec::new();
+        for (y, line) in lines.iter().enumerate() {
+            data.push(filter);
+            for (i, &byte) in line.iter().enumerate() {
+                let left = if i >= pixel_bytes { line[i - pixel_bytes] } else { 0 };
+                let up = if y > 0 { lines[y - 1][i] } else { 0 };
+                let up_left = if y > 0 && i >= pixel_bytes { lines[y - 1][i - pixel_bytes] } else { 0 };
+                let predicted = match filter {
+                    0 => 0,
+                    1 => left,
+                    2 => up,
+                    3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+                    _ => paeth(left, up, up_left),
+                };
+                data.push(byte.wrapping_sub(predicted));
+            }
+        }
+
+        let mut zlib = vec![0x78, 0x01, 0x01, data.len() as u8, (data.len() >> 8) as u8, !data.len() as u8,
+                            !(data.len() >> 8) as u8];
+        zlib.extend_from_slice(&data);
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in &data {
+            a = (a + u32::from(byte)) % 65521;
+            b = (b + a) % 65521;
+        }
+        zlib.extend_from_slice(&be32(b << 16 | a));
+
+        let mut png = images::PNG_SIGNATURE.to_vec();
+        let mut header = Vec::new();
+        header.extend_from_slice(&be32(width as u32));
+        header.extend_from_slice(&be32(height as u32));
+        header.extend_from_slice(&[depth, color_type, 0, 0, 0]);
+        chunk(&mut png, b"IHDR", &header);
+        if color_type == 3 {
+            let palette: Vec<u8> = (0..256).flat_map(|grey| vec![grey as u8; 3]).collect();
+            chunk(&mut png, b"PLTE", &palette);
+            // transparency by grey level, the alpha of the first pixel with it
+            let transparency: Vec<u8> = (0..256)
+                .map(|grey| pixels.iter().find(|&&(g, _)| g as usize == grey).map_or(255, |&(_, alpha)| alpha))
+                .collect();
+            chunk(&mut png, b"tRNS", &transparency);
+        }
+        chunk(&mut png, b"tEXt", b"Comment\0ancillary chunks are skipped");
+        chunk(&mut png, b"IDAT", &zlib);
+        chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    fn chunk(png: &mut Vec<u8>, kind: &[u8], body: &[u8]) {
+        png.extend_from_slice(&be32(body.len() as u32));
+        let start = png.len();
+        png.extend_from_slice(kind);
+        png.extend_from_slice(body);
+        let crc = crc::crc32(&png[start..]);
+        png.extend_from_slice(&be32(crc));
+    }
+
+    fn be32(value: u32) -> [u8; 4] {
+        [(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]
+    }
+
+    fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+        let (a, b, c) = (i16::from(left), i16::from(up), i16::from(up_left));
+        let p = a + b - c;
+        let (pa, pb, pc) = ((p - a).abs(), (p - b).abs(), (p - c).abs());
+        if pa <= pb && pa <= pc {
+            left
+        } else if pb <= pc {
+            up
+        } else {
+            up_left
+        }
+    }
+
+    #[test]
+    fn pgms_decode() -> Result<(), String> {
+        let levels: Vec<u8> = (0..12).map(|i| (i * 23) as u8).collect();
+        let expected: Vec<(u8, u8)> = levels.iter().map(|&grey| (grey, 255)).collect();
+
+        let mut binary = b"P5\n# a comment\n4 3\n255\n".to_vec();
+        binary.extend_from_slice(&levels);
+        compare("binary PGM", &images::decode(&binary)?, 4, 3, &expected)?;
+
+        let plain: Vec<String> = levels.iter().map(|grey| grey.to_string()).collect();
+        let plain = format!("P2\n4 # width\n3\n255\n{}\n", plain.join(" "));
+        compare("plain PGM", &images::decode(plain.as_bytes())?, 4, 3, &expected)?;
+
+        let mut wide = b"P5 4 3 65535\n".to_vec();
+        for &grey in &levels {
+            wide.extend_from_slice(&[grey, 0xFF]);
+        }
+        compare("16 bit PGM", &images::decode(&wide)?, 4, 3, &expected)?;
+
+        if images::decode(&binary[..binary.len() - 1]).is_ok() {
+            return Err(String::from("a truncated PGM decodes"));
+        }
+        for large in &["P5 65536 1 255\n", "P2 1 65536 255\n", "P2 4294967295 4294967295 255\n"] {
+            if images::decode(large.as_bytes()).is_ok() {
+                return Err(format!("a PGM headed {:?} decodes", large));
+            }
+        }
+        Ok(())
+    }
+
+    fn header(width: u8, height: u8, flags: u8, key: u8) -> Vec<u8> {
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&[VERSION, flags, key, 0, width, 0, height]);
+        header
+    }
+
+    #[test]
+    fn rle_packs_runs_and_literals() {
+        let mut expected = header(5, 1, FLAG_RLE, 0);
+        expected.extend_from_slice(&[REPEAT | 2, 7, 1, 1, 2]);
+        assert_eq!(encode(5, 1, &[7, 7, 7, 1, 2], None, true), expected);
+
+        // a pair stays in the literal it is in, but is a repeat of its own
+        // after a repeat
+        let mut expected = header(4, 1, FLAG_RLE, 0);
+        expected.extend_from_slice(&[3, 1, 2, 2, 3]);
+        assert_eq!(encode(4, 1, &[1, 2, 2, 3], None, true), expected);
+        let mut expected = header(6, 1, FLAG_RLE, 0);
+        expected.extend_from_slice(&[REPEAT | 2, 1, REPEAT | 1, 2, 0, 3]);
+        assert_eq!(encode(6, 1, &[1, 1, 1, 2, 2, 3], None, true), expected);
+
+        let mut expected = header(3, 1, FLAG_KEYED, 9);
+        expected.extend_from_slice(&[9, 9, 9]);
+        assert_eq!(encode(3, 1, &[9, 9, 9], Some(9), false), expected);
+    }
+
+    #[test]
+    fn keyed_bitmaps_leave_the_key_out() {
+        for &rle in &[false, true] {
+            let encoded = encode(3, 2, &[5, 9, 5, 9, 6, 9], Some(9), rle);
+            let mut buffer = Buffer::new();
+            Bitmap::parse(&encoded).unwrap().draw(&mut buffer, -1, 1);
+            let mut expected = vec![0; WIDTH * HEIGHT];
+            expected[WIDTH + 1] = 5;
+            expected[2 * WIDTH] = 6;
+            assert!(buffer.pixels == expected, "rle {}", rle);
+            assert_eq!(buffer.strays, 0);
+        }
+    }
+
+    #[test]
+    fn short_bitmaps_are_truncated() {
+        let encoded = encode(3, 2, &[1, 1, 1, 1, 2, 3], None, true);
+        for &cut in &[0, HEADER_LEN - 1, HEADER_LEN, encoded.len() - 1] {
+            assert_eq!(Bitmap::parse(&encoded[..cut]), Err(BitmapError::Truncated), "cut to {}", cut);
+        }
+        assert!(Bitmap::parse(&encoded).is_ok());
+    }
+
+    #[test]
+    fn bitmaps_draw_back_the_same() {
+        check_drawing(&Fuzz::new(200, 1)).unwrap();
+    }
+
+    #[test]
+    fn broken_bitmaps_are_refused_or_drawn_inside() {
+        check_broken(&Fuzz::new(200, 1)).unwrap();
+    }
+}
@@ -0,0 +1,439 @@
+// Checks the software blitter (see `src/lcd/blit.rs`), the fallback of the
+// DMA2D driver, against the arithmetic of the reference manual and against
+// plain per-pixel loops, and the page flipping of `FramebufferL8`, which only
+// copies the damage (see `src/lcd/damage.rs`), against a single buffer.
+
+use args::Args;
+use blit::{self, Alpha, Blitter, PixelFormat, Software, Source, Target};
+use damage::Damage;
+use fuzz::{Fuzz, XorShift};
+use rect::Rect;
+
+const USAGE: &str = "usage: pong-host blit check [--rounds N] [--seed N]";
+const FORMATS: [PixelFormat; 8] = [
+    PixelFormat::Argb8888,
+    PixelFormat::Rgb888,
+    PixelFormat::Rgb565,
+    PixelFormat::Argb1555,
+    PixelFormat::Argb4444,
+    PixelFormat::L8,
+    PixelFormat::Al88,
+    PixelFormat::A8,
+];
+const OUTPUTS: [PixelFormat; 5] = [
+    PixelFormat::Argb8888,
+    PixelFormat::Rgb888,
+    PixelFormat::Rgb565,
+    PixelFormat::Argb1555,
+    PixelFormat::Argb4444,
+];
+
+pub fn run(args: &mut Args) -> Result<(), String> {
+    match args.command().as_deref() {
+        Some("check") => check(args),
+        _ => Err(String::from(USAGE)),
+    }
+}
+
+fn check(args: &mut Args) -> Result<(), String> {
+    let fuzz = Fuzz::from_args(args, 500)?;
+    args.finish()?;
+
+    check_operations(&fuzz)?;
+    println!("{} rounds of fill, copy, convert and blend match per-pixel loops", fuzz.rounds());
+    let copied = check_page_flips(&fuzz)?;
+    println!("{} page flips keep both buffers equal, copying {} of {} pixels", fuzz.rounds(), copied,
+             fuzz.rounds() * 64 * 48);
+    Ok(())
+}
+
+fn check_operations(fuzz: &Fuzz) -> Result<(), String> {
+    fuzz.run(|_, random| {
+        let clut: Vec<u32> = (0..256).map(|_| random.next()).collect();
+        let source_format = FORMATS[random.below(FORMATS.len())];
+        let background_format = FORMATS[random.below(FORMATS.len())];
+        let target_format = OUTPUTS[random.below(OUTPUTS.len())];
+        let (width, height) = (1 + random.below(24), 1 + random.below(16));
+        let source = Image::random(source_format, width + random.below(8), height + random.below(8), random);
+        let background = Image::random(background_format, width + random.below(8), height + random.below(8), random);
+        let mut target = Image::random(target_format, width + random.below(8), height + random.below(8), random);
+        let rect = Rect::new(random.below(target.width - width + 1), random.below(target.height - height + 1), width,
+                             height);
+        let from = (random.below(source.width - width + 1), random.below(source.height - height + 1));
+        let behind = (random.below(background.width - width + 1), random.below(background.height - height + 1));
+        let alpha = match random.below(3) {
+            0 => Alpha::Keep,
+            1 => Alpha::Replace(random.next() as u8),
+            _ => Alpha::Multiply(random.next() as u8),
+        };
+        let color = random.next();
+        let fill = target.format.from_argb8888(random.next());
+        let describe = || format!("{:?} and {:?} to {:?} in {:?}", source_format, background_format, target_format,
+                                  rect);
+
+        // fill
+        let mut expected = target.clone();
+        for (x, y) in pixels(rect) {
+            expected.set(x, y, fill);
+        }
+        let mut result = target.clone();
+        Software.fill(&mut result.target(), rect, fill);
+        if result != expected {
+            return Err(format!("fill differs, {}", describe()));
+        }
+
+        // copy, in the source format
+        let mut copy_target = Image::random(source_format, target.width, target.height, random);
+        let mut expected = copy_target.clone();
+        for (x, y) in pixels(rect) {
+            expected.set(x, y, source.get(from.0 + x - rect.x, from.1 + y - rect.y));
+        }
+        Software.copy(&source.source().at(from.0, from.1), &mut copy_target.target(), rect);
+        if copy_target != expected {
+            return Err(format!("copy differs, {}", describe()));
+        }
+
+        // convert and blend, each pixel through the formats on its own
+        let argb = |image: &Image, x: usize, y: usize| {
+            let pixel = image.format.to_argb8888(image.get(x, y), &clut, color);
+            alpha.apply(pixel >> 24) << 24 | pixel & 0x00FF_FFFF
+        };
+        let mut converted = target.clone();
+        let mut blended = target.clone();
+        for (x, y) in pixels(rect) {
+            let foreground = argb(&source, from.0 + x - rect.x, from.1 + y - rect.y);
+            let back = argb(&background, behind.0 + x - rect.x, behind.1 + y - rect.y);
+            converted.set(x, y, target_format.from_argb8888(foreground));
+            blended.set(x, y, target_format.from_argb8888(blit::blend_pixel(foreground, back)));
+        }
+        let foreground = source.source().at(from.0, from.1).with_alpha(alpha).with_color(color).with_clut(&clut);
+        let back = background.source().at(behind.0, behind.1).with_alpha(alpha).with_color(color).with_clut(&clut);
+        let mut result = target.clone();
+        Software.convert(&foreground, &mut result.target(), rect);
+        if result != converted {
+            return Err(format!("convert differs, {}", describe()));
+        }
+        Software.blend(&foreground, &back, &mut target.target(), rect);
+        if target != blended {
+            return Err(format!("blend differs, {}", describe()));
+        }
+        Ok(())
+    })
+}
+
+// draws like the game does, pixels in clusters and some fills, into one of
+// two buffers and replays the damage into the other one on every swap, the
+// way `FramebufferL8::swap_buffers` does
+fn check_page_flips(fuzz: &Fuzz) -> Result<usize, String> {
+    let (width, height) = (64, 48);
+    let screen = Rect::new(0, 0, width, height);
+    let mut buffers = [vec![0u8; width * height], vec![0u8; width * height]];
+    let mut expected = vec![0u8; width * height];
+    let mut damage = Damage::new();
+    let mut current = 0;
+    let mut copied = 0;
+    fuzz.run(|frame, random| {
+        for _ in 0..random.below(6) {
+            let color = random.next() & 0xFF;
+            let rect = Rect::new(random.below(width), random.below(height), random.below(20), random.below(20))
+                .intersection(&screen);
+            if random.below(2) == 0 {
+                for target in &mut [&mut buffers[current], &mut expected] {
+                    Software.fill(&mut Target::new(target, PixelFormat::L8, width), rect, color);
+                }
+                damage.add(rect);
+            } else {
+                for (x, y) in pixels(rect) {
+                    if random.below(3) == 0 {
+                        buffers[current][y * width + x] = color as u8;
+                        expected[y * width + x] = color as u8;
+                        damage.add(Rect::new(x, y, 1, 1));
+                    }
+                }
+            }
+        }
+
+        swap(&mut buffers, current, &damage, width);
+        copied += damage.area();
+        damage.clear();
+        current = 1 - current;
+        if buffers[0] != expected || buffers[1] != expected {
+            return Err(format!("the buffers differ after the swap of frame {}", frame));
+        }
+        Ok(())
+    })?;
+    Ok(copied)
+}
+
+// copies the damage of buffer `current` into the other one
+fn swap(buffers: &mut [Vec<u8>; 2], current: usize, damage: &Damage, width: usize) {
+    let (front, back) = buffers.split_at_mut(1);
+    let (src, dest) = if current == 0 { (&front[0], &mut back[0]) } else { (&back[0], &mut front[0]) };
+    let mut target = Target::new(dest, PixelFormat::L8, width);
+    for rect in damage.rects() {
+        Software.copy(&Source::new(src, PixelFormat::L8, width).at(rect.x, rect.y), &mut target, *rect);
+    }
+}
+
+fn pixels(rect: Rect) -> Vec<(usize, usize)> {
+    let mut pixels = Vec::new();
+    for y in rect.y..rect.bottom() {
+        for x in rect.x..rect.right() {
+            pixels.push((x, y));
+        }
+    }
+    pixels
+}
+
+#[derive(Clone, PartialEq)]
+struct Image {
+    format: PixelFormat,
+    width: usize,
+    height: usize,
+    bytes: Vec<u8>,
+}
+
+impl Image {
+    fn random(format: PixelFormat, width: usize, height: usize, random: &mut XorShift) -> Image {
+        let bytes = (0..width * height * format.bytes_per_pixel()).map(|_| random.next() as u8).collect();
+        Image {
+            format,
+            width,
+            height,
+            bytes,
+        }
+    }
+
+    fn get(&self, x: usize, y: usize) -> u32 {
+        let size = self.format.bytes_per_pixel();
+        let offset = (y * self.width + x) * size;
+        (0..size).fold(0, |pixel, i| pixel | u32::from(self.bytes[offset + i]) << (8 * i))
+    }
+
+    fn set(&mut self, x: usize, y: usize, pixel: u32) {
+        let size = self.format.bytes_per_pixel();
+        let offset = (y * self.width + x) * size;
+        for i in 0..size {
+            self.bytes[offset + i] = (pixel >> (8 * i)) as u8;
+        }
+    }
+
+    fn source<'a>(&'a self) -> Source<'a> {
+        Source::new(&self.bytes, self.format, self.width)
+    }
+
+    fn target<'a>(&'a mut self) -> Target<'a> {
+        Target::new(&mut self.bytes, self.format, self.width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // values worked out by hand from the reference manual
+    #[test]
+    fn known_values() {
+        let clut = [0xFF10_2030, 0x8040_5060];
+        let color = 0x00AB_CDEF;
+        let widened = [
+            (PixelFormat::Rgb565, 0xF800, 0xFFFF_0000),
+            (PixelFormat::Rgb565, 0x0841, 0xFF08_0808),
+            (PixelFormat::Rgb565, 0xFFFF, 0xFFFF_FFFF),
+            (PixelFormat::Argb1555, 0x7FFF, 0x00FF_FFFF),
+            (PixelFormat::Argb1555, 0x8000, 0xFF00_0000),
+            (PixelFormat::Argb4444, 0x8F00, 0x88FF_0000),
+            (PixelFormat::Argb4444, 0x1234, 0x1122_3344),
+            (PixelFormat::Rgb888, 0x0012_3456, 0xFF12_3456),
+            (PixelFormat::L8, 1, 0x8040_5060),
+            (PixelFormat::Al88, 0xC001, 0xC040_5060),
+            (PixelFormat::Al88, 0x0000, 0x0010_2030),
+            (PixelFormat::A8, 0x80, 0x80AB_CDEF),
+        ];
+        for &(format, raw, argb) in &widened {
+            let result = format.to_argb8888(raw, &clut, color);
+            assert!(result == argb, "{:?} {:#x} widens to {:#010x}, not {:#010x}", format, raw, result, argb);
+        }
+        let narrowed = [
+            (PixelFormat::Rgb565, 0xFF80_4020, 0x8204),
+            (PixelFormat::Argb1555, 0x7FFF_FFFF, 0x7FFF),
+            (PixelFormat::Argb4444, 0xF0E0_D0C0, 0xFEDC),
+            (PixelFormat::Al88, 0x8012_3456, 0x8034),
+        ];
+        for &(format, argb, raw) in &narrowed {
+            let result = format.from_argb8888(argb);
+            assert!(result == raw, "{:#010x} narrows to {:?} {:#x}, not {:#x}", argb, format, result, raw);
+        }
+        let blends = [
+            (0x80FF_0000, 0xFF00_00FF, 0xFF80_007F), // half red over blue
+            (0xFF12_3456, 0xFF65_4321, 0xFF12_3456), // opaque foreground
+            (0x0012_3456, 0x8065_4321, 0x8065_4321), // invisible foreground
+            (0x8012_3456, 0x0065_4321, 0x8012_3456), // nothing behind it
+            (0x0012_3456, 0x0065_4321, 0x0000_0000),
+        ];
+        for &(foreground, background, out) in &blends {
+            let result = blit::blend_pixel(foreground, background);
+            assert!(result == out, "{:#010x} over {:#010x} is {:#010x}, not {:#010x}", foreground, background, result,
+                    out);
+        }
+        let alphas = [
+            (Alpha::Keep, 0x80, 0x80),
+            (Alpha::Replace(0x40), 0x80, 0x40),
+            (Alpha::Multiply(0x80), 0xFF, 0x80),
+        ];
+        for &(alpha, value, out) in &alphas {
+            assert!(alpha.apply(value) == out, "{:?} turns alpha {:#x} into {:#x}, not {:#x}", alpha, value,
+                    alpha.apply(value), out);
+        }
+    }
+
+    // narrowing a widened pixel gives back every raw pixel of the output formats
+    #[test]
+    fn round_trips() {
+        for &format in &OUTPUTS {
+            let limit: u32 = match format.bytes_per_pixel() {
+                2 => 1 << 16,
+                _ => 1 << 12, // a sample of the wide formats
+            };
+            for i in 0..limit {
+                let raw = match format {
+                    PixelFormat::Argb8888 => i.wrapping_mul(0x9E37_79B9),
+                    PixelFormat::Rgb888 => i.wrapping_mul(0x9E37_79B9) & 0x00FF_FFFF,
+                    _ => i,
+                };
+                let back = format.from_argb8888(format.to_argb8888(raw, &[], 0));
+                assert!(back == raw, "{:?} {:#x} comes back as {:#x}", format, raw, back);
+            }
+        }
+    }
+
+    // a fill, a copy from inside a source, and a conversion and a blend of
+    // pixels whose results are known
+    #[test]
+    fn fill_writes_the_rect() {
+        let mut bytes = vec![0u8; 4 * 3 * 2];
+        Software.fill(&mut Target::new(&mut bytes, PixelFormat::Rgb565, 4), Rect::new(1, 1, 2, 2), 0xABCD);
+        let pixels: Vec<u16> = bytes.chunks(2).map(|pixel| u16::from(pixel[0]) | u16::from(pixel[1]) << 8).collect();
+        assert_eq!(pixels, [0, 0, 0, 0, 0, 0xABCD, 0xABCD, 0, 0, 0xABCD, 0xABCD, 0]);
+    }
+
+    #[test]
+    fn copy_takes_the_source_from_its_offset() {
+        let source = [1, 2, 3, 4, 5, 6];
+        let mut target = [0u8; 8];
+        let from = Source::new(&source, PixelFormat::L8, 3).at(1, 0);
+        Software.copy(&from, &mut Target::new(&mut target, PixelFormat::L8, 4), Rect::new(1, 0, 2, 2));
+        assert_eq!(target, [0, 2, 3, 0, 0, 5, 6, 0]);
+    }
+
+    #[test]
+    fn convert_and_blend_known_pixels() {
+        let argb = |bytes: &[u8]| -> Vec<u32> {
+            bytes.chunks(4).map(|pixel| (0..4).fold(0, |argb, i| argb | u32::from(pixel[i]) << (8 * i))).collect()
+        };
+        // red and blue in RGB565
+        let rgb565 = [0x00, 0xF8, 0x1F, 0x00];
+        let mut converted = [0u8; 8];
+        let source = Source::new(&rgb565, PixelFormat::Rgb565, 2);
+        Software.convert(&source, &mut Target::new(&mut converted, PixelFormat::Argb8888, 2), Rect::new(0, 0, 2, 1));
+        assert_eq!(argb(&converted), [0xFFFF_0000, 0xFF00_00FF]);
+
+        // red at half and at full alpha over blue
+        let alphas = [0x80, 0xFF];
+        let blue = [0xFF, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0xFF];
+        let mut blended = [0u8; 8];
+        let foreground = Source::new(&alphas, PixelFormat::A8, 2).with_color(0x00FF_0000);
+        let background = Source::new(&blue, PixelFormat::Argb8888, 2);
+        Software.blend(&foreground, &background, &mut Target::new(&mut blended, PixelFormat::Argb8888, 2),
+                       Rect::new(0, 0, 2, 1));
+        assert_eq!(argb(&blended), [0xFF80_007F, 0xFFFF_0000]);
+    }
+
+    // the middle as words and the columns beside it as L8 give the same bytes as
+    // one L8 fill, at every alignment, with the middle word aligned
+    #[test]
+    fn l8_words_match_plain_fills() {
+        let pitch = 32;
+        let mut random = XorShift::new(1);
+        for alignment in 0..4 {
+            for x in 0..12 {
+                for width in 0..20 {
+                    let rect = Rect::new(x, 1, width, 2);
+                    let color = random.next() & 0xFF;
+                    // a buffer whose first pixel sits `alignment` bytes after a word
+                    let mut memory: Vec<u8> = (0..alignment + pitch * 4).map(|_| random.next() as u8).collect();
+                    let mut expected = memory.clone();
+                    Software.fill(&mut Target::new(&mut expected[alignment..], PixelFormat::L8, pitch), rect, color);
+
+                    let parts = blit::split_l8_words(rect, alignment);
+                    let middle = parts[1];
+                    assert!(middle.width == 0 || (alignment + middle.x).is_multiple_of(4),
+                            "{:?} at alignment {} has an unaligned middle {:?}", rect, alignment, middle);
+                    assert!(middle.width.is_multiple_of(4) && parts[0].width <= 3 && parts[2].width <= 3,
+                            "{:?} at alignment {} is split into {:?}", rect, alignment, parts);
+                    for &edge in &[parts[0], parts[2]] {
+                        Software.fill(&mut Target::new(&mut memory[alignment..], PixelFormat::L8, pitch), edge, color);
+                    }
+                    let start = alignment + middle.y * pitch + middle.x;
+                    let mut words = Target::new(&mut memory[start..], PixelFormat::Argb8888, pitch / 4);
+                    Software.fill(&mut words, Rect::new(0, 0, middle.width / 4, middle.height), blit::l8_word(color));
+                    assert!(memory == expected, "{:?} at alignment {} differs from a plain fill", rect, alignment);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn damage_merges_near_rects() {
+        let mut damage = Damage::new();
+        damage.add(Rect::new(0, 0, 10, 10));
+        damage.add(Rect::new(2, 2, 3, 3));
+        assert_eq!(damage.rects(), [Rect::new(0, 0, 10, 10)]);
+
+        damage.clear();
+        damage.add(Rect::new(0, 0, 1, 1));
+        damage.add(Rect::new(5, 0, 1, 1));
+        damage.add(Rect::new(30, 30, 2, 2));
+        assert_eq!(damage.rects(), [Rect::new(0, 0, 6, 1), Rect::new(30, 30, 2, 2)]);
+        assert_eq!(damage.area(), 10);
+    }
+
+    #[test]
+    fn damage_past_its_capacity_becomes_one_rect() {
+        let mut damage = Damage::new();
+        for i in 0..17 {
+            damage.add(Rect::new(20 * i, 0, 1, 1));
+        }
+        assert_eq!(damage.rects(), [Rect::new(0, 0, 20 * 16 + 1, 1)]);
+    }
+
+    // a fill and then a pixel, each drawn into the back buffer and copied into
+    // the other one on the swap
+    #[test]
+    fn page_flips_copy_the_damage() {
+        let width = 8;
+        let mut buffers = [vec![0u8; width * 4], vec![0u8; width * 4]];
+        let mut damage = Damage::new();
+        let rect = Rect::new(1, 1, 3, 2);
+        Software.fill(&mut Target::new(&mut buffers[0], PixelFormat::L8, width), rect, 5);
+        damage.add(rect);
+        swap(&mut buffers, 0, &damage, width);
+        let mut expected = vec![0u8; width * 4];
+        for (x, y) in pixels(rect) {
+            expected[y * width + x] = 5;
+        }
+        assert_eq!(buffers, [expected.clone(), expected.clone()]);
+
+        damage.clear();
+        buffers[1][6] = 9;
+        damage.add(Rect::new(6, 0, 1, 1));
+        swap(&mut buffers, 1, &damage, width);
+        expected[6] = 9;
+        assert_eq!(buffers, [expected.clone(), expected]);
+    }
+
+    #[test]
+    fn operations_match_pixel_loops() {
+        check_operations(&Fuzz::new(500, 1)).unwrap();
+    }
+}
@@ -0,0 +1,48 @@
+// Headless players for the host tools, they use the firmware's cpu opponent.
+
+use args::Args;
+use cpu::{CpuPlayer, Difficulty};
+
+const DIFFICULTIES: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+/// A cpu player for `player` whose difficulty comes from the `flag` option.
+pub fn from_args(args: &mut Args, flag: &str, player: usize, seed: u32) -> Result<CpuPlayer, String> {
+    let name = args.value(flag, String::from("hard"))?;
+    match DIFFICULTIES.iter().find(|difficulty| difficulty.name() == name) {
+        Some(&difficulty) => Ok(CpuPlayer::new(player, difficulty, seed)),
+        None => Err(format!("{} must be easy, normal or hard, not {}", flag, name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cpu::predict_y;
+    use network::BallPacket;
+
+    fn ball(x: i16, y: i16, x_vel: i16, y_vel: i16) -> BallPacket {
+        BallPacket { x, y, x_vel, y_vel }
+    }
+
+    #[test]
+    fn balls_without_bounces_go_straight() {
+        assert_eq!(predict_y(&ball(240, 136, 5, 1), 250), 138);
+        // one that does not move across never gets there
+        assert_eq!(predict_y(&ball(240, 100, 0, 3), 460), 100);
+        // one already past the line stays where it is
+        assert_eq!(predict_y(&ball(300, 100, 5, 3), 250), 100);
+    }
+
+    #[test]
+    fn balls_bounce_off_a_wall_once() {
+        // 15, then 10 touches the top wall and turns back
+        assert_eq!(predict_y(&ball(0, 20, 10, -5), 100), 60);
+        assert_eq!(predict_y(&ball(0, 252, 10, 5), 100), 212);
+    }
+
+    #[test]
+    fn balls_bounce_between_the_walls() {
+        assert_eq!(predict_y(&ball(20, 136, 5, 20), 460), 56);
+        // the mirror image lands mirrored about the middle
+        assert_eq!(predict_y(&ball(460, 136, -5, -20), 20), 216);
+    }
+}
@@ -0,0 +1,174 @@
+// Checks the framebuffer in every pixel format (see `src/lcd/canvas.rs`):
+// the same drawing on an L8 canvas and on one in another format leaves each
+// pixel at the color of its palette index, and the L8 canvas itself matches
+// a plain array of indices.
+
+use args::Args;
+use fuzz::Fuzz;
+use lcd::format::{Argb4444, Argb8888, Format, Rgb565, L8};
+use lcd::{Canvas, Framebuffer};
+use palette::{self, THEMES};
+use rect::Rect;
+
+const USAGE: &str = "usage: pong-host canvas check [--rounds N] [--seed N]";
+const WIDTH: usize = 40;
+const HEIGHT: usize = 30;
+const OPERATIONS: usize = 12; // per round
+
+pub fn run(args: &mut Args) -> Result<(), String> {
+    match args.command().as_deref() {
+        Some("check") => check(args),
+        _ => Err(String::from(USAGE)),
+    }
+}
+
+fn check(args: &mut Args) -> Result<(), String> {
+    let fuzz = Fuzz::from_args(args, 200)?;
+    args.finish()?;
+
+    check_drawing(&fuzz)?;
+    println!("{} rounds of {} operations draw the same colors in L8, RGB565, ARGB4444 and ARGB8888", fuzz.rounds(),
+             OPERATIONS);
+    Ok(())
+}
+
+fn check_drawing(fuzz: &Fuzz) -> Result<(), String> {
+    fuzz.run(|_, random| {
+        let theme = random.below(THEMES.len()) as u8;
+        let mut expected = vec![palette::BACKGROUND; WIDTH * HEIGHT];
+        let mut l8 = Canvas::<L8>::new(WIDTH, HEIGHT, theme);
+        let mut rgb565 = Canvas::<Rgb565>::new(WIDTH, HEIGHT, theme);
+        let mut argb4444 = Canvas::<Argb4444>::new(WIDTH, HEIGHT, theme);
+        let mut argb8888 = Canvas::<Argb8888>::new(WIDTH, HEIGHT, theme);
+        for operation in 0..OPERATIONS {
+            let color = random.next() as u8;
+            // reaching past the edges, which the canvases leave out
+            let rect = Rect::new(random.below(WIDTH + 8), random.below(HEIGHT + 8), random.below(24),
+                                 random.below(24));
+            let image: Vec<u8> = (0..(rect.width + 3) * rect.height).map(|_| random.next() as u8).collect();
+            let full: Vec<u8> = (0..WIDTH * HEIGHT).map(|_| random.next() as u8).collect();
+            let kind = random.below(10);
+            {
+                let mut canvases: [&mut dyn Framebuffer; 4] = [&mut l8, &mut rgb565, &mut argb4444, &mut argb8888];
+                for canvas in canvases.iter_mut() {
+                    match kind {
+                        0..=3 => canvas.set_pixel(rect.x, rect.y, color),
+                        4..=6 => canvas.fill_rect(rect, color),
+                        7 | 8 => canvas.copy_rect(&image, rect.width + 3, rect),
+                        _ if operation % 2 == 0 => canvas.clear(),
+                        _ => canvas.copy_rect(&full, WIDTH, Rect::new(0, 0, WIDTH, HEIGHT)),
+                    }
+                }
+            }
+            for y in 0..HEIGHT {
+                for x in 0..WIDTH {
+                    let inside = rect.contains(x as u16, y as u16);
+                    let index = &mut expected[y * WIDTH + x];
+                    match kind {
+                        0..=3 if x == rect.x && y == rect.y => *index = color,
+                        4..=6 if inside => *index = color,
+                        7 | 8 if inside => *index = image[(y - rect.y) * (rect.width + 3) + x - rect.x],
+                        9 if operation % 2 == 0 => *index = palette::BACKGROUND,
+                        9 => *index = full[y * WIDTH + x],
+                        _ => {}
+                    }
+                }
+            }
+
+            let describe = || format!("operation {} ({}) in {:?}", operation, kind, rect);
+            if l8.data() != &expected[..] {
+                return Err(format!("the L8 canvas differs, {}", describe()));
+            }
+            let clut = clut(theme);
+            compare(&rgb565, &expected, &clut).map_err(|e| format!("RGB565: {}, {}", e, describe()))?;
+            compare(&argb4444, &expected, &clut).map_err(|e| format!("ARGB4444: {}, {}", e, describe()))?;
+            compare(&argb8888, &expected, &clut).map_err(|e| format!("ARGB8888: {}, {}", e, describe()))?;
+        }
+        Ok(())
+    })
+}
+
+// every pixel of `canvas` is the color of its index in `expected`
+fn compare<F: Format>(canvas: &Canvas<F>, expected: &[u8], clut: &[u32]) -> Result<(), String> {
+    for y in 0..canvas.height() {
+        for x in 0..canvas.width() {
+            let index = expected[y * canvas.width() + x];
+            let raw = F::FORMAT.from_argb8888(clut[index as usize]);
+            if canvas.pixel(x, y) != raw {
+                return Err(format!("{}, {} is {:#x}, index {:#04x} is {:#x}", x, y, canvas.pixel(x, y), index, raw));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn clut(theme: u8) -> Vec<u32> {
+    (0..256)
+        .map(|i| {
+            let (red, green, blue) = palette::clut_entry(theme, i as u8);
+            0xFF00_0000 | u32::from(red) << 16 | u32::from(green) << 8 | u32::from(blue)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // worked out by hand for the greys of the classic theme
+    #[test]
+    fn named_colors_have_known_pixels() {
+        let values = [
+            ("background", palette::BACKGROUND, 0x0000, 0xF000, 0xFF00_0000),
+            ("text", palette::TEXT, 0xFFFF, 0xFFFF, 0xFFFF_FFFF),
+            ("racket", palette::RACKET, 0x94B2, 0xF999, 0xFF96_9696),
+            ("open set", palette::MARK_OPEN, 0x4208, 0xF444, 0xFF40_4040),
+        ];
+        for &(name, color, rgb565, argb4444, argb8888) in &values {
+            let results = [
+                ("L8", pixel::<L8>(color), u32::from(color)),
+                ("RGB565", pixel::<Rgb565>(color), rgb565),
+                ("ARGB4444", pixel::<Argb4444>(color), argb4444),
+                ("ARGB8888", pixel::<Argb8888>(color), argb8888),
+            ];
+            for &(format, result, expected) in &results {
+                assert!(result == expected, "{} is {:#x} in {}, not {:#x}", name, result, format, expected);
+            }
+        }
+    }
+
+    // the raw pixel `color` leaves on a canvas of the classic theme
+    fn pixel<F: Format>(color: u8) -> u32 {
+        let mut canvas = Canvas::<F>::new(2, 2, 0);
+        canvas.set_pixel(1, 1, color);
+        canvas.pixel(1, 1)
+    }
+
+    #[test]
+    fn fills_past_the_edge_stay_inside() {
+        let mut canvas = Canvas::<L8>::new(4, 3, 0);
+        canvas.fill_rect(Rect::new(2, 1, 10, 10), palette::TEXT);
+        let (b, t) = (palette::BACKGROUND, palette::TEXT);
+        assert_eq!(canvas.data(), [b, b, b, b, b, b, t, t, b, b, t, t]);
+    }
+
+    #[test]
+    fn copies_convert_the_visible_part_of_the_image() {
+        let (b, t) = (palette::BACKGROUND, palette::TEXT);
+        let image = [t, b, t, b, t, b];
+        let mut canvas = Canvas::<Rgb565>::new(3, 2, 0);
+        canvas.copy_rect(&image, 3, Rect::new(1, 0, 3, 2));
+        let pixels: Vec<u32> = (0..6).map(|i| canvas.pixel(i % 3, i / 3)).collect();
+        assert_eq!(pixels, [0, 0xFFFF, 0, 0, 0, 0xFFFF]);
+    }
+
+    // the text of the classic theme is white, the one of the green theme is its tint
+    #[test]
+    fn themes_color_what_is_drawn_after_them() {
+        let mut canvas = Canvas::<Argb8888>::new(2, 1, 0);
+        canvas.set_pixel(0, 0, palette::TEXT);
+        canvas.set_theme(1);
+        canvas.set_pixel(1, 0, palette::TEXT);
+        assert_eq!((canvas.pixel(0, 0), canvas.pixel(1, 0)), (0xFFFF_FFFF, 0xFF40_FF60));
+    }
+}
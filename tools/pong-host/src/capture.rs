@@ -0,0 +1,189 @@
+// Reads capture dumps the firmware wrote to semihosting (see
+// `Network::dump_capture`), prints them and replays them into a client.
+
+use args::Args;
+use network::capture::{self, Direction, Record};
+use network::packets::Serializable;
+use network::{Client, EthClient, GamestatePacket, InputPacket, Link, LinkError, WhoamiPacket};
+use std::fs;
+use std::net::SocketAddr;
+use std::thread;
+use std::time::{Duration, Instant};
+use udp::UdpLink;
+
+pub fn run(args: &mut Args) -> Result<(), String> {
+    match args.command().as_deref() {
+        Some("decode") => decode(args),
+        Some("replay") => replay(args),
+        _ => Err(String::from("usage: pong-host capture <decode|replay> FILE")),
+    }
+}
+
+fn decode(args: &mut Args) -> Result<(), String> {
+    let records = load(&args.positional("capture file")?)?;
+    args.finish()?;
+    let start = records.first().map_or(0, |record| record.time);
+    for record in &records {
+        println!(
+            "{:8} ms  {}  {}",
+            record.time - start,
+            match record.direction {
+                Direction::Sent => "tx",
+                Direction::Received => "rx",
+            },
+            describe(&record.data)
+        );
+    }
+    Ok(())
+}
+
+/// Feeds the captured gamestates into an `EthClient`, either in memory or
+/// over UDP to a board with the original timing.
+fn replay(args: &mut Args) -> Result<(), String> {
+    let partner = args.value("--partner", String::new())?;
+    let partner: Option<SocketAddr> = if partner.is_empty() {
+        None
+    } else {
+        Some(partner.parse().map_err(|_| format!("invalid address: {}", partner))?)
+    };
+    let bind: SocketAddr = args.value("--bind", "0.0.0.0:2018".parse().unwrap())?;
+    let records = load(&args.positional("capture file")?)?;
+    args.finish()?;
+
+    let gamestates: Vec<&Record> = records
+        .iter()
+        .filter(|record| record.data.len() == GamestatePacket::len())
+        .collect();
+    if gamestates.is_empty() {
+        return Err(String::from("the capture contains no gamestates"));
+    }
+    let start = gamestates[0].time;
+
+    match partner {
+        Some(partner) => {
+            let mut link = UdpLink::bind(bind, partner)?;
+            let started = Instant::now();
+            for record in &gamestates {
+                let due = Duration::from_millis((record.time - start) as u64);
+                let elapsed = started.elapsed();
+                if due > elapsed {
+                    thread::sleep(due - elapsed);
+                }
+                link.send_udp_packet(&record.data);
+            }
+            println!("sent {} gamestates to {}", link.sent, partner);
+        }
+        None => {
+            let mut client = EthClient::new();
+            let mut previous = None;
+            for record in &gamestates {
+                let mut link = ReplayLink {
+                    datagram: Some(record.data.clone()),
+                };
+                let gamestate = client.receive_gamestate(&mut link);
+                if previous != Some(gamestate) {
+                    println!("{:8} ms  {:?}", record.time - start, gamestate);
+                    previous = Some(gamestate);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the last complete dump in a semihosting log.
+pub fn load(path: &str) -> Result<Vec<Record>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+    let mut dump = None;
+    let mut current: Option<Vec<Record>> = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if !line.starts_with(capture::LINE_PREFIX) {
+            continue;
+        }
+        let command = line[capture::LINE_PREFIX.len()..].trim();
+        if command.starts_with("begin") {
+            current = Some(Vec::new());
+        } else if command == "end" {
+            dump = current.take().or(dump);
+        } else if let (Some(record), Some(records)) = (capture::parse_line(line), current.as_mut()) {
+            records.push(record);
+        }
+    }
+    dump.ok_or_else(|| format!("no complete capture dump in {}", path))
+}
+
+/// One line summary of a datagram, the protocol tells packets apart by length.
+pub fn describe(data: &[u8]) -> String {
+    if data.len() == GamestatePacket::len() {
+        format!("{:?}", GamestatePacket::deserialize(data))
+    } else if data.len() == InputPacket::len() {
+        format!("{:?}", InputPacket::deserialize(data))
+    } else if data.len() == WhoamiPacket::len() {
+        format!("{:?}", WhoamiPacket::deserialize(data))
+    } else {
+        format!("unknown {} bytes {:02x?}", data.len(), data)
+    }
+}
+
+// hands exactly one datagram to whoever polls it
+struct ReplayLink {
+    datagram: Option<Vec<u8>>,
+}
+
+impl Link for ReplayLink {
+    fn get_udp_packet(&mut self) -> Result<Option<Vec<u8>>, LinkError> {
+        Ok(self.datagram.take())
+    }
+
+    fn send_udp_packet(&mut self, _data: &[u8]) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_round_trip() {
+        for record in &[
+            Record { time: 0, direction: Direction::Sent, data: vec![] },
+            Record { time: 1234, direction: Direction::Received, data: vec![0x00, 0x7f, 0x80, 0xff] },
+            Record { time: usize::MAX, direction: Direction::Sent, data: vec![0xab; 24] },
+        ] {
+            let line = capture::format_record(record);
+            assert_eq!(capture::parse_line(&line).as_ref(), Some(record), "{}", line);
+        }
+        assert_eq!(capture::format_record(&Record { time: 5, direction: Direction::Received, data: vec![1, 0xa0] }),
+                   "capture: 5 rx 01a0");
+        // with the padding a log adds around it
+        assert!(capture::parse_line("  capture: 5 rx 01a0\r\n").is_some());
+    }
+
+    #[test]
+    fn malformed_lines_are_skipped() {
+        for line in &[
+            "",
+            "capture:",
+            "capture: begin 3 0",
+            "capture: end",
+            "capture 5 tx 01",
+            "other: 5 tx 01",
+            "capture: 5",
+            "capture: 5 up 01",
+            "capture: five tx 01",
+            "capture: 5 tx 012",
+            "capture: 5 tx 0g",
+            "capture: 5 tx 01 02",
+            "capture: 5 tx 0é",
+            "capture: 99999999999999999999999 tx 01",
+        ] {
+            assert_eq!(capture::parse_line(line), None, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn negative_times_are_skipped() {
+        assert_eq!(capture::parse_line("capture: -1 tx 01"), None);
+        assert_eq!(capture::parse_line("capture: -0 rx"), None);
+    }
+}
@@ -0,0 +1,260 @@
+// Annotated packet dumps and session statistics for the pong protocol.
+
+use args::Args;
+use capture;
+use network::capture::Direction;
+use network::dissect::{self, PacketKind};
+use pcap;
+use std::collections::BTreeMap;
+
+const PORT: u16 = 2018;
+const HISTOGRAM_BUCKET_MS: u64 = 4;
+const HISTOGRAM_BUCKETS: usize = 16;
+const HISTOGRAM_WIDTH: usize = 50;
+
+const USAGE: &str = "usage: pong-host dissect payload HEX
+       pong-host dissect pcap FILE [--port N] [--summary]
+       pong-host dissect capture FILE [--summary]";
+
+struct Packet {
+    time_us: u64,
+    flow: String,
+    data: Vec<u8>,
+}
+
+pub fn run(args: &mut Args) -> Result<(), String> {
+    let command = args.command();
+    let summary_only = args.flag("--summary");
+    let packets = match command.as_deref() {
+        Some("payload") => {
+            let hex = args.positional("payload")?;
+            args.finish()?;
+            let data = parse_hex(&hex)?;
+            println!("{}", dissect::format(&data, &dissect::dissect(&data)));
+            return Ok(());
+        }
+        Some("pcap") => {
+            let port = args.value("--port", PORT)?;
+            pcap::read(&args.positional("pcap file")?, port)?
+                .into_iter()
+                .map(|datagram| Packet {
+                    time_us: datagram.time_us,
+                    flow: format!("{} -> {}", datagram.src, datagram.dst),
+                    data: datagram.payload,
+                })
+                .collect::<Vec<_>>()
+        }
+        Some("capture") => capture::load(&args.positional("capture file")?)?
+            .into_iter()
+            .map(|record| Packet {
+                time_us: record.time as u64 * 1000,
+                flow: String::from(match record.direction {
+                    Direction::Sent => "tx",
+                    Direction::Received => "rx",
+                }),
+                data: record.data,
+            })
+            .collect(),
+        _ => return Err(String::from(USAGE)),
+    };
+    args.finish()?;
+
+    if !summary_only {
+        let start = packets.first().map_or(0, |packet| packet.time_us);
+        for packet in &packets {
+            let dissection = dissect::dissect(&packet.data);
+            println!(
+                "{:10.3} ms  {}  {}",
+                // pcaps merged from several captures can go back in time
+                (packet.time_us as i64 - start as i64) as f64 / 1000.0,
+                packet.flow,
+                dissect::format(&packet.data, &dissection)
+            );
+        }
+        println!();
+    }
+    summarize(&packets);
+    Ok(())
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<char> = hex.chars().filter(|c| c.is_ascii_hexdigit()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits in {}", hex));
+    }
+    Ok(digits
+        .chunks(2)
+        .map(|pair| (pair[0].to_digit(16).unwrap() * 16 + pair[1].to_digit(16).unwrap()) as u8)
+        .collect())
+}
+
+#[derive(Default)]
+struct Stream {
+    times_us: Vec<u64>,
+    malformed: usize,
+}
+
+/// Packets per type, estimated loss and inter-arrival histograms per flow.
+fn summarize(packets: &[Packet]) {
+    let mut streams: BTreeMap<(&str, PacketKind), Stream> = BTreeMap::new();
+    for packet in packets {
+        let dissection = dissect::dissect(&packet.data);
+        let stream = streams.entry((&packet.flow, dissection.kind)).or_default();
+        stream.times_us.push(packet.time_us);
+        if dissection.is_malformed() {
+            stream.malformed += 1;
+        }
+    }
+
+    for (&(flow, kind), stream) in &streams {
+        let gaps: Vec<u64> = stream.times_us.windows(2).map(|w| w[1].saturating_sub(w[0])).collect();
+        println!(
+            "{}  {}: {} packets, {} malformed",
+            flow,
+            kind.name(),
+            stream.times_us.len(),
+            stream.malformed
+        );
+        if gaps.is_empty() || kind == PacketKind::Whoami || kind == PacketKind::Unknown {
+            continue;
+        }
+        // gamestates and inputs are sent once per frame, so a gap of several
+        // frame intervals means the datagrams in between got lost
+        let mut sorted = gaps.clone();
+        sorted.sort();
+        let interval = sorted[sorted.len() / 2].max(1);
+        let missing: u64 = gaps
+            .iter()
+            .filter(|&&gap| gap * 2 > interval * 3)
+            .map(|&gap| (gap + interval / 2) / interval - 1)
+            .sum();
+        let expected = stream.times_us.len() as u64 + missing;
+        println!(
+            "    frame interval {:.1} ms, ~{} lost ({:.1}%)",
+            interval as f64 / 1000.0,
+            missing,
+            missing as f64 * 100.0 / expected as f64
+        );
+        print_histogram(&gaps);
+    }
+}
+
+fn print_histogram(gaps_us: &[u64]) {
+    let mut buckets = [0usize; HISTOGRAM_BUCKETS + 1];
+    for &gap in gaps_us {
+        let bucket = (gap / 1000 / HISTOGRAM_BUCKET_MS) as usize;
+        buckets[bucket.min(HISTOGRAM_BUCKETS)] += 1;
+    }
+    let largest = buckets.iter().cloned().max().unwrap_or(0).max(1);
+    for (i, &count) in buckets.iter().enumerate() {
+        if count == 0 {
+            continue;
+        }
+        let label = if i == HISTOGRAM_BUCKETS {
+            format!(">={:3} ms", i as u64 * HISTOGRAM_BUCKET_MS)
+        } else {
+            format!("{:3}-{:3} ms", i as u64 * HISTOGRAM_BUCKET_MS, (i as u64 + 1) * HISTOGRAM_BUCKET_MS)
+        };
+        let bar = "#".repeat((count * HISTOGRAM_WIDTH).div_ceil(largest));
+        println!("    {:>10} {:6} {}", label, count, bar);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network::packets::Serializable;
+    use network::{GamestatePacket, InputPacket, WhoamiPacket};
+    use rules::MatchSettings;
+
+    fn problems(data: &[u8]) -> Vec<&'static str> {
+        dissect::dissect(data).fields.iter().filter(|field| field.problem.is_some()).map(|field| field.name).collect()
+    }
+
+    // the fields follow each other and fill the packet
+    fn assert_laid_out(data: &[u8], kind: PacketKind, names: &[&str]) {
+        let dissection = dissect::dissect(data);
+        assert_eq!(dissection.kind, kind);
+        assert!(!dissection.is_malformed(), "{}", dissect::format(data, &dissection));
+        let fields: Vec<&str> = dissection.fields.iter().map(|field| field.name).collect();
+        assert_eq!(fields, names);
+        let mut offset = 0;
+        for field in &dissection.fields {
+            assert_eq!(field.offset, offset, "{}", field.name);
+            offset += field.len;
+        }
+        assert_eq!(offset, data.len());
+    }
+
+    #[test]
+    fn gamestates_are_24_bytes() {
+        let data = GamestatePacket::new(0).serialize();
+        assert_eq!(data.len(), 24);
+        assert_laid_out(&data, PacketKind::Gamestate, &[
+            "rackets[0].x", "rackets[0].y", "rackets[1].x", "rackets[1].y", "ball.x", "ball.y", "ball.x_vel",
+            "ball.y_vel", "score[0]", "score[1]", "state", "sets[0]", "sets[1]", "time_left", "votes",
+        ]);
+    }
+
+    #[test]
+    fn inputs_are_4_bytes() {
+        let data = InputPacket::new().serialize();
+        assert_eq!(data.len(), 4);
+        assert_laid_out(&data, PacketKind::Input, &["goal_y", "pause_requests", "vote"]);
+        assert_eq!(dissect::dissect(&data).fields[0].value, 136);
+        assert_eq!(problems(&[0xFF, 0xFF, 0, 0]), ["goal_y"]);
+        assert_eq!(problems(&[0, 0, 0, 3]), ["vote"]);
+    }
+
+    #[test]
+    fn whoamis_are_1_byte() {
+        for &is_server in &[false, true] {
+            assert_laid_out(&WhoamiPacket::new(is_server).serialize(), PacketKind::Whoami, &["is_server"]);
+        }
+        assert_eq!(problems(&[1]), ["is_server"]);
+    }
+
+    #[test]
+    fn settings_are_7_bytes() {
+        let data = MatchSettings::new().serialize();
+        assert_eq!(data.len(), 7);
+        assert_laid_out(&data, PacketKind::Settings, &[
+            "score_limit", "win_by_two", "time_limit", "best_of", "racket_size", "ball_speed",
+        ]);
+        assert_eq!(problems(&[0, 2, 0, 0, 2, 0, 0]),
+                   ["score_limit", "win_by_two", "best_of", "racket_size", "ball_speed"]);
+    }
+
+    #[test]
+    fn other_lengths_are_unknown() {
+        for len in &[0, 2, 3, 5, 6, 8, 23, 25, 1500] {
+            let data = vec![0; *len];
+            let dissection = dissect::dissect(&data);
+            assert_eq!(dissection.kind, PacketKind::Unknown, "{} bytes", len);
+            assert!(dissection.is_malformed() && dissection.fields.is_empty(), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn scores_and_sets_past_their_limits_are_malformed() {
+        let mut gamestate = GamestatePacket::new(0);
+        // 99 points fit on screen, 3 sets win a best of five
+        gamestate.score = [99, 99];
+        gamestate.sets = [3, 3];
+        assert!(problems(&gamestate.serialize()).is_empty());
+        gamestate.score = [100, 255];
+        gamestate.sets = [4, 255];
+        assert_eq!(problems(&gamestate.serialize()), ["score[0]", "score[1]", "sets[0]", "sets[1]"]);
+    }
+
+    #[test]
+    fn balls_past_their_limits_are_malformed() {
+        let mut gamestate = GamestatePacket::new(0);
+        gamestate.ball.x_vel = i16::MIN;
+        gamestate.ball.y_vel = i16::MAX;
+        gamestate.ball.x = -1;
+        gamestate.state = 2;
+        gamestate.votes = 1 << 2;
+        assert_eq!(problems(&gamestate.serialize()), ["ball.x", "ball.x_vel", "ball.y_vel", "state", "votes"]);
+    }
+}
@@ -0,0 +1,415 @@
+// Checks the drawing primitives (see `src/lcd/draw.rs`) and clipping: shapes
+// at any signed coordinates, through clip rectangles inside and around the
+// buffer, never write outside of it or the clip and cover exactly the pixels
+// of the shape that are inside, worked out pixel by pixel in 128 bits.
+// Filled shapes have to be drawn in spans. The tests also compare lines with
+// the textbook Bresenham loop and draw a few shapes whose pixels are known.
+
+use args::Args;
+use fuzz::{self, Buffer, Fuzz, XorShift, HEIGHT, WIDTH};
+use lcd::draw;
+use lcd::Framebuffer;
+use rect::Rect;
+
+const USAGE: &str = "usage: pong-host draw check [--rounds N] [--seed N]";
+
+pub fn run(args: &mut Args) -> Result<(), String> {
+    match args.command().as_deref() {
+        Some("check") => check(args),
+        _ => Err(String::from(USAGE)),
+    }
+}
+
+fn check(args: &mut Args) -> Result<(), String> {
+    let fuzz = Fuzz::from_args(args, 2000)?;
+    args.finish()?;
+
+    let drawn = check_shapes(&fuzz)?;
+    println!("{} shapes at signed coordinates draw {} pixels, all inside the buffer and their clips", fuzz.rounds(),
+             drawn);
+    Ok(())
+}
+
+fn check_shapes(fuzz: &Fuzz) -> Result<usize, String> {
+    let mut drawn = 0;
+    fuzz.run(|_, random| {
+        let shape = Shape::random(random);
+        let clips: Vec<Rect> = (0..random.below(3)).map(|_| fuzz::random_clip(random)).collect();
+        let color = 1 + random.below(255) as u8;
+
+        let mut buffer = Buffer::new();
+        fuzz::clipped(&mut buffer, &clips, &mut |framebuffer| shape.draw(framebuffer, color));
+        let describe = || format!("{:?} clipped to {:?}", shape, clips);
+        if buffer.strays > 0 {
+            return Err(format!("{} writes outside of the buffer, {}", buffer.strays, describe()));
+        }
+        if shape.is_filled() && buffer.pixel_calls > 0 {
+            return Err(format!("{} pixels set one by one, not in spans, {}", buffer.pixel_calls, describe()));
+        }
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let inside = clips.iter().all(|clip| clip.contains(x as u16, y as u16));
+                let expected = if inside && shape.covers(x as i128, y as i128) { color } else { 0 };
+                if buffer.pixels[y * WIDTH + x] != expected {
+                    return Err(format!("{}, {} is {}, not {}, {}", x, y, buffer.pixels[y * WIDTH + x], expected,
+                                       describe()));
+                }
+                if expected != 0 {
+                    drawn += 1;
+                }
+            }
+        }
+        Ok(())
+    })?;
+    Ok(drawn)
+}
+
+#[derive(Debug)]
+enum Shape {
+    Pixel { x: i32, y: i32 },
+    Rect { x: i32, y: i32, width: u32, height: u32 },
+    Line { x0: i32, y0: i32, x1: i32, y1: i32, dash: u32, gap: u32 },
+    Round { x: i32, y: i32, radius: u32, filled: bool },
+    Oval { x: i32, y: i32, radius_x: u16, radius_y: u16, filled: bool },
+    Rounded { x: i32, y: i32, width: u32, height: u32, radius: u32, filled: bool },
+    Polygon { points: Vec<(i16, i16)>, filled: bool },
+}
+
+impl Shape {
+    fn random(random: &mut XorShift) -> Shape {
+        let (x, y) = (coordinate(random), coordinate(random));
+        let filled = random.below(2) == 0;
+        match random.below(7) {
+            0 => Shape::Pixel { x, y },
+            1 => Shape::Rect {
+                x,
+                y,
+                width: size(random),
+                height: size(random),
+            },
+            2 => Shape::Line {
+                x0: x,
+                y0: y,
+                x1: coordinate(random),
+                y1: coordinate(random),
+                dash: if filled { 1 } else { random.below(6) as u32 },
+                gap: if filled { 0 } else { random.below(6) as u32 },
+            },
+            3 => Shape::Round {
+                x,
+                y,
+                radius: size(random),
+                filled,
+            },
+            4 => Shape::Oval {
+                x,
+                y,
+                radius_x: radius(random),
+                radius_y: radius(random),
+                filled,
+            },
+            5 => Shape::Rounded {
+                x,
+                y,
+                width: size(random),
+                height: size(random),
+                radius: size(random),
+                filled,
+            },
+            _ => Shape::Polygon {
+                points: (0..3 + random.below(4)).map(|_| (corner(random), corner(random))).collect(),
+                filled,
+            },
+        }
+    }
+
+    fn is_filled(&self) -> bool {
+        match *self {
+            Shape::Pixel { .. } | Shape::Line { .. } => false,
+            Shape::Rect { .. } => true,
+            Shape::Round { filled, .. }
+            | Shape::Oval { filled, .. }
+            | Shape::Rounded { filled, .. }
+            | Shape::Polygon { filled, .. } => filled,
+        }
+    }
+
+    fn draw(&self, framebuffer: &mut dyn Framebuffer, color: u8) {
+        match *self {
+            Shape::Pixel { x, y } => draw::pixel(framebuffer, x, y, color),
+            Shape::Rect { x, y, width, height } => draw::fill_rect(framebuffer, x, y, width, height, color),
+            Shape::Line { x0, y0, x1, y1, dash, gap } => {
+                if dash == 1 && gap == 0 {
+                    draw::line(framebuffer, x0, y0, x1, y1, color);
+                } else {
+                    draw::dashed_line(framebuffer, x0, y0, x1, y1, dash, gap, color);
+                }
+            }
+            Shape::Round { x, y, radius, filled: true } => draw::disc(framebuffer, x, y, radius, color),
+            Shape::Round { x, y, radius, filled: false } => draw::circle(framebuffer, x, y, radius, color),
+            Shape::Oval { x, y, radius_x, radius_y, filled: true } => {
+                draw::fill_ellipse(framebuffer, x, y, radius_x, radius_y, color)
+            }
+            Shape::Oval { x, y, radius_x, radius_y, filled: false } => {
+                draw::ellipse(framebuffer, x, y, radius_x, radius_y, color)
+            }
+            Shape::Rounded { x, y, width, height, radius, filled: true } => {
+                draw::fill_rounded_rect(framebuffer, x, y, width, height, radius, color)
+            }
+            Shape::Rounded { x, y, width, height, radius, filled: false } => {
+                draw::rounded_rect(framebuffer, x, y, width, height, radius, color)
+            }
+            Shape::Polygon { ref points, filled } => match (points.len(), filled) {
+                (3, true) => draw::fill_triangle(framebuffer, points[0], points[1], points[2], color),
+                (3, false) => draw::triangle(framebuffer, points[0], points[1], points[2], color),
+                (_, true) => draw::fill_polygon(framebuffer, points, color),
+                (_, false) => draw::polygon(framebuffer, points, color),
+            },
+        }
+    }
+
+    // an outline is what of the filled shape has a neighbour outside of it
+    fn covers(&self, x: i128, y: i128) -> bool {
+        let filled = self.is_filled();
+        match *self {
+            Shape::Line { x0, y0, x1, y1, dash, gap } => on_line(x0, y0, x1, y1, dash, gap, x, y),
+            Shape::Polygon { ref points, filled: false } => (0..points.len()).any(|i| {
+                let (x0, y0) = points[i];
+                let (x1, y1) = points[(i + 1) % points.len()];
+                on_line(x0.into(), y0.into(), x1.into(), y1.into(), 1, 0, x, y)
+            }),
+            _ if filled => self.inside(x, y),
+            Shape::Pixel { .. } => self.inside(x, y),
+            _ => {
+                self.inside(x, y)
+                    && !(self.inside(x - 1, y) && self.inside(x + 1, y) && self.inside(x, y - 1)
+                        && self.inside(x, y + 1))
+            }
+        }
+    }
+
+    fn inside(&self, px: i128, py: i128) -> bool {
+        match *self {
+            Shape::Pixel { x, y } => px == i128::from(x) && py == i128::from(y),
+            Shape::Rect { x, y, width, height } => {
+                let (x, y) = (i128::from(x), i128::from(y));
+                px >= x && px < x + i128::from(width) && py >= y && py < y + i128::from(height)
+            }
+            Shape::Round { x, y, radius, .. } => {
+                let (dx, dy) = (px - i128::from(x), py - i128::from(y));
+                dx * dx + dy * dy <= i128::from(radius) * i128::from(radius)
+            }
+            Shape::Oval { x, y, radius_x, radius_y, .. } => {
+                let (dx, dy) = (px - i128::from(x), py - i128::from(y));
+                let (rx, ry) = (i128::from(radius_x), i128::from(radius_y));
+                dx.abs() <= rx && dy.abs() <= ry && dx * dx * ry * ry + dy * dy * rx * rx <= rx * rx * ry * ry
+            }
+            Shape::Rounded { x, y, width, height, radius, .. } => {
+                let (x, y, width, height) = (i128::from(x), i128::from(y), i128::from(width), i128::from(height));
+                if px < x || px >= x + width || py < y || py >= y + height {
+                    return false;
+                }
+                // the nearest point of the rectangle the corners round
+                let radius = i128::from(radius).min((width - 1) / 2).min((height - 1) / 2);
+                let cx = px.max(x + radius).min(x + width - 1 - radius);
+                let cy = py.max(y + radius).min(y + height - 1 - radius);
+                (px - cx) * (px - cx) + (py - cy) * (py - cy) <= radius * radius
+            }
+            Shape::Polygon { ref points, .. } => {
+                // even-odd, counting the edges that cross the row left of
+                // the centre of the pixel, in half pixels
+                let (cx, cy) = (2 * px + 1, 2 * py + 1);
+                let mut crossings = 0;
+                for i in 0..points.len() {
+                    let (xa, ya) = (i128::from(points[i].0), i128::from(points[i].1));
+                    let next = points[(i + 1) % points.len()];
+                    let (xb, yb) = (i128::from(next.0), i128::from(next.1));
+                    if (2 * ya < cy) != (2 * yb < cy) {
+                        let twice = 2 * xa * (yb - ya) + (cy - 2 * ya) * (xb - xa);
+                        let at_or_left = if yb > ya { twice <= cx * (yb - ya) } else { twice >= cx * (yb - ya) };
+                        if at_or_left {
+                            crossings += 1;
+                        }
+                    }
+                }
+                crossings % 2 == 1
+            }
+            Shape::Line { .. } => unreachable!(),
+        }
+    }
+}
+
+// step k of a line along its longer axis is k, along the other one k times
+// the slope, halves rounded down
+#[allow(clippy::too_many_arguments)]
+fn on_line(x0: i32, y0: i32, x1: i32, y1: i32, dash: u32, gap: u32, x: i128, y: i128) -> bool {
+    let (x0, y0, x1, y1) = (i128::from(x0), i128::from(y0), i128::from(x1), i128::from(y1));
+    let steep = (y1 - y0).abs() > (x1 - x0).abs();
+    let (a0, a1, b0, b1, a, b) = if steep { (y0, y1, x0, x1, y, x) } else { (x0, x1, y0, y1, x, y) };
+    let (da, db) = ((a1 - a0).abs(), (b1 - b0).abs());
+    let k = (a - a0) * (a1 - a0).signum();
+    if da == 0 {
+        return a == a0 && b == b0 && (dash > 0 || gap == 0);
+    }
+    if (a - a0).abs() != k || k > da {
+        return false;
+    }
+    let period = i128::from(dash) + i128::from(gap);
+    let minor = (2 * k * db + da - 1) / (2 * da);
+    b == b0 + (b1 - b0).signum() * minor && (period == 0 || k % period < i128::from(dash))
+}
+
+// mostly near the buffer, sometimes at the ends of the range
+fn coordinate(random: &mut XorShift) -> i32 {
+    match random.below(10) {
+        0 => i32::MIN + random.below(100) as i32,
+        1 => i32::MAX - random.below(100) as i32,
+        _ => random.below(3 * WIDTH) as i32 - WIDTH as i32,
+    }
+}
+
+fn size(random: &mut XorShift) -> u32 {
+    match random.below(10) {
+        0 => u32::MAX - random.below(100) as u32,
+        1 => i32::MAX as u32 + random.below(100) as u32,
+        _ => random.below(WIDTH) as u32,
+    }
+}
+
+fn radius(random: &mut XorShift) -> u16 {
+    match random.below(10) {
+        0 => u16::MAX - random.below(100) as u16,
+        _ => random.below(WIDTH) as u16,
+    }
+}
+
+fn corner(random: &mut XorShift) -> i16 {
+    match random.below(12) {
+        0 => i16::MIN + random.below(100) as i16,
+        1 => i16::MAX - random.below(100) as i16,
+        _ => random.below(2 * WIDTH) as i16 - (WIDTH / 2) as i16,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lcd::Clipped;
+
+    // the rows of the top left corner of `buffer`, # where it is drawn
+    fn rows(buffer: &Buffer, width: usize, height: usize) -> Vec<String> {
+        (0..height)
+            .map(|y| (0..width).map(|x| if buffer.pixels[y * WIDTH + x] != 0 { '#' } else { '.' }).collect())
+            .collect()
+    }
+
+    #[test]
+    fn clip_keeps_the_part_inside() {
+        let bounds = Rect::new(0, 0, 10, 10);
+        assert_eq!(draw::clip(&bounds, -5, 2, 3, 20), Rect::new(0, 2, 3, 8));
+        assert!(draw::clip(&bounds, 10, 0, 20, 10).is_empty());
+        assert!(draw::clip(&bounds, -20, -20, 0, 0).is_empty());
+    }
+
+    #[test]
+    fn shapes_past_the_top_left_lose_what_is_outside() {
+        let mut buffer = Buffer::new();
+        draw::fill_rect(&mut buffer, -2, -1, 4, 3, 1);
+        draw::pixel(&mut buffer, -1, 3, 1);
+        draw::pixel(&mut buffer, i32::MIN, i32::MAX, 1);
+        assert_eq!(rows(&buffer, 4, 4), ["##..", "##..", "....", "...."]);
+        assert_eq!(buffer.strays, 0);
+    }
+
+    #[test]
+    fn nested_clips_draw_where_both_overlap() {
+        let mut buffer = Buffer::new();
+        {
+            let mut outer = Clipped::new(&mut buffer, &Rect::new(1, 0, 4, 3));
+            let mut inner = Clipped::new(&mut outer, &Rect::new(3, 1, 4, 4));
+            draw::fill_rect(&mut inner, -100, -100, 200, 200, 1);
+        }
+        assert_eq!(rows(&buffer, 6, 4), ["......", "...##.", "...##.", "......"]);
+        assert_eq!(buffer.strays, 0);
+    }
+
+    // every line between two points of a small grid, drawn in the middle of
+    // the buffer, against the loop that steps its error term
+    #[test]
+    fn lines_match_bresenham() {
+        for x0 in -6..7 {
+            for y0 in -6..7 {
+                for x1 in -6..7 {
+                    for y1 in -6..7 {
+                        let mut buffer = Buffer::new();
+                        draw::line(&mut buffer, 32 + x0, 24 + y0, 32 + x1, 24 + y1, 1);
+                        let mut expected = vec![0; WIDTH * HEIGHT];
+                        for (x, y) in bresenham(32 + x0, 24 + y0, 32 + x1, 24 + y1) {
+                            expected[y as usize * WIDTH + x as usize] = 1;
+                        }
+                        assert!(buffer.pixels == expected, "the line from {}, {} to {}, {} differs", x0, y0, x1, y1);
+                    }
+                }
+            }
+        }
+    }
+
+    fn bresenham(x0: i32, y0: i32, x1: i32, y1: i32) -> Vec<(i32, i32)> {
+        let steep = (y1 - y0).abs() > (x1 - x0).abs();
+        let (a0, a1, b0, b1) = if steep { (y0, y1, x0, x1) } else { (x0, x1, y0, y1) };
+        let (step_a, step_b) = ((a1 - a0).signum(), (b1 - b0).signum());
+        let (da, db) = ((a1 - a0).abs(), (b1 - b0).abs());
+        let mut pixels = Vec::new();
+        let (mut a, mut b) = (a0, b0);
+        let mut error = 2 * db - da;
+        for _ in 0..=da {
+            pixels.push(if steep { (b, a) } else { (a, b) });
+            if error > 0 {
+                b += step_b;
+                error -= 2 * da;
+            }
+            error += 2 * db;
+            a += step_a;
+        }
+        pixels
+    }
+
+    #[test]
+    fn dashes_start_at_the_first_point() {
+        let mut buffer = Buffer::new();
+        draw::dashed_line(&mut buffer, 0, 0, 11, 0, 2, 3, 1);
+        draw::dashed_line(&mut buffer, 0, 2, 0, 3, 2, 3, 1);
+        assert_eq!(rows(&buffer, 12, 4), ["##...##...##", "............", "#...........", "#..........."]);
+    }
+
+    #[test]
+    fn discs_and_circles_have_known_pixels() {
+        let mut buffer = Buffer::new();
+        draw::disc(&mut buffer, 2, 2, 2, 1);
+        draw::circle(&mut buffer, 8, 2, 2, 1);
+        assert_eq!(rows(&buffer, 11, 5), ["..#.....#..", ".###...#.#.", "#####.#...#", ".###...#.#.", "..#.....#.."]);
+    }
+
+    #[test]
+    fn rounded_rects_lose_their_corners() {
+        let mut buffer = Buffer::new();
+        draw::fill_rounded_rect(&mut buffer, 0, 0, 5, 4, 1, 1);
+        draw::rounded_rect(&mut buffer, 6, 0, 5, 4, 1, 1);
+        assert_eq!(rows(&buffer, 11, 4), [".###...###.", "#####.#...#", "#####.#...#", ".###...###."]);
+    }
+
+    // a pixel is filled when its centre is inside, so the rect covers 3 by 2
+    // and the triangle's long edge leaves out the pixels it halves
+    #[test]
+    fn polygons_fill_the_pixels_inside_their_edges() {
+        let mut buffer = Buffer::new();
+        draw::fill_polygon(&mut buffer, &[(1, 1), (4, 1), (4, 3), (1, 3)], 1);
+        draw::fill_triangle(&mut buffer, (6, 0), (10, 0), (6, 4), 1);
+        assert_eq!(rows(&buffer, 11, 5), ["......###..", ".###..##...", ".###..#....", "...........", "..........."]);
+    }
+
+    #[test]
+    fn shapes_cover_their_pixels_inside_the_clips() {
+        check_shapes(&Fuzz::new(2000, 1)).unwrap();
+    }
+}
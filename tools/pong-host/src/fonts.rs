@@ -0,0 +1,481 @@
+// Rasterises TrueType fonts into bitmap fonts (see `src/lcd/font.rs`), and
+// checks them: the rasteriser covers pixels by the exact area of an
+// outline, the glyphs of the font in `res` fit the boxes the scene and the
+// ui keep for them, and text written through the glyph atlas (see
+// `src/lcd/atlas.rs`) shows each glyph as it was rasterised, taking every
+// glyph from the font only once.
+
+use args::Args;
+use fuzz::{self, Buffer, Fuzz, HEIGHT, WIDTH};
+use lcd::font::{BitmapFont, FontError, GLYPH_LEN, HEADER_LEN, LEVELS, MAGIC, VERSION};
+use lcd::TextWriter;
+use palette;
+use rect::Rect;
+use std::f64::consts::PI;
+use std::fs;
+use std::slice;
+use truetype::{self, Raster};
+
+const USAGE: &str = "usage: pong-host font convert TTF OUT [--sizes 20,40] [--chars 32-126]
+       pong-host font check [--rounds N] [--seed N]";
+const SIZES: &str = "20,40";
+const CHARS: &str = "32-126";
+
+// the font the firmware writes with, and what it was converted to
+const TTF: &[u8] = include_bytes!("../../../res/RobotoMono-Bold.ttf");
+const FONT: &[u8] = include_bytes!("../../../res/RobotoMono-Bold.pfnt");
+
+// a glyph in the levels of the bitmap font, without empty rows and columns
+// around it
+#[derive(Debug, Clone, PartialEq)]
+struct Levels {
+    advance: u8,
+    left: i8,
+    top: i8,
+    width: u8,
+    height: u8,
+    levels: Vec<u8>,
+}
+
+pub fn run(args: &mut Args) -> Result<(), String> {
+    match args.command().as_deref() {
+        Some("convert") => convert(args),
+        Some("check") => check(args),
+        _ => Err(String::from(USAGE)),
+    }
+}
+
+fn convert(args: &mut Args) -> Result<(), String> {
+    let sizes = parse_sizes(&args.value("--sizes", String::from(SIZES))?)?;
+    let chars = parse_chars(&args.value("--chars", String::from(CHARS))?)?;
+    let input = args.positional("TTF")?;
+    let output = args.positional("OUT")?;
+    args.finish()?;
+
+    let ttf = fs::read(&input).map_err(|e| format!("{}: {}", input, e))?;
+    let font = convert_font(&ttf, &sizes, &chars).map_err(|e| format!("{}: {}", input, e))?;
+    fs::write(&output, &font).map_err(|e| format!("{}: {}", output, e))?;
+    let parsed = BitmapFont::parse(&font).map_err(|e| format!("{:?}", e))?;
+    for &size in &sizes {
+        let count = chars.iter().filter(|&&c| parsed.glyph(c, size).is_some()).count();
+        println!("{} px: {} of {} characters", size, count, chars.len());
+    }
+    println!("{}: {} bytes, {} of the TrueType font", output, font.len(), ttf.len());
+    Ok(())
+}
+
+fn parse_sizes(text: &str) -> Result<Vec<u8>, String> {
+    let mut sizes: Vec<u8> = text
+        .split(',')
+        .map(|size| size.trim().parse().map_err(|_| format!("invalid size {}", size)))
+        .collect::<Result<_, _>>()?;
+    sizes.sort();
+    sizes.dedup();
+    if sizes.contains(&0) || sizes.iter().any(|&size| size > 120) {
+        return Err(String::from("sizes go from 1 to 120 px"));
+    }
+    Ok(sizes)
+}
+
+// FIRST-LAST ranges of code points, separated by commas
+fn parse_chars(text: &str) -> Result<Vec<char>, String> {
+    let mut chars = Vec::new();
+    for range in text.split(',') {
+        let invalid = || format!("invalid range of characters {}", range);
+        let mut ends = range.splitn(2, '-').map(|end| end.trim().parse::<u32>());
+        let first = ends.next().and_then(Result::ok).ok_or_else(invalid)?;
+        let last = match ends.next() {
+            Some(last) => last.map_err(|_| invalid())?,
+            None => first,
+        };
+        chars.extend((first..=last).filter_map(std::char::from_u32));
+    }
+    chars.sort();
+    chars.dedup();
+    Ok(chars)
+}
+
+// every character the TrueType font has, at each size
+fn convert_font(ttf: &[u8], sizes: &[u8], chars: &[char]) -> Result<Vec<u8>, String> {
+    let font = truetype::Font::parse(ttf)?;
+    let mut glyphs = Vec::new();
+    for &size in sizes {
+        for &c in chars {
+            if let Some(index) = font.glyph_index(c)? {
+                let raster = truetype::rasterise(&font.outline(index, f64::from(size))?);
+                let advance = f64::from(font.advance(index)?) * font.scale(f64::from(size));
+                let levels = quantise(&raster, advance.round()).ok_or_else(|| {
+                    format!("{:?} at {} px is too large for the bitmap font", c, size)
+                })?;
+                glyphs.push((size, c, levels));
+            }
+        }
+    }
+    Ok(encode(sizes, &glyphs))
+}
+
+// the shade the firmware picked for a coverage before, in one of the levels
+fn level(coverage: f32) -> u8 {
+    ((coverage * 255.0 + 0.5) as u32 * u32::from(LEVELS - 1) / 255) as u8
+}
+
+fn quantise(raster: &Raster, advance: f64) -> Option<Levels> {
+    let at = |x: usize, y: usize| level(raster.coverage[y * raster.width + x]);
+    let rows: Vec<usize> = (0..raster.height).filter(|&y| (0..raster.width).any(|x| at(x, y) > 0)).collect();
+    let columns: Vec<usize> = (0..raster.width).filter(|&x| (0..raster.height).any(|y| at(x, y) > 0)).collect();
+    let (left, top, width, height) = match (rows.first(), rows.last(), columns.first(), columns.last()) {
+        (Some(&top), Some(&bottom), Some(&left), Some(&right)) => (left, top, right + 1 - left, bottom + 1 - top),
+        _ => (0, 0, 0, 0),
+    };
+    let mut levels = Vec::with_capacity(width * height);
+    for y in top..top + height {
+        for x in left..left + width {
+            levels.push(at(x, y));
+        }
+    }
+    let (x, y) = (raster.left + left as i32, raster.top + top as i32);
+    let fits = |value: i32, min: i32, max: i32| value >= min && value <= max;
+    if !fits(x, -128, 127) || !fits(y, -128, 127) || width > 255 || height > 255 || !fits(advance as i32, 0, 255) {
+        return None;
+    }
+    Some(Levels {
+        advance: advance as u8,
+        left: if width > 0 { x as i8 } else { 0 },
+        top: if height > 0 { y as i8 } else { 0 },
+        width: width as u8,
+        height: height as u8,
+        levels,
+    })
+}
+
+// the glyphs sorted by size and character; lines are as high as the size
+fn encode(sizes: &[u8], glyphs: &[(u8, char, Levels)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&[VERSION, sizes.len() as u8, (glyphs.len() >> 8) as u8, glyphs.len() as u8]);
+    for &size in sizes {
+        out.extend_from_slice(&[size, size]);
+    }
+    let mut coverage = Vec::new();
+    for &(size, c, ref glyph) in glyphs {
+        out.push(size);
+        out.extend_from_slice(&be32(c as u32));
+        out.extend_from_slice(&[glyph.advance, glyph.left as u8, glyph.top as u8, glyph.width, glyph.height]);
+        out.extend_from_slice(&be32(coverage.len() as u32));
+        for pair in glyph.levels.chunks(2) {
+            coverage.push(pair[0] << 4 | pair.get(1).cloned().unwrap_or(0));
+        }
+    }
+    out.extend_from_slice(&coverage);
+    out
+}
+
+fn be32(value: u32) -> [u8; 4] {
+    [(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]
+}
+
+fn check(args: &mut Args) -> Result<(), String> {
+    let fuzz = Fuzz::from_args(args, 500)?;
+    args.finish()?;
+
+    check_polygons(&fuzz)?;
+    println!("{} random polygons cover every pixel by the area inside it", fuzz.rounds());
+    check_writer(&fuzz)?;
+    println!("{} texts through clip rectangles show their glyphs, each taken from the font once", fuzz.rounds());
+    check_broken(&fuzz)?;
+    println!("{} truncated and damaged fonts are refused or written inside the buffer", 2 * fuzz.rounds());
+    Ok(())
+}
+
+// polygons around a point against their area and against samples
+fn check_polygons(fuzz: &Fuzz) -> Result<(), String> {
+    fuzz.run(|_, random| {
+        let (cx, cy) = (2.0 + random.below(2000) as f64 / 100.0, 2.0 + random.below(2000) as f64 / 100.0);
+        // no two corners half a turn apart, so the edges never cross
+        let corners = 4 + random.below(8);
+        let polygon: Vec<(f64, f64)> = (0..corners)
+            .map(|i| {
+                let angle = (i as f64 + random.below(1000) as f64 / 1000.0) * 2.0 * PI / corners as f64;
+                let radius = (1.0 + random.below(1000) as f64 / 100.0).min(cx).min(cy);
+                (cx + radius * angle.cos(), cy + radius * angle.sin())
+            })
+            .collect();
+        let raster = truetype::rasterise(slice::from_ref(&polygon));
+        let sum: f64 = raster.coverage.iter().map(|&coverage| f64::from(coverage)).sum();
+        let area = truetype::area(slice::from_ref(&polygon));
+        if (sum - area).abs() > 1e-3 * area.max(1.0) {
+            return Err(format!("{:?} covers {} pixels, its area is {}", polygon, sum, area));
+        }
+        for y in 0..raster.height {
+            for x in 0..raster.width {
+                let sampled = sample(&polygon, raster.left + x as i32, raster.top + y as i32);
+                let coverage = f64::from(raster.coverage[y * raster.width + x]);
+                if (coverage - sampled).abs() > 0.1 {
+                    return Err(format!("{}, {} of {:?} is covered {}, sampled {}", x, y, polygon, coverage,
+                                       sampled));
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+// how many of 16x16 points in a pixel are inside the polygon
+fn sample(polygon: &[(f64, f64)], x: i32, y: i32) -> f64 {
+    let mut inside = 0;
+    for sy in 0..16 {
+        for sx in 0..16 {
+            let (px, py) = (f64::from(x) + (f64::from(sx) + 0.5) / 16.0, f64::from(y) + (f64::from(sy) + 0.5) / 16.0);
+            let mut crossings = 0;
+            for (i, &(x0, y0)) in polygon.iter().enumerate() {
+                let (x1, y1) = polygon[(i + 1) % polygon.len()];
+                if (y0 > py) != (y1 > py) && px < x0 + (py - y0) * (x1 - x0) / (y1 - y0) {
+                    crossings += 1;
+                }
+            }
+            inside += crossings % 2;
+        }
+    }
+    f64::from(inside) / 256.0
+}
+
+fn check_writer(fuzz: &Fuzz) -> Result<(), String> {
+    let font = BitmapFont::parse(FONT).map_err(|e| format!("{:?}", e))?;
+    let colors = [palette::TEXT, palette::BALL, palette::RACKET, palette::CLOCK];
+    fuzz.run(|_, random| {
+        let size = if random.below(2) == 0 { 20 } else { 40 };
+        let text: String = (0..1 + random.below(5))
+            .map(|_| if random.below(20) == 0 { 'é' } else { (32 + random.below(95) as u8) as char })
+            .collect();
+        let (x, y) = (random.below(WIDTH), random.below(HEIGHT));
+        let clips: Vec<Rect> = (0..random.below(3)).map(|_| fuzz::random_clip(random)).collect();
+        let color = colors[random.below(colors.len())];
+        let describe = || format!("{:?} at {} px at {}, {} clipped to {:?}", text, size, x, y, clips);
+
+        // glyph by glyph, only where they cover
+        let mut expected = vec![0u8; WIDTH * HEIGHT];
+        let (mut caret, mut edges) = (x as i32, 0);
+        let mut distinct = Vec::new();
+        for c in text.chars() {
+            let c = if font.glyph(c, size).is_some() { c } else { '?' };
+            let glyph = font.glyph(c, size).unwrap();
+            if !distinct.contains(&c) {
+                distinct.push(c);
+            }
+            for gy in 0..usize::from(glyph.height) {
+                for gx in 0..usize::from(glyph.width) {
+                    let level = glyph.level(gx, gy);
+                    let px = caret + i32::from(glyph.left) + gx as i32;
+                    let py = y as i32 + i32::from(glyph.top) + gy as i32;
+                    let inside = px >= 0 && py >= 0 && (px as usize) < WIDTH && (py as usize) < HEIGHT
+                        && clips.iter().all(|clip| clip.contains(px as u16, py as u16));
+                    if level > 0 && inside {
+                        expected[py as usize * WIDTH + px as usize] = palette::shade(color, level * 17);
+                    }
+                    if level > 0 && level < LEVELS - 1 {
+                        edges += 1;
+                    }
+                }
+            }
+            caret += i32::from(glyph.advance);
+        }
+
+        let mut writer = TextWriter::new(font, size);
+        writer.set_color(color);
+        for pass in 0..2 {
+            let mut buffer = Buffer::new();
+            fuzz::clipped(&mut buffer, &clips, &mut |framebuffer| writer.write_at(framebuffer, &text, x, y));
+            if buffer.strays > 0 {
+                return Err(format!("{} writes outside of the buffer, {}", buffer.strays, describe()));
+            }
+            if buffer.pixel_calls > edges {
+                return Err(format!("{} pixels set one by one, the glyphs have {} edge pixels, {}",
+                                   buffer.pixel_calls, edges, describe()));
+            }
+            if let Some(i) = (0..WIDTH * HEIGHT).find(|&i| buffer.pixels[i] != expected[i]) {
+                return Err(format!("pass {}: {}, {} is {}, not {}, {}", pass, i % WIDTH, i / WIDTH, buffer.pixels[i],
+                                   expected[i], describe()));
+            }
+            if writer.atlas().len() != distinct.len() {
+                return Err(format!("pass {}: {} glyphs in the atlas, not {}, {}", pass, writer.atlas().len(),
+                                   distinct.len(), describe()));
+            }
+        }
+        Ok(())
+    })
+}
+
+// a shortened font is always refused, and a damaged one must not read past
+// it or write outside the buffer
+fn check_broken(fuzz: &Fuzz) -> Result<(), String> {
+    let sizes = [7, 12];
+    let chars = parse_chars("48-57,65-70")?;
+    let small = convert_font(TTF, &sizes, &chars)?;
+    let glyphs = sizes.len() * chars.len();
+    if small.len() < HEADER_LEN + 2 * sizes.len() + glyphs * GLYPH_LEN {
+        return Err(String::from("a font without coverage"));
+    }
+    let text: String = chars.iter().cloned().chain("?é".chars()).collect();
+    fuzz.run(|_, random| {
+        fuzz::broken(&small, &FontError::Truncated, random, |bytes, buffer, random| {
+            let font = BitmapFont::parse(bytes)?;
+            for &size in &sizes {
+                TextWriter::new(font, size).write_at(buffer, &text, random.below(WIDTH), random.below(HEIGHT));
+            }
+            Ok(())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // each pixel by how much of it overlaps the rectangle, in both windings
+    #[test]
+    fn rectangles_cover_their_overlap() -> Result<(), String> {
+        let (x0, y0, x1, y1) = (0.25, 0.5, 3.75, 2.25);
+        let rectangle = vec![(x0, y0), (x1, y0), (x1, y1), (x0, y1)];
+        let reversed: Vec<(f64, f64)> = rectangle.iter().rev().cloned().collect();
+        for polygon in &[rectangle, reversed] {
+            let raster = truetype::rasterise(slice::from_ref(polygon));
+            if (raster.left, raster.top, raster.width, raster.height) != (0, 0, 4, 3) {
+                return Err(format!("a rectangle is rasterised at {}, {} in {}x{}", raster.left, raster.top,
+                                   raster.width, raster.height));
+            }
+            for y in 0..3 {
+                for x in 0..4 {
+                    let overlap = |a: f64, b: f64, at: usize| (b.min(at as f64 + 1.0) - a.max(at as f64)).max(0.0);
+                    let expected = overlap(x0, x1, x) * overlap(y0, y1, y);
+                    let coverage = f64::from(raster.coverage[y * 4 + x]);
+                    if (coverage - expected).abs() > 1e-5 {
+                        return Err(format!("{}, {} of a rectangle is covered {}, not {}", x, y, coverage, expected));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // a square with a square hole running the other way
+    #[test]
+    fn holes_are_left_out() -> Result<(), String> {
+        let outer = vec![(0.0, 0.0), (6.0, 0.0), (6.0, 6.0), (0.0, 6.0)];
+        let hole = vec![(2.0, 2.0), (2.0, 4.0), (4.0, 4.0), (4.0, 2.0)];
+        let raster = truetype::rasterise(&[outer, hole]);
+        for (i, &coverage) in raster.coverage.iter().enumerate() {
+            let in_hole = (2..4).contains(&(i % 6)) && (2..4).contains(&(i / 6));
+            if (f64::from(coverage) - if in_hole { 0.0 } else { 1.0 }).abs() > 1e-5 {
+                return Err(format!("{}, {} of a square with a hole is covered {}", i % 6, i / 6, coverage));
+            }
+        }
+        Ok(())
+    }
+
+    // the font in res is what font convert makes of the TrueType font, and its
+    // glyphs fit the boxes of `scene::Font`
+    #[test]
+    fn font_fits_its_boxes() -> Result<(), String> {
+        let sizes = parse_sizes(SIZES)?;
+        let chars = parse_chars(CHARS)?;
+        let converted = convert_font(TTF, &sizes, &chars)?;
+        if converted != FONT {
+            return Err(String::from("res/RobotoMono-Bold.pfnt is not what font convert makes of the TrueType font"));
+        }
+        let font = BitmapFont::parse(FONT).map_err(|e| format!("{:?}", e))?;
+        let ttf = truetype::Font::parse(TTF)?;
+
+        // the boxes of `scene::Font` and the character width of the ui
+        let boxes = [(20, 12, 26), (40, 24, 48)];
+        for &(size, box_width, box_height) in &boxes {
+            let mut advances = Vec::new();
+            for &c in &chars {
+                let glyph = font.glyph(c, size).ok_or_else(|| format!("no {:?} at {} px", c, size))?;
+                advances.push(glyph.advance);
+                let (right, bottom) = (i32::from(glyph.left) + i32::from(glyph.width),
+                                       i32::from(glyph.top) + i32::from(glyph.height));
+                if glyph.left < 0 || glyph.top < 0 || right > box_width || bottom > box_height {
+                    return Err(format!("{:?} at {} px reaches out of its {}x{} box", c, size, box_width, box_height));
+                }
+
+                let outline = ttf.outline(ttf.glyph_index(c)?.unwrap(), f64::from(size))?;
+                let raster = truetype::rasterise(&outline);
+                let sum: f64 = raster.coverage.iter().map(|&coverage| f64::from(coverage)).sum();
+                let area = truetype::area(&outline);
+                if (sum - area).abs() > 1e-3 * area.max(1.0) {
+                    return Err(format!("{:?} at {} px covers {} pixels, its outline {}", c, size, sum, area));
+                }
+            }
+            if advances.iter().any(|&advance| advance != advances[0]) {
+                return Err(format!("the advances at {} px differ, the font is monospaced", size));
+            }
+        }
+        if font.glyph('0', 20).unwrap().advance != 9 {
+            return Err(String::from("the ui takes characters at 20 px to be 9 px apart"));
+        }
+        Ok(())
+    }
+
+    // the second A comes from the atlas and lands one advance further, the
+    // solid pixels of the glyph in the color itself
+    #[test]
+    fn repeated_glyphs_are_taken_from_the_atlas() {
+        let font = BitmapFont::parse(FONT).unwrap();
+        let glyph = font.glyph('A', 20).unwrap();
+        let advance = usize::from(glyph.advance);
+        let mut writer = TextWriter::new(font, 20);
+        let mut buffer = Buffer::new();
+        writer.write_at(&mut buffer, "AA", 0, 0);
+        assert_eq!(writer.atlas().len(), 1);
+        assert_eq!(buffer.strays, 0);
+
+        let mut solid = 0;
+        for gy in 0..usize::from(glyph.height) {
+            for gx in 0..usize::from(glyph.width) {
+                let (x, y) = (glyph.left as usize + gx, glyph.top as usize + gy);
+                assert_eq!(buffer.pixels[y * WIDTH + x], buffer.pixels[y * WIDTH + x + advance]);
+                if glyph.level(gx, gy) == LEVELS - 1 {
+                    assert_eq!(buffer.pixels[y * WIDTH + x], palette::TEXT);
+                    solid += 1;
+                }
+            }
+        }
+        assert!(solid > 0);
+    }
+
+    #[test]
+    fn missing_characters_are_question_marks() {
+        let font = BitmapFont::parse(FONT).unwrap();
+        let (mut question, mut missing) = (Buffer::new(), Buffer::new());
+        TextWriter::new(font, 20).write_at(&mut question, "?", 4, 4);
+        TextWriter::new(font, 20).write_at(&mut missing, "é", 4, 4);
+        assert!(question.pixels.iter().any(|&pixel| pixel != 0));
+        assert!(question.pixels == missing.pixels);
+    }
+
+    #[test]
+    fn short_fonts_are_truncated() -> Result<(), String> {
+        let small = convert_font(TTF, &[7], &parse_chars("48-49")?)?;
+        for &cut in &[0, HEADER_LEN - 1, HEADER_LEN, HEADER_LEN + 2, small.len() - 1] {
+            assert_eq!(BitmapFont::parse(&small[..cut]).err(), Some(FontError::Truncated), "cut to {}", cut);
+        }
+        assert!(BitmapFont::parse(&small).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn polygons_cover_their_area() {
+        check_polygons(&Fuzz::new(200, 1)).unwrap();
+    }
+
+    #[test]
+    fn texts_show_their_glyphs() {
+        check_writer(&Fuzz::new(200, 1)).unwrap();
+    }
+
+    #[test]
+    fn broken_fonts_are_refused_or_written_inside() {
+        check_broken(&Fuzz::new(200, 1)).unwrap();
+    }
+}
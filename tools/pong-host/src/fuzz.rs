@@ -0,0 +1,135 @@
+// The random half of the `check` subcommands, shared by them: rounds of a
+// check drawn from one generator, so a failing round comes back with the
+// same `--seed`, and a framebuffer that counts what lands outside of it.
+// The tests run each of them at a fixed seed.
+
+use args::Args;
+use lcd::{Clipped, Framebuffer};
+pub use network::sim::XorShift;
+use rect::Rect;
+use std::fmt::Debug;
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 48;
+
+pub struct Fuzz {
+    rounds: usize,
+    seed: u32,
+}
+
+impl Fuzz {
+    pub fn new(rounds: usize, seed: u32) -> Fuzz {
+        Fuzz { rounds, seed }
+    }
+
+    /// `--rounds`, `rounds` unless given, and `--seed`.
+    pub fn from_args(args: &mut Args, rounds: usize) -> Result<Fuzz, String> {
+        Ok(Fuzz::new(args.value("--rounds", rounds)?, args.value("--seed", 1)?))
+    }
+
+    pub fn rounds(&self) -> usize {
+        self.rounds
+    }
+
+    /// Runs `check` once per round, a failure names the round and the seed.
+    pub fn run<F>(&self, mut check: F) -> Result<(), String>
+    where
+        F: FnMut(usize, &mut XorShift) -> Result<(), String>,
+    {
+        let mut random = XorShift::new(self.seed);
+        for round in 0..self.rounds {
+            check(round, &mut random).map_err(|e| format!("round {} of seed {}: {}", round, self.seed, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Around, across and past the buffer.
+pub fn random_clip(random: &mut XorShift) -> Rect {
+    Rect::new(random.below(WIDTH + 16), random.below(HEIGHT + 16), random.below(WIDTH + 16),
+              random.below(HEIGHT + 16))
+}
+
+/// Draws through `clips`, each inside the one before.
+pub fn clipped(framebuffer: &mut dyn Framebuffer, clips: &[Rect], draw: &mut dyn FnMut(&mut dyn Framebuffer)) {
+    match clips.split_first() {
+        Some((clip, rest)) => clipped(&mut Clipped::new(framebuffer, clip), rest, draw),
+        None => draw(framebuffer),
+    }
+}
+
+/// `bytes` cut short are always refused as `truncated`, and with a few bytes
+/// damaged `draw` either refuses them or draws inside the buffer.
+pub fn broken<E, F>(bytes: &[u8], truncated: &E, random: &mut XorShift, mut draw: F) -> Result<(), String>
+where
+    E: Debug + PartialEq,
+    F: FnMut(&[u8], &mut Buffer, &mut XorShift) -> Result<(), E>,
+{
+    let cut = random.below(bytes.len());
+    match draw(&bytes[..cut], &mut Buffer::new(), random) {
+        Err(ref error) if error == truncated => {}
+        result => return Err(format!("cut to {} bytes of {} gives {:?}", cut, bytes.len(), result)),
+    }
+
+    let mut damaged = bytes.to_vec();
+    for _ in 0..1 + random.below(3) {
+        let i = random.below(damaged.len());
+        damaged[i] = random.below(256) as u8;
+    }
+    let mut buffer = Buffer::new();
+    if draw(&damaged, &mut buffer, random).is_ok() && buffer.strays > 0 {
+        return Err(format!("a damaged file writes {} pixels outside of the buffer", buffer.strays));
+    }
+    Ok(())
+}
+
+/// A framebuffer that counts writes outside of it instead of checking them,
+/// and the pixels set one at a time.
+pub struct Buffer {
+    pub pixels: Vec<u8>,
+    pub strays: usize,
+    pub pixel_calls: usize,
+}
+
+impl Buffer {
+    pub fn new() -> Buffer {
+        Buffer {
+            pixels: vec![0; WIDTH * HEIGHT],
+            strays: 0,
+            pixel_calls: 0,
+        }
+    }
+
+    fn write(&mut self, x: usize, y: usize, color: u8) {
+        if x < WIDTH && y < HEIGHT {
+            self.pixels[y * WIDTH + x] = color;
+        } else {
+            self.strays += 1;
+        }
+    }
+}
+
+impl Framebuffer for Buffer {
+    fn set_pixel(&mut self, x: usize, y: usize, color: u8) {
+        self.pixel_calls += 1;
+        self.write(x, y, color);
+    }
+
+    fn swap_buffers(&mut self) {}
+
+    fn clear(&mut self) {
+        self.pixels = vec![0; WIDTH * HEIGHT];
+    }
+
+    fn bounds(&self) -> Rect {
+        Rect::new(0, 0, WIDTH, HEIGHT)
+    }
+
+    fn fill_rect(&mut self, rect: Rect, color: u8) {
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                self.write(x, y, color);
+            }
+        }
+    }
+}
@@ -0,0 +1,407 @@
+// Reads PNG and PGM files for the asset converter, as grey levels with an
+// alpha each. Colors become their luma, the skins take their hue from the
+// palette. PNGs may have any color type at 8 or 16 bits, without
+// interlacing.
+
+use crc;
+
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<(u8, u8)>, // grey, alpha
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Image, String> {
+    if bytes.starts_with(PNG_SIGNATURE) {
+        decode_png(bytes)
+    } else if bytes.starts_with(b"P5") || bytes.starts_with(b"P2") {
+        decode_pgm(bytes)
+    } else {
+        Err(String::from("neither a PNG nor a PGM file"))
+    }
+}
+
+pub const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// The widest and tallest image a bitmap can hold.
+pub const MAX_SIZE: usize = 0xFFFF;
+
+fn decode_png(bytes: &[u8]) -> Result<Image, String> {
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut transparency: &[u8] = &[];
+    let mut data = Vec::new();
+    let mut rest = &bytes[PNG_SIGNATURE.len()..];
+    loop {
+        if rest.len() < 12 {
+            return Err(String::from("truncated PNG chunk"));
+        }
+        let length = be32(rest) as usize;
+        if rest.len() < 12 + length {
+            return Err(String::from("truncated PNG chunk"));
+        }
+        let (kind, body) = (&rest[4..8], &rest[8..8 + length]);
+        if crc::crc32(&rest[4..8 + length]) != be32(&rest[8 + length..]) {
+            return Err(format!("bad checksum on the {} chunk", String::from_utf8_lossy(kind)));
+        }
+        match kind {
+            b"IHDR" if body.len() == 13 => header = Some(body),
+            b"PLTE" => palette = body,
+            b"tRNS" => transparency = body,
+            b"IDAT" => data.extend_from_slice(body),
+            b"IEND" => break,
+            _ if kind[0] & 0x20 == 0 => {
+                return Err(format!("unknown critical chunk {}", String::from_utf8_lossy(kind)))
+            }
+            _ => {}
+        }
+        rest = &rest[12 + length..];
+    }
+
+    let header = header.ok_or("no IHDR chunk")?;
+    let (width, height) = (be32(header) as usize, be32(&header[4..]) as usize);
+    check_size(width, height)?;
+    let (depth, color_type) = (header[8], header[9]);
+    if header[12] != 0 {
+        return Err(String::from("interlaced PNGs are not supported"));
+    }
+    if depth != 8 && depth != 16 {
+        return Err(format!("{} bit PNGs are not supported", depth));
+    }
+    let channels = match color_type {
+        0 => 1,
+        2 => 3,
+        3 if depth == 8 => 1,
+        4 => 2,
+        6 => 4,
+        _ => return Err(format!("color type {} at {} bits is not supported", color_type, depth)),
+    };
+    let pixel_bytes = channels * usize::from(depth) / 8;
+    let raw = unfilter(&inflate_zlib(&data)?, width, height, pixel_bytes)?;
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for pixel in raw.chunks(pixel_bytes) {
+        // the high byte of 16 bit samples
+        let sample = |i: usize| pixel[i * pixel_bytes / channels];
+        pixels.push(match color_type {
+            0 => (sample(0), 255),
+            2 => (luma(sample(0), sample(1), sample(2)), 255),
+            3 => {
+                let index = usize::from(pixel[0]);
+                let color = palette.get(3 * index..3 * index + 3).ok_or("pixel outside of the palette")?;
+                (luma(color[0], color[1], color[2]), transparency.get(index).cloned().unwrap_or(255))
+            }
+            4 => (sample(0), sample(1)),
+            _ => (luma(sample(0), sample(1), sample(2)), sample(3)),
+        });
+    }
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+// Rec. 601, like most grey conversions
+fn luma(red: u8, green: u8, blue: u8) -> u8 {
+    ((u32::from(red) * 299 + u32::from(green) * 587 + u32::from(blue) * 114 + 500) / 1000) as u8
+}
+
+fn check_size(width: usize, height: usize) -> Result<(), String> {
+    if width > MAX_SIZE || height > MAX_SIZE {
+        return Err(format!("{}x{} is larger than a bitmap can be", width, height));
+    }
+    Ok(())
+}
+
+fn be32(bytes: &[u8]) -> u32 {
+    u32::from(bytes[0]) << 24 | u32::from(bytes[1]) << 16 | u32::from(bytes[2]) << 8 | u32::from(bytes[3])
+}
+
+// undoes the filter in front of each line
+fn unfilter(data: &[u8], width: usize, height: usize, pixel_bytes: usize) -> Result<Vec<u8>, String> {
+    let line = width.checked_mul(pixel_bytes).ok_or("the image is too large")?;
+    let length = (line + 1).checked_mul(height).ok_or("the image is too large")?;
+    if data.len() != length {
+        return Err(format!("{} bytes of pixels, a {}x{} image has {}", data.len(), width, height, length));
+    }
+    let mut out = vec![0u8; height * line];
+    for y in 0..height {
+        let filter = data[y * (line + 1)];
+        let source = &data[y * (line + 1) + 1..(y + 1) * (line + 1)];
+        for i in 0..line {
+            let left = if i >= pixel_bytes { out[y * line + i - pixel_bytes] } else { 0 };
+            let up = if y > 0 { out[(y - 1) * line + i] } else { 0 };
+            let up_left = if y > 0 && i >= pixel_bytes { out[(y - 1) * line + i - pixel_bytes] } else { 0 };
+            let predicted = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(format!("unknown filter {} on line {}", filter, y)),
+            };
+            out[y * line + i] = source[i].wrapping_add(predicted);
+        }
+    }
+    Ok(out)
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let (a, b, c) = (i16::from(left), i16::from(up), i16::from(up_left));
+    let p = a + b - c;
+    let (pa, pb, pc) = ((p - a).abs(), (p - b).abs(), (p - c).abs());
+    if pa <= pb && pa <= pc {
+        left
+    } else if pb <= pc {
+        up
+    } else {
+        up_left
+    }
+}
+
+fn inflate_zlib(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 6 || data[0] & 0x0F != 8 || (u16::from(data[0]) << 8 | u16::from(data[1])) % 31 != 0 {
+        return Err(String::from("not a zlib stream"));
+    }
+    if data[1] & 0x20 != 0 {
+        return Err(String::from("zlib streams with a preset dictionary are not supported"));
+    }
+    let out = inflate(&data[2..])?;
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in &out {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    let stored = be32(&data[data.len() - 4..]);
+    if b << 16 | a != stored {
+        return Err(String::from("bad zlib checksum"));
+    }
+    Ok(out)
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097,
+    6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+// deflate (RFC 1951), with canonical codes decoded a bit at a time
+fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut bits = Bits { data, position: 0 };
+    let mut out = Vec::new();
+    loop {
+        let last = bits.take(1)? == 1;
+        match bits.take(2)? {
+            0 => {
+                bits.position = bits.position.div_ceil(8) * 8;
+                let length = bits.take(16)? as usize;
+                if bits.take(16)? as usize != !length & 0xFFFF {
+                    return Err(String::from("bad length of a stored block"));
+                }
+                let start = bits.position / 8;
+                let block = data.get(start..start + length).ok_or("truncated stored block")?;
+                out.extend_from_slice(block);
+                bits.position += 8 * length;
+            }
+            1 => {
+                let mut lengths = [0u8; 288 + 32];
+                for (i, length) in lengths.iter_mut().enumerate() {
+                    *length = match i {
+                        0..=143 => 8,
+                        144..=255 => 9,
+                        256..=279 => 7,
+                        280..=287 => 8,
+                        _ => 5,
+                    };
+                }
+                let (literals, distances) = (Code::new(&lengths[..288]), Code::new(&lengths[288..]));
+                inflate_block(&mut bits, &literals, &distances, &mut out)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut bits)?;
+                inflate_block(&mut bits, &literals, &distances, &mut out)?;
+            }
+            _ => return Err(String::from("invalid block type")),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn dynamic_codes(bits: &mut Bits) -> Result<(Code, Code), String> {
+    let literal_count = bits.take(5)? as usize + 257;
+    let distance_count = bits.take(5)? as usize + 1;
+    let length_count = bits.take(4)? as usize + 4;
+    let mut code_lengths = [0u8; 19];
+    for &i in CODE_LENGTH_ORDER.iter().take(length_count) {
+        code_lengths[i] = bits.take(3)? as u8;
+    }
+    let code_lengths = Code::new(&code_lengths);
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_lengths.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or("a repeat before the first code length")?, 3 + bits.take(2)?),
+            17 => (0, 3 + bits.take(3)?),
+            _ => (0, 11 + bits.take(7)?),
+        };
+        for _ in 0..repeat {
+            lengths.push(value);
+        }
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err(String::from("code lengths run past the codes"));
+    }
+    Ok((Code::new(&lengths[..literal_count]), Code::new(&lengths[literal_count..])))
+}
+
+fn inflate_block(bits: &mut Bits, literals: &Code, distances: &Code, out: &mut Vec<u8>) -> Result<(), String> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+        } else if symbol == 256 {
+            return Ok(());
+        } else {
+            let index = symbol - 257;
+            if index >= LENGTH_BASE.len() {
+                return Err(String::from("invalid length code"));
+            }
+            let length = LENGTH_BASE[index] as usize + bits.take(LENGTH_EXTRA[index])? as usize;
+            let index = distances.decode(bits)? as usize;
+            if index >= DISTANCE_BASE.len() {
+                return Err(String::from("invalid distance code"));
+            }
+            let distance = DISTANCE_BASE[index] as usize + bits.take(DISTANCE_EXTRA[index])? as usize;
+            if distance > out.len() {
+                return Err(String::from("a distance reaches before the start"));
+            }
+            for _ in 0..length {
+                let byte = out[out.len() - distance];
+                out.push(byte);
+            }
+        }
+    }
+}
+
+struct Bits<'a> {
+    data: &'a [u8],
+    position: usize, // in bits
+}
+
+impl<'a> Bits<'a> {
+    // least significant bit first
+    fn take(&mut self, count: u8) -> Result<u32, String> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = *self.data.get(self.position / 8).ok_or("truncated deflate stream")?;
+            value |= u32::from(byte >> (self.position % 8) & 1) << i;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+}
+
+// a canonical Huffman code as the number of codes of each length and the
+// symbols in code order
+struct Code {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Code {
+    fn new(lengths: &[u8]) -> Code {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols = Vec::new();
+        for length in 1..16 {
+            for (symbol, _) in lengths.iter().enumerate().filter(|&(_, &l)| l == length) {
+                symbols.push(symbol as u16);
+            }
+        }
+        Code {
+            counts,
+            symbols,
+        }
+    }
+
+    // codes are read most significant bit first
+    fn decode(&self, bits: &mut Bits) -> Result<u16, String> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..16 {
+            code |= bits.take(1)? as i32;
+            let count = i32::from(self.counts[length]);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(String::from("invalid Huffman code"))
+    }
+}
+
+// binary or plain, with comments, up to 16 bit
+fn decode_pgm(bytes: &[u8]) -> Result<Image, String> {
+    let mut position = 2;
+    let mut fields = [0usize; 3];
+    for field in fields.iter_mut() {
+        loop {
+            while position < bytes.len() && (bytes[position] as char).is_whitespace() {
+                position += 1;
+            }
+            if position < bytes.len() && bytes[position] == b'#' {
+                while position < bytes.len() && bytes[position] != b'\n' {
+                    position += 1;
+                }
+            } else {
+                break;
+            }
+        }
+        let start = position;
+        while position < bytes.len() && bytes[position].is_ascii_digit() {
+            position += 1;
+        }
+        *field = String::from_utf8_lossy(&bytes[start..position]).parse().map_err(|_| "invalid PGM header")?;
+    }
+    let [width, height, max] = fields;
+    check_size(width, height)?;
+    if max == 0 || max > 65535 {
+        return Err(format!("invalid maximum grey level {}", max));
+    }
+    let scale = |value: usize| (value.min(max) * 255 / max) as u8;
+    let count = width * height;
+    let values: Vec<usize> = if bytes[1] == b'5' {
+        let sample = if max > 255 { 2 } else { 1 };
+        let data = &bytes[(position + 1).min(bytes.len())..];
+        if data.len() < count * sample {
+            return Err(String::from("truncated PGM"));
+        }
+        data.chunks(sample).take(count).map(|s| s.iter().fold(0, |v, &b| v << 8 | usize::from(b))).collect()
+    } else {
+        let text = String::from_utf8_lossy(&bytes[position..]);
+        let values: Result<Vec<usize>, _> = text.split_whitespace().take(count).map(str::parse).collect();
+        values.map_err(|_| "invalid grey level in PGM")?
+    };
+    if values.len() < count {
+        return Err(String::from("truncated PGM"));
+    }
+    Ok(Image {
+        width,
+        height,
+        pixels: values.into_iter().map(|value| (scale(value), 255)).collect(),
+    })
+}
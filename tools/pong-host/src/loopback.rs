@@ -0,0 +1,284 @@
+// Runs a server and a client over a simulated link and checks that they
+// end up agreeing on the gamestate, optionally with the client pausing the
+// match on the way or both voting for rematches. While the link is impaired
+// the client may lag behind, but never shows a state the server did not send.
+
+use args::Args;
+use lcd::HEIGHT;
+use matches;
+use network::sim::{Side, SimConfig, SimStats, SimWire};
+use network::{handle_network_client, handle_network_server};
+use network::packets::{Serializable, STATE_PAUSED, STATE_WON_PLAYER_1, VOTE_NONE};
+use network::{Client, EthClient, EthServer, GamestatePacket, InputPacket, Server};
+use pause::Pause;
+use rematch;
+use physics::PhysicsCache;
+use rules::MatchSettings;
+use std::collections::HashMap;
+
+const FRAME_MS: usize = 16; // the LCD refreshes at about 60 Hz
+const WHOAMI_INTERVAL_MS: usize = 200; // same interval as the firmware
+const HANDSHAKE_TIMEOUT_MS: usize = 10_000;
+const DRAIN_FRAMES: usize = 120;
+const REMATCH_MISS: i16 = 100; // pixels the client aims off with --rematches
+
+pub fn run(args: &mut Args) -> Result<(), String> {
+    let defaults = SimConfig::perfect();
+    let config = SimConfig {
+        latency_ms: args.value("--latency", 30)?,
+        jitter_ms: args.value("--jitter", 10)?,
+        loss_percent: args.value("--loss", 10)?,
+        duplicate_percent: args.value("--duplicate", 5)?,
+        reorder_percent: args.value("--reorder", 5)?,
+        capacity: args.value("--capacity", defaults.capacity)?,
+    };
+    let frames = args.value("--frames", 3600)?;
+    let seed = args.value("--seed", 2018)?;
+    let pause_at = args.value("--pause-at", 0)?;
+    let rematches = args.value("--rematches", 0)?;
+    let settings = matches::settings_from_args(args)?;
+    args.finish()?;
+
+    let stats = simulate(config, &settings, seed, frames, pause_at, rematches)?;
+    println!("{:?}", config);
+    println!("{:?}", settings);
+    println!("{:?}", stats);
+    println!("server and client converged after {} frames", frames);
+    Ok(())
+}
+
+/// Plays `frames` frames over an impaired link, then lets the link settle
+/// and fails if client and server disagree about the game, or if the client
+/// got ahead of the server on the way. The client asks for a pause at frame
+/// `pause_at`, 0 for never. With `rematches` both vote for a rematch after
+/// every match and at least that many have to start.
+pub fn simulate(config: SimConfig, settings: &MatchSettings, seed: u32, frames: usize, pause_at: usize,
+                rematches: usize) -> Result<SimStats, String> {
+    let mut wire = SimWire::new(config, seed);
+    let mut server = EthServer::new();
+    let mut client = EthClient::new();
+
+    let mut server_gamestate = GamestatePacket::new(seed as usize);
+    let mut client_gamestate = GamestatePacket::new(0);
+    let mut server_input = InputPacket::new();
+    let mut client_input = InputPacket::new();
+    let mut physics_cache = PhysicsCache::new();
+    let mut pause = Pause::new();
+    let mut rematches_started = 0;
+    // every gamestate the server sent, with the match it belongs to
+    let mut sent: HashMap<Vec<u8>, usize> = HashMap::new();
+    let unreceived = GamestatePacket::new(0);
+
+    let mut server_connected = false;
+    let mut client_connected = false;
+    let mut client_whoami_time = 0;
+    let mut now = 0;
+
+    for frame in 1..=frames {
+        now += FRAME_MS;
+        wire.set_time(now);
+
+        if server_connected {
+            let inputs = handle_network_server(
+                &mut server,
+                &mut wire.endpoint(Side::Server),
+                &mut server_gamestate,
+                &server_input,
+                pause.game_time(now),
+                settings,
+                &mut physics_cache,
+            );
+            sent.entry(server_gamestate.serialize()).or_insert(rematches_started);
+            pause.handle_requests(inputs[1].pause_requests, &mut server_gamestate, now);
+            if rematches > 0 && server_gamestate.state >= STATE_WON_PLAYER_1 {
+                rematch::vote(&mut server_gamestate, &mut server_input, true);
+                if rematch::count_votes(&mut server_gamestate, &inputs[1]) {
+                    server_gamestate = GamestatePacket::new(now);
+                    physics_cache = PhysicsCache::new();
+                    pause.reset();
+                    rematches_started += 1;
+                }
+            }
+        } else {
+            server_connected = server.is_client_connected(&mut wire.endpoint(Side::Server));
+        }
+
+        if client_connected {
+            handle_network_client(
+                &mut client,
+                &mut wire.endpoint(Side::Client),
+                &mut client_gamestate,
+                &client_input,
+            );
+            check_client(&sent, &client_gamestate, &unreceived, &server_gamestate, rematches_started)
+                .map_err(|e| format!("frame {}: {}", frame, e))?;
+            if client_gamestate.state < STATE_WON_PLAYER_1 {
+                client_input.vote = VOTE_NONE;
+            } else if rematches > 0 {
+                rematch::vote(&mut client_gamestate, &mut client_input, false);
+            }
+        } else {
+            client_whoami_time += FRAME_MS;
+            if client_whoami_time > WHOAMI_INTERVAL_MS {
+                client_whoami_time = 0;
+                client.send_whoami(&mut wire.endpoint(Side::Client));
+            }
+            client_connected = client.is_server_connected(&mut wire.endpoint(Side::Client));
+        }
+
+        // both players just follow the ball they see, for rematches the
+        // client aims off so the matches end
+        server_input.goal_y = server_gamestate.ball.y;
+        client_input.goal_y = client_gamestate.ball.y;
+        if rematches > 0 {
+            client_input.goal_y = (client_input.goal_y + REMATCH_MISS) % HEIGHT as i16;
+        }
+        if frame == pause_at {
+            client_input.pause_requests = client_input.pause_requests.wrapping_add(1);
+        }
+
+        if !(server_connected && client_connected) && now > HANDSHAKE_TIMEOUT_MS {
+            return Err(format!(
+                "handshake did not finish within {} ms (server {}, client {})",
+                HANDSHAKE_TIMEOUT_MS, server_connected, client_connected
+            ));
+        }
+    }
+    if !(server_connected && client_connected) {
+        return Err(String::from("handshake did not finish, run more frames"));
+    }
+
+    // freeze the game and heal the link so everything in flight can settle
+    wire.set_config(SimConfig {
+        capacity: config.capacity,
+        ..SimConfig::perfect()
+    });
+    let mut received_input = InputPacket::new();
+    for _ in 0..DRAIN_FRAMES {
+        now += FRAME_MS;
+        wire.set_time(now);
+        received_input = server.receive_input(&mut wire.endpoint(Side::Server));
+        pause.handle_requests(received_input.pause_requests, &mut server_gamestate, now);
+        server.send_gamestate(&mut wire.endpoint(Side::Server), &server_gamestate);
+        handle_network_client(
+            &mut client,
+            &mut wire.endpoint(Side::Client),
+            &mut client_gamestate,
+            &client_input,
+        );
+    }
+
+    if client_gamestate != server_gamestate {
+        return Err(format!(
+            "gamestates diverged\n server: {:?}\n client: {:?}",
+            server_gamestate, client_gamestate
+        ));
+    }
+    if client.match_settings() != Some(*settings) {
+        return Err(format!(
+            "settings diverged\n server: {:?}\n client: {:?}",
+            settings,
+            client.match_settings()
+        ));
+    }
+    if pause_at > 0 && server_gamestate.state < STATE_WON_PLAYER_1 && server_gamestate.state != STATE_PAUSED {
+        return Err(format!("the pause requested at frame {} never reached the server", pause_at));
+    }
+    if rematches_started < rematches {
+        return Err(format!(
+            "only {} of {} rematches started, run more frames or lower --first-to",
+            rematches_started, rematches
+        ));
+    }
+    if received_input != client_input {
+        return Err(format!(
+            "inputs diverged\n client sent: {:?}\n server has: {:?}",
+            client_input, received_input
+        ));
+    }
+    Ok(wire.stats)
+}
+
+// the client shows a gamestate the server sent, whose score is not above the
+// server's in the same match
+fn check_client(sent: &HashMap<Vec<u8>, usize>, client: &GamestatePacket, unreceived: &GamestatePacket,
+                server: &GamestatePacket, current_match: usize) -> Result<(), String> {
+    let sent_in = match sent.get(&client.serialize()) {
+        Some(&sent_in) => sent_in,
+        None if client == unreceived => return Ok(()),
+        None => return Err(format!("the client shows a gamestate the server never sent: {:?}", client)),
+    };
+    let ahead = (0..2).any(|i| client.score[i] > server.score[i] || client.sets[i] > server.sets[i]);
+    if sent_in == current_match && ahead {
+        return Err(format!(
+            "the client's score is ahead of the server's\n server: {:?}\n client: {:?}",
+            server, client
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use network::packets::STATE_RUNNING;
+
+    fn impaired() -> SimConfig {
+        SimConfig {
+            latency_ms: 30,
+            jitter_ms: 10,
+            loss_percent: 10,
+            duplicate_percent: 5,
+            reorder_percent: 5,
+            capacity: SimConfig::perfect().capacity,
+        }
+    }
+
+    #[test]
+    fn perfect_link_converges() {
+        let stats = simulate(SimConfig::perfect(), &MatchSettings::new(), 2018, 1200, 0, 0).unwrap();
+        assert_eq!((stats.lost, stats.duplicated, stats.reordered), (0, 0, 0));
+    }
+
+    #[test]
+    fn impaired_link_converges() {
+        for seed in 2018..2022 {
+            let stats = simulate(impaired(), &MatchSettings::new(), seed, 1800, 0, 0).unwrap();
+            assert!(stats.lost > 0 && stats.duplicated > 0 && stats.reordered > 0, "{:?}", stats);
+        }
+    }
+
+    #[test]
+    fn pause_reaches_the_server() {
+        simulate(impaired(), &MatchSettings::new(), 2018, 1800, 900, 0).unwrap();
+    }
+
+    #[test]
+    fn rematches_start() {
+        let settings = MatchSettings {
+            score_limit: 1,
+            ..MatchSettings::new()
+        };
+        simulate(impaired(), &settings, 2018, 3600, 0, 2).unwrap();
+    }
+
+    // a replay in the middle of a timed set holds the clock, the set loses
+    // only the time before it and after it
+    #[test]
+    fn held_clocks_stand_still() {
+        let mut pause = Pause::new();
+        let mut gamestate = GamestatePacket::new(0);
+        pause.hold(1000);
+        assert_eq!(pause.game_time(4000), 1000);
+        pause.release(4000);
+        assert_eq!(pause.game_time(5000), 2000);
+        assert_eq!(gamestate.state, STATE_RUNNING);
+
+        // a paused clock is not held twice
+        pause.toggle(&mut gamestate, 6000);
+        pause.hold(7000);
+        pause.release(8000);
+        pause.toggle(&mut gamestate, 9000);
+        assert_eq!(pause.game_time(10_000), 4000);
+    }
+}
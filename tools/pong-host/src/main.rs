@@ -0,0 +1,193 @@
+// Host side tooling for the pong protocol.
+//
+// The protocol and physics modules of the firmware are compiled in directly
+// (see `network.rs`), the modules below stand in for the parts of the
+// firmware they import that only exist on the board.
+
+extern crate core;
+
+macro_rules! hprintln {
+    ($($arg:tt)*) => { eprintln!($($arg)*) };
+}
+
+mod alloc {
+    pub use std::string::String;
+    pub use std::vec::Vec;
+}
+
+mod lcd {
+    pub use atlas;
+    pub use bitmap;
+    pub use blit;
+    pub use canvas::Canvas;
+    pub use draw;
+    pub use font;
+    pub use format;
+    pub use framebuffer::{Clipped, Framebuffer};
+    pub use text::TextWriter;
+
+    pub const HEIGHT: usize = 272;
+    pub const WIDTH: usize = 480;
+}
+
+mod ball {
+    pub const BALL_RADIUS: u16 = 10;
+}
+
+mod racket {
+    pub const RACKET_WIDTH: u16 = 10;
+}
+
+// the firmware modules keep their idioms and have parts the host does not use,
+// each allows only the lints that raises
+#[path = "../../../src/lcd/atlas.rs"]
+#[allow(bare_trait_objects, clippy::len_without_is_empty, clippy::redundant_field_names)]
+pub mod atlas;
+#[path = "../../../src/lcd/bitmap.rs"]
+#[allow(bare_trait_objects, clippy::while_let_on_iterator)]
+pub mod bitmap;
+#[path = "../../../src/lcd/blit.rs"]
+#[allow(clippy::match_like_matches_macro, clippy::redundant_field_names)]
+pub mod blit;
+#[path = "../../../src/lcd/canvas.rs"]
+#[allow(clippy::redundant_field_names)]
+pub mod canvas;
+#[path = "../../../src/lcd/damage.rs"]
+mod damage;
+#[path = "../../../src/lcd/draw.rs"]
+#[allow(bare_trait_objects, clippy::cast_abs_to_unsigned, clippy::manual_checked_ops, clippy::too_many_arguments)]
+pub mod draw;
+#[path = "../../../src/lcd/font.rs"]
+#[allow(clippy::manual_div_ceil, clippy::manual_is_multiple_of, clippy::unnecessary_map_or)]
+pub mod font;
+#[path = "../../../src/lcd/format.rs"]
+pub mod format;
+#[path = "../../../src/lcd/framebuffer.rs"]
+#[allow(bare_trait_objects, clippy::redundant_field_names)]
+pub mod framebuffer;
+#[path = "../../../src/lcd/text.rs"]
+#[allow(bare_trait_objects, clippy::redundant_field_names)]
+pub mod text;
+#[path = "../../../src/cpu.rs"]
+#[allow(dead_code, clippy::redundant_field_names)]
+mod cpu;
+#[path = "../../../src/crc.rs"]
+mod crc;
+#[path = "../../../src/matchfile.rs"]
+#[allow(dead_code, clippy::redundant_field_names, clippy::single_match)]
+mod matchfile;
+#[path = "../../../src/palette.rs"]
+#[allow(dead_code)]
+mod palette;
+#[path = "../../../src/pause.rs"]
+#[allow(dead_code)]
+mod pause;
+#[path = "../../../src/physics.rs"]
+#[allow(clippy::manual_clamp, clippy::manual_is_multiple_of, clippy::needless_range_loop, clippy::unnecessary_cast)]
+mod physics;
+#[path = "../../../src/rect.rs"]
+#[allow(clippy::redundant_field_names)]
+mod rect;
+#[path = "../../../src/rematch.rs"]
+#[allow(dead_code)]
+mod rematch;
+#[path = "../../../src/rules.rs"]
+#[allow(dead_code, clippy::vec_init_then_push)]
+mod rules;
+#[path = "../../../src/settings.rs"]
+#[cfg(test)]
+#[allow(clippy::redundant_field_names)]
+mod settings;
+#[path = "../../../src/storage.rs"]
+#[cfg(test)]
+#[allow(clippy::redundant_field_names, clippy::unnecessary_map_or)]
+mod storage;
+
+mod args;
+mod assets;
+mod blits;
+mod bot;
+mod canvases;
+mod capture;
+mod dissect;
+mod draws;
+mod fonts;
+mod fuzz;
+mod images;
+mod loopback;
+mod matches;
+mod network;
+#[cfg(test)]
+mod palettes;
+mod pcap;
+mod play;
+#[cfg(test)]
+mod store;
+mod truetype;
+mod udp;
+
+use std::env;
+use std::process;
+
+const USAGE: &str = "usage: pong-host <command> [options]
+
+commands:
+    server      wait for a board client and play against it with a bot
+                --bind ADDR --partner ADDR --frames N --level LEVEL, rules as for match record
+    client      connect to a board server and play against it with a bot
+                --bind ADDR --partner ADDR --frames N --level LEVEL
+    capture     decode FILE: print the packets of a capture dump from a semihosting log
+                replay FILE [--partner ADDR --bind ADDR]: feed its gamestates into a
+                client, in memory or over UDP to a board
+    dissect     payload HEX | pcap FILE [--port N] | capture FILE, with [--summary]:
+                annotated field dumps, malformed packets, loss and timing per flow
+    match       verify | summary | simulate [--trace] FILE: check and re-run a match file
+                or semihosting log, extract LOG OUT: save its match as a file,
+                record OUT [--frames N --seed N --cpu-1 LEVEL --cpu-2 LEVEL]: write a match
+                between two cpu players, LEVEL is easy, normal or hard
+    loopback    run a server and a client against each other over a simulated link
+                --frames N --seed N --latency MS --jitter MS
+                --loss PCT --duplicate PCT --reorder PCT --capacity N --pause-at FRAME --rematches N,
+                rules as for match record
+    blit        check [--rounds N --seed N]: compare the software blitter, the fallback of
+                the DMA2D, with per-pixel loops, and page flips that copy only the damage
+                with a single buffer
+    canvas      check [--rounds N --seed N]: draw the same pixels, fills and copies on
+                framebuffers in memory in L8, RGB565, ARGB4444 and ARGB8888 and compare
+                their colors
+    draw        check [--rounds N --seed N]: draw lines, circles, ellipses, rounded rects and
+                polygons at any signed coordinates through nested clip rectangles and
+                compare them with per-pixel tests, no pixel may land outside
+    asset       convert IN OUT [--color NAME --indices --clear-black --rle]: turn a PNG or PGM
+                file into a bitmap for include_bytes!, grey levels become shades of a palette
+                color or, with --indices, palette indices, transparent pixels are keyed out
+                check [--rounds N --seed N]: encode, decode and draw bitmaps through clip
+                rectangles, and refuse broken ones
+    font        convert TTF OUT [--sizes 20,40 --chars 32-126]: rasterise a TrueType font into
+                a bitmap font for include_bytes!, sizes in px, characters as ranges of code points
+                check [--rounds N --seed N]: the rasteriser against exact areas, text written
+                through the glyph atlas and clip rectangles, and broken fonts
+
+cargo test runs the tests of these tools and of the firmware modules they build";
+
+fn main() {
+    let mut args = args::Args::new(env::args().skip(1).collect());
+    let result = match args.command().as_deref() {
+        Some("server") => play::run_server(&mut args),
+        Some("client") => play::run_client(&mut args),
+        Some("capture") => capture::run(&mut args),
+        Some("dissect") => dissect::run(&mut args),
+        Some("loopback") => loopback::run(&mut args),
+        Some("match") => matches::run(&mut args),
+        Some("blit") => blits::run(&mut args),
+        Some("canvas") => canvases::run(&mut args),
+        Some("draw") => draws::run(&mut args),
+        Some("asset") => assets::run(&mut args),
+        Some("font") => fonts::run(&mut args),
+        _ => Err(String::from(USAGE)),
+    };
+    if let Err(message) = result {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
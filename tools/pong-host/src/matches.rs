@@ -0,0 +1,414 @@
+// Checks, summarises and re-simulates match files (see `src/matchfile.rs`).
+
+use args::Args;
+use bot;
+use matchfile::{self, MatchFile, MatchRecorder};
+use network::packets::{STATE_WON_PLAYER_1, STATE_WON_PLAYER_2};
+use network::packets::Serializable;
+use network::{GamestatePacket, InputPacket};
+use physics;
+use physics::PhysicsCache;
+use rules::MatchSettings;
+use std::fs;
+
+const USAGE: &str = "usage: pong-host match verify FILE
+       pong-host match summary FILE
+       pong-host match simulate FILE [--trace]
+       pong-host match extract LOG OUT
+       pong-host match record OUT [--frames N] [--seed N] [--cpu-1 LEVEL] [--cpu-2 LEVEL] [rules]
+
+rules: --first-to N --win-by-two --time SECONDS --best-of N --racket-size PIXELS --ball-speed N";
+
+// the board alternates between 16 and 17 ms frames at about 60 Hz
+const FRAME_TIMES_MS: [usize; 3] = [17, 16, 17];
+
+pub fn run(args: &mut Args) -> Result<(), String> {
+    match args.command().as_deref() {
+        Some("verify") => verify(args),
+        Some("summary") => summary(args),
+        Some("simulate") => simulate(args),
+        Some("extract") => extract(args),
+        Some("record") => record(args),
+        _ => Err(String::from(USAGE)),
+    }
+}
+
+fn verify(args: &mut Args) -> Result<(), String> {
+    let path = args.positional("match file")?;
+    args.finish()?;
+    let file = load(&path)?;
+    let result = file.simulate(|_, _| {});
+    if result != file.final_state {
+        return Err(format!(
+            "{}: the inputs do not reproduce the final gamestate\n  stored    {:?}\n  simulated {:?}",
+            path, file.final_state, result
+        ));
+    }
+    println!("{}: ok, {} ticks reproduce the final gamestate", path, file.ticks.len());
+    Ok(())
+}
+
+fn summary(args: &mut Args) -> Result<(), String> {
+    let path = args.positional("match file")?;
+    args.finish()?;
+    let size = read_match_bytes(&path)?.len();
+    let file = load(&path)?;
+    let header = &file.header;
+    let duration = file.ticks.last().map_or(0, |tick| tick.total_time - header.seed as usize);
+
+    println!("version    {}", header.version);
+    println!("players    {} vs {}", header.names[0], header.names[1]);
+    println!("seed       {}", header.seed);
+    match file.settings() {
+        Ok(settings) => println!("settings   {:?}", settings),
+        Err(e) => println!("settings   {:?}", e),
+    }
+    println!(
+        "ticks      {} over {}.{:03} s{}",
+        file.ticks.len(),
+        duration / 1000,
+        duration % 1000,
+        if header.truncated { " (truncated, the match went on)" } else { "" }
+    );
+    println!(
+        "size       {} bytes, {:.2} bytes per tick",
+        size,
+        size as f64 / file.ticks.len().max(1) as f64
+    );
+    println!(
+        "score      {}:{}, sets {}:{}  {}",
+        file.final_state.score[0],
+        file.final_state.score[1],
+        file.final_state.sets[0],
+        file.final_state.sets[1],
+        result(&file)
+    );
+    println!("verified   {}", if file.verify() { "yes" } else { "no, the inputs diverge" });
+    Ok(())
+}
+
+/// Re-runs the physics and prints every point, with `--trace` every tick.
+fn simulate(args: &mut Args) -> Result<(), String> {
+    let trace = args.flag("--trace");
+    let path = args.positional("match file")?;
+    args.finish()?;
+    let file = load(&path)?;
+    let start = file.header.seed as usize;
+
+    let mut score = [0, 0];
+    let mut sets = [0, 0];
+    let mut rally_ticks = 0;
+    let final_state = file.simulate(|tick, gamestate| {
+        rally_ticks += 1;
+        let time = tick.total_time - start;
+        if trace {
+            println!(
+                "{:8} ms  inputs {:4} {:4}  rackets {:4} {:4}  ball {:4},{:4} vel {:3},{:3}",
+                time,
+                tick.inputs[0].goal_y,
+                tick.inputs[1].goal_y,
+                gamestate.rackets[0].y,
+                gamestate.rackets[1].y,
+                gamestate.ball.x,
+                gamestate.ball.y,
+                gamestate.ball.x_vel,
+                gamestate.ball.y_vel
+            );
+        }
+        // the score starts over with every set
+        if gamestate.sets != sets {
+            let winner = if gamestate.sets[0] != sets[0] { 0 } else { 1 };
+            sets = gamestate.sets;
+            println!(
+                "{:8} ms  {} takes the set, sets {}:{}",
+                time,
+                file.header.names[winner],
+                sets[0],
+                sets[1]
+            );
+            rally_ticks = 0;
+        } else if gamestate.score != score {
+            let scorer = if gamestate.score[0] != score[0] { 0 } else { 1 };
+            println!(
+                "{:8} ms  {} scores after {} ticks, {}:{}",
+                time,
+                file.header.names[scorer],
+                rally_ticks,
+                gamestate.score[0],
+                gamestate.score[1]
+            );
+            rally_ticks = 0;
+        }
+        score = gamestate.score;
+    });
+    println!("{}", result(&file));
+    if final_state != file.final_state {
+        return Err(String::from("the simulation does not match the stored final gamestate"));
+    }
+    Ok(())
+}
+
+fn extract(args: &mut Args) -> Result<(), String> {
+    let log = args.positional("log file")?;
+    let out = args.positional("output file")?;
+    args.finish()?;
+    let bytes = read_match_bytes(&log)?;
+    MatchFile::parse(&bytes).map_err(|e| format!("{}: {:?}", log, e))?;
+    fs::write(&out, &bytes).map_err(|e| format!("could not write {}: {}", out, e))?;
+    println!("wrote {} bytes to {}", bytes.len(), out);
+    Ok(())
+}
+
+/// Writes a match between two cpu players, handy to try the other commands
+/// and to compare the difficulty levels.
+fn record(args: &mut Args) -> Result<(), String> {
+    let frames = args.value("--frames", 60 * 60 * 5)?;
+    let seed: usize = args.value("--seed", 2018)?;
+    let settings = settings_from_args(args)?;
+    let mut bots = [
+        bot::from_args(args, "--cpu-1", 0, seed as u32)?,
+        bot::from_args(args, "--cpu-2", 1, seed as u32 + 1)?,
+    ];
+    let out = args.positional("output file")?;
+    args.finish()?;
+
+    let names = [
+        format!("CPU 1 ({})", bots[0].difficulty().name()),
+        format!("CPU 2 ({})", bots[1].difficulty().name()),
+    ];
+    let mut recorder =
+        MatchRecorder::new(seed, &settings.serialize(), [&names[0], &names[1]], usize::MAX);
+    let mut gamestate = GamestatePacket::new(seed);
+    let mut cache = PhysicsCache::new();
+    let mut inputs = [InputPacket::new(), InputPacket::new()];
+    let mut total_time = seed;
+    for frame in 0..frames {
+        total_time += FRAME_TIMES_MS[frame % FRAME_TIMES_MS.len()];
+        physics::calculate_physics(&mut gamestate, inputs, total_time, &settings, &mut cache);
+        recorder.record(inputs, total_time, &gamestate);
+        if gamestate.state >= STATE_WON_PLAYER_1 {
+            break;
+        }
+        for (bot, input) in bots.iter_mut().zip(inputs.iter_mut()) {
+            bot.play(&gamestate, input);
+        }
+    }
+
+    let bytes = recorder.to_bytes();
+    fs::write(&out, &bytes).map_err(|e| format!("could not write {}: {}", out, e))?;
+    println!("wrote {} ticks in {} bytes to {}", recorder.tick_count(), bytes.len(), out);
+    Ok(())
+}
+
+/// Match rules from `--first-to`, `--win-by-two`, `--time`, `--best-of`,
+/// `--racket-size` and `--ball-speed`.
+pub fn settings_from_args(args: &mut Args) -> Result<MatchSettings, String> {
+    let defaults = MatchSettings::new();
+    let settings = MatchSettings {
+        score_limit: args.value("--first-to", defaults.score_limit)?,
+        win_by_two: args.flag("--win-by-two"),
+        time_limit: args.value("--time", defaults.time_limit)?,
+        best_of: args.value("--best-of", defaults.best_of)?,
+        racket_size: args.value("--racket-size", defaults.racket_size)?,
+        ball_speed: args.value("--ball-speed", defaults.ball_speed)?,
+    };
+    if !settings.is_valid() {
+        return Err(format!("unplayable rules: {:?}", settings));
+    }
+    Ok(settings)
+}
+
+fn result(file: &MatchFile) -> String {
+    match file.final_state.state {
+        STATE_WON_PLAYER_1 => format!("{} won", file.header.names[0]),
+        STATE_WON_PLAYER_2 => format!("{} won", file.header.names[1]),
+        _ => String::from("not decided"),
+    }
+}
+
+fn load(path: &str) -> Result<MatchFile, String> {
+    MatchFile::parse(&read_match_bytes(path)?).map_err(|e| format!("{}: {:?}", path, e))
+}
+
+/// A binary match file, or the last match dumped in a semihosting log.
+fn read_match_bytes(path: &str) -> Result<Vec<u8>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+    if bytes.starts_with(b"PONG") {
+        return Ok(bytes);
+    }
+    matchfile::parse_dump(&String::from_utf8_lossy(&bytes))
+        .ok_or_else(|| format!("{} is neither a match file nor a log with a complete match dump", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cpu::{CpuPlayer, Difficulty};
+    use crc;
+    use matchfile::MatchError;
+    use rules::MAX_BALL_SPEED;
+
+    // matches of several sets at every ball speed serve the first ball of each
+    // set once, like the first set's
+    #[test]
+    fn sets_serve_their_first_ball_once() -> Result<(), String> {
+        for ball_speed in 1..MAX_BALL_SPEED + 1 {
+            for seed in 0..8 {
+                let settings = MatchSettings {
+                    score_limit: 2,
+                    best_of: 5,
+                    ball_speed,
+                    ..MatchSettings::new()
+                };
+                check_serves(&settings, 2018 + seed)?;
+            }
+        }
+        Ok(())
+    }
+
+    // the first ball of every set leaves as fast as a single serve makes it
+    fn check_serves(settings: &MatchSettings, seed: usize) -> Result<usize, String> {
+        let mut bots = [
+            CpuPlayer::new(0, Difficulty::Easy, seed as u32),
+            CpuPlayer::new(1, Difficulty::Easy, seed as u32 + 1),
+        ];
+        let mut gamestate = GamestatePacket::new(seed);
+        let mut cache = PhysicsCache::new();
+        let mut inputs = [InputPacket::new(), InputPacket::new()];
+        let mut total_time = seed;
+        // the ball of a set that has just started, before it is served
+        let mut unserved = Some(gamestate.ball);
+        let mut sets = 0;
+        for frame in 0..60 * 60 * 60 {
+            total_time += FRAME_TIMES_MS[frame % FRAME_TIMES_MS.len()];
+            let sets_before = gamestate.sets;
+            physics::calculate_physics(&mut gamestate, inputs, total_time, settings, &mut cache);
+            if let Some(ball) = unserved.take() {
+                let once = (ball.x_vel.abs() + settings.serve_boost()).max(1);
+                sets += 1;
+                if gamestate.ball.x_vel.abs() != once {
+                    return Err(format!(
+                        "seed {}: set {} started at speed {}, serving its ball once makes {} ({:?})",
+                        seed,
+                        sets,
+                        gamestate.ball.x_vel.abs(),
+                        once,
+                        settings
+                    ));
+                }
+            }
+            if gamestate.state >= STATE_WON_PLAYER_1 {
+                return Ok(sets);
+            }
+            if gamestate.sets != sets_before {
+                let mut ball = gamestate.ball;
+                ball.reset(total_time);
+                unserved = Some(ball);
+            }
+            for (bot, input) in bots.iter_mut().zip(inputs.iter_mut()) {
+                bot.play(&gamestate, input);
+            }
+        }
+        Err(format!("seed {}: the match with {:?} was not decided", seed, settings))
+    }
+
+    // a match between two bots, with the inputs of every tick
+    fn recorded(frames: usize) -> (Vec<u8>, Vec<[InputPacket; 2]>) {
+        let settings = MatchSettings::new();
+        let mut bots = [CpuPlayer::new(0, Difficulty::Hard, 7), CpuPlayer::new(1, Difficulty::Easy, 8)];
+        let mut recorder = MatchRecorder::new(2018, &settings.serialize(), ["left", "right"], usize::MAX);
+        let mut gamestate = GamestatePacket::new(2018);
+        let mut cache = PhysicsCache::new();
+        let mut inputs = [InputPacket::new(), InputPacket::new()];
+        let mut recorded = Vec::new();
+        let mut total_time = 2018;
+        for frame in 0..frames {
+            total_time += FRAME_TIMES_MS[frame % FRAME_TIMES_MS.len()];
+            physics::calculate_physics(&mut gamestate, inputs, total_time, &settings, &mut cache);
+            recorder.record(inputs, total_time, &gamestate);
+            recorded.push(inputs);
+            for (bot, input) in bots.iter_mut().zip(inputs.iter_mut()) {
+                bot.play(&gamestate, input);
+            }
+        }
+        (recorder.to_bytes(), recorded)
+    }
+
+    // a file of no settings and no names around `body`, with a valid checksum
+    fn with_body(tick_count: u32, body: &[u8]) -> Vec<u8> {
+        let mut file = b"PONG".to_vec();
+        file.extend_from_slice(&[matchfile::VERSION, 0, 0, 0, 0, 0, 0, 0, 0]);
+        file.extend_from_slice(&tick_count.to_be_bytes());
+        file.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        file.extend_from_slice(body);
+        file.extend(GamestatePacket::new(0).serialize());
+        let checksum = crc::crc32(&file);
+        file.extend_from_slice(&checksum.to_be_bytes());
+        file
+    }
+
+    #[test]
+    fn recorded_matches_round_trip() {
+        let (bytes, inputs) = recorded(1200);
+        let file = MatchFile::parse(&bytes).unwrap();
+        assert_eq!(file.header.names, ["left", "right"]);
+        assert_eq!(file.header.seed, 2018);
+        assert!(!file.header.truncated);
+        let parsed: Vec<[InputPacket; 2]> = file.ticks.iter().map(|tick| tick.inputs).collect();
+        let goals = |inputs: &[[InputPacket; 2]]| -> Vec<[i16; 2]> {
+            inputs.iter().map(|inputs| [inputs[0].goal_y, inputs[1].goal_y]).collect()
+        };
+        assert_eq!(goals(&parsed), goals(&inputs));
+        assert!(file.verify());
+    }
+
+    #[test]
+    fn damaged_files_fail_their_checksum() {
+        let (bytes, _) = recorded(300);
+        for &i in &[5, 20, bytes.len() / 2, bytes.len() - 1] {
+            let mut damaged = bytes.clone();
+            damaged[i] ^= 0x04;
+            match MatchFile::parse(&damaged) {
+                Err(MatchError::Checksum { .. }) => {}
+                result => panic!("byte {} damaged gives {:?}", i, result.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_files_are_refused() {
+        let (bytes, _) = recorded(300);
+        for cut in 0..bytes.len() {
+            assert!(MatchFile::parse(&bytes[..cut]).is_err(), "cut to {} bytes", cut);
+        }
+        // a body shorter than its length, with a checksum over what is there
+        let mut short = with_body(1, &[0]);
+        short[17..21].copy_from_slice(&100u32.to_be_bytes()); // body_len
+        let crc_at = short.len() - 4;
+        let checksum = crc::crc32(&short[..crc_at]);
+        short[crc_at..].copy_from_slice(&checksum.to_be_bytes());
+        assert_eq!(MatchFile::parse(&short).err(), Some(MatchError::Truncated));
+    }
+
+    #[test]
+    fn tick_counts_are_bounded_by_the_body() {
+        // a run byte holds at most 32 ticks
+        assert_eq!(MatchFile::parse(&with_body(32, &[0x1f])).unwrap().ticks.len(), 32);
+        for &count in &[33, 1 << 20, u32::MAX] {
+            assert_eq!(MatchFile::parse(&with_body(count, &[0x1f])).err(),
+                       Some(MatchError::Invalid("more ticks than the input stream can hold")), "{} ticks", count);
+        }
+    }
+
+    #[test]
+    fn racket_targets_stay_on_screen() {
+        // the first input starts at 136, zigzag varints move it
+        assert!(MatchFile::parse(&with_body(1, &[0x80, 0x8e, 0x02])).is_ok()); // + 135 to 271
+        // + 136 to 272, - 137 to -1, and the second input + 32632 around to -32768
+        let bodies: [&[u8]; 3] = [&[0x80, 0x90, 0x02], &[0x80, 0x91, 0x02], &[0x40, 0xf0, 0xfd, 0x03]];
+        for body in &bodies {
+            assert_eq!(MatchFile::parse(&with_body(1, body)).err(),
+                       Some(MatchError::Invalid("racket target off screen")), "{:02x?}", body);
+        }
+    }
+}
@@ -0,0 +1,29 @@
+// The firmware network module without the smoltcp backed `Network`.
+
+#[path = "../../../src/network/capture.rs"]
+#[allow(dead_code, clippy::manual_is_multiple_of, clippy::redundant_field_names, clippy::trim_split_whitespace)]
+pub mod capture;
+#[path = "../../../src/network/dissect.rs"]
+#[allow(clippy::redundant_field_names)]
+pub mod dissect;
+#[path = "../../../src/network/link.rs"]
+#[allow(dead_code)]
+pub mod link;
+#[path = "../../../src/network/packets.rs"]
+#[allow(dead_code, clippy::redundant_field_names, clippy::vec_init_then_push)]
+pub mod packets;
+#[path = "../../../src/network/peer.rs"]
+#[allow(dead_code, bare_trait_objects)]
+#[allow(clippy::bool_comparison, clippy::collapsible_match, clippy::needless_ifs, clippy::single_match)]
+pub mod peer;
+#[path = "../../../src/network/sim.rs"]
+#[allow(dead_code, clippy::redundant_field_names)]
+pub mod sim;
+
+pub use self::link::{Link, LinkError};
+pub use self::packets::BallPacket;
+pub use self::packets::GamestatePacket;
+pub use self::packets::InputPacket;
+pub use self::packets::WhoamiPacket;
+pub use self::peer::{Client, EthClient, EthServer, Server};
+pub use self::peer::{handle_network_client, handle_network_server};
@@ -0,0 +1,68 @@
+// Tests of the themes (see `src/palette.rs`): every ramp fades from the
+// background to its named color, and the themes that replaced the tints show
+// the named colors in the grey levels and tints of before.
+
+use palette::{self, SHADES, THEMES};
+
+// the grey levels the colors had, and the tints of the settings, before
+// the palette
+const GREYS: [(&str, u8, u8); 12] = [
+    ("background", palette::BACKGROUND, 0),
+    ("text", palette::TEXT, 255),
+    ("ball", palette::BALL, 255),
+    ("racket", palette::RACKET, 150),
+    ("clock", palette::CLOCK, 128),
+    ("won set", palette::MARK_WON, 255),
+    ("open set", palette::MARK_OPEN, 64),
+    ("panel border", palette::PANEL_BORDER, 255),
+    ("border", palette::BORDER, 96),
+    ("focus", palette::FOCUS, 192),
+    ("pressed", palette::PRESSED, 255),
+    ("panel", palette::PANEL, 0),
+];
+const TINTS: [(u8, u8, u8); 4] = [(255, 255, 255), (64, 255, 96), (255, 176, 0), (96, 160, 255)];
+
+#[test]
+fn ramps_fade_from_the_background() {
+    for (i, theme) in THEMES.iter().enumerate() {
+        let i = i as u8;
+        let background = palette::clut_entry(i, palette::BACKGROUND);
+        for &(name, color, _) in GREYS.iter() {
+            assert!(palette::shade(color, 255) == color && palette::shade(color, 0) & (SHADES - 1) == 0,
+                    "{}: the {} shades do not span its ramp", theme.name, name);
+            let full = palette::clut_entry(i, color);
+            let mut previous = background;
+            for coverage in 0..256 {
+                let entry = palette::clut_entry(i, palette::shade(color, coverage as u8));
+                assert!(between(previous, entry, full), "{}: the {} ramp turns back at coverage {}", theme.name, name,
+                        coverage);
+                previous = entry;
+            }
+            assert!(previous == full, "{}: the {} ramp ends at {:?}, not {:?}", theme.name, name, previous, full);
+        }
+    }
+}
+
+#[test]
+fn themes_keep_the_tints() {
+    for (i, &tint) in TINTS.iter().enumerate() {
+        for &(name, color, grey) in GREYS.iter() {
+            let scale = |channel: u8| (u16::from(channel) * u16::from(grey) / 255) as u8;
+            let before = (scale(tint.0), scale(tint.1), scale(tint.2));
+            let now = palette::clut_entry(i as u8, color);
+            assert!(now == before, "{}: {} is {:?}, it was {:?}", THEMES[i].name, name, now, before);
+        }
+    }
+}
+
+// each channel of `entry` lies between `previous` and `to`
+fn between(previous: (u8, u8, u8), entry: (u8, u8, u8), to: (u8, u8, u8)) -> bool {
+    let channel = |previous: u8, entry: u8, to: u8| {
+        if previous <= to {
+            previous <= entry && entry <= to
+        } else {
+            to <= entry && entry <= previous
+        }
+    };
+    channel(previous.0, entry.0, to.0) && channel(previous.1, entry.1, to.1) && channel(previous.2, entry.2, to.2)
+}
@@ -0,0 +1,117 @@
+// Just enough libpcap reading to get the UDP payloads of the game out.
+
+use std::fs;
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const IP_PROTOCOL_UDP: u8 = 17;
+
+pub struct Datagram {
+    pub time_us: u64,
+    pub src: SocketAddrV4,
+    pub dst: SocketAddrV4,
+    pub payload: Vec<u8>,
+}
+
+/// All IPv4 UDP datagrams from or to `port`, in file order.
+pub fn read(path: &str, port: u16) -> Result<Vec<Datagram>, String> {
+    let file = fs::read(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+    if file.len() < 24 {
+        return Err(format!("{} is too short for a pcap file", path));
+    }
+    let magic = [file[0], file[1], file[2], file[3]];
+    let (big_endian, nanoseconds) = match magic {
+        [0xd4, 0xc3, 0xb2, 0xa1] => (false, false),
+        [0x4d, 0x3c, 0xb2, 0xa1] => (false, true),
+        [0xa1, 0xb2, 0xc3, 0xd4] => (true, false),
+        [0xa1, 0xb2, 0x3c, 0x4d] => (true, true),
+        _ => return Err(format!("{} is not a pcap file (pcapng is not supported)", path)),
+    };
+    let u32_at = |offset: usize| {
+        let bytes = [file[offset], file[offset + 1], file[offset + 2], file[offset + 3]];
+        if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    };
+    let linktype = u32_at(20) & 0xffff;
+
+    let mut datagrams = Vec::new();
+    let mut offset = 24;
+    while offset + 16 <= file.len() {
+        let seconds = u64::from(u32_at(offset));
+        let fraction = u64::from(u32_at(offset + 4));
+        let captured = u32_at(offset + 8) as usize;
+        let start = offset + 16;
+        offset = start + captured;
+        if offset > file.len() {
+            break; // truncated last packet
+        }
+        let time_us = seconds * 1_000_000 + if nanoseconds { fraction / 1000 } else { fraction };
+        if let Some(ip) = ipv4_packet(linktype, &file[start..offset]) {
+            if let Some(datagram) = udp_datagram(time_us, ip, port) {
+                datagrams.push(datagram);
+            }
+        }
+    }
+    Ok(datagrams)
+}
+
+fn ipv4_packet(linktype: u32, frame: &[u8]) -> Option<&[u8]> {
+    let be16 = |offset: usize| u16::from(frame[offset]) << 8 | u16::from(frame[offset + 1]);
+    let (ethertype, header) = match linktype {
+        LINKTYPE_ETHERNET if frame.len() >= 14 => {
+            if be16(12) == ETHERTYPE_VLAN && frame.len() >= 18 {
+                (be16(16), 18)
+            } else {
+                (be16(12), 14)
+            }
+        }
+        LINKTYPE_LINUX_SLL if frame.len() >= 16 => (be16(14), 16),
+        LINKTYPE_LINUX_SLL2 if frame.len() >= 20 => (be16(0), 20),
+        LINKTYPE_NULL if frame.len() >= 4 => (ETHERTYPE_IPV4, 4),
+        LINKTYPE_RAW | LINKTYPE_IPV4 => (ETHERTYPE_IPV4, 0),
+        _ => return None,
+    };
+    if ethertype != ETHERTYPE_IPV4 {
+        return None;
+    }
+    Some(&frame[header..])
+}
+
+fn udp_datagram(time_us: u64, ip: &[u8], port: u16) -> Option<Datagram> {
+    if ip.len() < 20 || ip[0] >> 4 != 4 || ip[9] != IP_PROTOCOL_UDP {
+        return None;
+    }
+    let fragment_offset = (u16::from(ip[6]) << 8 | u16::from(ip[7])) & 0x1fff;
+    if fragment_offset != 0 {
+        return None;
+    }
+    let header = usize::from(ip[0] & 0x0f) * 4;
+    let udp = ip.get(header..)?;
+    if udp.len() < 8 {
+        return None;
+    }
+    let src_port = u16::from(udp[0]) << 8 | u16::from(udp[1]);
+    let dst_port = u16::from(udp[2]) << 8 | u16::from(udp[3]);
+    let udp_len = usize::from(u16::from(udp[4]) << 8 | u16::from(udp[5]));
+    if src_port != port && dst_port != port {
+        return None;
+    }
+    let payload = udp.get(8..udp_len)?;
+    Some(Datagram {
+        time_us,
+        src: SocketAddrV4::new(Ipv4Addr::new(ip[12], ip[13], ip[14], ip[15]), src_port),
+        dst: SocketAddrV4::new(Ipv4Addr::new(ip[16], ip[17], ip[18], ip[19]), dst_port),
+        payload: payload.to_vec(),
+    })
+}
@@ -0,0 +1,190 @@
+// Plays the server or client role of the real protocol against a board,
+// with the cpu player as the local player.
+
+use args::Args;
+use bot;
+use matches;
+use network::packets::STATE_WON_PLAYER_1;
+use network::{handle_network_client, handle_network_server};
+use network::{Client, EthClient, EthServer, GamestatePacket, InputPacket, Server};
+use physics::PhysicsCache;
+use std::net::SocketAddr;
+use std::thread;
+use std::time::{Duration, Instant};
+use udp::UdpLink;
+
+const FRAME_MS: u64 = 16; // the board draws at about 60 fps
+const WHOAMI_INTERVAL_MS: usize = 200; // same interval as the firmware
+const RESTART_DELAY_MS: usize = 3000;
+const STATUS_INTERVAL_MS: usize = 1000;
+
+// where the boards live, see the addresses in main.rs
+const BIND_ADDR: &str = "0.0.0.0:2018";
+const SERVER_ADDR: &str = "141.52.46.1:2018";
+const CLIENT_ADDR: &str = "141.52.46.2:2018";
+
+pub fn run_server(args: &mut Args) -> Result<(), String> {
+    let settings = matches::settings_from_args(args)?;
+    let mut bot = bot::from_args(args, "--level", 0, 2018)?;
+    let (mut link, frames) = open_link(args, CLIENT_ADDR)?;
+    let mut server = EthServer::new();
+    let mut clock = Clock::new();
+
+    println!("waiting for a client...");
+    while !server.is_client_connected(&mut link) {
+        clock.wait_frame();
+    }
+    println!("client connected");
+
+    let mut gamestate = GamestatePacket::new(clock.now());
+    let mut input = InputPacket::new();
+    let mut physics_cache = PhysicsCache::new();
+    let mut status = Status::new();
+    let mut won_at = None;
+
+    while frames == 0 || clock.frame < frames {
+        let now = clock.now();
+        // the bot restarts finished games instead of a touch
+        if gamestate.state >= STATE_WON_PLAYER_1 {
+            match won_at {
+                None => {
+                    println!("game over: {:?} state {}", gamestate.score, gamestate.state);
+                    won_at = Some(now);
+                }
+                Some(time) if now - time > RESTART_DELAY_MS => {
+                    gamestate = GamestatePacket::new(now);
+                    physics_cache = PhysicsCache::new();
+                    won_at = None;
+                }
+                Some(_) => {}
+            }
+        }
+
+        bot.play(&gamestate, &mut input);
+        handle_network_server(
+            &mut server,
+            &mut link,
+            &mut gamestate,
+            &input,
+            now,
+            &settings,
+            &mut physics_cache,
+        );
+        status.print(now, &gamestate, &link);
+        clock.wait_frame();
+    }
+    Ok(())
+}
+
+pub fn run_client(args: &mut Args) -> Result<(), String> {
+    let mut bot = bot::from_args(args, "--level", 1, 2018)?;
+    let (mut link, frames) = open_link(args, SERVER_ADDR)?;
+    let mut client = EthClient::new();
+    let mut clock = Clock::new();
+
+    println!("connecting to the server...");
+    let mut whoami_time = WHOAMI_INTERVAL_MS;
+    loop {
+        if whoami_time >= WHOAMI_INTERVAL_MS {
+            whoami_time = 0;
+            client.send_whoami(&mut link);
+        }
+        if client.is_server_connected(&mut link) {
+            break;
+        }
+        clock.wait_frame();
+        whoami_time += FRAME_MS as usize;
+    }
+    println!("server connected, playing {:?}", client.match_settings());
+
+    let mut gamestate = GamestatePacket::new(0);
+    let mut input = InputPacket::new();
+    let mut status = Status::new();
+
+    while frames == 0 || clock.frame < frames {
+        let now = clock.now();
+        handle_network_client(&mut client, &mut link, &mut gamestate, &input);
+        bot.play(&gamestate, &mut input);
+        status.print(now, &gamestate, &link);
+        clock.wait_frame();
+    }
+    Ok(())
+}
+
+fn open_link(args: &mut Args, default_partner: &str) -> Result<(UdpLink, usize), String> {
+    let bind: SocketAddr = args.value("--bind", BIND_ADDR.parse().unwrap())?;
+    let partner: SocketAddr = args.value("--partner", default_partner.parse().unwrap())?;
+    let frames = args.value("--frames", 0)?;
+    args.finish()?;
+    println!("listening on {}, partner is {}", bind, partner);
+    Ok((UdpLink::bind(bind, partner)?, frames))
+}
+
+// paces the loop like the LCD interrupt paces the board
+struct Clock {
+    start: Instant,
+    next_frame: Instant,
+    frame: usize,
+}
+
+impl Clock {
+    fn new() -> Clock {
+        let now = Instant::now();
+        Clock {
+            start: now,
+            next_frame: now,
+            frame: 0,
+        }
+    }
+
+    fn now(&self) -> usize {
+        let elapsed = self.start.elapsed();
+        elapsed.as_secs() as usize * 1000 + elapsed.subsec_millis() as usize
+    }
+
+    fn wait_frame(&mut self) {
+        self.frame += 1;
+        self.next_frame += Duration::from_millis(FRAME_MS);
+        let now = Instant::now();
+        if self.next_frame > now {
+            thread::sleep(self.next_frame - now);
+        } else {
+            self.next_frame = now; // we are late, don't try to catch up
+        }
+    }
+}
+
+struct Status {
+    last_print: usize,
+    last_sent: usize,
+    last_received: usize,
+}
+
+impl Status {
+    fn new() -> Status {
+        Status {
+            last_print: 0,
+            last_sent: 0,
+            last_received: 0,
+        }
+    }
+
+    fn print(&mut self, now: usize, gamestate: &GamestatePacket, link: &UdpLink) {
+        if now - self.last_print < STATUS_INTERVAL_MS {
+            return;
+        }
+        println!(
+            "score {}:{} state {:3} ball ({:3}, {:3}) | {} packets/s out, {} packets/s in",
+            gamestate.score[0],
+            gamestate.score[1],
+            gamestate.state,
+            gamestate.ball.x,
+            gamestate.ball.y,
+            link.sent - self.last_sent,
+            link.received - self.last_received
+        );
+        self.last_print = now;
+        self.last_sent = link.sent;
+        self.last_received = link.received;
+    }
+}
@@ -0,0 +1,202 @@
+// Tests of the settings store (see `src/storage.rs`) against an in-memory
+// flash sector that can lose power in the middle of a write or an erase.
+
+use palette::THEMES;
+use settings::{self, Settings, NAME_CHARACTERS, NAME_LENGTH};
+use storage::{Flash, FlashError, Store};
+
+const SECTOR: usize = 4096;
+const RECORD_OVERHEAD: usize = 7; // header and crc around the payload
+
+/// A flash sector in memory. Like the real one it refuses to program bytes
+/// that are not erased. With `power_left` set, it stops after that many more
+/// bytes of programming or erasing, as if the board was reset.
+#[derive(Clone)]
+struct MemoryFlash {
+    bytes: Vec<u8>,
+    erases: usize,
+    power_left: Option<usize>,
+}
+
+impl MemoryFlash {
+    fn new(length: usize) -> MemoryFlash {
+        MemoryFlash {
+            bytes: vec![0xFF; length],
+            erases: 0,
+            power_left: None,
+        }
+    }
+
+    // false once the power is gone
+    fn spend(&mut self) -> bool {
+        match self.power_left {
+            Some(0) => false,
+            Some(ref mut left) => {
+                *left -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+}
+
+impl Flash for MemoryFlash {
+    fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    fn read(&self, offset: usize, buffer: &mut [u8]) {
+        buffer.copy_from_slice(&self.bytes[offset..offset + buffer.len()]);
+    }
+
+    fn erase(&mut self) -> Result<(), FlashError> {
+        self.erases += 1;
+        for i in 0..self.bytes.len() {
+            if !self.spend() {
+                return Err(FlashError::Erase);
+            }
+            self.bytes[i] = 0xFF;
+        }
+        Ok(())
+    }
+
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), FlashError> {
+        for (i, &byte) in data.iter().enumerate() {
+            if self.bytes[offset + i] != 0xFF || !self.spend() {
+                return Err(FlashError::Program);
+            }
+            self.bytes[offset + i] = byte;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn records_round_trip() -> Result<(), String> {
+    for n in 0..64 {
+        let settings = variant(n);
+        let record = settings.serialize();
+        if Settings::deserialize(&record) != Some(settings.clone()) {
+            return Err(format!("{:?} does not survive serialization", settings));
+        }
+        let mut other_version = record.clone();
+        other_version[0] = settings::VERSION + 1;
+        if Settings::deserialize(&other_version).is_some() {
+            return Err(String::from("a record of another version was accepted"));
+        }
+        if Settings::deserialize(&record[..record.len() - 1]).is_some() && !settings.player_name.is_empty() {
+            return Err(String::from("a truncated record was accepted"));
+        }
+    }
+    Ok(())
+}
+
+// saves until the sector wraps a few times, every reopen finds the last save
+#[test]
+fn saves_survive_resets() -> Result<(), String> {
+    let mut store = Store::open(MemoryFlash::new(SECTOR));
+    if store.load().is_some() {
+        return Err(String::from("an erased sector holds a record"));
+    }
+    for n in 0..2000 {
+        let settings = variant(n);
+        store.save(&settings.serialize()).map_err(|e| format!("save {} failed: {:?}", n, e))?;
+        let free = store.free_bytes();
+        store.save(&settings.serialize()).map_err(|e| format!("save {} failed: {:?}", n, e))?;
+        if store.free_bytes() != free {
+            return Err(String::from("saving unchanged settings wrote to the flash"));
+        }
+        // a reset
+        store = Store::open(store.flash().clone());
+        if load(&store) != Some(settings) {
+            return Err(format!("save {} was not found after a reset", n));
+        }
+    }
+    if store.flash().erases < 2 {
+        return Err(format!("{} erases, the sector did not wrap", store.flash().erases));
+    }
+    Ok(())
+}
+
+// cuts the power after every possible number of bytes of a save that fits
+// and of one that needs an erase, the store then holds the old or the new
+// settings and still accepts saves
+#[test]
+fn power_cuts_keep_a_record() -> Result<(), String> {
+    let mut nearly_full = Store::open(MemoryFlash::new(SECTOR));
+    let mut n = 0;
+    let record_length = variant(0).serialize().len() + RECORD_OVERHEAD;
+    while nearly_full.free_bytes() >= record_length {
+        n += 1;
+        nearly_full.save(&variant(n).serialize()).map_err(|e| format!("{:?}", e))?;
+    }
+    let mut fresh = Store::open(MemoryFlash::new(SECTOR));
+    fresh.save(&variant(n).serialize()).map_err(|e| format!("{:?}", e))?;
+
+    for start in &[fresh, nearly_full] {
+        let old = load(start);
+        let new = variant(n + 1);
+        let mut power = 0;
+        loop {
+            let mut flash = start.flash().clone();
+            flash.power_left = Some(power);
+            let mut store = Store::open(flash);
+            let saved = store.save(&new.serialize()).is_ok();
+
+            let mut flash = store.flash().clone();
+            flash.power_left = None;
+            let mut store = Store::open(flash);
+            let found = load(&store);
+            let expected = if saved { Some(new.clone()) } else { old.clone() };
+            // an erase takes the old record with it before the new one is written
+            if found != expected && (saved || found.is_some()) {
+                return Err(format!("after {} bytes: found {:?}, expected {:?}", power, found, expected));
+            }
+            store.save(&new.serialize()).map_err(|e| format!("no save after a power cut: {:?}", e))?;
+            if load(&Store::open(store.flash().clone())) != Some(new.clone()) {
+                return Err(format!("the save after a cut at {} bytes was lost", power));
+            }
+            if saved {
+                break;
+            }
+            power += 1;
+        }
+    }
+    Ok(())
+}
+
+// a corrupted newest record falls back to the one before it
+#[test]
+fn corrupted_records_are_skipped() -> Result<(), String> {
+    let mut store = Store::open(MemoryFlash::new(SECTOR));
+    for n in 0..3 {
+        store.save(&variant(n).serialize()).map_err(|e| format!("{:?}", e))?;
+    }
+    let mut flash = store.flash().clone();
+    let length = variant(2).serialize().len() + RECORD_OVERHEAD;
+    let offset = SECTOR - store.free_bytes() - length / 2;
+    flash.bytes[offset] ^= 0x01;
+    if load(&Store::open(flash)) != Some(variant(1)) {
+        return Err(String::from("a corrupted record was not skipped"));
+    }
+    Ok(())
+}
+
+fn load(store: &Store<MemoryFlash>) -> Option<Settings> {
+    store.load().and_then(|record| Settings::deserialize(&record))
+}
+
+// a valid settings record that differs for each n
+fn variant(n: usize) -> Settings {
+    let mut settings = Settings::new();
+    settings.match_settings.score_limit = (n % 21) as u8 + 1;
+    settings.match_settings.racket_size = 10 + (n % 11) as u8 * 5;
+    settings.match_settings.ball_speed = (n % 5) as u8 + 1;
+    settings.match_settings.win_by_two = n % 2 == 1;
+    settings.theme = (n % THEMES.len()) as u8;
+    settings.server_ip[3] = (n % 254) as u8 + 1;
+    settings.player_name = (0..n % (NAME_LENGTH + 1))
+        .map(|i| NAME_CHARACTERS[(n + i) % NAME_CHARACTERS.len()] as char)
+        .collect();
+    settings
+}
@@ -0,0 +1,445 @@
+// Reads the outlines of TrueType fonts and rasterises them for the font
+// converter. Glyphs are placed like the renderer the firmware used to have:
+// a size is the height from the lowest descender to the highest ascender,
+// and the baseline lies that ascent below the top of the line. Curves are
+// flattened into short lines, and each pixel is covered by the exact area
+// of the polygon inside it.
+
+pub struct Font<'a> {
+    cmap: &'a [u8], // the subtable for Unicode
+    glyf: &'a [u8],
+    loca: &'a [u8],
+    hmtx: &'a [u8],
+    long_loca: bool,
+    glyph_count: usize,
+    h_metric_count: usize,
+    pub ascent: i16,
+    pub descent: i16,
+}
+
+pub struct Raster {
+    pub left: i32,
+    pub top: i32,
+    pub width: usize,
+    pub height: usize,
+    pub coverage: Vec<f32>, // from 0 to 1, line by line
+}
+
+// a point of a contour in font units, and whether it is on the curve
+type Point = (f64, f64, bool);
+
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const ARGS_ARE_XY_VALUES: u16 = 0x0002;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+impl<'a> Font<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Font<'a>, String> {
+        let table = |tag: &[u8]| -> Result<&'a [u8], String> {
+            let count = usize::from(u16_at(data, 4)?);
+            for i in 0..count {
+                let record = 12 + 16 * i;
+                if data.get(record..record + 4) == Some(tag) {
+                    let offset = u32_at(data, record + 8)? as usize;
+                    let length = u32_at(data, record + 12)? as usize;
+                    return data.get(offset..offset + length).ok_or_else(|| truncated(tag));
+                }
+            }
+            Err(format!("no {} table", String::from_utf8_lossy(tag)))
+        };
+        match u32_at(data, 0)? {
+            0x0001_0000 | 0x7472_7565 => {}
+            _ => return Err(String::from("not a TrueType font")),
+        }
+        let (head, hhea, maxp, cmap) = (table(b"head")?, table(b"hhea")?, table(b"maxp")?, table(b"cmap")?);
+
+        let mut unicode = None;
+        for i in 0..usize::from(u16_at(cmap, 2)?) {
+            let (platform, encoding) = (u16_at(cmap, 4 + 8 * i)?, u16_at(cmap, 6 + 8 * i)?);
+            let offset = u32_at(cmap, 8 + 8 * i)? as usize;
+            let subtable = cmap.get(offset..).ok_or_else(|| truncated(b"cmap"))?;
+            let format = u16_at(subtable, 0)?;
+            if (platform == 0 || platform == 3 && (encoding == 1 || encoding == 10)) && (format == 4 || format == 12) {
+                unicode = Some(subtable);
+                if format == 12 {
+                    break; // covers more than the basic plane
+                }
+            }
+        }
+
+        Ok(Font {
+            cmap: unicode.ok_or("no Unicode character map")?,
+            glyf: table(b"glyf")?,
+            loca: table(b"loca")?,
+            hmtx: table(b"hmtx")?,
+            long_loca: u16_at(head, 50)? != 0,
+            glyph_count: usize::from(u16_at(maxp, 4)?),
+            h_metric_count: usize::from(u16_at(hhea, 34)?),
+            ascent: u16_at(hhea, 4)? as i16,
+            descent: u16_at(hhea, 6)? as i16,
+        })
+    }
+
+    /// Font units to pixels for fonts of `size` pixels.
+    pub fn scale(&self, size: f64) -> f64 {
+        size / (f64::from(self.ascent) - f64::from(self.descent))
+    }
+
+    pub fn glyph_index(&self, c: char) -> Result<Option<u16>, String> {
+        let cmap = self.cmap;
+        let c = c as u32;
+        if u16_at(cmap, 0)? == 12 {
+            for i in 0..u32_at(cmap, 12)? as usize {
+                let group = 16 + 12 * i;
+                let (first, last) = (u32_at(cmap, group)?, u32_at(cmap, group + 4)?);
+                if c >= first && c <= last {
+                    return Ok(Some((u32_at(cmap, group + 8)? + c - first) as u16));
+                }
+            }
+            return Ok(None);
+        }
+        if c > 0xFFFF {
+            return Ok(None);
+        }
+        let segments = usize::from(u16_at(cmap, 6)? / 2);
+        let (ends, starts, deltas, ranges) = (14, 16 + 2 * segments, 16 + 4 * segments, 16 + 6 * segments);
+        for i in 0..segments {
+            if c > u32::from(u16_at(cmap, ends + 2 * i)?) {
+                continue;
+            }
+            let start = u32::from(u16_at(cmap, starts + 2 * i)?);
+            if c < start {
+                return Ok(None);
+            }
+            let delta = u32::from(u16_at(cmap, deltas + 2 * i)?);
+            let range = usize::from(u16_at(cmap, ranges + 2 * i)?);
+            let glyph = if range == 0 {
+                c
+            } else {
+                let glyph = u32::from(u16_at(cmap, ranges + 2 * i + range + 2 * (c - start) as usize)?);
+                if glyph == 0 {
+                    return Ok(None);
+                }
+                glyph
+            };
+            return Ok(Some((glyph + delta) as u16));
+        }
+        Ok(None)
+    }
+
+    /// In font units.
+    pub fn advance(&self, glyph: u16) -> Result<u16, String> {
+        let metric = usize::from(glyph).min(self.h_metric_count.max(1) - 1);
+        u16_at(self.hmtx, 4 * metric)
+    }
+
+    // the contours of a glyph, composite ones put together
+    fn contours(&self, glyph: u16, depth: u8) -> Result<Vec<Vec<Point>>, String> {
+        if usize::from(glyph) >= self.glyph_count || depth > 8 {
+            return Err(format!("no glyph {}", glyph));
+        }
+        let (start, end) = if self.long_loca {
+            let i = 4 * usize::from(glyph);
+            (u32_at(self.loca, i)? as usize, u32_at(self.loca, i + 4)? as usize)
+        } else {
+            let i = 2 * usize::from(glyph);
+            (2 * usize::from(u16_at(self.loca, i)?), 2 * usize::from(u16_at(self.loca, i + 2)?))
+        };
+        if start == end {
+            return Ok(Vec::new()); // a space
+        }
+        let data = self.glyf.get(start..end).ok_or_else(|| truncated(b"glyf"))?;
+        let count = u16_at(data, 0)? as i16;
+        if count < 0 {
+            return self.composite(data, depth);
+        }
+
+        let mut ends = Vec::new();
+        for i in 0..count as usize {
+            ends.push(usize::from(u16_at(data, 10 + 2 * i)?));
+        }
+        let points = ends.last().map_or(0, |&last| last + 1);
+        let instructions = usize::from(u16_at(data, 10 + 2 * count as usize)?);
+        let mut position = 12 + 2 * count as usize + instructions;
+        let mut flags = Vec::with_capacity(points);
+        while flags.len() < points {
+            let flag = byte_at(data, position)?;
+            position += 1;
+            let repeat = if flag & 8 != 0 {
+                position += 1;
+                byte_at(data, position - 1)?
+            } else {
+                0
+            };
+            for _ in 0..=repeat {
+                flags.push(flag);
+            }
+        }
+        flags.truncate(points);
+        // x and y are deltas, short ones with a sign bit, long ones signed
+        let mut coordinates = |short: u8, same: u8| -> Result<Vec<f64>, String> {
+            let mut value = 0i32;
+            let mut values = Vec::with_capacity(points);
+            for &flag in &flags {
+                if flag & short != 0 {
+                    let delta = i32::from(byte_at(data, position)?);
+                    position += 1;
+                    value += if flag & same != 0 { delta } else { -delta };
+                } else if flag & same == 0 {
+                    value += i32::from(u16_at(data, position)? as i16);
+                    position += 2;
+                }
+                values.push(f64::from(value));
+            }
+            Ok(values)
+        };
+        let xs = coordinates(0x02, 0x10)?;
+        let ys = coordinates(0x04, 0x20)?;
+
+        let mut contours = Vec::new();
+        let mut first = 0;
+        for &end in &ends {
+            if end < first || end >= points {
+                return Err(format!("glyph {} has a contour out of order", glyph));
+            }
+            contours.push((first..=end).map(|i| (xs[i], ys[i], flags[i] & 1 != 0)).collect());
+            first = end + 1;
+        }
+        Ok(contours)
+    }
+
+    fn composite(&self, data: &[u8], depth: u8) -> Result<Vec<Vec<Point>>, String> {
+        let mut contours = Vec::new();
+        let mut position = 10;
+        loop {
+            let flags = u16_at(data, position)?;
+            let glyph = u16_at(data, position + 2)?;
+            position += 4;
+            if flags & ARGS_ARE_XY_VALUES == 0 {
+                return Err(String::from("composite glyphs placed by matching points are not supported"));
+            }
+            let (dx, dy) = if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+                position += 4;
+                (u16_at(data, position - 4)? as i16, u16_at(data, position - 2)? as i16)
+            } else {
+                position += 2;
+                (i16::from(byte_at(data, position - 2)? as i8), i16::from(byte_at(data, position - 1)? as i8))
+            };
+            let mut f2dot14 = |count: usize| -> Result<Vec<f64>, String> {
+                let values: Result<Vec<f64>, String> = (0..count)
+                    .map(|i| u16_at(data, position + 2 * i).map(|v| f64::from(v as i16) / 16384.0))
+                    .collect();
+                position += 2 * count;
+                values
+            };
+            let (a, b, c, d) = if flags & WE_HAVE_A_SCALE != 0 {
+                let scale = f2dot14(1)?;
+                (scale[0], 0.0, 0.0, scale[0])
+            } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+                let scale = f2dot14(2)?;
+                (scale[0], 0.0, 0.0, scale[1])
+            } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+                let scale = f2dot14(4)?;
+                (scale[0], scale[1], scale[2], scale[3])
+            } else {
+                (1.0, 0.0, 0.0, 1.0)
+            };
+            for contour in self.contours(glyph, depth + 1)? {
+                contours.push(contour
+                    .into_iter()
+                    .map(|(x, y, on)| (a * x + c * y + f64::from(dx), b * x + d * y + f64::from(dy), on))
+                    .collect());
+            }
+            if flags & MORE_COMPONENTS == 0 {
+                return Ok(contours);
+            }
+        }
+    }
+
+    /// The outline of a glyph at `size` pixels as closed polygons, in
+    /// pixels from the top of the line where the glyph is written.
+    pub fn outline(&self, glyph: u16, size: f64) -> Result<Vec<Vec<(f64, f64)>>, String> {
+        let scale = self.scale(size);
+        let baseline = f64::from(self.ascent) * scale;
+        let mut polygons = Vec::new();
+        for contour in self.contours(glyph, 0)? {
+            let points: Vec<(f64, f64, bool)> =
+                contour.iter().map(|&(x, y, on)| (x * scale, baseline - y * scale, on)).collect();
+            if points.is_empty() {
+                continue;
+            }
+            // start on the curve, between two control points if none is
+            let start = match points.iter().position(|&(_, _, on)| on) {
+                Some(i) => (points[i].0, points[i].1, i),
+                None => {
+                    let (a, b) = (points[0], points[points.len() - 1]);
+                    ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0, 0)
+                }
+            };
+            let mut polygon = vec![(start.0, start.1)];
+            let mut control: Option<(f64, f64)> = None;
+            let mut current = (start.0, start.1);
+            for k in 1..=points.len() {
+                let (x, y, on) = points[(start.2 + k) % points.len()];
+                match (control, on) {
+                    (None, true) => {
+                        polygon.push((x, y));
+                        current = (x, y);
+                    }
+                    (None, false) => control = Some((x, y)),
+                    (Some(c), true) => {
+                        flatten(&mut polygon, current, c, (x, y));
+                        current = (x, y);
+                        control = None;
+                    }
+                    (Some(c), false) => {
+                        let middle = ((c.0 + x) / 2.0, (c.1 + y) / 2.0);
+                        flatten(&mut polygon, current, c, middle);
+                        current = middle;
+                        control = Some((x, y));
+                    }
+                }
+            }
+            if let Some(c) = control {
+                flatten(&mut polygon, current, c, (start.0, start.1));
+            }
+            polygons.push(polygon);
+        }
+        Ok(polygons)
+    }
+}
+
+// a quadratic curve as lines less than a fiftieth of a pixel off it
+fn flatten(polygon: &mut Vec<(f64, f64)>, from: (f64, f64), control: (f64, f64), to: (f64, f64)) {
+    let deviation = (from.0 - 2.0 * control.0 + to.0).hypot(from.1 - 2.0 * control.1 + to.1);
+    let steps = 1 + (deviation / (8.0 * 0.02)).sqrt() as usize;
+    for i in 1..=steps {
+        let t = i as f64 / steps as f64;
+        let (a, b, c) = ((1.0 - t) * (1.0 - t), 2.0 * t * (1.0 - t), t * t);
+        polygon.push((a * from.0 + b * control.0 + c * to.0, a * from.1 + b * control.1 + c * to.1));
+    }
+}
+
+/// The area the polygons enclose, holes running the other way around.
+pub fn area(polygons: &[Vec<(f64, f64)>]) -> f64 {
+    let mut sum = 0.0;
+    for polygon in polygons {
+        for (i, &(x0, y0)) in polygon.iter().enumerate() {
+            let (x1, y1) = polygon[(i + 1) % polygon.len()];
+            sum += x0 * y1 - x1 * y0;
+        }
+    }
+    (sum / 2.0).abs()
+}
+
+/// Covers each pixel by the area of the polygons inside it. Every line adds
+/// the area it closes off to the right of it to the pixels it crosses, and
+/// the pixels to its right by how much of their height it spans, so that a
+/// running sum along each row gives the coverage.
+pub fn rasterise(polygons: &[Vec<(f64, f64)>]) -> Raster {
+    let points = polygons.iter().flat_map(|polygon| polygon.iter());
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+    for &(x, y) in points {
+        min_x = min_x.min(x);
+        min_y = min_y.min(y);
+        max_x = max_x.max(x);
+        max_y = max_y.max(y);
+    }
+    if min_x > max_x {
+        return Raster {
+            left: 0,
+            top: 0,
+            width: 0,
+            height: 0,
+            coverage: Vec::new(),
+        };
+    }
+    let (left, top) = (min_x.floor(), min_y.floor());
+    let (width, height) = ((max_x.ceil() - left) as usize, (max_y.ceil() - top) as usize);
+    let stride = width + 2; // a line at the right edge adds to the pixel past it
+    let mut accumulation = vec![0f64; stride * height];
+    for polygon in polygons {
+        for (i, &(x0, y0)) in polygon.iter().enumerate() {
+            let (x1, y1) = polygon[(i + 1) % polygon.len()];
+            add_line(&mut accumulation, stride, height, (x0 - left, y0 - top), (x1 - left, y1 - top));
+        }
+    }
+
+    let mut coverage = Vec::with_capacity(width * height);
+    for row in accumulation.chunks(stride) {
+        let mut sum = 0.0;
+        for &value in &row[..width] {
+            sum += value;
+            coverage.push(sum.abs().min(1.0) as f32);
+        }
+    }
+    Raster {
+        left: left as i32,
+        top: top as i32,
+        width,
+        height,
+        coverage,
+    }
+}
+
+fn add_line(accumulation: &mut [f64], stride: usize, height: usize, p0: (f64, f64), p1: (f64, f64)) {
+    if p0.1 == p1.1 {
+        return;
+    }
+    let (direction, p0, p1) = if p0.1 < p1.1 { (1.0, p0, p1) } else { (-1.0, p1, p0) };
+    let dxdy = (p1.0 - p0.0) / (p1.1 - p0.1);
+    let mut x = p0.0;
+    for y in p0.1 as usize..height.min(p1.1.ceil() as usize) {
+        let row = y * stride;
+        let dy = ((y + 1) as f64).min(p1.1) - (y as f64).max(p0.1);
+        let next = x + dxdy * dy;
+        let d = dy * direction;
+        let (x0, x1) = if x < next { (x, next) } else { (next, x) };
+        let (x0_floor, x1_ceil) = (x0.floor(), x1.ceil());
+        let (i0, i1) = (x0_floor as usize, x1_ceil as usize);
+        if i1 <= i0 + 1 {
+            // within one pixel, split at the middle of the line
+            let middle = 0.5 * (x + next) - x0_floor;
+            accumulation[row + i0] += d - d * middle;
+            accumulation[row + i0 + 1] += d * middle;
+        } else {
+            let s = 1.0 / (x1 - x0);
+            let x0_fraction = x0 - x0_floor;
+            let a0 = 0.5 * s * (1.0 - x0_fraction) * (1.0 - x0_fraction);
+            let x1_fraction = x1 - x1_ceil + 1.0;
+            let am = 0.5 * s * x1_fraction * x1_fraction;
+            accumulation[row + i0] += d * a0;
+            if i1 == i0 + 2 {
+                accumulation[row + i0 + 1] += d * (1.0 - a0 - am);
+            } else {
+                let a1 = s * (1.5 - x0_fraction);
+                accumulation[row + i0 + 1] += d * (a1 - a0);
+                for i in i0 + 2..i1 - 1 {
+                    accumulation[row + i] += d * s;
+                }
+                let a2 = a1 + (i1 - i0 - 3) as f64 * s;
+                accumulation[row + i1 - 1] += d * (1.0 - a2 - am);
+            }
+            accumulation[row + i1] += d * am;
+        }
+        x = next;
+    }
+}
+
+fn truncated(tag: &[u8]) -> String {
+    format!("truncated {} table", String::from_utf8_lossy(tag))
+}
+
+fn byte_at(data: &[u8], offset: usize) -> Result<u8, String> {
+    data.get(offset).cloned().ok_or_else(|| String::from("truncated font"))
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, String> {
+    Ok(u16::from(byte_at(data, offset)?) << 8 | u16::from(byte_at(data, offset + 1)?))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, String> {
+    Ok(u32::from(u16_at(data, offset)?) << 16 | u32::from(u16_at(data, offset + 2)?))
+}
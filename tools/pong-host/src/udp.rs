@@ -0,0 +1,55 @@
+// `Link` on top of a host UDP socket, to talk to a real board.
+
+use network::{Link, LinkError};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+
+const MAX_DATAGRAM: usize = 512; // same as the socket buffers on the board
+
+pub struct UdpLink {
+    socket: UdpSocket,
+    partner: SocketAddr,
+    pub sent: usize,
+    pub received: usize,
+}
+
+impl UdpLink {
+    pub fn bind(local: SocketAddr, partner: SocketAddr) -> Result<UdpLink, String> {
+        let socket =
+            UdpSocket::bind(local).map_err(|e| format!("could not bind {}: {}", local, e))?;
+        socket
+            .set_nonblocking(true)
+            .map_err(|e| format!("could not make socket non-blocking: {}", e))?;
+        Ok(UdpLink {
+            socket,
+            partner,
+            sent: 0,
+            received: 0,
+        })
+    }
+}
+
+impl Link for UdpLink {
+    fn get_udp_packet(&mut self) -> Result<Option<Vec<u8>>, LinkError> {
+        let mut buffer = [0u8; MAX_DATAGRAM];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((len, from)) => {
+                    if from.ip() != self.partner.ip() {
+                        continue; // like the board, only listen to the partner
+                    }
+                    self.received += 1;
+                    return Ok(Some(buffer[..len].to_vec()));
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(None),
+                Err(_) => return Err(LinkError::Transport("udp socket")),
+            }
+        }
+    }
+
+    fn send_udp_packet(&mut self, data: &[u8]) {
+        if self.socket.send_to(data, self.partner).is_ok() {
+            self.sent += 1;
+        }
+    }
+}
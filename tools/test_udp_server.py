@@ -7,12 +7,17 @@ UDP_ADDRESS = 'localhost'
 UDP_ADDRESS = '141.52.46.1'
 UDP_PORT = 2018
 
-MESSAGE = b'\x00\x00\x60d\x01\x90\x00d\x00\xc8\x00d\x00\x01\x00\x01\xff\x00'
-data = unpack('>hhhhhhhhBB', MESSAGE)
+# GamestatePacket: rackets (x, y) x2, ball x, y, x_vel, y_vel, score x2, state,
+# sets x2, seconds left in a timed set, rematch votes
+# for a peer that speaks the whole protocol see pong-host
+GAMESTATE_FORMAT = '>hhhhhhhhBBBBBHB'
+
+MESSAGE = b'\x00\n\x00\x88\x01\xd6\x00\x88\x00\xf0\x00\x88\x00\x03\xff\xfd\x00\x00\x00\x00\x00\x00\x00\x00'
+data = unpack(GAMESTATE_FORMAT, MESSAGE)
 print(data)
 
-gamestate = (0, 0, 400, 100, 200, 100, 1, 1, 0, 0)
-data = pack('>hhhhhhhhBB', gamestate)
+gamestate = (10, 136, 470, 136, 240, 136, 3, -3, 0, 0, 0, 0, 0, 0, 0)
+data = pack(GAMESTATE_FORMAT, *gamestate)
 print(data)
 
 exit()
@@ -1,5 +1,6 @@
 #!/usr/bin/env python
 import socket
+import struct
 
 
 # UDP_IP ='141.52.46.1'
@@ -8,7 +9,13 @@ UDP_IP = '141.52.46.2'
 UDP_PORT = 2018
 # MESSAGE = b'\x01' # down
 
-MESSAGE = b'\x00\x00\x60d\x01\x90\x00d\x00\xc8\x00d\x00\x01\x00\x01\xff\x00' # gamestate
+# GamestatePacket, 24 bytes big endian:
+#   h  racket 1 x, racket 1 y, racket 2 x, racket 2 y
+#   h  ball x, ball y, ball x_vel, ball y_vel
+#   B  score player 1, score player 2, state, sets player 1, sets player 2
+#   H  time_left, seconds left in a timed set
+#   B  votes, the rematch vote bits
+MESSAGE = struct.pack('>hhhhhhhhBBBBBHB', 10, 136, 470, 136, 240, 136, 3, -3, 0, 0, 0, 0, 0, 0, 0)
 
 
 sock = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)